@@ -1,5 +1,7 @@
 use super::*;
 use clap::Parser;
+use eclss_api::air_quality::{AqiCategory, Co2Band, IaqCategory};
+use eclss_api::schema;
 use embedded_graphics::geometry::Point;
 use embedded_graphics::mono_font::MonoTextStyle;
 use embedded_graphics::pixelcolor::BinaryColor;
@@ -105,6 +107,7 @@ struct ValuePositions {
     humidity: Point,
     tvoc: Point,
     co2: Point,
+    aqi: Point,
 }
 
 const OFFSET: i32 = 2;
@@ -143,6 +146,7 @@ where
     const HUMI: &str = "HUMIDITY: ";
     const CO_2: &str = "CO2:      ";
     const TVOC: &str = "TVOC:     ";
+    const AQI: &str = "AQI:      ";
 
     // const WIDTH: usize = {
     //     let labels = [TIME, TEMP, HUMIDITY, TVOC, CO2];
@@ -196,13 +200,15 @@ where
     let (temp, pt) = draw_label(TEMP, pt)?;
     let (humidity, pt) = draw_label(HUMI, pt)?;
     let (co2, pt) = draw_label(CO_2, pt)?;
-    let (tvoc, _) = draw_label(TVOC, pt)?;
+    let (tvoc, pt) = draw_label(TVOC, pt)?;
+    let (aqi, _) = draw_label(AQI, pt)?;
     Ok(ValuePositions {
         time,
         temp,
         humidity,
         tvoc,
         co2,
+        aqi,
     })
 }
 
@@ -241,24 +247,42 @@ where
     draw_value(
         mean(&metrics.temp_c).map(|temp_c| {
             let temp_f = temp_c_to_f(temp_c);
-            format!("{temp_c:2.2} °C / {temp_f:3.2} °F")
+            let unit = unit(&schema::TEMP_C);
+            format!("{temp_c:2.2}{unit} / {temp_f:3.2} °F")
         }),
         positions.temp,
     )?;
 
     draw_value(
-        mean(&metrics.rel_humidity_percent).map(|h| format!("{h:2.2}%")),
+        mean(&metrics.rel_humidity_percent)
+            .map(|h| format!("{h:2.2}{}", unit(&schema::REL_HUMIDITY_PERCENT))),
         positions.humidity,
     )?;
 
+    let co2_band = worst(&metrics.co2_comfort_band)
+        .and_then(|level| Co2Band::from_level(level as u8))
+        .map(|band| band.as_str())
+        .unwrap_or_default();
     draw_value(
-        mean(&metrics.co2_ppm).map(|co2| format!("{co2:.2} ppm")),
+        mean(&metrics.co2_ppm).map(|co2| format!("{co2:.2}{} {co2_band}", unit(&schema::CO2_PPM))),
         positions.co2,
     )?;
 
+    let iaq = worst(&metrics.tvoc_iaq_category)
+        .and_then(|level| IaqCategory::from_level(level as u8))
+        .map(|category| category.as_str())
+        .unwrap_or_default();
     draw_value(
-        mean(&metrics.tvoc_ppb).map(|tvoc| format!("{tvoc:.2} ppb")),
+        mean(&metrics.tvoc_ppb).map(|tvoc| format!("{tvoc:.2}{} {iaq}", unit(&schema::TVOC_PPB))),
         positions.tvoc,
     )?;
+
+    draw_value(
+        worst(&metrics.aqi).map(|aqi| {
+            let category = AqiCategory::from_aqi(aqi as u16);
+            format!("{aqi:.0} {}", category.short_str())
+        }),
+        positions.aqi,
+    )?;
     Ok(())
 }
@@ -109,8 +109,22 @@ fn temp_c_to_f(temp_c: f64) -> f64 {
     (temp_c * 1.8) + 32.0
 }
 
+/// Returns the unit symbol of `metric`, preceded by a space, or an empty
+/// string if it doesn't have one.
+fn unit(metric: &eclss_api::schema::Metric) -> String {
+    metric
+        .unit_symbol()
+        .map(|symbol| format!(" {symbol}"))
+        .unwrap_or_default()
+}
+
+/// Returns the fused reading in `measurements`, if the node reports one, or
+/// the mean of all the readings otherwise.
 fn mean(measurements: impl AsRef<[eclss_api::Measurement]>) -> Option<f64> {
     let measurements = measurements.as_ref();
+    if let Some(fused) = measurements.iter().find(|m| m.sensor.is_fused()) {
+        return Some(fused.value);
+    }
     let len = measurements.len();
     if len == 0 {
         return None;
@@ -118,3 +132,28 @@ fn mean(measurements: impl AsRef<[eclss_api::Measurement]>) -> Option<f64> {
     let sum: f64 = measurements.iter().map(|m| m.value).sum();
     Some(sum / len as f64)
 }
+
+/// Returns the highest of `measurements`.
+///
+/// Air quality indices are shown for the sensor with the worst air quality,
+/// rather than averaged, so that a bad reading isn't hidden by good ones.
+fn worst(measurements: impl AsRef<[eclss_api::Measurement]>) -> Option<f64> {
+    measurements
+        .as_ref()
+        .iter()
+        .map(|m| m.value)
+        .reduce(f64::max)
+}
+
+/// Returns the mean of the particulate `measurements` of particles with the
+/// given `diameter`, such as `"2.5"`.
+fn mean_pm(measurements: &[eclss_api::ParticulateMeasurement], diameter: &str) -> Option<f64> {
+    let (sum, len) = measurements
+        .iter()
+        .filter(|m| m.diameter == diameter)
+        .fold((0.0, 0), |(sum, len), m| (sum + m.value, len + 1));
+    if len == 0 {
+        return None;
+    }
+    Some(sum / len as f64)
+}
@@ -4,7 +4,10 @@ use crossterm::{
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     ExecutableCommand,
 };
-use eclss_api::Metrics;
+use eclss_api::{
+    air_quality::{AqiCategory, Co2Band, IaqCategory},
+    schema, Metrics,
+};
 use futures::stream::StreamExt;
 use ratatui::{
     prelude::*,
@@ -118,7 +121,8 @@ impl Widget for &App {
                             "Temperature: ".into(),
                             Span::styled(format!("{temp_f:.2} °F"), reading_style),
                             " / ".into(),
-                            Span::styled(format!("{temp_c:.2} °C"), reading_style),
+                            Span::styled(format!("{temp_c:.2}"), reading_style),
+                            unit(&schema::TEMP_C).into(),
                         ])
                     })
                     .unwrap_or_else(|| {
@@ -133,7 +137,7 @@ impl Widget for &App {
                         Line::from(vec![
                             "Relative Humidity: ".into(),
                             Span::styled(format!("{h:02.2}"), reading_style),
-                            "%".into(),
+                            unit(&schema::REL_HUMIDITY_PERCENT).into(),
                         ])
                     })
                     .unwrap_or_else(|| {
@@ -148,7 +152,7 @@ impl Widget for &App {
                         Line::from(vec![
                             "Absolute Humidity: ".into(),
                             Span::styled(format!("{h:02.2}"), reading_style),
-                            " g/m³".into(),
+                            unit(&schema::ABS_HUMIDITY_GRAMS_M3).into(),
                         ])
                     })
                     .unwrap_or_else(|| {
@@ -158,29 +162,64 @@ impl Widget for &App {
                         ])
                     });
 
+                let co2_band = worst(&metrics.co2_comfort_band)
+                    .and_then(|level| Co2Band::from_level(level as u8))
+                    .map(|band| format!(" ({band})"))
+                    .unwrap_or_default();
                 let co2 = mean(&metrics.co2_ppm)
                     .map(|co2| {
                         Line::from(vec![
                             "CO₂: ".into(),
                             Span::styled(format!("{co2:03.2}"), reading_style),
-                            " ppm".into(),
+                            unit(&schema::CO2_PPM).into(),
+                            co2_band.into(),
                         ])
                     })
                     .unwrap_or_else(|| {
                         Line::from(vec!["CO₂: ".into(), Span::styled("???", reading_style)])
                     });
 
+                let iaq = worst(&metrics.tvoc_iaq_category)
+                    .and_then(|level| IaqCategory::from_level(level as u8))
+                    .map(|category| format!(" ({category})"))
+                    .unwrap_or_default();
                 let tvoc = mean(&metrics.tvoc_ppb)
                     .map(|t| {
                         Line::from(vec![
                             "tVOC: ".into(),
                             Span::styled(format!("{t:03.2}"), reading_style),
-                            " ppb".into(),
+                            unit(&schema::TVOC_PPB).into(),
+                            iaq.into(),
                         ])
                     })
                     .unwrap_or_else(|| {
                         Line::from(vec!["tVOC: ".into(), Span::styled("???", reading_style)])
                     });
+
+                let pm2_5 = mean_pm(&metrics.pm_concentration_ug_m3, "2.5")
+                    .map(|pm| {
+                        Line::from(vec![
+                            "PM2.5: ".into(),
+                            Span::styled(format!("{pm:.1}"), reading_style),
+                            unit(&schema::PM_CONCENTRATION).into(),
+                        ])
+                    })
+                    .unwrap_or_else(|| {
+                        Line::from(vec!["PM2.5: ".into(), Span::styled("???", reading_style)])
+                    });
+
+                let aqi = worst(&metrics.aqi)
+                    .map(|aqi| {
+                        let category = AqiCategory::from_aqi(aqi as u16);
+                        Line::from(vec![
+                            "AQI: ".into(),
+                            Span::styled(format!("{aqi:.0}"), reading_style),
+                            format!(" ({category})").into(),
+                        ])
+                    })
+                    .unwrap_or_else(|| {
+                        Line::from(vec!["AQI: ".into(), Span::styled("???", reading_style)])
+                    });
                 Text::from(vec![
                     self.conn.clone(),
                     Line::from(Vec::new()),
@@ -189,6 +228,8 @@ impl Widget for &App {
                     abs_humidity,
                     co2,
                     tvoc,
+                    pm2_5,
+                    aqi,
                 ])
             }
             Err(ref error) => {
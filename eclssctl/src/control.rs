@@ -0,0 +1,200 @@
+//! Remote operations on nodes' sensors, using the daemon's sensor control
+//! endpoints.
+use crate::NodeQuery;
+use anyhow::Context;
+use eclss_api::{
+    control::{CalibrateRequest, CalibrateResponse, REFERENCE_PPM},
+    SensorId, SensorName, SensorState,
+};
+use futures::stream::StreamExt;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::time::Duration;
+use tokio::task::JoinSet;
+
+/// How long to wait for a node to respond to a request.
+///
+/// The daemon waits up to 30 seconds for a sensor to execute a command, so
+/// this is somewhat longer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// What to reset with `eclssctl reset`.
+#[derive(Copy, Clone, Debug, clap::ValueEnum)]
+pub(crate) enum ResetKind {
+    /// soft reset sensors, and initialize them again
+    Sensor,
+    /// discard SGP30s' learned baseline calibration
+    Baseline,
+    /// run SEN55s' fan cleaning
+    Fan,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum Operation {
+    Calibrate { reference_ppm: u16 },
+    Reset(ResetKind),
+}
+
+impl Operation {
+    /// The sensor models this operation is performed on when no sensors are
+    /// selected explicitly, or `None` if it's performed on every sensor.
+    fn models(&self) -> Option<&'static [SensorName]> {
+        match self {
+            Self::Calibrate { .. } => {
+                Some(&[SensorName::Scd30, SensorName::Scd40, SensorName::Scd41])
+            }
+            Self::Reset(ResetKind::Sensor) => None,
+            Self::Reset(ResetKind::Baseline) => Some(&[SensorName::Sgp30]),
+            Self::Reset(ResetKind::Fan) => Some(&[SensorName::Sen55]),
+        }
+    }
+
+    /// Performs this operation on `sensor`, returning a description of the
+    /// result.
+    async fn send(
+        &self,
+        client: &reqwest::Client,
+        node: &reqwest::Url,
+        sensor: SensorId,
+    ) -> anyhow::Result<String> {
+        let endpoint = match self {
+            Self::Calibrate { .. } => "calibrate",
+            Self::Reset(ResetKind::Sensor) => "reset",
+            Self::Reset(ResetKind::Baseline) => "reset-baseline",
+            Self::Reset(ResetKind::Fan) => "clean-fan",
+        };
+        let url = node.join(&format!("/sensors/{sensor}/{endpoint}"))?;
+        let request = client.post(url);
+        match *self {
+            Self::Calibrate { reference_ppm } => {
+                let CalibrateResponse { correction_ppm, .. } =
+                    post(request.json(&CalibrateRequest { reference_ppm }))
+                        .await?
+                        .json()
+                        .await
+                        .context("failed to read calibration response")?;
+                Ok(match correction_ppm {
+                    Some(correction) => format!("recalibrated (correction: {correction} ppm)"),
+                    None => "recalibrated".to_owned(),
+                })
+            }
+            Self::Reset(kind) => {
+                post(request).await?;
+                Ok(match kind {
+                    ResetKind::Sensor => "reset",
+                    ResetKind::Baseline => "baseline reset, recalibrating",
+                    ResetKind::Fan => "fan cleaning started",
+                }
+                .to_owned())
+            }
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Calibrate { .. } => f.write_str("calibrate"),
+            Self::Reset(ResetKind::Sensor) => f.write_str("reset"),
+            Self::Reset(ResetKind::Baseline) => f.write_str("reset baseline"),
+            Self::Reset(ResetKind::Fan) => f.write_str("clean fan"),
+        }
+    }
+}
+
+/// Performs `operation` on the `sensors` of each node selected by `query`,
+/// or on every present sensor it applies to if no sensors are given.
+///
+/// Results are printed per node. A node on which any operation failed
+/// completes with an error.
+pub(crate) async fn run(
+    operation: Operation,
+    sensors: Vec<SensorId>,
+    mut query: NodeQuery,
+    background: &mut JoinSet<anyhow::Result<()>>,
+) -> anyhow::Result<()> {
+    if let Operation::Calibrate { reference_ppm } = operation {
+        anyhow::ensure!(
+            REFERENCE_PPM.contains(&reference_ppm),
+            "reference CO₂ concentration must be between {} and {} ppm",
+            REFERENCE_PPM.start(),
+            REFERENCE_PPM.end(),
+        );
+    }
+
+    tracing::info!(?operation, ?sensors, ?query, "sending commands to nodes...");
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .context("failed to build HTTP client")?;
+    let mut urls = query.urls(background).await?;
+    while let Some(url) = urls.next().await {
+        let (name, url) = url?;
+        let client = client.clone();
+        let sensors = sensors.clone();
+        background.spawn(async move {
+            let mut output = format!("node: {name} ({url})\n");
+            let result = run_node(operation, sensors, &client, &url, &mut output).await;
+            print!("{output}");
+            result.with_context(|| format!("failed to {operation} on {name}"))
+        });
+    }
+
+    Ok(())
+}
+
+async fn run_node(
+    operation: Operation,
+    mut sensors: Vec<SensorId>,
+    client: &reqwest::Client,
+    url: &reqwest::Url,
+    output: &mut String,
+) -> anyhow::Result<()> {
+    if sensors.is_empty() {
+        let states = client
+            .get(url.join("/sensors.json")?)
+            .send()
+            .await?
+            .json::<BTreeMap<String, SensorState>>()
+            .await
+            .context("failed to read sensor states")?;
+        sensors = states
+            .into_iter()
+            .filter(|(_, state)| state.found() && state.status().is_present())
+            // skip sensors this version of eclssctl doesn't know about.
+            .filter_map(|(id, _)| id.parse::<SensorId>().ok())
+            .filter(|id| operation.models().is_none_or(|m| m.contains(&id.name)))
+            .collect();
+    }
+
+    if sensors.is_empty() {
+        writeln!(output, "    no sensors to {operation}")?;
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for &sensor in &sensors {
+        match operation.send(client, url, sensor).await {
+            Ok(result) => writeln!(output, "    sensor {sensor}: {result}")?,
+            Err(error) => {
+                failed += 1;
+                writeln!(output, "    sensor {sensor}: failed: {error:#}")?;
+            }
+        }
+    }
+
+    anyhow::ensure!(failed == 0, "{failed} of {} sensors failed", sensors.len());
+    Ok(())
+}
+
+/// Sends a control request, returning an error with the response body if the
+/// node responds with an error status.
+async fn post(request: reqwest::RequestBuilder) -> anyhow::Result<reqwest::Response> {
+    let response = request.send().await?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let message = response.text().await.unwrap_or_default();
+    anyhow::bail!("{status}: {message}")
+}
@@ -1,5 +1,6 @@
 use anyhow::{Context, Ok};
 use clap::Parser;
+use eclss_api::SensorId;
 use eclss_app::{TraceArgs, TraceFormat};
 use futures::stream::{self, Stream, StreamExt};
 use std::collections::{BTreeMap, HashSet};
@@ -8,6 +9,8 @@ use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::task::JoinSet;
 
+mod control;
+
 #[derive(Debug, Parser)]
 struct Args {
     #[clap(flatten)]
@@ -31,14 +34,55 @@ enum Command {
         #[clap(flatten)]
         query: NodeQuery,
     },
+
+    /// print a node's current sensor readings
+    Metrics {
+        #[clap(flatten)]
+        query: NodeQuery,
+    },
+
+    /// perform a forced recalibration of CO₂ sensors
+    ///
+    /// The sensors should have been running for at least a few minutes in air
+    /// with a known, stable CO₂ concentration (such as outdoors, where it's
+    /// about 420 ppm).
+    Calibrate {
+        /// the CO₂ concentration that the sensors are exposed to, in ppm
+        #[clap(long, short)]
+        reference_ppm: u16,
+
+        /// the sensors to recalibrate (defaults to all CO₂ sensors)
+        #[clap(long = "sensor", short)]
+        sensors: Vec<SensorId>,
+
+        #[clap(flatten)]
+        query: NodeQuery,
+    },
+
+    /// reset sensors, their baseline calibration, or their fans
+    Reset {
+        /// what to reset
+        #[clap(value_enum, default_value = "sensor")]
+        kind: control::ResetKind,
+
+        /// the sensors to reset (defaults to all sensors supporting this kind
+        /// of reset)
+        #[clap(long = "sensor", short)]
+        sensors: Vec<SensorId>,
+
+        #[clap(flatten)]
+        query: NodeQuery,
+    },
 }
 
 #[derive(Debug, Parser)]
 #[command(next_help_heading = "Node Selection")]
 struct NodeQuery {
+    /// the URL of a node to select
     #[clap(long = "url", short)]
     urls: Vec<reqwest::Url>,
 
+    /// select discovered nodes in this location
     #[clap(long = "location", short)]
     locations: Vec<String>,
 }
@@ -57,10 +101,30 @@ impl NodeQuery {
         &mut self,
         background: &mut JoinSet<anyhow::Result<()>>,
     ) -> anyhow::Result<Pin<Box<dyn Stream<Item = anyhow::Result<(String, reqwest::Url)>>>>> {
-        let discover_all = self.locations.is_empty() && self.urls.is_empty();
-        if discover_all {
-            let rx = discover(Duration::from_secs(1), background)?;
-            let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(|info| {
+        let urls = std::mem::take(&mut self.urls);
+        // nodes are discovered if they're selected by location, or if no
+        // nodes are selected at all.
+        let discover_nodes = !self.locations.is_empty() || urls.is_empty();
+        let urls = stream::iter(
+            urls.into_iter()
+                .map(|url| Ok((url.host_str().unwrap().to_owned(), url))),
+        );
+        if !discover_nodes {
+            return Ok(Box::pin(urls));
+        }
+
+        let locations = std::mem::take(&mut self.locations);
+        let rx = discover(Duration::from_secs(1), background)?;
+        let discovered = tokio_stream::wrappers::ReceiverStream::new(rx)
+            .filter(move |info| {
+                let selected = locations.is_empty()
+                    || info
+                        .location
+                        .as_ref()
+                        .is_some_and(|location| locations.contains(location));
+                std::future::ready(selected)
+            })
+            .map(|info| {
                 let addr = info.addrs.iter().next().ok_or_else(|| {
                     anyhow::anyhow!("no addresses resolved for {}", info.hostname)
                 })?;
@@ -69,13 +133,7 @@ impl NodeQuery {
                     .with_context(|| format!("failed to parse URL for {}", info.hostname))?;
                 Ok((info.hostname, url))
             });
-            return Ok(Box::pin(stream));
-        }
-        Ok(Box::pin(stream::iter(
-            std::mem::take(&mut self.urls)
-                .into_iter()
-                .map(|url| Ok((url.host_str().unwrap().to_owned(), url))),
-        )))
+        Ok(Box::pin(urls.chain(discovered)))
     }
 }
 
@@ -109,16 +167,111 @@ async fn main() -> anyhow::Result<()> {
                 });
             }
         }
+        Command::Metrics { mut query } => {
+            tracing::info!(?query, "querying node metrics...");
+            let mut urls = query.urls(&mut background).await?;
+            while let Some(url) = urls.next().await {
+                let (name, url) = url?;
+                background.spawn(async move {
+                    let metrics = reqwest::get(url.join("/metrics.json")?)
+                        .await?
+                        .json::<eclss_api::Metrics>()
+                        .await?;
+                    print!("node: {name} ({url})\n{}", fmt_metrics(&metrics)?);
+                    Ok(())
+                });
+            }
+        }
+        Command::Calibrate {
+            reference_ppm,
+            sensors,
+            query,
+        } => {
+            let operation = control::Operation::Calibrate { reference_ppm };
+            control::run(operation, sensors, query, &mut background).await?
+        }
+        Command::Reset {
+            kind,
+            sensors,
+            query,
+        } => {
+            let operation = control::Operation::Reset(kind);
+            control::run(operation, sensors, query, &mut background).await?
+        }
     };
 
+    // keep going if one node fails, so that every node's results are reported.
+    let mut failed = 0;
     while let Some(bg) = background.join_next().await {
-        bg.context("a background task panicked")?
-            .context("a background task returned an error")?;
+        let result = bg
+            .context("a background task panicked")
+            .and_then(|result| result.context("a background task returned an error"));
+        if let Err(error) = result {
+            eprintln!("error: {error:#}");
+            failed += 1;
+        }
     }
+    anyhow::ensure!(failed == 0, "{failed} background tasks failed");
 
     Ok(())
 }
 
+/// Formats every metric with readings, labeled using the metrics schema.
+fn fmt_metrics(metrics: &eclss_api::Metrics) -> anyhow::Result<String> {
+    use eclss_api::Readings;
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    if metrics.schema_version > eclss_api::schema::VERSION {
+        writeln!(
+            out,
+            "    (node uses metrics schema v{}, newer than v{}; some metrics may be missing)",
+            metrics.schema_version,
+            eclss_api::schema::VERSION,
+        )?;
+    }
+    for (metric, readings) in metrics.iter() {
+        if readings.is_empty() {
+            continue;
+        }
+        writeln!(out, "    {}: {}", metric.name, metric.help)?;
+        let unit = metric
+            .unit_symbol()
+            .map(|symbol| format!(" {symbol}"))
+            .unwrap_or_default();
+        match readings {
+            Readings::Sensor(readings) => {
+                for eclss_api::Measurement { value, sensor, .. } in readings {
+                    writeln!(out, "        {sensor}: {value}{unit}")?;
+                }
+            }
+            Readings::Diameter(readings) => {
+                for eclss_api::ParticulateMeasurement {
+                    value,
+                    sensor,
+                    diameter,
+                    ..
+                } in readings
+                {
+                    writeln!(out, "        {sensor} ({diameter} µm): {value}{unit}")?;
+                }
+            }
+            Readings::DeviceStatusFlag(readings) => {
+                for eclss_api::DeviceStatusMeasurement {
+                    value,
+                    sensor,
+                    flag,
+                    ..
+                } in readings
+                {
+                    writeln!(out, "        {sensor} {flag}: {value}{unit}")?;
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
 async fn discover_cmd(
     duration: humantime::Duration,
     background: &mut JoinSet<anyhow::Result<()>>,
@@ -0,0 +1,661 @@
+//! Threshold alerting on sensor readings.
+use crate::{
+    notify::{AlertRule, Event, Notifier},
+    unix_now,
+};
+use anyhow::Context;
+use eclss::SensorMetrics;
+use eclss_api::{
+    alerts::{Aggregate, Alert, AlertState, Comparison},
+    SensorId,
+};
+use eclss_axum::alerts::AlertSource;
+use serde::{Deserialize, Deserializer};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, clap::Parser)]
+#[command(next_help_heading = "Alerts")]
+pub(super) struct AlertArgs {
+    /// Path to a TOML file containing alert rules.
+    ///
+    /// Each rule is a `[[rule]]` table, like:
+    ///
+    /// ```toml
+    /// [[rule]]
+    /// name = "co2-high"
+    /// metric = "co2_ppm"
+    /// comparison = "above"
+    /// threshold = 1200
+    /// for = "10m"
+    /// hysteresis = 50
+    /// ```
+    ///
+    /// If a rule has a `sensor`, it applies to that sensor's readings only.
+    /// Otherwise, it applies to the `aggregate` ("mean", "min", or "max") of
+    /// all sensors' readings of that metric.
+    ///
+    /// Rules for particulate matter metrics must also have a `diameter`, such
+    /// as `metric = "pm_concentration_ug_m3"` and `diameter = "2.5"` for
+    /// PM2.5.
+    #[clap(long = "alert-rules", env = "ECLSS_ALERT_RULES")]
+    rules: Option<PathBuf>,
+
+    /// How often to evaluate alert rules.
+    #[clap(
+        id = "alert_interval",
+        long = "alert-interval",
+        env = "ECLSS_ALERT_INTERVAL",
+        default_value = "10s",
+        value_parser = humantime::parse_duration,
+    )]
+    interval: Duration,
+}
+
+pub(super) struct Alerts {
+    rules: Vec<Rule>,
+    states: Mutex<Vec<RuleState>>,
+    interval: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    name: String,
+    metric: String,
+    /// The particle diameter, in µm, for particulate matter metrics.
+    #[serde(default)]
+    diameter: Option<String>,
+    #[serde(default)]
+    sensor: Option<SensorId>,
+    #[serde(default)]
+    aggregate: Option<Aggregate>,
+    comparison: Comparison,
+    threshold: f64,
+    /// How long the condition must hold before the alert fires.
+    #[serde(default, rename = "for", deserialize_with = "deserialize_duration")]
+    hold: Duration,
+    /// How far back past the threshold the value must go before a firing
+    /// alert resolves.
+    #[serde(default)]
+    hysteresis: f64,
+}
+
+/// A current reading of a metric.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Reading {
+    metric: &'static str,
+    sensor: SensorId,
+    /// The particle diameter, for particulate matter metrics.
+    diameter: Option<&'static str>,
+    value: f64,
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+enum RuleState {
+    #[default]
+    Inactive,
+    Active {
+        state: AlertState,
+        since: u64,
+        value: f64,
+    },
+}
+
+impl AlertArgs {
+    pub(super) async fn load(&self) -> anyhow::Result<Alerts> {
+        let rules = match self.rules {
+            Some(ref path) => {
+                let file = tokio::fs::read_to_string(path).await.with_context(|| {
+                    format!("failed to read alert rules from {}", path.display())
+                })?;
+                let rules = parse_rules(&file)
+                    .with_context(|| format!("invalid alert rules in {}", path.display()))?;
+                tracing::info!(path = %path.display(), rules = rules.len(), "loaded alert rules");
+                rules
+            }
+            None => {
+                tracing::debug!("no alert rules configured");
+                Vec::new()
+            }
+        };
+        Ok(Alerts::new(rules, self.interval))
+    }
+}
+
+fn parse_rules(file: &str) -> anyhow::Result<Vec<Rule>> {
+    let RulesFile { rules } = toml::from_str(file)?;
+    for (i, rule) in rules.iter().enumerate() {
+        anyhow::ensure!(!rule.name.is_empty(), "rule {i} has an empty name");
+        anyhow::ensure!(
+            !rules[..i].iter().any(|r| r.name == rule.name),
+            "duplicate rule name {:?}",
+            rule.name
+        );
+        let metric = rule.metric.as_str();
+        if SensorMetrics::PM_READINGS.contains(&metric) {
+            let diameter = rule.diameter.as_deref().with_context(|| {
+                format!(
+                    "rule {:?} has particulate matter metric {metric:?}, but no diameter",
+                    rule.name
+                )
+            })?;
+            anyhow::ensure!(
+                parse_diameter(diameter).is_some_and(|d| d.is_finite() && d > 0.0),
+                "rule {:?} has invalid diameter {diameter:?} (expected a size in µm, such as \"2.5\")",
+                rule.name
+            );
+        } else {
+            anyhow::ensure!(
+                SensorMetrics::READINGS.contains(&metric),
+                "rule {:?} has unknown metric {metric:?} (expected one of: {})",
+                rule.name,
+                SensorMetrics::READINGS
+                    .iter()
+                    .chain(SensorMetrics::PM_READINGS)
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            anyhow::ensure!(
+                rule.diameter.is_none(),
+                "rule {:?} has a diameter, but {metric:?} is not a particulate matter metric",
+                rule.name
+            );
+        }
+        anyhow::ensure!(
+            rule.sensor.is_none() || rule.aggregate.is_none(),
+            "rule {:?} has both a sensor and an aggregate; an aggregate only applies to \
+            rules for all sensors",
+            rule.name
+        );
+        anyhow::ensure!(
+            rule.threshold.is_finite(),
+            "rule {:?} has a non-finite threshold",
+            rule.name
+        );
+        anyhow::ensure!(
+            rule.hysteresis >= 0.0,
+            "rule {:?} has a negative hysteresis",
+            rule.name
+        );
+    }
+    Ok(rules)
+}
+
+impl Alerts {
+    fn new(rules: Vec<Rule>, interval: Duration) -> Self {
+        let states = Mutex::new(vec![RuleState::Inactive; rules.len()]);
+        Self {
+            rules,
+            states,
+            interval,
+        }
+    }
+
+    /// Evaluates the alert rules against the current metrics, forever.
+    pub(super) async fn run(&'static self, metrics: &'static SensorMetrics, notifier: Notifier) {
+        if self.rules.is_empty() {
+            return;
+        }
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut readings = Vec::new();
+        loop {
+            interval.tick().await;
+            readings.clear();
+            metrics.for_each_reading(|metric, sensor, value| {
+                readings.push(Reading {
+                    metric,
+                    sensor,
+                    diameter: None,
+                    value,
+                })
+            });
+            metrics.for_each_pm_reading(|metric, label, value| {
+                readings.push(Reading {
+                    metric,
+                    sensor: label.sensor,
+                    diameter: Some(label.diameter),
+                    value,
+                })
+            });
+            for event in self.evaluate(unix_now(), &readings) {
+                notifier.notify(event);
+            }
+        }
+    }
+
+    /// Evaluates the alert rules against `readings`, returning notification
+    /// events for any alerts which started firing or resolved.
+    fn evaluate(&self, now: u64, readings: &[Reading]) -> Vec<Event> {
+        let mut events = Vec::new();
+        let mut states = self.states.lock().unwrap();
+        for (rule, state) in self.rules.iter().zip(states.iter_mut()) {
+            let Some(value) = rule.value(readings) else {
+                // No readings for this metric; leave the alert in its current
+                // state until the sensor comes back.
+                continue;
+            };
+            let next = rule.next_state(*state, now, value);
+            match (state.alert_state(), next.alert_state()) {
+                (None, Some(AlertState::Pending)) => {
+                    tracing::debug!(rule = %rule.name, value, "alert pending");
+                }
+                (None | Some(AlertState::Pending), Some(AlertState::Firing)) => {
+                    tracing::warn!(
+                        rule = %rule.name,
+                        metric = %rule.metric,
+                        diameter = ?rule.diameter,
+                        sensor = ?rule.sensor,
+                        value,
+                        threshold = rule.threshold,
+                        "alert firing: {} is {} {}",
+                        rule.metric,
+                        rule.comparison,
+                        rule.threshold,
+                    );
+                    events.push(rule.event(value, true));
+                }
+                (Some(AlertState::Firing), None) => {
+                    tracing::info!(
+                        rule = %rule.name,
+                        metric = %rule.metric,
+                        diameter = ?rule.diameter,
+                        sensor = ?rule.sensor,
+                        value,
+                        threshold = rule.threshold,
+                        "alert resolved"
+                    );
+                    events.push(rule.event(value, false));
+                }
+                (Some(AlertState::Pending), None) => {
+                    tracing::debug!(rule = %rule.name, value, "pending alert cleared");
+                }
+                _ => {}
+            }
+            *state = next;
+        }
+        events
+    }
+}
+
+impl AlertSource for Alerts {
+    fn active_alerts(&self) -> Vec<Alert> {
+        let states = self.states.lock().unwrap();
+        self.rules
+            .iter()
+            .zip(states.iter())
+            .filter_map(|(rule, state)| match *state {
+                RuleState::Inactive => None,
+                RuleState::Active {
+                    state,
+                    since,
+                    value,
+                } => Some(Alert {
+                    rule: rule.name.clone(),
+                    metric: rule.metric.clone(),
+                    diameter: rule.diameter.clone(),
+                    sensor: rule.sensor,
+                    comparison: rule.comparison,
+                    threshold: rule.threshold,
+                    value,
+                    state,
+                    since,
+                }),
+            })
+            .collect()
+    }
+}
+
+// === impl RuleState ===
+
+impl RuleState {
+    fn alert_state(&self) -> Option<AlertState> {
+        match *self {
+            Self::Inactive => None,
+            Self::Active { state, .. } => Some(state),
+        }
+    }
+}
+
+// === impl Rule ===
+
+impl Rule {
+    /// Returns the value this rule applies to, if there are any readings of
+    /// its metric.
+    fn value(&self, readings: &[Reading]) -> Option<f64> {
+        let diameter = self.diameter.as_deref().and_then(parse_diameter);
+        let mut values = readings
+            .iter()
+            .filter(|reading| reading.metric == self.metric)
+            .filter(|reading| self.sensor.is_some() || !reading.sensor.is_fused())
+            .filter(|reading| reading.diameter.and_then(parse_diameter) == diameter)
+            .filter(|reading| self.sensor.is_none_or(|s| s == reading.sensor))
+            .map(|reading| reading.value);
+        match self.sensor {
+            Some(_) => values.next(),
+            None => self.aggregate.unwrap_or_default().apply(values),
+        }
+    }
+
+    fn event(&self, value: f64, firing: bool) -> Event {
+        let rule = AlertRule {
+            name: &self.name,
+            metric: &self.metric,
+            diameter: self.diameter.as_deref(),
+            sensor: self.sensor,
+            comparison: self.comparison,
+            threshold: self.threshold,
+        };
+        Event::alert(&rule, value, firing)
+    }
+
+    fn next_state(&self, state: RuleState, now: u64, value: f64) -> RuleState {
+        let matches = self.comparison.matches(value, self.threshold);
+        let hold = self.hold.as_secs();
+        match state {
+            RuleState::Inactive if matches => RuleState::Active {
+                state: if hold == 0 {
+                    AlertState::Firing
+                } else {
+                    AlertState::Pending
+                },
+                since: now,
+                value,
+            },
+            RuleState::Inactive => RuleState::Inactive,
+            RuleState::Active {
+                state: AlertState::Pending,
+                since,
+                ..
+            } => match matches {
+                false => RuleState::Inactive,
+                true if now.saturating_sub(since) >= hold => RuleState::Active {
+                    state: AlertState::Firing,
+                    since: now,
+                    value,
+                },
+                true => RuleState::Active {
+                    state: AlertState::Pending,
+                    since,
+                    value,
+                },
+            },
+            RuleState::Active {
+                state: AlertState::Firing,
+                since,
+                ..
+            } => {
+                let resolve_threshold = match self.comparison {
+                    Comparison::Above => self.threshold - self.hysteresis,
+                    Comparison::Below => self.threshold + self.hysteresis,
+                };
+                let resolved = match self.comparison {
+                    Comparison::Above => value <= resolve_threshold,
+                    Comparison::Below => value >= resolve_threshold,
+                };
+                if resolved {
+                    RuleState::Inactive
+                } else {
+                    RuleState::Active {
+                        state: AlertState::Firing,
+                        since,
+                        value,
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parses a particle diameter, so that `"10"` and `"10.0"` are the same.
+fn parse_diameter(diameter: &str) -> Option<f64> {
+    diameter.parse().ok()
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    humantime::parse_duration(&s).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eclss::fusion;
+    use eclss_api::SensorName;
+
+    const CO2_RULE: &str = r#"
+        [[rule]]
+        name = "co2-high"
+        metric = "co2_ppm"
+        comparison = "above"
+        threshold = 1200
+        for = "10m"
+        hysteresis = 50
+    "#;
+
+    fn alerts(rules: &str) -> Alerts {
+        Alerts::new(parse_rules(rules).unwrap(), Duration::from_secs(10))
+    }
+
+    fn reading(metric: &'static str, sensor: SensorName, value: f64) -> Reading {
+        Reading {
+            metric,
+            sensor: SensorId::new(sensor),
+            diameter: None,
+            value,
+        }
+    }
+
+    fn co2(value: f64) -> [Reading; 1] {
+        [reading("co2_ppm", SensorName::Scd41, value)]
+    }
+
+    fn states(alerts: &Alerts) -> Vec<(String, AlertState)> {
+        alerts
+            .active_alerts()
+            .into_iter()
+            .map(|alert| (alert.rule, alert.state))
+            .collect()
+    }
+
+    #[test]
+    fn fires_after_hold_duration() {
+        let alerts = alerts(CO2_RULE);
+        alerts.evaluate(0, &co2(1300.0));
+        assert_eq!(states(&alerts), [("co2-high".into(), AlertState::Pending)]);
+        alerts.evaluate(300, &co2(1300.0));
+        assert_eq!(states(&alerts), [("co2-high".into(), AlertState::Pending)]);
+        assert_eq!(alerts.evaluate(600, &co2(1250.0)).len(), 1);
+        assert_eq!(states(&alerts), [("co2-high".into(), AlertState::Firing)]);
+
+        let alert = &alerts.active_alerts()[0];
+        assert_eq!(alert.value, 1250.0);
+        assert_eq!(alert.since, 600);
+    }
+
+    #[test]
+    fn pending_alert_clears() {
+        let alerts = alerts(CO2_RULE);
+        alerts.evaluate(0, &co2(1300.0));
+        alerts.evaluate(300, &co2(1100.0));
+        assert_eq!(states(&alerts), []);
+        // the hold duration restarts
+        alerts.evaluate(400, &co2(1300.0));
+        alerts.evaluate(900, &co2(1300.0));
+        assert_eq!(states(&alerts), [("co2-high".into(), AlertState::Pending)]);
+    }
+
+    #[test]
+    fn resolves_with_hysteresis() {
+        let alerts = alerts(CO2_RULE);
+        alerts.evaluate(0, &co2(1300.0));
+        alerts.evaluate(600, &co2(1300.0));
+        // below the threshold, but within the hysteresis band
+        alerts.evaluate(700, &co2(1180.0));
+        assert_eq!(states(&alerts), [("co2-high".into(), AlertState::Firing)]);
+        assert_eq!(alerts.evaluate(800, &co2(1150.0)).len(), 1);
+        assert_eq!(states(&alerts), []);
+    }
+
+    #[test]
+    fn missing_readings_keep_state() {
+        let alerts = alerts(CO2_RULE);
+        alerts.evaluate(0, &co2(1300.0));
+        alerts.evaluate(600, &co2(1300.0));
+        alerts.evaluate(700, &[]);
+        assert_eq!(states(&alerts), [("co2-high".into(), AlertState::Firing)]);
+    }
+
+    #[test]
+    fn aggregate_and_sensor_rules() {
+        let alerts = alerts(
+            r#"
+            [[rule]]
+            name = "max-temp"
+            metric = "temp_c"
+            aggregate = "max"
+            comparison = "above"
+            threshold = 30
+
+            [[rule]]
+            name = "mean-temp"
+            metric = "temp_c"
+            comparison = "above"
+            threshold = 30
+
+            [[rule]]
+            name = "sht41-cold"
+            metric = "temp_c"
+            sensor = "SHT41"
+            comparison = "below"
+            threshold = 15
+            "#,
+        );
+        alerts.evaluate(
+            0,
+            &[
+                reading("temp_c", SensorName::Scd41, 31.0),
+                reading("temp_c", SensorName::Sht41, 14.0),
+            ],
+        );
+        assert_eq!(
+            states(&alerts),
+            [
+                ("max-temp".into(), AlertState::Firing),
+                ("sht41-cold".into(), AlertState::Firing),
+            ]
+        );
+    }
+
+    #[test]
+    fn aggregates_ignore_fused_readings() {
+        let alerts = alerts(
+            r#"
+            [[rule]]
+            name = "min-temp"
+            metric = "temp_c"
+            aggregate = "min"
+            comparison = "below"
+            threshold = 15
+            "#,
+        );
+        let fused = Reading {
+            sensor: fusion::FUSED,
+            ..reading("temp_c", SensorName::Scd41, 10.0)
+        };
+        alerts.evaluate(0, &[reading("temp_c", SensorName::Scd41, 20.0), fused]);
+        assert_eq!(states(&alerts), []);
+    }
+
+    #[test]
+    fn pm_rules() {
+        let alerts = alerts(
+            r#"
+            [[rule]]
+            name = "pm2.5-high"
+            metric = "pm_concentration_ug_m3"
+            diameter = "2.5"
+            comparison = "above"
+            threshold = 35
+            "#,
+        );
+        let pm = |diameter, value| Reading {
+            diameter: Some(diameter),
+            ..reading("pm_concentration_ug_m3", SensorName::Pmsa003i, value)
+        };
+        alerts.evaluate(0, &[pm("1.0", 40.0), pm("2.5", 30.0), pm("10.0", 50.0)]);
+        assert_eq!(states(&alerts), []);
+        assert_eq!(
+            alerts
+                .evaluate(10, &[pm("2.5", 36.0), pm("10.0", 50.0)])
+                .len(),
+            1
+        );
+        assert_eq!(states(&alerts), [("pm2.5-high".into(), AlertState::Firing)]);
+        assert_eq!(alerts.active_alerts()[0].diameter.as_deref(), Some("2.5"));
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(parse_rules(
+            r#"
+            [[rule]]
+            name = "bad"
+            metric = "radon_bq_m3"
+            comparison = "above"
+            threshold = 100
+            "#
+        )
+        .is_err());
+        assert!(parse_rules(&format!("{CO2_RULE}\n{CO2_RULE}")).is_err());
+        // particulate matter metrics need a diameter
+        assert!(parse_rules(
+            r#"
+            [[rule]]
+            name = "pm-high"
+            metric = "pm_concentration_ug_m3"
+            comparison = "above"
+            threshold = 35
+            "#
+        )
+        .is_err());
+        // ...and other metrics don't have one
+        assert!(parse_rules(
+            r#"
+            [[rule]]
+            name = "co2-high"
+            metric = "co2_ppm"
+            diameter = "2.5"
+            comparison = "above"
+            threshold = 1200
+            "#
+        )
+        .is_err());
+        // an aggregate can't apply to a single sensor
+        assert!(parse_rules(
+            r#"
+            [[rule]]
+            name = "sht41-hot"
+            metric = "temp_c"
+            sensor = "SHT41"
+            aggregate = "max"
+            comparison = "above"
+            threshold = 30
+            "#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! Loading settings from a TOML configuration file.
+//!
+//! The configuration file is deserialized into a [`ConfigFile`], and each
+//! setting in it is used unless the same setting was passed on the command
+//! line or set by an environment variable.
+use crate::sensors::{self, SensorSettings};
+use crate::Args;
+use anyhow::Context;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches};
+use eclss_app::TraceConfig;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// The settings in a configuration file.
+///
+/// For example:
+///
+/// ```toml
+/// i2cdev = "/dev/i2c-1"
+/// listen_addr = "0.0.0.0:4200"
+/// location = "office"
+/// mdns = true
+/// sensors = ["SCD41", "SHT41:floor", "SHT41:ceiling"]
+/// state_dir = "/var/lib/eclss"
+///
+/// [eclss]
+/// max_init_attempts = 10
+/// log_reading_interval = "1m"
+///
+/// [eclss.retries]
+/// initial_backoff = "1s"
+/// max_backoff = "5m"
+///
+/// [trace]
+/// filter = "info,eclss=debug"
+/// format = "journald"
+/// timestamps = false
+///
+/// [sensor."SHT41:ceiling"]
+/// address = 0x45
+/// offset = { temp = -1.5 }
+/// ```
+///
+/// The `[eclss]` table is the global sensor configuration, with the same
+/// settings as the flags of the same names, and each `[sensor.NAME]` table
+/// holds the per-sensor settings for one sensor (see [`SensorSettings`]).
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    i2cdev: Option<PathBuf>,
+    listen_addr: Option<SocketAddr>,
+    location: Option<Arc<str>>,
+    mdns: Option<bool>,
+    sensors: Option<Vec<String>>,
+    state_dir: Option<PathBuf>,
+    eclss: eclss::Config,
+    trace: TraceConfig,
+    sensor: BTreeMap<String, SensorSettings>,
+}
+
+/// Parses command-line arguments, merging in the configuration file passed
+/// with `--config` (if there is one).
+///
+/// If the arguments are invalid or `--help` is passed, this prints a message
+/// and exits, like [`clap::Parser::parse`].
+pub(super) fn parse_args() -> anyhow::Result<Args> {
+    try_parse_args_from(std::env::args_os()).map_err(|error| {
+        match error.downcast::<clap::Error>() {
+            Ok(error) => error.exit(),
+            Err(error) => error,
+        }
+    })
+}
+
+fn try_parse_args_from(argv: impl IntoIterator<Item = OsString>) -> anyhow::Result<Args> {
+    let mut cmd = Args::command();
+    let matches = cmd.try_get_matches_from_mut(argv)?;
+    let mut args = Args::from_arg_matches(&matches).map_err(|error| error.format(&mut cmd))?;
+    if let Some(path) = args.config.clone() {
+        let file = std::fs::read_to_string(&path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        merge_file(&mut args, &matches, &file)
+            .with_context(|| format!("invalid config file {}", path.display()))?;
+    }
+    Ok(args)
+}
+
+/// Merges the settings in a TOML configuration `file` into `args`, parsed from
+/// `matches`.
+fn merge_file(args: &mut Args, matches: &ArgMatches, file: &str) -> anyhow::Result<()> {
+    let file = toml::from_str::<ConfigFile>(file)?;
+    merge(matches, "i2cdev", &mut args.i2cdev, file.i2cdev);
+    merge(
+        matches,
+        "listen_addr",
+        &mut args.listen_addr,
+        file.listen_addr,
+    );
+    merge(
+        matches,
+        "location",
+        &mut args.location,
+        file.location.map(Some),
+    );
+    merge(matches, "mdns", &mut args.mdns, file.mdns);
+    let sensors = file
+        .sensors
+        .map(|sensors| {
+            sensors
+                .iter()
+                .map(|sensor| sensors::parse_sensor(sensor))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()
+        .map_err(anyhow::Error::msg)?;
+    merge(matches, "sensors", &mut args.sensors, sensors);
+    merge(matches, "path", &mut args.storage.path, file.state_dir);
+
+    let eclss = file.eclss;
+    let config = &mut args.sensor_config;
+    merge(
+        matches,
+        "max_init_attempts",
+        &mut config.max_init_attempts,
+        Some(eclss.max_init_attempts),
+    );
+    merge(
+        matches,
+        "abs_humidity_interval",
+        &mut config.abs_humidity_interval,
+        Some(eclss.abs_humidity_interval),
+    );
+    merge(
+        matches,
+        "log_reading_interval",
+        &mut config.log_reading_interval,
+        Some(eclss.log_reading_interval),
+    );
+    merge(
+        matches,
+        "fan_cleaning_interval",
+        &mut config.fan_cleaning_interval,
+        Some(eclss.fan_cleaning_interval),
+    );
+    merge(
+        matches,
+        "max_reading_age",
+        &mut config.max_reading_age,
+        Some(eclss.max_reading_age),
+    );
+    merge(
+        matches,
+        "initial_backoff",
+        &mut config.retries.initial_backoff,
+        Some(eclss.retries.initial_backoff),
+    );
+    merge(
+        matches,
+        "max_backoff",
+        &mut config.retries.max_backoff,
+        Some(eclss.retries.max_backoff),
+    );
+
+    args.trace.merge_config(file.trace, matches)?;
+    args.sensor_overrides.merge_config(file.sensor)?;
+    Ok(())
+}
+
+/// Sets `arg` to the `value` from the configuration file, unless the argument
+/// `id` was passed on the command line or set by an environment variable.
+fn merge<T>(matches: &ArgMatches, id: &str, arg: &mut T, value: Option<T>) {
+    let from_cli = matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    );
+    if let Some(value) = value.filter(|_| !from_cli) {
+        *arg = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eclss::sensor::SensorId;
+    use std::time::Duration;
+
+    fn parse(file: &str, argv: &[&str]) -> anyhow::Result<Args> {
+        let matches = Args::command()
+            .try_get_matches_from(std::iter::once("eclssd").chain(argv.iter().copied()))?;
+        let mut args = Args::from_arg_matches(&matches)?;
+        merge_file(&mut args, &matches, file)?;
+        Ok(args)
+    }
+
+    fn sensors(names: &[&str]) -> Vec<SensorId> {
+        names.iter().map(|name| name.parse().unwrap()).collect()
+    }
+
+    const FILE: &str = r#"
+        listen_addr = "0.0.0.0:4200"
+        sensors = ["SCD41", "SEN55"]
+        location = "office"
+        state_dir = "/srv/eclss"
+
+        [eclss]
+        log_reading_interval = "1m"
+        max_reading_age = "5m"
+
+        [eclss.retries]
+        initial_backoff = "1s"
+        max_backoff = "2m"
+
+        [trace]
+        filter = "debug"
+        timestamps = false
+
+        [sensor.SCD41]
+        address = 0x62
+        offset = { temp = -2.0 }
+    "#;
+
+    #[test]
+    fn args_are_valid() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn file_sets_settings() {
+        let args = parse(FILE, &[]).unwrap();
+        assert_eq!(args.listen_addr, "0.0.0.0:4200".parse().unwrap());
+        assert_eq!(args.sensors, sensors(&["SCD41", "SEN55"]));
+        assert_eq!(args.location.as_deref(), Some("office"));
+        assert_eq!(args.storage.path, PathBuf::from("/srv/eclss"));
+        let config = &args.sensor_config;
+        assert_eq!(config.log_reading_interval, Duration::from_secs(60));
+        assert_eq!(config.max_reading_age, Some(Duration::from_secs(300)));
+        assert_eq!(config.retries.initial_backoff, Duration::from_secs(1));
+        assert_eq!(config.retries.max_backoff, Duration::from_secs(120));
+        // settings which aren't in the file keep their defaults.
+        assert_eq!(config.abs_humidity_interval, 1);
+        assert_eq!(args.i2cdev, PathBuf::from("/dev/i2c-1"));
+    }
+
+    #[test]
+    fn cli_overrides_file() {
+        let args = parse(
+            FILE,
+            &[
+                "--listen-addr",
+                "127.0.0.1:9999",
+                "--sensor",
+                "SHT41",
+                "--initial-backoff",
+                "2s",
+                "--location",
+                "lab",
+            ],
+        )
+        .unwrap();
+        assert_eq!(args.listen_addr, "127.0.0.1:9999".parse().unwrap());
+        assert_eq!(args.sensors, sensors(&["SHT41"]));
+        assert_eq!(args.location.as_deref(), Some("lab"));
+        assert_eq!(
+            args.sensor_config.retries.initial_backoff,
+            Duration::from_secs(2)
+        );
+        // settings which weren't overridden still come from the file.
+        assert_eq!(
+            args.sensor_config.retries.max_backoff,
+            Duration::from_secs(120)
+        );
+    }
+
+    #[test]
+    fn env_overrides_file() {
+        std::env::set_var("ECLSS_I2C_DEV", "/dev/i2c-7");
+        let args = parse(r#"i2cdev = "/dev/i2c-3""#, &[]);
+        std::env::remove_var("ECLSS_I2C_DEV");
+        assert_eq!(args.unwrap().i2cdev, PathBuf::from("/dev/i2c-7"));
+    }
+
+    #[test]
+    fn cli_disables_file_flags() {
+        let args = parse("mdns = true", &[]).unwrap();
+        assert!(args.mdns);
+        let args = parse("mdns = true", &["--mdns", "false"]).unwrap();
+        assert!(!args.mdns);
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(parse("bogus = 1", &[]).is_err());
+        assert!(parse(r#"config = "other.toml""#, &[]).is_err());
+        assert!(parse(r#"location = ["a", "b"]"#, &[]).is_err());
+        assert!(parse(r#"sensors = ["FUSED"]"#, &[]).is_err());
+        assert!(parse("[eclss]\nbogus = 1", &[]).is_err());
+        assert!(parse("[eclss]\nlog_reading_interval = \"soon\"", &[]).is_err());
+        assert!(parse("[trace]\nformat = \"xml\"", &[]).is_err());
+        assert!(parse("[sensor.SHT41]\naddress = 0x80", &[]).is_err());
+        assert!(parse("[sensor.FUSED]\naddress = 0x44", &[]).is_err());
+    }
+}
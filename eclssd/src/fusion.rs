@@ -0,0 +1,134 @@
+//! Sensor fusion configuration.
+use eclss::fusion::{Adjustment, Config, Quantity};
+use std::time::Duration;
+
+#[derive(Clone, Debug, clap::Parser)]
+#[command(next_help_heading = "Sensor Fusion")]
+pub(super) struct FusionArgs {
+    /// Disable sensor fusion.
+    ///
+    /// By default, readings of the same quantity from several sensors are
+    /// combined into a single best estimate, which is reported as the `FUSED`
+    /// sensor.
+    #[clap(long = "no-fusion", env = "ECLSS_NO_FUSION")]
+    disabled: bool,
+
+    /// How often to compute fused readings.
+    #[clap(
+        id = "fusion_interval",
+        long = "fusion-interval",
+        env = "ECLSS_FUSION_INTERVAL",
+        default_value = "10s",
+        value_parser = parse_interval,
+    )]
+    interval: Duration,
+
+    /// Set how much a sensor's readings count towards fused readings, as
+    /// `SENSOR[/QUANTITY]=WEIGHT`.
+    ///
+    /// `QUANTITY` is one of `temp`, `humidity`, `co2`, or `pressure`; if it is
+    /// omitted, the weight applies to all of the sensor's readings. Sensors
+    /// have a weight of 1 by default, and a weight of 0 excludes a sensor. For
+    /// example, `--fusion-weight SCD41/temp=0.5` halves the weight of an
+    /// SCD41's temperature readings.
+    #[clap(
+        long = "fusion-weight",
+        value_name = "SENSOR[/QUANTITY]=WEIGHT",
+        value_parser = parse_weight,
+    )]
+    weights: Vec<Adjustment>,
+}
+
+impl FusionArgs {
+    /// Returns the sensor fusion configuration, or `None` if sensor fusion is
+    /// disabled.
+    pub(super) fn config(&self) -> Option<Config> {
+        if self.disabled {
+            return None;
+        }
+        Some(Config {
+            interval: self.interval,
+            weights: self.weights.clone().leak(),
+        })
+    }
+}
+
+fn parse_interval(s: &str) -> Result<Duration, String> {
+    let interval =
+        humantime::parse_duration(s).map_err(|error| format!("invalid duration {s:?}: {error}"))?;
+    if interval.is_zero() {
+        return Err("fusion interval must be greater than zero".to_string());
+    }
+    Ok(interval)
+}
+
+fn parse_weight(s: &str) -> Result<Adjustment, String> {
+    let adjustment = parse_adjustment(s)?;
+    if adjustment.value < 0.0 {
+        return Err(format!(
+            "weight must not be negative, got {}",
+            adjustment.value
+        ));
+    }
+    Ok(adjustment)
+}
+
+fn parse_adjustment(s: &str) -> Result<Adjustment, String> {
+    let (target, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected SENSOR[/QUANTITY]=VALUE, got {s:?}"))?;
+    let (sensor, quantity) = match target.split_once('/') {
+        Some((sensor, quantity)) => {
+            let quantity = quantity
+                .trim()
+                .parse::<Quantity>()
+                .map_err(|error| format!("invalid quantity {quantity:?}: {error}"))?;
+            (sensor, Some(quantity))
+        }
+        None => (target, None),
+    };
+    let sensor = crate::sensors::parse_sensor(sensor)?;
+    let value = value
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| format!("invalid number {value:?}"))?;
+    Ok(Adjustment {
+        sensor,
+        quantity,
+        value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eclss_api::{SensorId, SensorName};
+
+    #[test]
+    fn parse_adjustments() {
+        assert_eq!(
+            parse_weight("SCD41/temp=0.5").unwrap(),
+            Adjustment {
+                sensor: SensorId::new(SensorName::Scd41),
+                quantity: Some(Quantity::Temp),
+                value: 0.5,
+            }
+        );
+        assert_eq!(
+            parse_weight("SHT41:ceiling=1.5").unwrap(),
+            Adjustment {
+                sensor: "SHT41:ceiling".parse().unwrap(),
+                quantity: None,
+                value: 1.5,
+            }
+        );
+        assert!(parse_weight("SCD41=-1").is_err());
+        assert!(parse_weight("SCD41/tvoc=1").is_err());
+        assert!(parse_weight("SCD42=1").is_err());
+        assert!(parse_weight("FUSED=1").is_err());
+        assert!(parse_weight("SCD41/co2").is_err());
+        assert!(parse_weight("SCD41/co2=NaN").is_err());
+    }
+}
@@ -0,0 +1,520 @@
+//! In-memory history of downsampled sensor readings.
+use crate::{
+    storage::{self, StateDir},
+    unix_now,
+};
+use anyhow::Context;
+use eclss::SensorMetrics;
+use eclss_api::{
+    history::{History as HistoryResponse, Sample, Series as SeriesResponse},
+    SensorId,
+};
+use eclss_axum::history::{HistoryQuery, HistorySource};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[derive(Debug, clap::Parser)]
+#[command(next_help_heading = "History")]
+pub(super) struct HistoryArgs {
+    /// How long to retain the history of sensor readings for.
+    #[clap(
+        long = "history-retention",
+        env = "ECLSS_HISTORY_RETENTION",
+        default_value = "24h",
+        value_parser = humantime::parse_duration,
+    )]
+    retention: Duration,
+
+    /// The resolution at which to record the history of sensor readings.
+    ///
+    /// Readings are averaged over each interval of this length.
+    #[clap(
+        long = "history-resolution",
+        env = "ECLSS_HISTORY_RESOLUTION",
+        default_value = "1m",
+        value_parser = humantime::parse_duration,
+    )]
+    resolution: Duration,
+
+    /// If set, periodically save the history of sensor readings in the state
+    /// directory, and again when the daemon shuts down, so that it is
+    /// preserved when the daemon restarts.
+    #[clap(long = "history-persist", env = "ECLSS_HISTORY_PERSIST")]
+    persist: bool,
+}
+
+pub(super) struct History {
+    series: Mutex<HashMap<Key, Series>>,
+    resolution_secs: u64,
+    retention_secs: u64,
+    capacity: usize,
+    path: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct Key {
+    metric: &'static str,
+    sensor: SensorId,
+    /// The particle diameter, for particulate matter metrics.
+    diameter: Option<Cow<'static, str>>,
+}
+
+#[derive(Debug, Default)]
+struct Series {
+    samples: VecDeque<Sample>,
+    bucket: Bucket,
+}
+
+/// Readings within the current interval, which have not yet been recorded as
+/// a sample.
+#[derive(Debug, Default)]
+struct Bucket {
+    start: u64,
+    sum: f64,
+    count: u32,
+}
+
+/// The on-disk representation of the history.
+#[derive(Serialize, Deserialize)]
+struct Persisted {
+    series: Vec<PersistedSeries>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedSeries {
+    metric: String,
+    sensor: SensorId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    diameter: Option<String>,
+    samples: Vec<Sample>,
+}
+
+/// How often to save the history, if persistence is enabled.
+const PERSIST_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The maximum interval between readings of the current metric values.
+const MAX_SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+impl HistoryArgs {
+    pub(super) async fn build(&self, state_dir: &StateDir) -> anyhow::Result<History> {
+        anyhow::ensure!(
+            self.resolution >= Duration::from_secs(1),
+            "history resolution must be at least 1 second"
+        );
+        anyhow::ensure!(
+            self.retention >= self.resolution,
+            "history retention must be at least as long as the history resolution"
+        );
+        let resolution_secs = self.resolution.as_secs();
+        let retention_secs = self.retention.as_secs();
+        let history = History {
+            series: Mutex::new(HashMap::new()),
+            resolution_secs,
+            retention_secs,
+            capacity: (retention_secs / resolution_secs) as usize,
+            path: self.persist.then(|| state_dir.file("history.json")),
+        };
+        history.load().await?;
+        Ok(history)
+    }
+}
+
+impl History {
+    /// Records the current value of each metric, forever.
+    pub(super) async fn run(&'static self, metrics: &'static SensorMetrics) {
+        let mut interval = tokio::time::interval(
+            MAX_SAMPLE_INTERVAL.min(Duration::from_secs(self.resolution_secs)),
+        );
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let mut last_persisted = tokio::time::Instant::now();
+        loop {
+            interval.tick().await;
+            self.record(unix_now(), metrics);
+
+            if self.path.is_some() && last_persisted.elapsed() >= PERSIST_INTERVAL {
+                if let Err(error) = self.persist().await {
+                    tracing::warn!(%error, "failed to persist history");
+                }
+                last_persisted = tokio::time::Instant::now();
+            }
+        }
+    }
+
+    /// Records the readings in each series' current interval, and saves the
+    /// history, if persistence is enabled, so that the readings since it was
+    /// last saved aren't lost when the daemon shuts down.
+    pub(super) async fn shutdown(&self) {
+        if self.path.is_none() {
+            return;
+        }
+        for series in self.series.lock().unwrap().values_mut() {
+            series.flush(self.capacity);
+        }
+        match self.persist().await {
+            Ok(()) => tracing::info!("saved history"),
+            Err(error) => tracing::warn!(%error, "failed to persist history"),
+        }
+    }
+
+    fn record(&self, now: u64, metrics: &SensorMetrics) {
+        let bucket_start = now - (now % self.resolution_secs);
+        let mut series = self.series.lock().unwrap();
+        let mut record = |key: Key, value: f64| {
+            let series = series.entry(key).or_default();
+            if series.bucket.start != bucket_start {
+                series.flush(self.capacity);
+                series.bucket.start = bucket_start;
+            }
+            series.bucket.sum += value;
+            series.bucket.count += 1;
+        };
+        metrics.for_each_reading(|metric, sensor, value| {
+            if sensor.is_fused() {
+                return;
+            }
+            let key = Key {
+                metric,
+                sensor,
+                diameter: None,
+            };
+            record(key, value)
+        });
+        metrics.for_each_pm_reading(|metric, label, value| {
+            let key = Key {
+                metric,
+                sensor: label.sensor,
+                diameter: Some(Cow::Borrowed(label.diameter)),
+            };
+            record(key, value)
+        });
+    }
+
+    fn query(&self, query: &HistoryQuery, now: u64) -> Option<HistoryResponse> {
+        let metric = recorded_metric(&query.metric)?;
+        let since = now.saturating_sub(
+            query
+                .since
+                .map(|since| since.as_secs())
+                .unwrap_or(self.retention_secs),
+        );
+        // the step must be a multiple of the resolution at which samples were
+        // recorded.
+        let step_secs = query
+            .step
+            .map(|step| step.as_secs().div_ceil(self.resolution_secs) * self.resolution_secs)
+            .unwrap_or(self.resolution_secs)
+            .max(self.resolution_secs);
+
+        let series = self.series.lock().unwrap();
+        let mut response = series
+            .iter()
+            .filter(|(key, _)| key.metric == metric)
+            .filter(|(key, _)| query.sensor.is_none_or(|sensor| key.sensor == sensor))
+            .filter(|(key, _)| {
+                query
+                    .diameter
+                    .as_deref()
+                    .is_none_or(|diameter| key.diameter.as_deref() == Some(diameter))
+            })
+            .map(|(key, series)| SeriesResponse {
+                sensor: key.sensor,
+                diameter: key.diameter.as_deref().map(str::to_string),
+                samples: downsample(
+                    series.samples.iter().filter(|s| s.timestamp >= since),
+                    step_secs,
+                ),
+            })
+            .collect::<Vec<_>>();
+        response.sort_by_cached_key(|series| (series.sensor.to_string(), series.diameter.clone()));
+        Some(HistoryResponse {
+            metric: metric.to_string(),
+            step_secs,
+            series: response,
+        })
+    }
+
+    async fn load(&self) -> anyhow::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let buf = match tokio::fs::read(path).await {
+            Ok(buf) => buf,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to read history from {}", path.display()))
+            }
+        };
+        let persisted = match serde_json::from_slice::<Persisted>(&buf) {
+            Ok(persisted) => persisted,
+            Err(error) => {
+                // don't refuse to start just because the history is corrupt.
+                tracing::warn!(%error, path = %path.display(), "failed to parse history, discarding it");
+                return Ok(());
+            }
+        };
+
+        let since = unix_now().saturating_sub(self.retention_secs);
+        let mut series = self.series.lock().unwrap();
+        for PersistedSeries {
+            metric,
+            sensor,
+            diameter,
+            samples,
+        } in persisted.series
+        {
+            let Some(metric) = recorded_metric(&metric) else {
+                tracing::debug!(?metric, "ignoring history for unknown metric");
+                continue;
+            };
+            let mut samples = samples
+                .into_iter()
+                .filter(|s| s.timestamp >= since)
+                .collect::<VecDeque<_>>();
+            while samples.len() > self.capacity {
+                samples.pop_front();
+            }
+            let key = Key {
+                metric,
+                sensor,
+                diameter: diameter.map(Cow::Owned),
+            };
+            series.insert(
+                key,
+                Series {
+                    samples,
+                    bucket: Bucket::default(),
+                },
+            );
+        }
+        tracing::info!(path = %path.display(), series = series.len(), "loaded history");
+        Ok(())
+    }
+
+    async fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let persisted = {
+            let series = self.series.lock().unwrap();
+            Persisted {
+                series: series
+                    .iter()
+                    .map(|(key, series)| PersistedSeries {
+                        metric: key.metric.to_string(),
+                        sensor: key.sensor,
+                        diameter: key.diameter.as_deref().map(str::to_string),
+                        samples: series.samples.iter().copied().collect(),
+                    })
+                    .collect(),
+            }
+        };
+        let buf = serde_json::to_vec(&persisted).context("failed to serialize history")?;
+        storage::write_atomic(path, &buf)
+            .await
+            .context("failed to write history")?;
+        tracing::debug!(path = %path.display(), "persisted history");
+        Ok(())
+    }
+}
+
+impl HistorySource for History {
+    fn history(&self, query: &HistoryQuery) -> Option<HistoryResponse> {
+        self.query(query, unix_now())
+    }
+}
+
+// === impl Series ===
+
+impl Series {
+    /// Records the mean of the readings in the current bucket as a sample.
+    fn flush(&mut self, capacity: usize) {
+        let Bucket { start, sum, count } = std::mem::take(&mut self.bucket);
+        if count == 0 {
+            return;
+        }
+        if self.samples.len() >= capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            timestamp: start,
+            value: sum / count as f64,
+        });
+    }
+}
+
+/// Returns the name of the recorded metric named `name`, if it's recorded.
+fn recorded_metric(name: &str) -> Option<&'static str> {
+    SensorMetrics::READINGS
+        .iter()
+        .chain(SensorMetrics::PM_READINGS)
+        .find(|&&metric| metric == name)
+        .copied()
+}
+
+/// Averages consecutive samples into intervals of `step_secs`.
+fn downsample<'a>(samples: impl Iterator<Item = &'a Sample>, step_secs: u64) -> Vec<Sample> {
+    let mut downsampled = Vec::new();
+    let mut bucket = Bucket::default();
+    for sample in samples {
+        let start = sample.timestamp - (sample.timestamp % step_secs);
+        if bucket.count > 0 && bucket.start != start {
+            downsampled.push(Sample {
+                timestamp: bucket.start,
+                value: bucket.sum / bucket.count as f64,
+            });
+            bucket = Bucket::default();
+        }
+        bucket.start = start;
+        bucket.sum += sample.value;
+        bucket.count += 1;
+    }
+    if bucket.count > 0 {
+        downsampled.push(Sample {
+            timestamp: bucket.start,
+            value: bucket.sum / bucket.count as f64,
+        });
+    }
+    downsampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(values: &[(u64, f64)]) -> Vec<Sample> {
+        values
+            .iter()
+            .map(|&(timestamp, value)| Sample { timestamp, value })
+            .collect()
+    }
+
+    fn history() -> History {
+        History {
+            series: Mutex::new(HashMap::new()),
+            resolution_secs: 60,
+            retention_secs: 60 * 60,
+            capacity: 60,
+            path: None,
+        }
+    }
+
+    #[test]
+    fn records_pm_readings() {
+        use eclss::metrics::DiameterLabel;
+        use eclss_api::{schema, SensorName};
+
+        let metrics = SensorMetrics::new();
+        let sensor = SensorId::new(SensorName::Pmsa003i);
+        for (diameter, value) in [("2.5", 12.0), ("10.0", 20.0)] {
+            metrics
+                .pm_conc
+                .register(DiameterLabel { diameter, sensor })
+                .unwrap()
+                .set_value(value);
+        }
+        let history = history();
+        history.record(0, &metrics);
+        history.record(60, &metrics);
+
+        let query = |diameter: Option<&str>| HistoryQuery {
+            metric: schema::PM_CONCENTRATION.name.to_string(),
+            sensor: None,
+            diameter: diameter.map(str::to_string),
+            since: None,
+            step: None,
+        };
+        let response = history.query(&query(Some("2.5")), 120).unwrap();
+        assert_eq!(response.series.len(), 1);
+        assert_eq!(response.series[0].diameter.as_deref(), Some("2.5"));
+        assert_eq!(response.series[0].samples, samples(&[(0, 12.0)]));
+
+        let response = history.query(&query(None), 120).unwrap();
+        assert_eq!(response.series.len(), 2);
+
+        assert!(history
+            .query(
+                &HistoryQuery {
+                    metric: "pm_conc".to_string(),
+                    ..query(None)
+                },
+                120
+            )
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn shutdown_saves_current_interval() {
+        use eclss::metrics::DiameterLabel;
+        use eclss_api::{schema, SensorName};
+
+        let path = std::env::temp_dir().join(format!(
+            "eclssd-history-shutdown-{}.json",
+            std::process::id()
+        ));
+        let _ = tokio::fs::remove_file(&path).await;
+        let metrics = SensorMetrics::new();
+        metrics
+            .pm_conc
+            .register(DiameterLabel {
+                diameter: "2.5",
+                sensor: SensorId::new(SensorName::Pmsa003i),
+            })
+            .unwrap()
+            .set_value(12.0);
+        let saved = History {
+            path: Some(path.clone()),
+            ..history()
+        };
+        let now = unix_now();
+        let start = now - now % 60;
+        saved.record(now, &metrics);
+        saved.shutdown().await;
+
+        let restored = History {
+            path: Some(path.clone()),
+            ..history()
+        };
+        restored.load().await.unwrap();
+        let query = HistoryQuery {
+            metric: schema::PM_CONCENTRATION.name.to_string(),
+            sensor: None,
+            diameter: None,
+            since: None,
+            step: None,
+        };
+        let response = restored.query(&query, now).unwrap();
+        assert_eq!(response.series[0].samples, samples(&[(start, 12.0)]));
+        let _ = tokio::fs::remove_file(&path).await;
+    }
+
+    #[test]
+    fn downsample_averages_steps() {
+        let input = samples(&[(0, 1.0), (60, 3.0), (120, 5.0), (180, 7.0), (240, 9.0)]);
+        assert_eq!(
+            downsample(input.iter(), 120),
+            samples(&[(0, 2.0), (120, 6.0), (240, 9.0)])
+        );
+    }
+
+    #[test]
+    fn series_flush_is_bounded() {
+        let mut series = Series::default();
+        for i in 0..5 {
+            series.bucket = Bucket {
+                start: i * 60,
+                sum: i as f64 * 2.0,
+                count: 2,
+            };
+            series.flush(3);
+        }
+        assert_eq!(
+            series.samples.into_iter().collect::<Vec<_>>(),
+            samples(&[(120, 2.0), (180, 3.0), (240, 4.0)])
+        );
+    }
+}
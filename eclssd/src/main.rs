@@ -1,22 +1,67 @@
 use anyhow::Context;
 use clap::Parser;
-use eclss::sensor::{self, SensorName};
+#[cfg(feature = "sim")]
+use eclss::mock::{MockError, MockI2c};
+use eclss::sensor::{self, SensorId, SensorName};
 use eclss::Eclss;
 use eclss_app::TraceArgs;
 use embedded_hal::i2c::{self, I2c as BlockingI2c};
 use embedded_hal_async::{delay::DelayNs, i2c::I2c};
-use linux_embedded_hal::I2cdev;
+use linux_embedded_hal::{I2CError, I2cdev};
+use std::fmt;
 use std::future::Future;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
+mod alerts;
+mod config;
+mod fusion;
+mod history;
 #[cfg(feature = "mdns")]
 mod mdns;
+#[cfg(feature = "mqtt")]
+mod mqtt;
+mod notify;
+mod probe;
+mod sensors;
+#[cfg(feature = "sim")]
+mod sim;
 mod storage;
+mod supervisor;
 
 #[derive(Debug, Parser)]
 struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Path to a TOML configuration file.
+    ///
+    /// The file may set the I²C device, listen address, location, mDNS,
+    /// enabled sensors, state directory, global sensor and retry settings,
+    /// tracing settings, and per-sensor settings, such as:
+    ///
+    /// ```toml
+    /// listen_addr = "0.0.0.0:4200"
+    /// location = "office"
+    /// sensors = ["SCD41", "SEN55"]
+    ///
+    /// [eclss.retries]
+    /// initial_backoff = "1s"
+    ///
+    /// [trace]
+    /// format = "journald"
+    ///
+    /// [sensor.SCD41]
+    /// poll_interval = "10s"
+    /// offset = { temp = -2.0 }
+    /// ```
+    ///
+    /// Flags passed on the command line or set by environment variables
+    /// override values in the configuration file.
+    #[clap(short, long, env = "ECLSS_CONFIG")]
+    config: Option<PathBuf>,
+
     /// Path to the Linux i2cdev I²C device to use to communicate with sensors.
     #[clap(short, long, env = "ECLSS_I2C_DEV", default_value = "/dev/i2c-1")]
     i2cdev: PathBuf,
@@ -44,36 +89,83 @@ struct Args {
 
     /// List of sensors to enable.
     ///
-    /// If no sensors are provided here, the ECLSS daemon will attempt to
-    /// connect to all sensors that are enabled at compile time.
-    #[clap(long = "sensor", short, default_values_t = DEFAULT_SENSORS)]
-    sensors: Vec<SensorName>,
+    /// If no sensors are provided here, the ECLSS daemon scans the I²C bus
+    /// and enables each sensor that it detects, if support for that sensor
+    /// was enabled at compile time. Sensors on other buses or behind I²C
+    /// multiplexers are not detected, and must be listed here.
+    ///
+    /// To enable more than one sensor of the same model, give each one an
+    /// instance name, as `MODEL:INSTANCE` (e.g. `--sensor SHT41:floor
+    /// --sensor SHT41:ceiling`), and configure their addresses or buses with
+    /// the sensor override flags.
+    #[clap(long = "sensor", short, value_parser = sensors::parse_sensor)]
+    sensors: Vec<SensorId>,
 
     /// Sensor configuration
     #[clap(flatten)]
     sensor_config: eclss::Config,
 
+    /// Per-sensor configuration overrides.
+    #[clap(flatten)]
+    sensor_overrides: sensors::SensorArgs,
+
     /// Storage configuration.
     #[clap(flatten)]
     storage: storage::StorageArgs,
 
+    #[clap(flatten)]
+    fusion: fusion::FusionArgs,
+
+    #[clap(flatten)]
+    history: history::HistoryArgs,
+
+    #[clap(flatten)]
+    alerts: alerts::AlertArgs,
+
+    #[clap(flatten)]
+    notify: notify::NotifyArgs,
+
+    #[cfg(feature = "mqtt")]
+    #[clap(flatten)]
+    mqtt: mqtt::MqttArgs,
+
+    #[clap(flatten)]
+    supervisor: supervisor::SupervisorArgs,
+
+    #[cfg(feature = "sim")]
+    #[clap(flatten)]
+    sim: sim::SimArgs,
+
     #[clap(flatten)]
     trace: TraceArgs,
 }
 
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+    /// Scan the I²C bus, print the sensors that were detected, and exit.
+    Probe,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
+    let mut args = config::parse_args()?;
     args.trace.trace_init();
     use eclss::metrics::*;
 
     tracing::info!(
         location = ?args.location,
+        config_file = ?args.config,
         version = %env!("CARGO_PKG_VERSION"),
         listen_addr = ?args.listen_addr,
         mdns = args.mdns,
         storage = ?args.storage,
+        fusion = ?args.fusion,
+        history = ?args.history,
+        alerts = ?args.alerts,
+        notify = ?args.notify,
         config = ?args.sensor_config,
+        sensor_overrides = ?args.sensor_overrides,
+        supervisor = ?args.supervisor,
         "starting environmental controls and life support systems..."
     );
     tracing::debug!(
@@ -89,21 +181,92 @@ async fn main() -> anyhow::Result<()> {
         SENSORS
     );
 
-    let dev = I2cdev::new(&args.i2cdev)
-        .with_context(|| format!("failed to open I2C device {}", args.i2cdev.display()))?;
-    tracing::info!(path = %args.i2cdev.display(), "opened I²C device");
+    let sim_bus = sim_bus(&mut args);
+    let sim = sim_bus.is_some();
+    let bus = match sim_bus {
+        Some(bus) => bus,
+        None => {
+            let dev = I2cdev::new(&args.i2cdev)
+                .with_context(|| format!("failed to open I2C device {}", args.i2cdev.display()))?;
+            tracing::info!(path = %args.i2cdev.display(), "opened I²C device");
+            Bus::Linux(AsyncI2c(dev))
+        }
+    };
+
+    let eclss: &'static eclss::Eclss<_, 16> = Box::leak::<'static>(Box::new(
+        eclss::Eclss::<_, 16>::new(bus).with_max_reading_age(args.sensor_config.max_reading_age),
+    ));
+
+    if let Some(Command::Probe) = args.command {
+        probe::print(&probe::scan(eclss.bus()).await);
+        return Ok(());
+    }
+
+    // if no sensors were enabled explicitly, detect them, and keep looking for
+    // new sensors while running.
+    let discover = args.sensors.is_empty();
+    if discover {
+        tracing::info!(path = %args.i2cdev.display(), "probing for sensors...");
+        let detected = probe::sensors(&probe::scan(eclss.bus()).await);
+        if detected.is_empty() {
+            tracing::warn!(path = %args.i2cdev.display(), "no sensors detected");
+        }
+        args.sensor_overrides.use_detected(&detected);
+        args.sensors = detected.into_iter().map(|(sensor, _)| sensor).collect();
+    }
+
+    let sensor_configs =
+        args.sensor_overrides
+            .configs(&args.sensors, &args.i2cdev, eclss.bus(), sim)?;
+
+    if let Some(config) = args.fusion.config() {
+        tokio::spawn(async move {
+            if let Err(error) = eclss.run_fusion(config, GoodDelay::default()).await {
+                tracing::error!(%error, "sensor fusion failed");
+            }
+        });
+    }
+
+    let state_dir = args.storage.ensure_state_dir().await?;
+
+    let history: &'static history::History = Box::leak(Box::new(
+        args.history
+            .build(&state_dir)
+            .await
+            .context("failed to initialize history")?,
+    ));
+    tokio::spawn(history.run(eclss.metrics()));
 
-    let eclss: &'static eclss::Eclss<_, 16> =
-        Box::leak::<'static>(Box::new(eclss::Eclss::<_, 16>::new(AsyncI2c(dev))));
+    let alerts: &'static alerts::Alerts = Box::leak(Box::new(
+        args.alerts
+            .load()
+            .await
+            .context("failed to load alert rules")?,
+    ));
+    let (notifier, dispatcher) = args
+        .notify
+        .load(args.location.clone())
+        .await
+        .context("failed to load notification config")?;
+    if let Some(dispatcher) = dispatcher {
+        tokio::spawn(dispatcher.run());
+    }
+    tokio::spawn(notifier.clone().watch_sensors(eclss.sensors()));
+    tokio::spawn(alerts.run(eclss.metrics(), notifier));
+
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = args.mqtt.build(args.location.clone()) {
+        tokio::spawn(mqtt.run(eclss.metrics(), eclss.sensors()));
+    }
 
     let listener = tokio::net::TcpListener::bind(args.listen_addr).await?;
     tracing::info!(listen_addr = ?args.listen_addr, "listening...");
-    let server = tokio::spawn({
-        let location = args.location.clone();
+    tokio::spawn({
+        let app = eclss_axum::app(eclss, args.location.clone())
+            .merge(eclss_axum::history::routes(history))
+            .merge(eclss_axum::alerts::routes(alerts));
         async move {
-            eclss_axum::axum::serve(listener, eclss_axum::app(eclss, location))
-                .await
-                .unwrap();
+            eclss_axum::axum::serve(listener, app).await.unwrap();
         }
     });
 
@@ -113,55 +276,101 @@ async fn main() -> anyhow::Result<()> {
         #[cfg(not(feature = "mdns"))]
         anyhow::bail!("mDNS advertisement requires the `mdns` feature to be enabled");
     }
-    let state_dir = args.storage.ensure_state_dir().await?;
 
-    let mut sensor_tasks = tokio::task::JoinSet::new();
+    let shutdown = shutdown_signal()?;
+    let mut supervisor = args
+        .supervisor
+        .build(eclss, &state_dir, &args.sensor_config);
     tracing::info!("Enabling the following sensors: {:?}", args.sensors);
     for sensor in args.sensors {
-        sensor_tasks.spawn(run_sensor(eclss, &state_dir, &args.sensor_config, sensor));
+        supervisor.start(sensor, sensor_configs[&sensor]);
     }
-
-    while let Some(join) = sensor_tasks.join_next().await {
-        join.context("a sensor task panicked")??;
+    if discover {
+        supervisor.discover_new_sensors(args.sensor_overrides.clone());
     }
+    let result = supervisor.run(shutdown).await;
+    history.shutdown().await;
+    result
+}
 
-    server.await.context("HTTP server panicked")?;
+/// Returns a future that completes when the daemon receives a SIGTERM or
+/// SIGINT.
+///
+/// The signal handlers are registered immediately, so that signals received
+/// before the future is first polled are not lost.
+fn shutdown_signal() -> anyhow::Result<impl Future<Output = ()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut sigterm =
+        signal(SignalKind::terminate()).context("failed to register SIGTERM handler")?;
+    let mut sigint =
+        signal(SignalKind::interrupt()).context("failed to register SIGINT handler")?;
+    Ok(async move {
+        let signal = tokio::select! {
+            _ = sigterm.recv() => "SIGTERM",
+            _ = sigint.recv() => "SIGINT",
+        };
+        tracing::info!("received {signal}, shutting down...");
+    })
+}
 
-    Ok(())
+/// If simulation mode is enabled, starts simulating sensors, and returns the
+/// simulated bus.
+#[cfg(feature = "sim")]
+fn sim_bus(args: &mut Args) -> Option<Bus> {
+    if !args.sim.enabled {
+        return None;
+    }
+    if args.sensors.is_empty() {
+        args.sensors = DEFAULT_SENSORS.to_vec();
+    }
+    let sim = args.sim.build(&args.sensors);
+    args.sensors = sim.sensors().to_vec();
+    let bus = sim.bus();
+    tokio::spawn(sim.run());
+    Some(Bus::Sim(bus))
 }
 
-const DEFAULT_SENSORS: &[SensorName] = &[
+#[cfg(not(feature = "sim"))]
+fn sim_bus(_: &mut Args) -> Option<Bus> {
+    None
+}
+
+/// The sensors which are simulated if no sensors are enabled in simulation
+/// mode.
+#[cfg(feature = "sim")]
+const DEFAULT_SENSORS: &[SensorId] = &[
     #[cfg(feature = "pmsa003i")]
-    SensorName::Pmsa003i,
+    SensorId::new(SensorName::Pmsa003i),
     #[cfg(feature = "scd41")]
-    SensorName::Scd41,
+    SensorId::new(SensorName::Scd41),
     #[cfg(feature = "scd30")]
-    SensorName::Scd30,
+    SensorId::new(SensorName::Scd30),
     #[cfg(feature = "sen55")]
-    SensorName::Sen55,
+    SensorId::new(SensorName::Sen55),
     #[cfg(feature = "sgp30")]
-    SensorName::Sgp30,
+    SensorId::new(SensorName::Sgp30),
     #[cfg(feature = "sht41")]
-    SensorName::Sht41,
+    SensorId::new(SensorName::Sht41),
     #[cfg(feature = "ens160")]
-    SensorName::Ens160,
+    SensorId::new(SensorName::Ens160),
     #[cfg(feature = "bme680")]
-    SensorName::Bme680,
+    SensorId::new(SensorName::Bme680),
 ];
 
 fn run_sensor(
-    eclss: &'static Eclss<AsyncI2c<I2cdev>, 16>,
+    eclss: &'static Eclss<Bus, 16>,
     state_dir: &storage::StateDir,
     sensor_config: &eclss::Config,
-    name: SensorName,
+    overrides: sensor::SensorConfig<Bus>,
+    name: SensorId,
 ) -> impl Future<Output = anyhow::Result<()>> + Send + 'static {
     let config = sensor_config.clone();
     let state_dir = state_dir.clone();
     async move {
-        match name {
+        match name.name {
             #[cfg(feature = "pmsa003i")]
             SensorName::Pmsa003i => {
-                let sensor = sensor::Pmsa003i::new(eclss, &config);
+                let sensor = sensor::Pmsa003i::new_with_config(eclss, &config, overrides)?;
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -169,7 +378,17 @@ fn run_sensor(
             }
             #[cfg(feature = "scd41")]
             SensorName::Scd41 => {
-                let sensor = sensor::Scd41::new(eclss, &config, GoodDelay::default());
+                let state = state_dir
+                    .sensor_state(name)
+                    .await
+                    .with_context(|| format!("failed to open state file for {name}"))?;
+                let sensor = sensor::Scd41::new_with_config(
+                    eclss,
+                    &config,
+                    overrides,
+                    GoodDelay::default(),
+                )?
+                .with_storage(state);
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -177,7 +396,17 @@ fn run_sensor(
             }
             #[cfg(feature = "scd40")]
             SensorName::Scd40 => {
-                let sensor = sensor::Scd40::new(eclss, &config, GoodDelay::default());
+                let state = state_dir
+                    .sensor_state(name)
+                    .await
+                    .with_context(|| format!("failed to open state file for {name}"))?;
+                let sensor = sensor::Scd40::new_with_config(
+                    eclss,
+                    &config,
+                    overrides,
+                    GoodDelay::default(),
+                )?
+                .with_storage(state);
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -185,7 +414,17 @@ fn run_sensor(
             }
             #[cfg(feature = "scd30")]
             SensorName::Scd30 => {
-                let sensor = sensor::Scd30::new(eclss, &config, GoodDelay::default());
+                let state = state_dir
+                    .sensor_state(name)
+                    .await
+                    .with_context(|| format!("failed to open state file for {name}"))?;
+                let sensor = sensor::Scd30::new_with_config(
+                    eclss,
+                    &config,
+                    overrides,
+                    GoodDelay::default(),
+                )?
+                .with_storage(state);
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -193,7 +432,17 @@ fn run_sensor(
             }
             #[cfg(feature = "sen55")]
             SensorName::Sen55 => {
-                let sensor = sensor::Sen55::new(eclss, &config, GoodDelay::default());
+                let state = state_dir
+                    .sensor_state(name)
+                    .await
+                    .with_context(|| format!("failed to open state file for {name}"))?;
+                let sensor = sensor::Sen55::new_with_config(
+                    eclss,
+                    &config,
+                    overrides,
+                    GoodDelay::default(),
+                )?
+                .with_storage(state);
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -205,8 +454,13 @@ fn run_sensor(
                     .sensor_state(name)
                     .await
                     .with_context(|| format!("failed to open state file for {name}"))?;
-                let sensor =
-                    sensor::Sgp30::new(eclss, &config, GoodDelay::default()).with_storage(state);
+                let sensor = sensor::Sgp30::new_with_config(
+                    eclss,
+                    &config,
+                    overrides,
+                    GoodDelay::default(),
+                )?
+                .with_storage(state);
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -214,7 +468,12 @@ fn run_sensor(
             }
             #[cfg(feature = "sht41")]
             SensorName::Sht41 => {
-                let sensor = sensor::Sht41::new(eclss, &config, GoodDelay::default());
+                let sensor = sensor::Sht41::new_with_config(
+                    eclss,
+                    &config,
+                    overrides,
+                    GoodDelay::default(),
+                )?;
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -222,7 +481,17 @@ fn run_sensor(
             }
             #[cfg(feature = "ens160")]
             SensorName::Ens160 => {
-                let sensor = sensor::Ens160::new(eclss, &config, GoodDelay::default());
+                let state = state_dir
+                    .sensor_state(name)
+                    .await
+                    .with_context(|| format!("failed to open state file for {name}"))?;
+                let sensor = sensor::Ens160::new_with_config(
+                    eclss,
+                    &config,
+                    overrides,
+                    GoodDelay::default(),
+                )?
+                .with_storage(state);
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -230,7 +499,17 @@ fn run_sensor(
             }
             #[cfg(feature = "bme680")]
             SensorName::Bme680 => {
-                let sensor = sensor::Bme680::new(eclss, &config, GoodDelay::default());
+                let state = state_dir
+                    .sensor_state(name)
+                    .await
+                    .with_context(|| format!("failed to open state file for {name}"))?;
+                let sensor = sensor::Bme680::new_with_config(
+                    eclss,
+                    &config,
+                    overrides,
+                    GoodDelay::default(),
+                )?
+                .with_storage(state);
                 eclss
                     .run_sensor(sensor, config, GoodDelay::default())
                     .await
@@ -241,6 +520,73 @@ fn run_sensor(
     }
 }
 
+/// The I²C bus that sensors are attached to: either a real Linux i2cdev, or a
+/// mock bus with emulated sensors when running in simulation mode.
+enum Bus {
+    Linux(AsyncI2c<I2cdev>),
+    #[cfg(feature = "sim")]
+    Sim(MockI2c),
+}
+
+#[derive(Debug)]
+enum BusError {
+    Linux(I2CError),
+    #[cfg(feature = "sim")]
+    Sim(MockError),
+}
+
+impl I2c for Bus {
+    async fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [i2c::Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        match self {
+            Bus::Linux(bus) => bus
+                .transaction(address, operations)
+                .await
+                .map_err(BusError::Linux),
+            #[cfg(feature = "sim")]
+            Bus::Sim(bus) => bus
+                .transaction(address, operations)
+                .await
+                .map_err(BusError::Sim),
+        }
+    }
+}
+
+impl i2c::ErrorType for Bus {
+    type Error = BusError;
+}
+
+impl i2c::Error for BusError {
+    fn kind(&self) -> i2c::ErrorKind {
+        match self {
+            BusError::Linux(e) => i2c::Error::kind(e),
+            #[cfg(feature = "sim")]
+            BusError::Sim(e) => i2c::Error::kind(e),
+        }
+    }
+}
+
+impl fmt::Display for BusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BusError::Linux(e) => fmt::Display::fmt(e, f),
+            #[cfg(feature = "sim")]
+            BusError::Sim(e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+/// Returns the current time as a Unix timestamp in seconds.
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
 struct AsyncI2c<I>(I);
 impl<I, A> I2c<A> for AsyncI2c<I>
 where
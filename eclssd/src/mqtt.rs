@@ -0,0 +1,642 @@
+//! Publishes sensor readings to an MQTT broker, with Home Assistant MQTT
+//! discovery.
+use eclss::{sensor::Registry, SensorMetrics};
+use eclss_api::{schema, SensorId, SensorStatus};
+use rumqttc::{AsyncClient, ConnectionError, EventLoop, LastWill, MqttOptions, Packet, QoS};
+use std::collections::{HashMap, HashSet};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+#[derive(Debug, clap::Parser)]
+#[command(next_help_heading = "MQTT")]
+pub(super) struct MqttArgs {
+    /// Hostname of an MQTT broker to publish sensor readings to.
+    ///
+    /// If this is not set, readings are not published over MQTT.
+    #[clap(long = "mqtt-host", env = "ECLSS_MQTT_HOST")]
+    host: Option<String>,
+
+    /// Port of the MQTT broker.
+    #[clap(long = "mqtt-port", env = "ECLSS_MQTT_PORT", default_value_t = 1883)]
+    port: u16,
+
+    /// Username to authenticate with the MQTT broker.
+    #[clap(
+        long = "mqtt-username",
+        env = "ECLSS_MQTT_USERNAME",
+        requires = "password"
+    )]
+    username: Option<String>,
+
+    /// Password to authenticate with the MQTT broker.
+    #[clap(
+        long = "mqtt-password",
+        env = "ECLSS_MQTT_PASSWORD",
+        hide_env_values = true
+    )]
+    password: Option<String>,
+
+    /// Prefix for topics that readings are published to.
+    ///
+    /// Readings are published to `<PREFIX>/<NODE ID>/<SENSOR>/<METRIC>`.
+    #[clap(
+        long = "mqtt-topic-prefix",
+        env = "ECLSS_MQTT_TOPIC_PREFIX",
+        default_value = "eclss"
+    )]
+    topic_prefix: String,
+
+    /// Identifies this node in MQTT topics and Home Assistant entity IDs.
+    ///
+    /// If this is not set, it is derived from `--location`, or "eclssd" if no
+    /// location is set.
+    #[clap(long = "mqtt-node-id", env = "ECLSS_MQTT_NODE_ID")]
+    node_id: Option<String>,
+
+    /// Publish Home Assistant MQTT discovery messages.
+    #[clap(
+        long = "mqtt-discovery",
+        env = "ECLSS_MQTT_DISCOVERY",
+        action = clap::ArgAction::Set,
+        value_parser = clap::value_parser!(bool),
+        default_value_t = true,
+    )]
+    discovery: bool,
+
+    /// Prefix for Home Assistant MQTT discovery topics.
+    #[clap(
+        long = "mqtt-discovery-prefix",
+        env = "ECLSS_MQTT_DISCOVERY_PREFIX",
+        default_value = "homeassistant"
+    )]
+    discovery_prefix: String,
+
+    /// How often to publish sensor readings.
+    #[clap(
+        id = "mqtt_interval",
+        long = "mqtt-interval",
+        env = "ECLSS_MQTT_INTERVAL",
+        default_value = "10s",
+        value_parser = humantime::parse_duration,
+    )]
+    interval: Duration,
+}
+
+pub(super) struct Mqtt {
+    client: AsyncClient,
+    eventloop: EventLoop,
+    topics: Topics,
+    discovery: bool,
+    interval: Duration,
+    location: Option<Arc<str>>,
+}
+
+/// Builds MQTT topic names.
+#[derive(Clone, Debug)]
+struct Topics {
+    prefix: String,
+    node: String,
+    discovery_prefix: String,
+}
+
+/// A gauge published over MQTT.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+struct Gauge {
+    sensor: SensorId,
+    metric: &'static str,
+    /// The particle diameter, for particulate matter gauges.
+    diameter: Option<&'static str>,
+}
+
+/// Publishing state, which is reset whenever the client reconnects, so that
+/// retained messages are re-sent.
+#[derive(Debug, Default)]
+struct Published {
+    discovered: HashSet<Gauge>,
+    available: HashMap<SensorId, bool>,
+}
+
+const ONLINE: &str = "online";
+const OFFLINE: &str = "offline";
+
+impl MqttArgs {
+    pub(super) fn build(&self, location: Option<Arc<str>>) -> Option<Mqtt> {
+        let host = self.host.as_ref()?;
+        let node = match (&self.node_id, &location) {
+            (Some(node), _) => node.clone(),
+            (None, Some(location)) => slugify(location),
+            (None, None) => "eclssd".to_string(),
+        };
+        let topics = Topics {
+            prefix: self.topic_prefix.trim_end_matches('/').to_string(),
+            node,
+            discovery_prefix: self.discovery_prefix.trim_end_matches('/').to_string(),
+        };
+
+        let mut options = MqttOptions::new(format!("eclssd-{}", topics.node), host, self.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        options.set_last_will(LastWill::new(
+            topics.node_status(),
+            OFFLINE,
+            QoS::AtLeastOnce,
+            true,
+        ));
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            options.set_credentials(username, password);
+        }
+        let (client, eventloop) = AsyncClient::new(options, 64);
+        tracing::info!(host, port = self.port, node = %topics.node, "publishing readings over MQTT");
+        Some(Mqtt {
+            client,
+            eventloop,
+            topics,
+            discovery: self.discovery,
+            interval: self.interval,
+            location,
+        })
+    }
+}
+
+impl Mqtt {
+    pub(super) async fn run<const SENSORS: usize>(
+        self,
+        metrics: &'static SensorMetrics,
+        sensors: &'static Registry<SENSORS>,
+    ) {
+        let Self {
+            client,
+            eventloop,
+            topics,
+            discovery,
+            interval,
+            location,
+        } = self;
+        let resync = Arc::new(AtomicBool::new(true));
+        tokio::spawn(drive(
+            eventloop,
+            client.clone(),
+            topics.clone(),
+            discovery,
+            resync.clone(),
+        ));
+
+        let mut published = Published::default();
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            if resync.swap(false, Ordering::AcqRel) {
+                published = Published::default();
+            }
+            if let Err(error) = publish(
+                &client,
+                &topics,
+                discovery,
+                location.as_deref(),
+                &mut published,
+                metrics,
+                sensors,
+            )
+            .await
+            {
+                tracing::warn!(%error, "failed to publish readings over MQTT");
+            }
+        }
+    }
+}
+
+/// Drives the MQTT connection, and marks everything for republishing when
+/// the client (or Home Assistant) reconnects.
+async fn drive(
+    mut eventloop: EventLoop,
+    client: AsyncClient,
+    topics: Topics,
+    discovery: bool,
+    resync: Arc<AtomicBool>,
+) {
+    let ha_status = topics.discovery_status();
+    loop {
+        match eventloop.poll().await {
+            Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => {
+                tracing::info!("connected to MQTT broker");
+                resync.store(true, Ordering::Release);
+                // the event loop must keep being polled for these requests
+                // to complete, so don't wait for them here.
+                let client = client.clone();
+                let topics = topics.clone();
+                let ha_status = ha_status.clone();
+                tokio::spawn(async move {
+                    let res = client
+                        .publish(topics.node_status(), QoS::AtLeastOnce, true, ONLINE)
+                        .await;
+                    if let Err(error) = res {
+                        tracing::warn!(%error, "failed to publish MQTT node status");
+                    }
+                    if discovery {
+                        if let Err(error) = client.subscribe(ha_status, QoS::AtLeastOnce).await {
+                            tracing::warn!(%error, "failed to subscribe to Home Assistant status");
+                        }
+                    }
+                });
+            }
+            Ok(rumqttc::Event::Incoming(Packet::Publish(publish))) => {
+                if publish.topic == ha_status && &publish.payload[..] == ONLINE.as_bytes() {
+                    tracing::info!("Home Assistant came online, republishing discovery");
+                    resync.store(true, Ordering::Release);
+                }
+            }
+            Ok(_) => {}
+            Err(error) => {
+                tracing::warn!(%error, "MQTT connection error");
+                if !matches!(error, ConnectionError::RequestsDone) {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    }
+}
+
+async fn publish<const SENSORS: usize>(
+    client: &AsyncClient,
+    topics: &Topics,
+    discovery: bool,
+    location: Option<&str>,
+    published: &mut Published,
+    metrics: &SensorMetrics,
+    sensors: &Registry<SENSORS>,
+) -> Result<(), rumqttc::ClientError> {
+    for (&sensor, state) in sensors.iter() {
+        let available = state.status() == SensorStatus::Up;
+        if published.available.insert(sensor, available) != Some(available) {
+            let payload = if available { ONLINE } else { OFFLINE };
+            client
+                .publish(topics.availability(sensor), QoS::AtLeastOnce, true, payload)
+                .await?;
+        }
+    }
+
+    let mut readings = Vec::new();
+    metrics.for_each_reading(|metric, sensor, value| {
+        readings.push((
+            Gauge {
+                sensor,
+                metric,
+                diameter: None,
+            },
+            value,
+        ))
+    });
+    metrics.for_each_pm_reading(|metric, label, value| {
+        readings.push((
+            Gauge {
+                sensor: label.sensor,
+                metric,
+                diameter: Some(label.diameter),
+            },
+            value,
+        ))
+    });
+
+    for (gauge, value) in readings {
+        if discovery && published.discovered.insert(gauge) {
+            let config = gauge.discovery_config(topics, location);
+            client
+                .publish(
+                    topics.discovery(&gauge),
+                    QoS::AtLeastOnce,
+                    true,
+                    config.to_string(),
+                )
+                .await?;
+        }
+        client
+            .publish(
+                topics.state(&gauge),
+                QoS::AtMostOnce,
+                false,
+                value.to_string(),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+// === impl Topics ===
+
+impl Topics {
+    fn node_status(&self) -> String {
+        format!("{}/{}/status", self.prefix, self.node)
+    }
+
+    fn availability(&self, sensor: SensorId) -> String {
+        format!(
+            "{}/{}/{}/availability",
+            self.prefix,
+            self.node,
+            sensor_id(sensor)
+        )
+    }
+
+    fn state(&self, gauge: &Gauge) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.prefix,
+            self.node,
+            sensor_id(gauge.sensor),
+            gauge.object_id()
+        )
+    }
+
+    fn discovery(&self, gauge: &Gauge) -> String {
+        format!(
+            "{}/sensor/{}/{}_{}/config",
+            self.discovery_prefix,
+            self.node,
+            sensor_id(gauge.sensor),
+            gauge.object_id()
+        )
+    }
+
+    fn discovery_status(&self) -> String {
+        format!("{}/status", self.discovery_prefix)
+    }
+}
+
+// === impl Gauge ===
+
+// Particulate matter metric names, so that they can be matched on.
+const PM_CONC: &str = schema::PM_CONCENTRATION.name;
+const PM_CONC_STANDARD: &str = schema::PM_CONCENTRATION_STANDARD.name;
+const PM_COUNT: &str = schema::PM_COUNT.name;
+const PM_NUMBER_CONC: &str = schema::PM_NUMBER_CONCENTRATION.name;
+
+impl Gauge {
+    /// Returns the gauge's name within its sensor, such as `co2_ppm` or
+    /// `pm_concentration_ug_m3_2_5`.
+    fn object_id(&self) -> String {
+        match self.diameter {
+            Some(diameter) => format!("{}_{}", self.metric, diameter.replace('.', "_")),
+            None => self.metric.to_string(),
+        }
+    }
+
+    fn name(&self) -> String {
+        match (self.metric, self.diameter) {
+            (PM_CONC, Some(d)) => format!("PM{d}"),
+            (PM_CONC_STANDARD, Some(d)) => format!("PM{d} (standard)"),
+            (PM_COUNT, Some(d)) => format!("Particles > {d} µm"),
+            (PM_NUMBER_CONC, Some(d)) => format!("Particles ≤ {d} µm"),
+            ("temp_c", _) => "Temperature".to_string(),
+            ("co2_ppm", _) => "CO₂".to_string(),
+            ("eco2_ppm", _) => "eCO₂".to_string(),
+            ("rel_humidity_percent", _) => "Relative humidity".to_string(),
+            ("abs_humidity_grams_m3", _) => "Absolute humidity".to_string(),
+            ("pressure_hpa", _) => "Pressure".to_string(),
+            ("gas_resistance", _) => "Gas resistance".to_string(),
+            ("tvoc_ppb", _) => "tVOC".to_string(),
+            ("tvoc_iaq_index", _) => "VOC index".to_string(),
+            ("nox_iaq_index", _) => "NOx index".to_string(),
+            ("aqi", _) => "AQI".to_string(),
+            ("co2_comfort_band", _) => "CO₂ comfort band".to_string(),
+            ("tvoc_iaq_category", _) => "tVOC IAQ category".to_string(),
+            (metric, _) => metric.to_string(),
+        }
+    }
+
+    fn unit(&self) -> Option<&'static str> {
+        match self.metric {
+            "temp_c" => Some("°C"),
+            "co2_ppm" | "eco2_ppm" => Some("ppm"),
+            "rel_humidity_percent" => Some("%"),
+            "abs_humidity_grams_m3" => Some("g/m³"),
+            "pressure_hpa" => Some("hPa"),
+            "gas_resistance" => Some("Ω"),
+            "tvoc_ppb" => Some("ppb"),
+            PM_CONC | PM_CONC_STANDARD | PM_COUNT | PM_NUMBER_CONC => {
+                schema::get(self.metric).and_then(schema::Metric::unit_symbol)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the Home Assistant sensor device class for this gauge.
+    fn device_class(&self) -> Option<&'static str> {
+        match (self.metric, self.diameter) {
+            ("temp_c", _) => Some("temperature"),
+            ("co2_ppm", _) => Some("carbon_dioxide"),
+            ("rel_humidity_percent", _) => Some("humidity"),
+            ("pressure_hpa", _) => Some("atmospheric_pressure"),
+            ("tvoc_ppb", _) => Some("volatile_organic_compounds_parts"),
+            ("aqi", _) => Some("aqi"),
+            (PM_CONC, Some("1.0")) => Some("pm1"),
+            (PM_CONC, Some("2.5")) => Some("pm25"),
+            (PM_CONC, Some("10.0")) => Some("pm10"),
+            _ => None,
+        }
+    }
+
+    fn discovery_config(&self, topics: &Topics, location: Option<&str>) -> serde_json::Value {
+        let sensor = sensor_id(self.sensor);
+        let node = &topics.node;
+        let mut config = serde_json::json!({
+            "name": self.name(),
+            "unique_id": format!("eclss_{node}_{sensor}_{}", self.object_id()),
+            "object_id": format!("eclss_{node}_{sensor}_{}", self.object_id()),
+            "state_topic": topics.state(self),
+            "state_class": "measurement",
+            "availability": [
+                { "topic": topics.node_status() },
+                { "topic": topics.availability(self.sensor) },
+            ],
+            "availability_mode": "all",
+            "device": {
+                "identifiers": [format!("eclss_{node}_{sensor}")],
+                "name": match location {
+                    Some(location) => format!("{} ({location})", self.sensor),
+                    None => self.sensor.to_string(),
+                },
+                "model": self.sensor.name.to_string(),
+                "sw_version": env!("CARGO_PKG_VERSION"),
+                "suggested_area": location,
+            },
+        });
+        if let Some(unit) = self.unit() {
+            config["unit_of_measurement"] = unit.into();
+        }
+        if let Some(class) = self.device_class() {
+            config["device_class"] = class.into();
+        }
+        config
+    }
+}
+
+fn sensor_id(sensor: SensorId) -> String {
+    let model = <&'static str>::from(sensor.name).to_ascii_lowercase();
+    match sensor.instance {
+        Some(instance) => format!("{model}_{}", slugify(instance.as_str())),
+        None => model,
+    }
+}
+
+/// Converts a location name into something suitable for use in MQTT topics
+/// and Home Assistant IDs.
+fn slugify(s: &str) -> String {
+    let slug = s
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    slug.trim_matches('_').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eclss_api::SensorName;
+
+    fn topics() -> Topics {
+        Topics {
+            prefix: "eclss".to_string(),
+            node: "office".to_string(),
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+
+    const PM2_5: Gauge = Gauge {
+        sensor: SensorId::new(SensorName::Pmsa003i),
+        metric: PM_CONC,
+        diameter: Some("2.5"),
+    };
+
+    const CO2: Gauge = Gauge {
+        sensor: SensorId::new(SensorName::Scd41),
+        metric: "co2_ppm",
+        diameter: None,
+    };
+
+    #[test]
+    fn topic_names() {
+        let topics = topics();
+        assert_eq!(topics.state(&CO2), "eclss/office/scd41/co2_ppm");
+        assert_eq!(
+            topics.state(&PM2_5),
+            "eclss/office/pmsa003i/pm_concentration_ug_m3_2_5"
+        );
+        assert_eq!(
+            topics.availability(SensorId::new(SensorName::Scd41)),
+            "eclss/office/scd41/availability"
+        );
+        assert_eq!(
+            topics.discovery(&PM2_5),
+            "homeassistant/sensor/office/pmsa003i_pm_concentration_ug_m3_2_5/config"
+        );
+    }
+
+    #[test]
+    fn instance_topic_names() {
+        let sensor = "SHT41:Near-Floor".parse::<SensorId>().unwrap();
+        let temp = Gauge {
+            sensor,
+            metric: "temp_c",
+            diameter: None,
+        };
+        let topics = topics();
+        assert_eq!(topics.state(&temp), "eclss/office/sht41_near_floor/temp_c");
+        assert_eq!(
+            topics.availability(sensor),
+            "eclss/office/sht41_near_floor/availability"
+        );
+        assert_eq!(
+            temp.discovery_config(&topics, None)["unique_id"],
+            "eclss_office_sht41_near_floor_temp_c"
+        );
+    }
+
+    #[test]
+    fn discovery_config() {
+        let config = CO2.discovery_config(&topics(), Some("Office"));
+        assert_eq!(config["unique_id"], "eclss_office_scd41_co2_ppm");
+        assert_eq!(config["state_topic"], "eclss/office/scd41/co2_ppm");
+        assert_eq!(config["unit_of_measurement"], "ppm");
+        assert_eq!(config["device_class"], "carbon_dioxide");
+        assert_eq!(config["device"]["name"], "SCD41 (Office)");
+        assert_eq!(
+            config["availability"][1]["topic"],
+            "eclss/office/scd41/availability"
+        );
+
+        let config = PM2_5.discovery_config(&topics(), None);
+        assert_eq!(config["name"], "PM2.5");
+        assert_eq!(config["unit_of_measurement"], "µg/m³");
+        assert_eq!(config["device_class"], "pm25");
+    }
+
+    #[test]
+    fn slugify_location() {
+        assert_eq!(slugify("Living Room (north)"), "living_room__north");
+    }
+
+    /// Publishes to a real MQTT broker on localhost:1883, such as
+    /// `mosquitto`. Run with `cargo test --features mqtt -- --ignored`.
+    #[tokio::test]
+    #[ignore]
+    async fn publishes_to_local_broker() {
+        static METRICS: SensorMetrics = SensorMetrics::new();
+        static SENSORS: Registry<4> = Registry::new();
+        METRICS
+            .co2_ppm
+            .register(SensorId::new(SensorName::Scd41))
+            .unwrap()
+            .set_value(1234.0);
+
+        let (subscriber, mut sub_events) = AsyncClient::new(
+            MqttOptions::new("eclssd-test-subscriber", "localhost", 1883),
+            16,
+        );
+        subscriber
+            .subscribe("eclss-test/#", QoS::AtLeastOnce)
+            .await
+            .unwrap();
+        subscriber
+            .subscribe("homeassistant-test/#", QoS::AtLeastOnce)
+            .await
+            .unwrap();
+
+        let args = <MqttArgs as clap::Parser>::parse_from([
+            "eclssd",
+            "--mqtt-host=localhost",
+            "--mqtt-topic-prefix=eclss-test",
+            "--mqtt-discovery-prefix=homeassistant-test",
+            "--mqtt-node-id=test",
+            "--mqtt-interval=100ms",
+        ]);
+        let mqtt = args.build(None).unwrap();
+        tokio::spawn(mqtt.run(&METRICS, &SENSORS));
+
+        const STATE: &str = "eclss-test/test/scd41/co2_ppm";
+        const CONFIG: &str = "homeassistant-test/sensor/test/scd41_co2_ppm/config";
+        let mut seen = HashMap::new();
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        while !(seen.contains_key(STATE) && seen.contains_key(CONFIG)) {
+            let event = tokio::time::timeout_at(deadline, sub_events.poll())
+                .await
+                .expect("timed out waiting for messages")
+                .expect("subscriber connection failed");
+            if let rumqttc::Event::Incoming(Packet::Publish(publish)) = event {
+                seen.insert(publish.topic, publish.payload);
+            }
+        }
+
+        assert_eq!(&seen[STATE][..], b"1234");
+        let config: serde_json::Value = serde_json::from_slice(&seen[CONFIG]).unwrap();
+        assert_eq!(config["device_class"], "carbon_dioxide");
+    }
+}
@@ -0,0 +1,1117 @@
+//! Outbound notifications for alerts and sensor faults.
+use crate::{unix_now, GoodDelay};
+use anyhow::Context;
+use eclss::{retry::ExpBackoff, sensor::Registry};
+use eclss_api::{alerts::Comparison, SensorId, SensorStatus};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+#[derive(Debug, clap::Parser)]
+#[command(next_help_heading = "Notifications")]
+pub(super) struct NotifyArgs {
+    /// Path to a TOML file configuring notification sinks.
+    ///
+    /// Notifications are sent when an alert fires or resolves, and when a
+    /// sensor which was previously up starts failing, goes missing, or
+    /// recovers. For example:
+    ///
+    /// ```toml
+    /// cooldown = "10m"
+    /// max_per_hour = 30
+    ///
+    /// [[webhook]]
+    /// url = "https://example.com/hook"
+    /// body = '{"text": "{{title}}: {{message}}"}'
+    /// headers = { Authorization = "Bearer hunter2" }
+    ///
+    /// [[ntfy]]
+    /// url = "https://ntfy.sh/my-eclss"
+    /// priority = 4
+    ///
+    /// [[gotify]]
+    /// url = "https://gotify.example.com"
+    /// token = "AbCdEf"
+    /// ```
+    ///
+    /// Webhook body templates may reference any field of the notification
+    /// event as `{{field}}`: `kind`, `title`, `message`, `rule`, `metric`,
+    /// `diameter`, `sensor`, `status`, `value`, `threshold`, `location`, and
+    /// `timestamp`. If no body template is provided, the event is sent as
+    /// JSON.
+    #[clap(long = "notify-config", env = "ECLSS_NOTIFY_CONFIG")]
+    notify_config: Option<PathBuf>,
+}
+
+/// A handle for sending notifications.
+#[derive(Clone, Debug)]
+pub(super) struct Notifier {
+    tx: Option<mpsc::Sender<Event>>,
+}
+
+/// Delivers notifications to the configured sinks.
+pub(super) struct Dispatcher {
+    rx: mpsc::Receiver<Event>,
+    sinks: Arc<[Sink]>,
+    client: reqwest::Client,
+    limiter: Limiter,
+    location: Option<Arc<str>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(super) struct Event {
+    kind: EventKind,
+    /// Identifies the alert rule or sensor this event is about, for
+    /// deduplication.
+    #[serde(skip)]
+    key: String,
+    title: String,
+    message: String,
+    rule: Option<String>,
+    metric: Option<String>,
+    /// The particle diameter, for particulate matter alerts.
+    diameter: Option<String>,
+    sensor: Option<SensorId>,
+    status: Option<SensorStatus>,
+    value: Option<f64>,
+    threshold: Option<f64>,
+    location: Option<Arc<str>>,
+    timestamp: u64,
+}
+
+/// The alert rule which an alert [`Event`] is about.
+#[derive(Copy, Clone, Debug)]
+pub(super) struct AlertRule<'a> {
+    pub(super) name: &'a str,
+    pub(super) metric: &'a str,
+    /// The particle diameter, for particulate matter metrics.
+    pub(super) diameter: Option<&'a str>,
+    pub(super) sensor: Option<SensorId>,
+    pub(super) comparison: Comparison,
+    pub(super) threshold: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum EventKind {
+    AlertFiring,
+    AlertResolved,
+    SensorFault,
+    SensorMissing,
+    SensorRecovered,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NotifyConfig {
+    /// The minimum interval between notifications about the same alert or
+    /// sensor.
+    #[serde(
+        default = "default_cooldown",
+        deserialize_with = "deserialize_duration"
+    )]
+    cooldown: Duration,
+    /// The maximum number of notifications to send in any hour.
+    #[serde(default = "default_max_per_hour")]
+    max_per_hour: usize,
+    #[serde(default)]
+    webhook: Vec<Webhook>,
+    #[serde(default)]
+    ntfy: Vec<Ntfy>,
+    #[serde(default)]
+    gotify: Vec<Gotify>,
+}
+
+#[derive(Debug)]
+enum Sink {
+    Webhook(Webhook),
+    Ntfy(Ntfy),
+    Gotify(Gotify),
+}
+
+/// A generic JSON webhook.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Webhook {
+    url: String,
+    /// A template for the request body.
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    headers: BTreeMap<String, String>,
+}
+
+/// An ntfy topic.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Ntfy {
+    url: String,
+    #[serde(default)]
+    priority: Option<u8>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    token: Option<String>,
+}
+
+/// A Gotify server.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Gotify {
+    url: String,
+    token: String,
+    #[serde(default = "default_gotify_priority")]
+    priority: u8,
+}
+
+/// Deduplicates and rate limits notifications.
+#[derive(Debug)]
+struct Limiter {
+    cooldown: Duration,
+    max_per_hour: usize,
+    /// The notification state of each alert or sensor.
+    keys: HashMap<String, KeyState>,
+    /// The times at which notifications were sent in the last hour.
+    sent: VecDeque<Instant>,
+}
+
+/// The state of an alert or sensor, as seen by the [`Limiter`].
+#[derive(Debug, Default)]
+struct KeyState {
+    /// The kind and time of the last notification which was sent.
+    last_sent: Option<(EventKind, Instant)>,
+    /// The latest event which was held back by the cooldown, to be sent once
+    /// the cooldown has passed.
+    pending: Option<Event>,
+}
+
+/// Tracks sensor statuses, to decide when to notify about a sensor.
+#[derive(Debug, Default)]
+struct SensorWatch {
+    sensors: HashMap<SensorId, Watched>,
+}
+
+/// A sensor's state, as seen by the [`SensorWatch`].
+#[derive(Debug, Default)]
+struct Watched {
+    /// Set if the sensor has been up since the last fault notification.
+    up: bool,
+    /// Set if a fault notification has been sent, and the sensor hasn't
+    /// recovered since.
+    faulted: bool,
+    /// Whether the sensor had been found when it was last checked.
+    found: bool,
+    /// When the sensor was last seen leaving the up state, if it isn't up.
+    down_since: Option<Instant>,
+}
+
+/// How many times to attempt to deliver a notification to a sink.
+const MAX_ATTEMPTS: usize = 5;
+
+/// How often to check whether sensor statuses have changed.
+const SENSOR_WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a sensor which was up may spend without an error, but not up
+/// (such as while it's being reset), before it's reported as failing.
+const SENSOR_STUCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+
+fn default_cooldown() -> Duration {
+    Duration::from_secs(10 * 60)
+}
+
+fn default_max_per_hour() -> usize {
+    30
+}
+
+fn default_gotify_priority() -> u8 {
+    5
+}
+
+impl NotifyArgs {
+    pub(super) async fn load(
+        &self,
+        location: Option<Arc<str>>,
+    ) -> anyhow::Result<(Notifier, Option<Dispatcher>)> {
+        let Some(ref path) = self.notify_config else {
+            tracing::debug!("no notification sinks configured");
+            return Ok((Notifier { tx: None }, None));
+        };
+        let file = tokio::fs::read_to_string(path).await.with_context(|| {
+            format!("failed to read notification config from {}", path.display())
+        })?;
+        let config = toml::from_str::<NotifyConfig>(&file)
+            .with_context(|| format!("invalid notification config in {}", path.display()))?;
+        let (notifier, dispatcher) = config.build(location)?;
+        tracing::info!(path = %path.display(), sinks = dispatcher.sinks.len(), "loaded notification sinks");
+        Ok((notifier, Some(dispatcher)))
+    }
+}
+
+impl NotifyConfig {
+    fn build(self, location: Option<Arc<str>>) -> anyhow::Result<(Notifier, Dispatcher)> {
+        let sinks = self
+            .webhook
+            .into_iter()
+            .map(Sink::Webhook)
+            .chain(self.ntfy.into_iter().map(Sink::Ntfy))
+            .chain(self.gotify.into_iter().map(Sink::Gotify))
+            .collect::<Arc<[_]>>();
+        for sink in sinks.iter() {
+            reqwest::Url::parse(sink.url())
+                .with_context(|| format!("invalid notification URL {:?}", sink.url()))?;
+        }
+        let client = reqwest::Client::builder()
+            .user_agent(concat!("eclssd/", env!("CARGO_PKG_VERSION")))
+            .timeout(Duration::from_secs(10))
+            .build()
+            .context("failed to build HTTP client")?;
+        let (tx, rx) = mpsc::channel(64);
+        let dispatcher = Dispatcher {
+            rx,
+            sinks,
+            client,
+            limiter: Limiter::new(self.cooldown, self.max_per_hour),
+            location,
+        };
+        Ok((Notifier { tx: Some(tx) }, dispatcher))
+    }
+}
+
+// === impl Notifier ===
+
+impl Notifier {
+    pub(super) fn notify(&self, event: Event) {
+        let Some(ref tx) = self.tx else {
+            return;
+        };
+        if let Err(error) = tx.try_send(event) {
+            tracing::warn!(%error, "dropping notification");
+        }
+    }
+
+    /// Sends notifications when a sensor which was up starts failing, goes
+    /// missing, or recovers, forever.
+    pub(super) async fn watch_sensors<const SENSORS: usize>(
+        self,
+        sensors: &'static Registry<SENSORS>,
+    ) {
+        if self.tx.is_none() {
+            return;
+        }
+        let mut watch = SensorWatch::default();
+        let mut interval = tokio::time::interval(SENSOR_WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            for (&sensor, state) in sensors.iter() {
+                if let Some(event) = watch.update(sensor, state.status(), state.found(), now) {
+                    self.notify(event);
+                }
+            }
+        }
+    }
+}
+
+// === impl Event ===
+
+impl Event {
+    pub(super) fn alert(alert: &AlertRule<'_>, value: f64, firing: bool) -> Self {
+        let AlertRule {
+            name: rule,
+            metric,
+            diameter,
+            sensor,
+            comparison,
+            threshold,
+        } = *alert;
+        let source = match sensor {
+            Some(sensor) => sensor.to_string(),
+            None => "all sensors".to_string(),
+        };
+        let (key, quantity) = match diameter {
+            Some(diameter) => (
+                format!("alert:{rule}:{diameter}"),
+                format!("{metric} ({diameter} µm)"),
+            ),
+            None => (format!("alert:{rule}"), metric.to_string()),
+        };
+        let (kind, title, message) = if firing {
+            (
+                EventKind::AlertFiring,
+                format!("Alert firing: {rule}"),
+                format!("{quantity} is {comparison} {threshold} ({value:.1}, {source})"),
+            )
+        } else {
+            (
+                EventKind::AlertResolved,
+                format!("Alert resolved: {rule}"),
+                format!("{quantity} is back within {threshold} ({value:.1}, {source})"),
+            )
+        };
+        Self {
+            kind,
+            key,
+            title,
+            message,
+            rule: Some(rule.to_string()),
+            metric: Some(metric.to_string()),
+            diameter: diameter.map(str::to_string),
+            sensor,
+            status: None,
+            value: Some(value),
+            threshold: Some(threshold),
+            location: None,
+            timestamp: unix_now(),
+        }
+    }
+
+    fn sensor_fault(sensor: SensorId, status: SensorStatus) -> Self {
+        Self {
+            kind: EventKind::SensorFault,
+            key: format!("sensor:{sensor}"),
+            title: format!("Sensor fault: {sensor}"),
+            message: format!("{sensor} is failing: {status}"),
+            rule: None,
+            metric: None,
+            diameter: None,
+            sensor: Some(sensor),
+            status: Some(status),
+            value: None,
+            threshold: None,
+            location: None,
+            timestamp: unix_now(),
+        }
+    }
+
+    fn sensor_missing(sensor: SensorId, status: SensorStatus) -> Self {
+        Self {
+            kind: EventKind::SensorMissing,
+            key: format!("sensor:{sensor}"),
+            title: format!("Sensor missing: {sensor}"),
+            message: format!("{sensor} is no longer responding, and may have been unplugged"),
+            rule: None,
+            metric: None,
+            diameter: None,
+            sensor: Some(sensor),
+            status: Some(status),
+            value: None,
+            threshold: None,
+            location: None,
+            timestamp: unix_now(),
+        }
+    }
+
+    fn sensor_recovered(sensor: SensorId) -> Self {
+        Self {
+            kind: EventKind::SensorRecovered,
+            key: format!("sensor:{sensor}"),
+            title: format!("Sensor recovered: {sensor}"),
+            message: format!("{sensor} is up again"),
+            rule: None,
+            metric: None,
+            diameter: None,
+            sensor: Some(sensor),
+            status: Some(SensorStatus::Up),
+            value: None,
+            threshold: None,
+            location: None,
+            timestamp: unix_now(),
+        }
+    }
+}
+
+// === impl Dispatcher ===
+
+impl Dispatcher {
+    pub(super) async fn run(mut self) {
+        loop {
+            let event = match self.limiter.next_due() {
+                Some(due) => tokio::select! {
+                    event = self.rx.recv() => event,
+                    _ = tokio::time::sleep_until(due.into()) => {
+                        for event in self.limiter.due(Instant::now()) {
+                            self.send(event);
+                        }
+                        continue;
+                    }
+                },
+                None => self.rx.recv().await,
+            };
+            let Some(event) = event else {
+                break;
+            };
+            if self.limiter.admit(&event, Instant::now()) {
+                self.send(event);
+            }
+        }
+    }
+
+    fn send(&self, mut event: Event) {
+        if let Some(ref location) = self.location {
+            event.title = format!("[{location}] {}", event.title);
+            event.location = Some(location.clone());
+        }
+        let event = Arc::new(event);
+        for i in 0..self.sinks.len() {
+            tokio::spawn(deliver(
+                self.client.clone(),
+                self.sinks.clone(),
+                i,
+                event.clone(),
+            ));
+        }
+    }
+}
+
+async fn deliver(client: reqwest::Client, sinks: Arc<[Sink]>, i: usize, event: Arc<Event>) {
+    let sink = &sinks[i];
+    let backoff = ExpBackoff::new(Duration::from_secs(1)).with_max(Duration::from_secs(60));
+    for attempt in 1..=MAX_ATTEMPTS {
+        let error = match sink.request(&client, &event).send().await {
+            Ok(rsp) if rsp.status().is_success() => {
+                tracing::debug!(url = %sink.url(), kind = ?event.kind, "sent notification");
+                return;
+            }
+            Ok(rsp) => {
+                let status = rsp.status();
+                if status.is_client_error() && status != reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    tracing::warn!(url = %sink.url(), %status, "notification rejected, not retrying");
+                    return;
+                }
+                format!("server responded with {status}")
+            }
+            Err(error) => error.to_string(),
+        };
+        tracing::warn!(url = %sink.url(), %error, "failed to send notification (attempt {attempt})");
+        if attempt < MAX_ATTEMPTS {
+            backoff.wait(&mut GoodDelay::default()).await;
+        }
+    }
+    tracing::warn!(url = %sink.url(), "giving up on notification after {MAX_ATTEMPTS} attempts");
+}
+
+// === impl Sink ===
+
+impl Sink {
+    fn url(&self) -> &str {
+        match self {
+            Sink::Webhook(Webhook { url, .. })
+            | Sink::Ntfy(Ntfy { url, .. })
+            | Sink::Gotify(Gotify { url, .. }) => url,
+        }
+    }
+
+    fn request(&self, client: &reqwest::Client, event: &Event) -> reqwest::RequestBuilder {
+        match self {
+            Sink::Webhook(Webhook { url, body, headers }) => {
+                let mut req = client.post(url);
+                for (name, value) in headers {
+                    req = req.header(name, value);
+                }
+                match body {
+                    Some(template) => req
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .body(render(template, event)),
+                    None => req.json(event),
+                }
+            }
+            Sink::Ntfy(Ntfy {
+                url,
+                priority,
+                tags,
+                token,
+            }) => {
+                let mut req = client
+                    .post(url)
+                    .header("Title", &event.title)
+                    .body(event.message.clone());
+                if let Some(priority) = priority {
+                    req = req.header("Priority", priority.to_string());
+                }
+                if !tags.is_empty() {
+                    req = req.header("Tags", tags.join(","));
+                }
+                if let Some(token) = token {
+                    req = req.bearer_auth(token);
+                }
+                req
+            }
+            Sink::Gotify(Gotify {
+                url,
+                token,
+                priority,
+            }) => {
+                #[derive(Serialize)]
+                struct Message<'a> {
+                    title: &'a str,
+                    message: &'a str,
+                    priority: u8,
+                }
+                client
+                    .post(format!("{}/message", url.trim_end_matches('/')))
+                    .header("X-Gotify-Key", token)
+                    .json(&Message {
+                        title: &event.title,
+                        message: &event.message,
+                        priority: *priority,
+                    })
+            }
+        }
+    }
+}
+
+/// Renders a webhook body template, replacing `{{field}}` with the value of
+/// that field of `event`.
+///
+/// Since templates are JSON, string values are escaped as JSON string
+/// contents; fields which are not present are replaced with an empty string.
+fn render(template: &str, event: &Event) -> String {
+    let fields = serde_json::to_value(event).unwrap_or_default();
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(len) = rest[start..].find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        let name = rest[start + 2..start + len].trim();
+        match fields.get(name) {
+            Some(serde_json::Value::String(s)) => {
+                let escaped = serde_json::to_string(s).unwrap_or_default();
+                rendered.push_str(&escaped[1..escaped.len() - 1]);
+            }
+            Some(serde_json::Value::Null) | None => {}
+            Some(value) => rendered.push_str(&value.to_string()),
+        }
+        rest = &rest[start + len + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+// === impl Limiter ===
+
+impl Limiter {
+    fn new(cooldown: Duration, max_per_hour: usize) -> Self {
+        Self {
+            cooldown,
+            max_per_hour,
+            keys: HashMap::new(),
+            sent: VecDeque::new(),
+        }
+    }
+
+    /// Returns `true` if `event` should be sent now.
+    ///
+    /// An event is a duplicate if the last notification sent for its key was
+    /// of the same kind, and no other event has been held back since. Events
+    /// which arrive during the cooldown are held back, and the latest one for
+    /// each key is returned by [`Limiter::due`] once the cooldown has passed,
+    /// so that an alert which resolves (or a sensor which recovers) shortly
+    /// after a notification is still reported.
+    fn admit(&mut self, event: &Event, now: Instant) -> bool {
+        let state = self.keys.entry(event.key.clone()).or_default();
+        let pending = state.pending.take();
+        if let Some((kind, at)) = state.last_sent {
+            if kind == event.kind && pending.is_none() {
+                tracing::debug!(key = %event.key, kind = ?event.kind, "suppressing duplicate notification");
+                return false;
+            }
+            if now.duration_since(at) < self.cooldown {
+                tracing::debug!(key = %event.key, kind = ?event.kind, "holding notification until the cooldown has passed");
+                state.pending = Some(event.clone());
+                return false;
+            }
+        }
+
+        if !take_slot(&mut self.sent, self.max_per_hour, now) {
+            tracing::warn!(key = %event.key, kind = ?event.kind, "notification rate limit exceeded, dropping notification");
+            return false;
+        }
+        state.last_sent = Some((event.kind, now));
+        true
+    }
+
+    /// Returns the time at which the next held back event may be sent.
+    fn next_due(&self) -> Option<Instant> {
+        self.keys
+            .values()
+            .filter(|state| state.pending.is_some())
+            .filter_map(|state| state.last_sent)
+            .map(|(_, at)| at + self.cooldown)
+            .min()
+    }
+
+    /// Returns the held back events whose cooldown has passed.
+    fn due(&mut self, now: Instant) -> Vec<Event> {
+        let mut due = Vec::new();
+        for state in self.keys.values_mut() {
+            let cooled_down = state
+                .last_sent
+                .is_some_and(|(_, at)| now.duration_since(at) >= self.cooldown);
+            if !cooled_down {
+                continue;
+            }
+            let Some(event) = state.pending.take() else {
+                continue;
+            };
+            if !take_slot(&mut self.sent, self.max_per_hour, now) {
+                tracing::warn!(key = %event.key, kind = ?event.kind, "notification rate limit exceeded, dropping notification");
+                continue;
+            }
+            state.last_sent = Some((event.kind, now));
+            due.push(event);
+        }
+        due
+    }
+}
+
+// === impl SensorWatch ===
+
+impl SensorWatch {
+    /// Records that `sensor` had `status` at `now`, returning an event to send,
+    /// if any.
+    ///
+    /// Only sensors which have been up are reported, so that a sensor which
+    /// isn't connected doesn't generate a notification at startup. Once up,
+    /// a sensor is reported as failing if it reports an error, if it stops
+    /// acknowledging its address, or if it stays in any other state for
+    /// longer than [`SENSOR_STUCK_TIMEOUT`], and as missing if it is marked
+    /// as not found. A sensor which has been reported is not reported again
+    /// until it has recovered.
+    fn update(
+        &mut self,
+        sensor: SensorId,
+        status: SensorStatus,
+        found: bool,
+        now: Instant,
+    ) -> Option<Event> {
+        let watched = self.sensors.entry(sensor).or_default();
+        let was_found = std::mem::replace(&mut watched.found, found);
+        if status == SensorStatus::Up {
+            watched.down_since = None;
+            watched.up = true;
+            if std::mem::take(&mut watched.faulted) {
+                return Some(Event::sensor_recovered(sensor));
+            }
+            return None;
+        }
+
+        let down_since = *watched.down_since.get_or_insert(now);
+        if !watched.up {
+            return None;
+        }
+
+        let event = if was_found && !found {
+            Event::sensor_missing(sensor, status)
+        } else if status.is_error()
+            || status == SensorStatus::NoAcknowledge
+            || now.duration_since(down_since) >= SENSOR_STUCK_TIMEOUT
+        {
+            Event::sensor_fault(sensor, status)
+        } else {
+            return None;
+        };
+        watched.up = false;
+        watched.faulted = true;
+        Some(event)
+    }
+}
+
+/// Records a notification sent at `now`, unless `max_per_hour` notifications
+/// have already been sent in the last hour.
+fn take_slot(sent: &mut VecDeque<Instant>, max_per_hour: usize, now: Instant) -> bool {
+    while sent
+        .front()
+        .is_some_and(|&at| now.duration_since(at) >= HOUR)
+    {
+        sent.pop_front();
+    }
+    if sent.len() >= max_per_hour {
+        return false;
+    }
+    sent.push_back(now);
+    true
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    humantime::parse_duration(&s).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eclss_api::SensorName;
+    use eclss_axum::axum::{
+        extract::State, http::HeaderMap, http::StatusCode, routing::post, Router,
+    };
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct StandIn {
+        requests: Arc<Mutex<Vec<(String, HeaderMap, String)>>>,
+        /// The number of requests to fail before succeeding.
+        failures: Arc<Mutex<usize>>,
+    }
+
+    impl StandIn {
+        async fn serve(&self) -> String {
+            async fn handle(
+                State(stand_in): State<StandIn>,
+                uri: eclss_axum::axum::http::Uri,
+                headers: HeaderMap,
+                body: String,
+            ) -> StatusCode {
+                stand_in
+                    .requests
+                    .lock()
+                    .unwrap()
+                    .push((uri.path().to_string(), headers, body));
+                let mut failures = stand_in.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return StatusCode::SERVICE_UNAVAILABLE;
+                }
+                StatusCode::OK
+            }
+            let app = Router::new()
+                .route("/*path", post(handle))
+                .with_state(self.clone());
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            tokio::spawn(async move { eclss_axum::axum::serve(listener, app).await });
+            format!("http://{addr}")
+        }
+
+        async fn wait_for(&self, n: usize) -> Vec<(String, HeaderMap, String)> {
+            for _ in 0..100 {
+                if self.requests.lock().unwrap().len() >= n {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    fn dispatcher(config: &str) -> (Notifier, Dispatcher) {
+        toml::from_str::<NotifyConfig>(config)
+            .unwrap()
+            .build(Some("lab".into()))
+            .unwrap()
+    }
+
+    fn co2_alert(firing: bool) -> Event {
+        let rule = AlertRule {
+            name: "co2-high",
+            metric: "co2_ppm",
+            diameter: None,
+            sensor: Some(SensorId::new(SensorName::Scd41)),
+            comparison: Comparison::Above,
+            threshold: 1200.0,
+        };
+        Event::alert(&rule, 1300.0, firing)
+    }
+
+    #[tokio::test]
+    async fn webhook_template() {
+        let stand_in = StandIn::default();
+        let url = stand_in.serve().await;
+        let (notifier, dispatcher) = dispatcher(&format!(
+            r#"
+            [[webhook]]
+            url = "{url}/hook"
+            body = '{{"text": "{{{{title}}}}: {{{{message}}}}", "value": {{{{value}}}}}}'
+            headers = {{ Authorization = "Bearer hunter2" }}
+            "#
+        ));
+        tokio::spawn(dispatcher.run());
+        notifier.notify(co2_alert(true));
+
+        let requests = stand_in.wait_for(1).await;
+        let (path, headers, body) = &requests[0];
+        assert_eq!(path, "/hook");
+        assert_eq!(headers["authorization"], "Bearer hunter2");
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "text": "[lab] Alert firing: co2-high: co2_ppm is above 1200 (1300.0, SCD41)",
+                "value": 1300.0,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn ntfy_and_gotify() {
+        let stand_in = StandIn::default();
+        let url = stand_in.serve().await;
+        let (notifier, dispatcher) = dispatcher(&format!(
+            r#"
+            [[ntfy]]
+            url = "{url}/eclss"
+            priority = 4
+            tags = ["warning", "eclss"]
+
+            [[gotify]]
+            url = "{url}/gotify/"
+            token = "AbCdEf"
+            "#
+        ));
+        tokio::spawn(dispatcher.run());
+        notifier.notify(Event::sensor_fault(
+            SensorId::new(SensorName::Sgp30),
+            SensorStatus::BusError,
+        ));
+
+        let mut requests = stand_in.wait_for(2).await;
+        requests.sort_by(|a, b| a.0.cmp(&b.0));
+        let (path, headers, body) = &requests[0];
+        assert_eq!(path, "/eclss");
+        assert_eq!(headers["title"], "[lab] Sensor fault: SGP30");
+        assert_eq!(headers["priority"], "4");
+        assert_eq!(headers["tags"], "warning,eclss");
+        assert_eq!(body, "SGP30 is failing: BusError");
+
+        let (path, headers, body) = &requests[1];
+        assert_eq!(path, "/gotify/message");
+        assert_eq!(headers["x-gotify-key"], "AbCdEf");
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!(body["title"], "[lab] Sensor fault: SGP30");
+        assert_eq!(body["priority"], 5);
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors() {
+        let stand_in = StandIn::default();
+        *stand_in.failures.lock().unwrap() = 1;
+        let url = stand_in.serve().await;
+        let (notifier, dispatcher) = dispatcher(&format!("[[webhook]]\nurl = \"{url}/hook\""));
+        tokio::spawn(dispatcher.run());
+        notifier.notify(co2_alert(true));
+
+        let requests = stand_in.wait_for(2).await;
+        assert_eq!(requests.len(), 2);
+        let event: serde_json::Value = serde_json::from_str(&requests[1].2).unwrap();
+        assert_eq!(event["kind"], "alert_firing");
+        assert_eq!(event["location"], "lab");
+    }
+
+    #[test]
+    fn limiter_dedups_and_cools_down() {
+        let mut limiter = Limiter::new(Duration::from_secs(60), 3);
+        let start = Instant::now();
+        assert!(limiter.admit(&co2_alert(true), start));
+        // duplicate
+        assert!(!limiter.admit(&co2_alert(true), start + Duration::from_secs(120)));
+        // cooldown
+        assert!(!limiter.admit(&co2_alert(false), start + Duration::from_secs(30)));
+        assert!(limiter.admit(&co2_alert(false), start + Duration::from_secs(90)));
+        // a different key is not affected by the cooldown
+        let fault = Event::sensor_fault(
+            SensorId::new(SensorName::Sht41),
+            SensorStatus::NoAcknowledge,
+        );
+        assert!(limiter.admit(&fault, start + Duration::from_secs(91)));
+        // ...but is affected by the global rate limit
+        assert!(!limiter.admit(&co2_alert(true), start + Duration::from_secs(200)));
+        assert!(limiter.admit(&co2_alert(true), start + HOUR + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn limiter_refires_after_suppressed_resolve() {
+        let mut limiter = Limiter::new(Duration::from_secs(60), 10);
+        let start = Instant::now();
+        assert!(limiter.admit(&co2_alert(true), start));
+        // the alert resolves during the cooldown, so that isn't sent...
+        assert!(!limiter.admit(&co2_alert(false), start + Duration::from_secs(30)));
+        // ...and it fires again, which is sent once the cooldown has passed.
+        assert!(!limiter.admit(&co2_alert(true), start + Duration::from_secs(45)));
+        assert!(!limiter.admit(&co2_alert(false), start + Duration::from_secs(50)));
+        assert!(limiter.admit(&co2_alert(true), start + Duration::from_secs(90)));
+        // it's still firing, so this is a duplicate.
+        assert!(!limiter.admit(&co2_alert(true), start + Duration::from_secs(200)));
+    }
+
+    #[test]
+    fn limiter_sends_resolve_after_cooldown() {
+        let mut limiter = Limiter::new(Duration::from_secs(60), 10);
+        let start = Instant::now();
+        assert!(limiter.admit(&co2_alert(true), start));
+        assert_eq!(limiter.next_due(), None);
+        // the alert resolves during the cooldown, so it's held back...
+        assert!(!limiter.admit(&co2_alert(false), start + Duration::from_secs(30)));
+        assert_eq!(limiter.next_due(), Some(start + Duration::from_secs(60)));
+        assert_eq!(limiter.due(start + Duration::from_secs(59)), Vec::new());
+        // ...and sent once the cooldown has passed.
+        let due = limiter.due(start + Duration::from_secs(60));
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].kind, EventKind::AlertResolved);
+        assert_eq!(limiter.next_due(), None);
+        assert!(!limiter.admit(&co2_alert(false), start + Duration::from_secs(200)));
+    }
+
+    #[tokio::test]
+    async fn sends_resolve_after_cooldown() {
+        let stand_in = StandIn::default();
+        let url = stand_in.serve().await;
+        let (notifier, dispatcher) = dispatcher(&format!(
+            "cooldown = \"100ms\"\n[[webhook]]\nurl = \"{url}/hook\""
+        ));
+        tokio::spawn(dispatcher.run());
+        notifier.notify(co2_alert(true));
+        notifier.notify(co2_alert(false));
+
+        let requests = stand_in.wait_for(2).await;
+        let kinds = requests
+            .iter()
+            .map(|(_, _, body)| {
+                serde_json::from_str::<serde_json::Value>(body).unwrap()["kind"].clone()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, ["alert_firing", "alert_resolved"]);
+    }
+
+    #[test]
+    fn particulate_alerts_include_diameter() {
+        let pm = |diameter| {
+            let rule = AlertRule {
+                name: "pm-high",
+                metric: "pm_concentration_ug_m3",
+                diameter: Some(diameter),
+                sensor: None,
+                comparison: Comparison::Above,
+                threshold: 35.0,
+            };
+            Event::alert(&rule, 40.0, true)
+        };
+        let (pm2_5, pm10) = (pm("2.5"), pm("10.0"));
+        assert_ne!(pm2_5.key, pm10.key);
+        assert_eq!(
+            pm2_5.message,
+            "pm_concentration_ug_m3 (2.5 µm) is above 35 (40.0, all sensors)"
+        );
+        assert_eq!(pm2_5.diameter.as_deref(), Some("2.5"));
+
+        // alerts for different diameters don't suppress each other.
+        let mut limiter = Limiter::new(Duration::from_secs(60), 10);
+        let now = Instant::now();
+        assert!(limiter.admit(&pm2_5, now));
+        assert!(limiter.admit(&pm10, now));
+    }
+
+    fn kinds(events: impl IntoIterator<Item = Option<Event>>) -> Vec<Option<EventKind>> {
+        events.into_iter().map(|e| e.map(|e| e.kind)).collect()
+    }
+
+    #[test]
+    fn watch_reports_errors_and_recovery() {
+        let sensor = SensorId::new(SensorName::Scd41);
+        let mut watch = SensorWatch::default();
+        let start = Instant::now();
+        let mut update =
+            |status, secs| watch.update(sensor, status, true, start + Duration::from_secs(secs));
+        assert_eq!(
+            kinds([
+                update(SensorStatus::Initializing, 0),
+                update(SensorStatus::Up, 1),
+                update(SensorStatus::SensorError, 2),
+                // only reported once
+                update(SensorStatus::BusError, 3),
+                update(SensorStatus::Up, 4),
+                update(SensorStatus::NoAcknowledge, 5),
+                update(SensorStatus::Up, 6),
+            ]),
+            [
+                None,
+                None,
+                Some(EventKind::SensorFault),
+                None,
+                Some(EventKind::SensorRecovered),
+                Some(EventKind::SensorFault),
+                Some(EventKind::SensorRecovered),
+            ]
+        );
+    }
+
+    #[test]
+    fn watch_reports_stuck_sensors() {
+        let sensor = SensorId::new(SensorName::Sgp30);
+        let mut watch = SensorWatch::default();
+        let start = Instant::now();
+        let mut update =
+            |status, secs| watch.update(sensor, status, true, start + Duration::from_secs(secs));
+        assert_eq!(update(SensorStatus::Up, 0), None);
+        assert_eq!(update(SensorStatus::Initializing, 1), None);
+        assert_eq!(update(SensorStatus::Initializing, 60), None);
+        let event = update(SensorStatus::Initializing, 61).unwrap();
+        assert_eq!(event.kind, EventKind::SensorFault);
+        assert_eq!(event.status, Some(SensorStatus::Initializing));
+        assert_eq!(update(SensorStatus::Initializing, 200), None);
+        assert_eq!(
+            update(SensorStatus::Up, 201).map(|e| e.kind),
+            Some(EventKind::SensorRecovered)
+        );
+    }
+
+    #[test]
+    fn watch_reports_missing_sensors() {
+        let sensor = SensorId::new(SensorName::Sht41);
+        let mut watch = SensorWatch::default();
+        let now = Instant::now();
+        assert_eq!(watch.update(sensor, SensorStatus::Up, true, now), None);
+        let event = watch
+            .update(sensor, SensorStatus::NoAcknowledge, false, now)
+            .unwrap();
+        assert_eq!(event.kind, EventKind::SensorMissing);
+        assert_eq!(event.key, "sensor:SHT41");
+        assert_eq!(
+            watch.update(sensor, SensorStatus::NoAcknowledge, false, now),
+            None
+        );
+        assert_eq!(
+            watch
+                .update(sensor, SensorStatus::Up, true, now)
+                .map(|e| e.kind),
+            Some(EventKind::SensorRecovered)
+        );
+    }
+
+    #[test]
+    fn watch_ignores_sensors_which_were_never_up() {
+        let sensor = SensorId::new(SensorName::Sen55);
+        let mut watch = SensorWatch::default();
+        let start = Instant::now();
+        for (secs, status, found) in [
+            (0, SensorStatus::Initializing, true),
+            (1, SensorStatus::SensorError, true),
+            (120, SensorStatus::Initializing, true),
+            (121, SensorStatus::NoAcknowledge, false),
+        ] {
+            let now = start + Duration::from_secs(secs);
+            assert_eq!(watch.update(sensor, status, found, now), None);
+        }
+    }
+
+    #[test]
+    fn render_escapes_strings() {
+        let mut event = co2_alert(true);
+        event.message = "a \"quoted\" message".to_string();
+        assert_eq!(
+            render(
+                "{\"m\": \"{{ message }}\", \"loc\": \"{{location}}\"}",
+                &event
+            ),
+            r#"{"m": "a \"quoted\" message", "loc": ""}"#
+        );
+    }
+}
@@ -0,0 +1,127 @@
+//! Detecting which sensors are connected to the I²C bus.
+use crate::{Bus, GoodDelay};
+use eclss::probe::{self, Detected};
+use eclss::SharedBus;
+use eclss_api::{InstanceName, SensorId, SensorName};
+
+/// Scans `bus` for supported sensors.
+pub(super) async fn scan(bus: &'static SharedBus<Bus>) -> Vec<Detected> {
+    let mut detected = Vec::new();
+    let mut i2c = bus;
+    probe::probe(&mut i2c, &mut GoodDelay::default(), |sensor| {
+        detected.push(sensor)
+    })
+    .await;
+    detected
+}
+
+/// Returns the ID and address of each `detected` sensor which can be run by
+/// this build of `eclssd`, named as described in [`detected_id`].
+pub(super) fn sensors(detected: &[Detected]) -> Vec<(SensorId, u8)> {
+    let mut sensors = Vec::with_capacity(detected.len());
+    for &Detected { name, address } in detected {
+        if !is_enabled(name) {
+            tracing::warn!(
+                "detected {name} at {address:#04x}, but it was not enabled at compile time"
+            );
+            continue;
+        }
+        let id = detected_id(name, address);
+        tracing::info!(sensor = %id, "detected {name} at {address:#04x}");
+        sensors.push((id, address));
+    }
+    sensors
+}
+
+/// Returns the ID of a sensor of the `name` model which was detected at
+/// `address`.
+///
+/// A sensor at its model's default address is named by its model (e.g.
+/// `SHT41`), and a sensor at any other address is also named by its address
+/// (e.g. `SHT41:0x45`). Since a sensor's name doesn't depend on which other
+/// sensors were detected, or in which order, sensors of the same model are
+/// always named the same way, and keep their stored state across restarts.
+pub(super) fn detected_id(name: SensorName, address: u8) -> SensorId {
+    if probe::addresses(name).first() == Some(&address) {
+        return SensorId::new(name);
+    }
+    let instance = format!("{address:#04x}")
+        .parse::<InstanceName>()
+        .expect("an I²C address is a valid instance name");
+    SensorId::with_instance(name, instance)
+}
+
+/// Prints the `detected` sensors, for `eclssd probe`.
+pub(super) fn print(detected: &[Detected]) {
+    if detected.is_empty() {
+        println!("no sensors found");
+        return;
+    }
+    println!("ADDRESS  SENSOR");
+    for &Detected { name, address } in detected {
+        if is_enabled(name) {
+            println!("{address:#04x}     {name}");
+        } else {
+            println!("{address:#04x}     {name:<8} (not enabled at compile time)");
+        }
+    }
+}
+
+/// Returns `true` if support for the `name` sensor model was enabled at
+/// compile time.
+pub(super) fn is_enabled(name: SensorName) -> bool {
+    match name {
+        SensorName::Bme680 => cfg!(feature = "bme680"),
+        SensorName::Ens160 => cfg!(feature = "ens160"),
+        SensorName::Pmsa003i => cfg!(feature = "pmsa003i"),
+        SensorName::Scd30 => cfg!(feature = "scd30"),
+        SensorName::Scd40 => cfg!(feature = "scd40"),
+        SensorName::Scd41 => cfg!(feature = "scd41"),
+        SensorName::Sht41 => cfg!(feature = "sht41"),
+        SensorName::Sgp30 => cfg!(feature = "sgp30"),
+        SensorName::Sen55 => cfg!(feature = "sen55"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(all(feature = "sht41", feature = "sgp30"))]
+    fn duplicate_models_are_named_by_address() {
+        let sht41 = |address| Detected {
+            name: SensorName::Sht41,
+            address,
+        };
+        let sgp30 = Detected {
+            name: SensorName::Sgp30,
+            address: 0x58,
+        };
+        let ids = |detected: &[Detected]| {
+            sensors(detected)
+                .iter()
+                .map(|(id, address)| (id.to_string(), *address))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            ids(&[sht41(0x44), sht41(0x45), sgp30]),
+            [
+                ("SHT41".to_string(), 0x44),
+                ("SHT41:0x45".to_string(), 0x45),
+                ("SGP30".to_string(), 0x58)
+            ]
+        );
+        // names don't depend on the order sensors are detected in, or on
+        // which other sensors were detected.
+        assert_eq!(
+            ids(&[sht41(0x45), sht41(0x44)]),
+            [
+                ("SHT41:0x45".to_string(), 0x45),
+                ("SHT41".to_string(), 0x44)
+            ]
+        );
+        assert_eq!(ids(&[sht41(0x45)]), [("SHT41:0x45".to_string(), 0x45)]);
+    }
+}
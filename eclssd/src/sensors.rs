@@ -0,0 +1,834 @@
+//! Per-sensor configuration overrides.
+use crate::{AsyncI2c, Bus};
+use anyhow::Context;
+use eclss::{
+    fusion::{self, Quantity},
+    sensor::{Calibration, Compensation, SensorConfig},
+    SharedBus, Tca9548a,
+};
+use eclss_api::SensorId;
+use linux_embedded_hal::I2cdev;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone, Debug, Default, clap::Parser)]
+#[command(next_help_heading = "Sensor Overrides")]
+pub(super) struct SensorArgs {
+    /// Override a sensor's I²C address, as `SENSOR=ADDRESS`.
+    ///
+    /// For example, `--sensor-address SHT41=0x45` configures an SHT41 part
+    /// which uses the alternate address 0x45. Addresses may be hexadecimal
+    /// (with a `0x` prefix) or decimal. If multiple instances of a sensor model
+    /// are enabled, `SENSOR` is the instance's `MODEL:INSTANCE` name, as in
+    /// `--sensor-address SHT41:ceiling=0x45`.
+    #[clap(
+        long = "sensor-address",
+        value_name = "SENSOR=ADDRESS",
+        value_parser = parse_address,
+    )]
+    addresses: Vec<(SensorId, u8)>,
+
+    /// Override the Linux i2cdev I²C device that a sensor is attached to, as
+    /// `SENSOR=PATH`.
+    ///
+    /// Sensors which are not overridden use the device set by `--i2cdev`.
+    #[clap(
+        long = "sensor-bus",
+        value_name = "SENSOR=PATH",
+        value_parser = parse_bus,
+    )]
+    buses: Vec<(SensorId, PathBuf)>,
+
+    /// Override how often a sensor is polled, as `SENSOR=DURATION`.
+    #[clap(
+        long = "sensor-poll-interval",
+        value_name = "SENSOR=DURATION",
+        value_parser = parse_poll_interval,
+    )]
+    poll_intervals: Vec<(SensorId, Duration)>,
+
+    /// Attach a sensor to a channel of a TCA9548A I²C multiplexer, as
+    /// `SENSOR=CHANNEL[@ADDRESS]`.
+    ///
+    /// The multiplexer is on the sensor's bus (`--sensor-bus`, or `--i2cdev`
+    /// by default). If no address is given, the multiplexer is at its default
+    /// address, 0x70. For example, `--sensor-mux SHT41:left=0 --sensor-mux
+    /// SHT41:right=1` configures two SHT41s on channels 0 and 1 of a
+    /// multiplexer at 0x70.
+    #[clap(
+        long = "sensor-mux",
+        value_name = "SENSOR=CHANNEL[@ADDRESS]",
+        value_parser = parse_mux,
+    )]
+    muxes: Vec<(SensorId, MuxChannel)>,
+
+    /// Correct a sensor's temperature or humidity readings by adding an
+    /// offset, as `SENSOR/QUANTITY=OFFSET`.
+    ///
+    /// `QUANTITY` is `temp` (in °C) or `humidity` (in % RH). For example,
+    /// `--sensor-offset SCD41/temp=-2` corrects an SCD41 which reads 2 °C hot
+    /// because of self-heating. Relative humidity readings are recomputed for
+    /// the corrected temperature before any humidity offset is added. SCD40
+    /// and SCD41 temperature offsets are written to the sensor, if it supports
+    /// the resulting offset.
+    #[clap(
+        long = "sensor-offset",
+        value_name = "SENSOR/QUANTITY=OFFSET",
+        value_parser = parse_offset,
+        allow_hyphen_values = true,
+    )]
+    offsets: Vec<(SensorId, Correction)>,
+
+    /// Scale a sensor's temperature or humidity readings, as
+    /// `SENSOR/QUANTITY=SCALE`.
+    ///
+    /// Readings are multiplied by the scale before any offset is added.
+    #[clap(
+        long = "sensor-scale",
+        value_name = "SENSOR/QUANTITY=SCALE",
+        value_parser = parse_scale,
+    )]
+    scales: Vec<(SensorId, Correction)>,
+
+    /// Set which sensors a sensor's temperature, humidity, or pressure
+    /// compensation is taken from, as `SENSOR/QUANTITY=SOURCE[,SOURCE...]`.
+    ///
+    /// `QUANTITY` is `temp`, `humidity`, or `pressure`, and the first `SOURCE`
+    /// with a current reading is used. For example, `--sensor-compensation
+    /// SGP30/humidity=SHT41,BME680` compensates an SGP30 for the humidity
+    /// measured by the SHT41, or by the BME680 if the SHT41 is down, and
+    /// `--sensor-compensation SCD41/pressure=BME680` compensates an SCD41 for
+    /// the BME680's pressure readings. `FUSED` is the fused estimate. By
+    /// default, sensors are compensated from the fused estimate, or from the
+    /// mean of every healthy sensor's readings.
+    #[clap(
+        long = "sensor-compensation",
+        value_name = "SENSOR/QUANTITY=SOURCE[,SOURCE...]",
+        value_parser = parse_compensation,
+    )]
+    compensations: Vec<(SensorId, Sources)>,
+}
+
+/// A sensor's settings in the configuration file.
+///
+/// Each setting is equivalent to the `--sensor-*` flag of the same name, as
+/// in:
+///
+/// ```toml
+/// [sensor."SHT41:ceiling"]
+/// address = 0x45
+/// bus = "/dev/i2c-3"
+/// poll_interval = "5s"
+/// mux = { channel = 2, address = 0x71 }
+/// offset = { temp = -1.5 }
+/// scale = { humidity = 1.02 }
+/// compensation = { pressure = ["BME680", "FUSED"] }
+/// ```
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct SensorSettings {
+    address: Option<u8>,
+    bus: Option<PathBuf>,
+    poll_interval: Option<String>,
+    mux: Option<MuxSettings>,
+    offset: BTreeMap<String, f32>,
+    scale: BTreeMap<String, f32>,
+    compensation: BTreeMap<String, Vec<String>>,
+}
+
+/// A multiplexer channel in the configuration file.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MuxSettings {
+    channel: u8,
+    address: Option<u8>,
+}
+
+/// An offset or scale for one of a sensor's readings.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Correction {
+    quantity: Quantity,
+    value: f32,
+}
+
+/// The sources of one of a sensor's compensation inputs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Sources {
+    quantity: Quantity,
+    sensors: &'static [SensorId],
+}
+
+/// A channel of a TCA9548A I²C multiplexer.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct MuxChannel {
+    channel: u8,
+    address: u8,
+}
+
+impl SensorArgs {
+    /// Returns the configuration for each of the enabled `sensors`, opening
+    /// any I²C devices other than the `default_bus` that they are attached to.
+    /// `eclss_bus` is the already opened `default_bus`, which multiplexers on
+    /// that bus are attached to.
+    ///
+    /// In simulation mode, all sensors are on the simulated bus at their
+    /// default addresses, so only poll interval overrides are used.
+    pub(super) fn configs(
+        &self,
+        sensors: &[SensorId],
+        default_bus: &Path,
+        eclss_bus: &'static SharedBus<Bus>,
+        sim: bool,
+    ) -> anyhow::Result<HashMap<SensorId, SensorConfig<Bus>>> {
+        for (i, sensor) in sensors.iter().enumerate() {
+            anyhow::ensure!(
+                !sensors[..i].contains(sensor),
+                "sensor {sensor} is enabled more than once; use `MODEL:INSTANCE` \
+                to enable multiple sensors of the same model"
+            );
+        }
+        let overridden = self
+            .addresses
+            .iter()
+            .map(|(sensor, _)| sensor)
+            .chain(self.buses.iter().map(|(sensor, _)| sensor))
+            .chain(self.poll_intervals.iter().map(|(sensor, _)| sensor))
+            .chain(self.muxes.iter().map(|(sensor, _)| sensor))
+            .chain(self.offsets.iter().map(|(sensor, _)| sensor))
+            .chain(self.scales.iter().map(|(sensor, _)| sensor))
+            .chain(self.compensations.iter().map(|(sensor, _)| sensor));
+        for sensor in overridden {
+            if !sensors.contains(sensor) {
+                tracing::warn!("ignoring configuration for {sensor}, which is not enabled");
+            }
+        }
+        for (sensor, sources) in &self.compensations {
+            let quantity = sources.quantity;
+            for source in sources
+                .sensors
+                .iter()
+                .filter(|source| **source != fusion::FUSED && !sensors.contains(source))
+            {
+                tracing::warn!("{sensor} {quantity} compensation source {source} is not enabled");
+            }
+        }
+        if sim && !(self.addresses.is_empty() && self.buses.is_empty() && self.muxes.is_empty()) {
+            tracing::warn!("ignoring sensor address, bus, and mux overrides in simulation mode");
+        }
+
+        let mut buses = HashMap::<&Path, &'static SharedBus<Bus>>::new();
+        let mut configs = HashMap::new();
+        for &sensor in sensors {
+            let mut config = SensorConfig {
+                poll_interval: find(&self.poll_intervals, sensor).copied(),
+                instance: sensor.instance,
+                calibration: self.calibration(sensor),
+                compensation: self.compensation(sensor),
+                ..Default::default()
+            };
+            if !sim {
+                config.address = find(&self.addresses, sensor).copied();
+                let bus = match find(&self.buses, sensor) {
+                    Some(path) if path != default_bus => Some(match buses.get(path.as_path()) {
+                        Some(bus) => bus,
+                        None => {
+                            let bus = open_bus(path)?;
+                            buses.insert(path, bus);
+                            bus
+                        }
+                    }),
+                    _ => None,
+                };
+                config.bus = match find(&self.muxes, sensor) {
+                    Some(&MuxChannel { channel, address }) => {
+                        let mux = Tca9548a::new(bus.unwrap_or(eclss_bus), address)?;
+                        let channel = mux
+                            .channel(channel)
+                            .with_context(|| format!("invalid mux channel for {sensor}"))?;
+                        Some(&*Box::leak(Box::new(channel)))
+                    }
+                    None => bus,
+                };
+            }
+            tracing::debug!(
+                %sensor,
+                address = ?config.address,
+                bus = ?find(&self.buses, sensor),
+                mux = ?find(&self.muxes, sensor),
+                poll_interval = ?config.poll_interval,
+                calibration = ?config.calibration,
+                compensation = ?config.compensation,
+                "configured sensor"
+            );
+            configs.insert(sensor, config);
+        }
+        Ok(configs)
+    }
+
+    /// Returns the configuration for a `sensor` which was detected at `address`
+    /// on the default bus after startup.
+    pub(super) fn detected_config(&self, sensor: SensorId, address: u8) -> SensorConfig<Bus> {
+        SensorConfig {
+            address: Some(address),
+            poll_interval: find(&self.poll_intervals, sensor).copied(),
+            instance: sensor.instance,
+            calibration: self.calibration(sensor),
+            compensation: self.compensation(sensor),
+            ..Default::default()
+        }
+    }
+
+    /// Returns the calibration for `sensor`'s readings. Later offsets and
+    /// scales for the same quantity take precedence.
+    fn calibration(&self, sensor: SensorId) -> Calibration {
+        let mut calibration = Calibration::IDENTITY;
+        let offsets = self.offsets.iter().map(|(name, c)| (name, c, true));
+        let scales = self.scales.iter().map(|(name, c)| (name, c, false));
+        for (_, &Correction { quantity, value }, is_offset) in
+            offsets.chain(scales).filter(|(name, ..)| **name == sensor)
+        {
+            let correction = match quantity {
+                Quantity::Humidity => &mut calibration.humidity,
+                _ => &mut calibration.temp,
+            };
+            if is_offset {
+                correction.offset = value;
+            } else {
+                correction.scale = value;
+            }
+        }
+        calibration
+    }
+
+    /// Returns the sources of `sensor`'s compensation inputs. Later sources for
+    /// the same quantity take precedence.
+    fn compensation(&self, sensor: SensorId) -> Compensation {
+        let mut compensation = Compensation::AUTOMATIC;
+        for &(_, Sources { quantity, sensors }) in self
+            .compensations
+            .iter()
+            .filter(|(name, _)| *name == sensor)
+        {
+            match quantity {
+                Quantity::Temp => compensation.temp = sensors,
+                Quantity::Humidity => compensation.humidity = sensors,
+                _ => compensation.pressure = sensors,
+            }
+        }
+        compensation
+    }
+
+    /// Adds per-sensor `settings` from the configuration file, keyed by sensor.
+    ///
+    /// Flags for the same sensor and setting take precedence over the file.
+    pub(super) fn merge_config(
+        &mut self,
+        settings: BTreeMap<String, SensorSettings>,
+    ) -> anyhow::Result<()> {
+        let mut file = Self::default();
+        for (sensor, settings) in settings {
+            let sensor = parse_sensor(&sensor).map_err(anyhow::Error::msg)?;
+            settings
+                .add_to(sensor, &mut file)
+                .map_err(anyhow::Error::msg)
+                .with_context(|| format!("invalid settings for {sensor}"))?;
+        }
+        // later overrides take precedence, so put these first.
+        self.addresses.splice(0..0, file.addresses);
+        self.buses.splice(0..0, file.buses);
+        self.poll_intervals.splice(0..0, file.poll_intervals);
+        self.muxes.splice(0..0, file.muxes);
+        self.offsets.splice(0..0, file.offsets);
+        self.scales.splice(0..0, file.scales);
+        self.compensations.splice(0..0, file.compensations);
+        Ok(())
+    }
+
+    /// Configures the `detected` sensors to use the addresses they were found
+    /// at, unless their addresses are overridden.
+    pub(super) fn use_detected(&mut self, detected: &[(SensorId, u8)]) {
+        // later overrides take precedence, so put these first.
+        self.addresses.splice(0..0, detected.iter().copied());
+    }
+}
+
+// === impl SensorSettings ===
+
+impl SensorSettings {
+    /// Adds these settings for `sensor` to `args`, validating them like the
+    /// equivalent flags.
+    fn add_to(self, sensor: SensorId, args: &mut SensorArgs) -> Result<(), String> {
+        if let Some(address) = self.address {
+            args.addresses.push((sensor, i2c_address(address)?));
+        }
+        if let Some(bus) = self.bus {
+            args.buses.push((sensor, bus));
+        }
+        if let Some(interval) = self.poll_interval {
+            args.poll_intervals
+                .push((sensor, poll_interval(&interval)?));
+        }
+        if let Some(MuxSettings { channel, address }) = self.mux {
+            let address = match address {
+                Some(address) => i2c_address(address)?,
+                None => Tca9548a::<Bus>::DEFAULT_ADDRESS,
+            };
+            args.muxes.push((sensor, mux_channel(channel, address)?));
+        }
+        for (quantity, offset) in self.offset {
+            args.offsets.push((sensor, correction(&quantity, offset)?));
+        }
+        for (quantity, scale) in self.scale {
+            args.scales
+                .push((sensor, check_scale(correction(&quantity, scale)?)?));
+        }
+        for (quantity, sources) in self.compensation {
+            let sources =
+                compensation_sources(sensor, &quantity, sources.iter().map(String::as_str))?;
+            args.compensations.push((sensor, sources));
+        }
+        Ok(())
+    }
+}
+
+fn open_bus(path: &Path) -> anyhow::Result<&'static SharedBus<Bus>> {
+    let dev = I2cdev::new(path)
+        .with_context(|| format!("failed to open I2C device {}", path.display()))?;
+    tracing::info!(path = %path.display(), "opened I²C device");
+    Ok(Box::leak(Box::new(SharedBus::new(Bus::Linux(AsyncI2c(
+        dev,
+    ))))))
+}
+
+/// Returns the last override for `sensor`, as later flags take precedence.
+fn find<T>(overrides: &[(SensorId, T)], sensor: SensorId) -> Option<&T> {
+    overrides
+        .iter()
+        .rev()
+        .find(|(name, _)| *name == sensor)
+        .map(|(_, value)| value)
+}
+
+/// Parses the ID of a sensor to enable or configure.
+///
+/// The `FUSED` pseudo-sensor isn't a physical sensor, so it's rejected.
+pub(super) fn parse_sensor(s: &str) -> Result<SensorId, String> {
+    let sensor = s
+        .trim()
+        .parse::<SensorId>()
+        .map_err(|error| format!("invalid sensor {s:?}: {error}"))?;
+    if sensor.is_fused() {
+        return Err(format!("{sensor} is not a physical sensor"));
+    }
+    Ok(sensor)
+}
+
+fn split_override(s: &str) -> Result<(SensorId, &str), String> {
+    let (sensor, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected SENSOR=VALUE, got {s:?}"))?;
+    Ok((parse_sensor(sensor)?, value.trim()))
+}
+
+fn parse_address(s: &str) -> Result<(SensorId, u8), String> {
+    let (sensor, addr) = split_override(s)?;
+    Ok((sensor, parse_i2c_address(addr)?))
+}
+
+fn parse_mux(s: &str) -> Result<(SensorId, MuxChannel), String> {
+    let (sensor, value) = split_override(s)?;
+    let (channel, address) = match value.split_once('@') {
+        Some((channel, address)) => (channel.trim(), parse_i2c_address(address.trim())?),
+        None => (value, Tca9548a::<Bus>::DEFAULT_ADDRESS),
+    };
+    let channel = channel
+        .parse::<u8>()
+        .map_err(|_| format!("invalid TCA9548A channel {channel:?} (expected 0-7)"))?;
+    Ok((sensor, mux_channel(channel, address)?))
+}
+
+fn mux_channel(channel: u8, address: u8) -> Result<MuxChannel, String> {
+    if channel >= Tca9548a::<Bus>::CHANNELS {
+        return Err(format!("invalid TCA9548A channel {channel} (expected 0-7)"));
+    }
+    Ok(MuxChannel { channel, address })
+}
+
+fn parse_offset(s: &str) -> Result<(SensorId, Correction), String> {
+    parse_correction(s)
+}
+
+fn parse_scale(s: &str) -> Result<(SensorId, Correction), String> {
+    let (sensor, correction) = parse_correction(s)?;
+    Ok((sensor, check_scale(correction)?))
+}
+
+fn check_scale(scale: Correction) -> Result<Correction, String> {
+    if scale.value <= 0.0 {
+        return Err(format!(
+            "scale must be greater than zero, got {}",
+            scale.value
+        ));
+    }
+    Ok(scale)
+}
+
+fn parse_correction(s: &str) -> Result<(SensorId, Correction), String> {
+    let expected = || format!("expected SENSOR/QUANTITY=VALUE, got {s:?}");
+    let (target, value) = s.split_once('=').ok_or_else(expected)?;
+    let (sensor, quantity) = target.split_once('/').ok_or_else(expected)?;
+    let sensor = parse_sensor(sensor)?;
+    let value = value
+        .trim()
+        .parse::<f32>()
+        .map_err(|_| format!("invalid number {value:?}"))?;
+    Ok((sensor, correction(quantity, value)?))
+}
+
+fn correction(quantity: &str, value: f32) -> Result<Correction, String> {
+    let quantity = match quantity.trim().parse::<Quantity>() {
+        Ok(quantity @ (Quantity::Temp | Quantity::Humidity)) => quantity,
+        _ => {
+            return Err(format!(
+                "invalid quantity {quantity:?} (expected `temp` or `humidity`)"
+            ))
+        }
+    };
+    if !value.is_finite() {
+        return Err(format!("invalid number {value}"));
+    }
+    Ok(Correction { quantity, value })
+}
+
+fn parse_compensation(s: &str) -> Result<(SensorId, Sources), String> {
+    let expected = || format!("expected SENSOR/QUANTITY=SOURCE[,SOURCE...], got {s:?}");
+    let (target, sources) = s.split_once('=').ok_or_else(expected)?;
+    let (sensor, quantity) = target.split_once('/').ok_or_else(expected)?;
+    let sensor = parse_sensor(sensor)?;
+    Ok((
+        sensor,
+        compensation_sources(sensor, quantity, sources.split(','))?,
+    ))
+}
+
+fn compensation_sources<'a>(
+    sensor: SensorId,
+    quantity: &str,
+    sources: impl Iterator<Item = &'a str>,
+) -> Result<Sources, String> {
+    let quantity = match quantity.trim().parse::<Quantity>() {
+        Ok(quantity @ (Quantity::Temp | Quantity::Humidity | Quantity::Pressure)) => quantity,
+        _ => {
+            return Err(format!(
+                "invalid quantity {quantity:?} (expected `temp`, `humidity`, or `pressure`)"
+            ))
+        }
+    };
+    let sources = sources
+        .map(|source| {
+            let source = source
+                .trim()
+                .parse::<SensorId>()
+                .map_err(|error| format!("invalid source sensor {source:?}: {error}"))?;
+            if source == sensor {
+                return Err(format!("{sensor} can't compensate for its own readings"));
+            }
+            Ok(source)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Sources {
+        quantity,
+        sensors: sources.leak(),
+    })
+}
+
+fn parse_i2c_address(addr: &str) -> Result<u8, String> {
+    let parsed = match addr.strip_prefix("0x").or_else(|| addr.strip_prefix("0X")) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => addr.parse::<u8>(),
+    };
+    parsed
+        .map_err(|_| format!("invalid 7-bit I²C address {addr:?}"))
+        .and_then(i2c_address)
+}
+
+fn i2c_address(address: u8) -> Result<u8, String> {
+    if address > 0x7f {
+        return Err(format!("invalid 7-bit I²C address {address:#x}"));
+    }
+    Ok(address)
+}
+
+fn parse_bus(s: &str) -> Result<(SensorId, PathBuf), String> {
+    let (sensor, path) = split_override(s)?;
+    Ok((sensor, PathBuf::from(path)))
+}
+
+fn parse_poll_interval(s: &str) -> Result<(SensorId, Duration), String> {
+    let (sensor, interval) = split_override(s)?;
+    Ok((sensor, poll_interval(interval)?))
+}
+
+fn poll_interval(interval: &str) -> Result<Duration, String> {
+    let interval = humantime::parse_duration(interval)
+        .map_err(|error| format!("invalid duration {interval:?}: {error}"))?;
+    if interval.is_zero() {
+        return Err("poll interval must be greater than zero".to_string());
+    }
+    Ok(interval)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use eclss_api::SensorName;
+
+    #[test]
+    fn parse_sensors() {
+        assert_eq!(
+            parse_sensor("sht41:floor").unwrap(),
+            "SHT41:floor".parse().unwrap()
+        );
+        assert!(parse_sensor("SHT42").is_err());
+        assert!(parse_sensor("FUSED").is_err());
+    }
+
+    #[test]
+    fn parse_overrides() {
+        assert_eq!(
+            parse_address("SHT41=0x45").unwrap(),
+            (SensorId::new(SensorName::Sht41), 0x45)
+        );
+        assert_eq!(
+            parse_address("bme680=118").unwrap(),
+            (SensorId::new(SensorName::Bme680), 0x76)
+        );
+        assert!(parse_address("SHT41=0x80").is_err());
+        assert!(parse_address("SHT42=0x45").is_err());
+        assert!(parse_address("SHT41").is_err());
+        assert!(parse_address("FUSED=0x45").is_err());
+        assert_eq!(
+            parse_address("SHT41:ceiling=0x45").unwrap(),
+            ("SHT41:ceiling".parse().unwrap(), 0x45)
+        );
+
+        assert_eq!(
+            parse_poll_interval("SCD41=30s").unwrap(),
+            (SensorId::new(SensorName::Scd41), Duration::from_secs(30))
+        );
+        assert!(parse_poll_interval("SCD41=0s").is_err());
+
+        assert_eq!(
+            parse_bus("SEN55=/dev/i2c-3").unwrap(),
+            (
+                SensorId::new(SensorName::Sen55),
+                PathBuf::from("/dev/i2c-3")
+            )
+        );
+    }
+
+    #[test]
+    fn parse_muxes() {
+        let sensor = "SHT41:left".parse::<SensorId>().unwrap();
+        assert_eq!(
+            parse_mux("SHT41:left=3").unwrap(),
+            (
+                sensor,
+                MuxChannel {
+                    channel: 3,
+                    address: 0x70
+                }
+            )
+        );
+        assert_eq!(
+            parse_mux("SHT41:left=7@0x71").unwrap(),
+            (
+                sensor,
+                MuxChannel {
+                    channel: 7,
+                    address: 0x71
+                }
+            )
+        );
+        assert!(parse_mux("SHT41=8").is_err());
+        assert!(parse_mux("SHT41=0@0x80").is_err());
+        assert!(parse_mux("SHT41=@0x70").is_err());
+    }
+
+    #[test]
+    fn parse_corrections() {
+        assert_eq!(
+            parse_offset("SCD41/temp=-2").unwrap(),
+            (
+                SensorId::new(SensorName::Scd41),
+                Correction {
+                    quantity: Quantity::Temp,
+                    value: -2.0
+                }
+            )
+        );
+        assert_eq!(
+            parse_scale("SHT41:ceiling/humidity=1.05").unwrap(),
+            (
+                "SHT41:ceiling".parse().unwrap(),
+                Correction {
+                    quantity: Quantity::Humidity,
+                    value: 1.05
+                }
+            )
+        );
+        assert!(parse_offset("SCD41=-2").is_err());
+        assert!(parse_offset("SCD41/co2=100").is_err());
+        assert!(parse_scale("SCD41/temp=0").is_err());
+        assert!(parse_offset("FUSED/temp=-2").is_err());
+    }
+
+    #[test]
+    fn calibration_from_corrections() {
+        let scd41 = SensorId::new(SensorName::Scd41);
+        let args = SensorArgs::parse_from([
+            "eclssd",
+            "--sensor-offset",
+            "SCD41/temp=-1",
+            "--sensor-offset",
+            "SCD41/temp=-2",
+            "--sensor-scale",
+            "SCD41/humidity=1.1",
+            "--sensor-offset",
+            "SHT41/temp=1",
+        ]);
+        let calibration = args.calibration(scd41);
+        assert_eq!(calibration.temp.offset, -2.0);
+        assert_eq!(calibration.temp.scale, 1.0);
+        assert_eq!(calibration.humidity.offset, 0.0);
+        assert_eq!(calibration.humidity.scale, 1.1);
+        assert!(args
+            .calibration(SensorId::new(SensorName::Sen55))
+            .is_identity());
+    }
+
+    #[test]
+    fn parse_compensations() {
+        let (sensor, sources) = parse_compensation("SGP30/humidity=SHT41:desk, BME680").unwrap();
+        assert_eq!(sensor, SensorId::new(SensorName::Sgp30));
+        assert_eq!(sources.quantity, Quantity::Humidity);
+        assert_eq!(
+            sources.sensors,
+            [
+                "SHT41:desk".parse().unwrap(),
+                SensorId::new(SensorName::Bme680)
+            ]
+        );
+        let (_, sources) = parse_compensation("SCD41/pressure=FUSED").unwrap();
+        assert_eq!(sources.sensors, [fusion::FUSED]);
+        assert!(parse_compensation("SGP30=SHT41").is_err());
+        assert!(parse_compensation("SGP30/co2=SCD41").is_err());
+        assert!(parse_compensation("SGP30/humidity=").is_err());
+        assert!(parse_compensation("SGP30/humidity=SHT41,SHT42").is_err());
+        assert!(parse_compensation("BME680/temp=BME680").is_err());
+        assert!(parse_compensation("FUSED/humidity=SHT41").is_err());
+    }
+
+    #[test]
+    fn compensation_from_args() {
+        let args = SensorArgs::parse_from([
+            "eclssd",
+            "--sensor-compensation",
+            "ENS160/temp=SCD41",
+            "--sensor-compensation",
+            "ENS160/temp=SHT41,SCD41",
+            "--sensor-compensation",
+            "SCD41/pressure=BME680",
+        ]);
+        let sht41 = SensorId::new(SensorName::Sht41);
+        let scd41 = SensorId::new(SensorName::Scd41);
+        let compensation = args.compensation(SensorId::new(SensorName::Ens160));
+        assert_eq!(compensation.temp, [sht41, scd41]);
+        assert!(compensation.humidity.is_empty());
+        assert!(compensation.pressure.is_empty());
+        assert_eq!(
+            args.compensation(scd41).pressure,
+            [SensorId::new(SensorName::Bme680)]
+        );
+        assert_eq!(args.compensation(sht41), Compensation::AUTOMATIC);
+    }
+
+    #[test]
+    fn later_overrides_win() {
+        let overrides = [
+            (SensorId::new(SensorName::Sht41), 0x44),
+            (SensorId::new(SensorName::Sht41), 0x45),
+        ];
+        assert_eq!(
+            find(&overrides, SensorId::new(SensorName::Sht41)),
+            Some(&0x45)
+        );
+        assert_eq!(find(&overrides, SensorId::new(SensorName::Scd41)), None);
+    }
+
+    fn merge_file(args: &[&str], file: &str) -> anyhow::Result<SensorArgs> {
+        let mut args =
+            SensorArgs::try_parse_from(std::iter::once("eclssd").chain(args.iter().copied()))?;
+        args.merge_config(toml::from_str(file)?)?;
+        Ok(args)
+    }
+
+    #[test]
+    fn file_settings() {
+        let sht41 = "SHT41:ceiling".parse::<SensorId>().unwrap();
+        let args = merge_file(
+            &["--sensor-address", "SHT41:ceiling=0x44"],
+            r#"
+            ["SHT41:ceiling"]
+            address = 0x45
+            bus = "/dev/i2c-3"
+            poll_interval = "5s"
+            mux = { channel = 2 }
+            offset = { temp = -1.5 }
+            scale = { humidity = 1.02 }
+            compensation = { pressure = ["BME680", "FUSED"] }
+            "#,
+        )
+        .unwrap();
+        // flags take precedence over the file.
+        assert_eq!(find(&args.addresses, sht41), Some(&0x44));
+        assert_eq!(find(&args.buses, sht41), Some(&PathBuf::from("/dev/i2c-3")));
+        assert_eq!(
+            find(&args.poll_intervals, sht41),
+            Some(&Duration::from_secs(5))
+        );
+        assert_eq!(
+            find(&args.muxes, sht41),
+            Some(&MuxChannel {
+                channel: 2,
+                address: 0x70
+            })
+        );
+        let calibration = args.calibration(sht41);
+        assert_eq!(calibration.temp.offset, -1.5);
+        assert_eq!(calibration.humidity.scale, 1.02);
+        assert_eq!(
+            args.compensation(sht41).pressure,
+            &[SensorId::new(SensorName::Bme680), fusion::FUSED]
+        );
+    }
+
+    #[test]
+    fn invalid_file_settings() {
+        for file in [
+            "[SHT41]\naddress = 0x80",
+            "[SHT41]\npoll_interval = \"0s\"",
+            "[SHT41]\nmux = { channel = 8 }",
+            "[SHT41]\noffset = { pressure = 1.0 }",
+            "[SHT41]\nscale = { temp = 0.0 }",
+            "[SHT41]\ncompensation = { humidity = [\"SHT41\"] }",
+            "[SHT41]\nbogus = 1",
+            "[FUSED]\naddress = 0x44",
+            "[SHT42]\naddress = 0x44",
+        ] {
+            assert!(merge_file(&[], file).is_err(), "{file}");
+        }
+    }
+}
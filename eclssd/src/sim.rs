@@ -0,0 +1,419 @@
+//! Simulation mode: runs the real sensor drivers against emulated sensors on a
+//! mock I²C bus, which report synthetic, slowly-varying readings.
+use eclss::mock::{self, MockI2c};
+use eclss_api::{SensorId, SensorName};
+use std::f64::consts::TAU;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, clap::Parser)]
+#[command(next_help_heading = "Simulation")]
+pub(super) struct SimArgs {
+    /// Run in simulation mode, with emulated sensors producing synthetic data
+    /// rather than real sensors on an I²C bus.
+    ///
+    /// The real sensor drivers run against a mock I²C bus, so the HTTP API
+    /// behaves exactly as it would on a real node. Only sensors that have an
+    /// emulation (SCD41, SGP30, SHT41, PMSA003I and SEN55) are enabled in
+    /// simulation mode.
+    #[clap(long = "sim", env = "ECLSS_SIM")]
+    pub(super) enabled: bool,
+
+    /// Length of one simulated day.
+    ///
+    /// This can be shortened in order to watch the diurnal curves change
+    /// more quickly.
+    #[clap(
+        long = "sim-day",
+        default_value = "24h",
+        value_parser = humantime::parse_duration,
+    )]
+    day: Duration,
+
+    /// How often to update the simulated readings.
+    #[clap(
+        long = "sim-interval",
+        default_value = "1s",
+        value_parser = humantime::parse_duration,
+    )]
+    interval: Duration,
+
+    /// Amount of random noise added to each reading, as a fraction of the
+    /// curve's amplitude.
+    #[clap(long = "sim-noise", default_value_t = 0.05)]
+    noise: f64,
+
+    /// Overrides the diurnal curve for a simulated quantity.
+    ///
+    /// Curves are formatted as `QUANTITY=MEAN:AMPLITUDE[:PEAK_HOUR]`, where
+    /// QUANTITY is one of `temp`, `humidity`, `co2`, `tvoc`, or `pm2_5`,
+    /// and PEAK_HOUR is the (UTC) hour of the simulated day at which the
+    /// quantity peaks. For example, `--sim-curve co2=800:400:19` simulates
+    /// CO₂ levels ranging from 400 to 1200 ppm, peaking at 19:00.
+    #[clap(long = "sim-curve", value_name = "CURVE")]
+    curves: Vec<CurveArg>,
+}
+
+/// A diurnal curve: a sine wave with a period of one simulated day.
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Curve {
+    mean: f64,
+    amplitude: f64,
+    peak_hour: f64,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Quantity {
+    Temp,
+    Humidity,
+    Co2,
+    Tvoc,
+    Pm2_5,
+}
+
+#[derive(Clone, Debug)]
+struct CurveArg {
+    quantity: Quantity,
+    curve: Curve,
+}
+
+#[derive(Debug)]
+struct Curves {
+    temp: Curve,
+    humidity: Curve,
+    co2: Curve,
+    tvoc: Curve,
+    pm2_5: Curve,
+}
+
+/// A simulated reading of every quantity, at one instant.
+#[derive(Copy, Clone, Debug)]
+struct Reading {
+    temp_c: f64,
+    humidity_percent: f64,
+    co2_ppm: f64,
+    tvoc_ppb: f64,
+    pm2_5: f64,
+}
+
+pub(super) struct Sim {
+    bus: MockI2c,
+    sensors: Vec<SensorId>,
+    /// The sensor models which are emulated on the bus. Multiple instances of
+    /// the same model share one emulated device.
+    models: Vec<SensorName>,
+    curves: Curves,
+    day: Duration,
+    interval: Duration,
+    noise: f64,
+    rng: XorShift,
+}
+
+impl SimArgs {
+    /// Builds a mock I²C bus with emulated devices for `sensors`.
+    pub(super) fn build(&self, sensors: &[SensorId]) -> Sim {
+        let bus = MockI2c::new();
+        let mut emulated = Vec::with_capacity(sensors.len());
+        let mut models = Vec::with_capacity(sensors.len());
+        for &sensor in sensors {
+            if models.contains(&sensor.name) {
+                emulated.push(sensor);
+                continue;
+            }
+            match sensor.name {
+                SensorName::Scd41 => bus.attach(mock::Scd41::ADDR, mock::Scd41::new()),
+                SensorName::Sgp30 => bus.attach(mock::Sgp30::ADDR, mock::Sgp30::new()),
+                SensorName::Sht41 => bus.attach(mock::Sht41::ADDR, mock::Sht41::new()),
+                SensorName::Pmsa003i => bus.attach(mock::Pmsa003i::ADDR, mock::Pmsa003i::new()),
+                SensorName::Sen55 => bus.attach(mock::Sen55::ADDR, mock::Sen55::new()),
+                _ => {
+                    tracing::warn!("{sensor} cannot be simulated, skipping it");
+                    continue;
+                }
+            }
+            emulated.push(sensor);
+            models.push(sensor.name);
+        }
+
+        let mut curves = Curves::default();
+        for &CurveArg { quantity, curve } in &self.curves {
+            *curves.get_mut(quantity) = curve;
+        }
+        tracing::info!(?curves, day = ?self.day, noise = self.noise, "simulating sensors: {emulated:?}");
+
+        let mut sim = Sim {
+            bus,
+            sensors: emulated,
+            models,
+            curves,
+            day: self.day,
+            interval: self.interval,
+            noise: self.noise,
+            rng: XorShift::from_time(),
+        };
+        // make sure readings are plausible before the first update.
+        sim.update();
+        sim
+    }
+}
+
+impl Sim {
+    pub(super) fn bus(&self) -> MockI2c {
+        self.bus.clone()
+    }
+
+    /// Returns the sensors which are being simulated.
+    pub(super) fn sensors(&self) -> &[SensorId] {
+        &self.sensors
+    }
+
+    /// Updates the simulated readings forever.
+    pub(super) async fn run(mut self) {
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            self.update();
+        }
+    }
+
+    fn update(&mut self) {
+        let day_fraction = {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let day = self.day.as_secs_f64().max(1.0);
+            (now.as_secs_f64() % day) / day
+        };
+        let base = self.curves.at(day_fraction);
+        tracing::trace!(?base, day_fraction, "updating simulated readings");
+
+        for sensor in self.models.clone() {
+            // each sensor sees the same underlying curves, plus its own noise,
+            // so that multiple sensors don't report identical values.
+            let r = self.noisy(base);
+            let bus = &self.bus;
+            match sensor {
+                SensorName::Scd41 => bus.device(mock::Scd41::ADDR, |d: &mut mock::Scd41| {
+                    d.co2_ppm = r.co2_ppm as u16;
+                    d.temp_c = r.temp_c as f32;
+                    d.humidity_percent = r.humidity_percent as f32;
+                }),
+                SensorName::Sgp30 => bus.device(mock::Sgp30::ADDR, |d: &mut mock::Sgp30| {
+                    d.tvoc_ppb = r.tvoc_ppb as u16;
+                    d.co2eq_ppm = (400.0 + r.tvoc_ppb * 2.0) as u16;
+                }),
+                SensorName::Sht41 => bus.device(mock::Sht41::ADDR, |d: &mut mock::Sht41| {
+                    d.temp_c = r.temp_c as f32;
+                    d.humidity_percent = r.humidity_percent as f32;
+                }),
+                SensorName::Pmsa003i => {
+                    bus.device(mock::Pmsa003i::ADDR, |d: &mut mock::Pmsa003i| {
+                        let pm = [r.pm2_5 * 0.7, r.pm2_5, r.pm2_5 * 1.3];
+                        d.concentrations = pm.map(|pm| pm as u16);
+                        d.concentrations_standard = pm.map(|pm| (pm * 1.1) as u16);
+                        // particle counts per 0.1L fall off with diameter.
+                        d.counts = [60.0, 18.0, 4.0, 0.8, 0.3, 0.1].map(|k| (r.pm2_5 * k) as u16);
+                    })
+                }
+                SensorName::Sen55 => bus.device(mock::Sen55::ADDR, |d: &mut mock::Sen55| {
+                    let pm2_5 = r.pm2_5 as f32;
+                    d.pm_conc = [pm2_5 * 0.7, pm2_5, pm2_5 * 1.15, pm2_5 * 1.3];
+                    d.pm_count = [
+                        pm2_5 * 5.0,
+                        pm2_5 * 5.8,
+                        pm2_5 * 6.0,
+                        pm2_5 * 6.1,
+                        pm2_5 * 6.1,
+                    ];
+                    d.temp_c = r.temp_c as f32;
+                    d.humidity_percent = r.humidity_percent as f32;
+                    // the VOC index is 100 for "typical" air, so scale it
+                    // relative to the mean tVOC concentration.
+                    d.voc_index =
+                        (100.0 * r.tvoc_ppb / self.curves.tvoc.mean).clamp(1.0, 500.0) as f32;
+                    d.nox_index = 1.0;
+                }),
+                _ => None,
+            };
+        }
+    }
+
+    fn noisy(&mut self, base: Reading) -> Reading {
+        let noise = self.noise;
+        let mut jitter = |value: f64, curve: &Curve, min: f64, max: f64| {
+            (value + self.rng.noise() * curve.amplitude * noise).clamp(min, max)
+        };
+        let Curves {
+            temp,
+            humidity,
+            co2,
+            tvoc,
+            pm2_5,
+        } = &self.curves;
+        Reading {
+            temp_c: jitter(base.temp_c, temp, -40.0, 85.0),
+            humidity_percent: jitter(base.humidity_percent, humidity, 0.0, 100.0),
+            co2_ppm: jitter(base.co2_ppm, co2, 400.0, 40_000.0),
+            tvoc_ppb: jitter(base.tvoc_ppb, tvoc, 0.0, 59_999.0),
+            pm2_5: jitter(base.pm2_5, pm2_5, 0.0, 1000.0),
+        }
+    }
+}
+
+// === impl Curves ===
+
+impl Curves {
+    fn get_mut(&mut self, quantity: Quantity) -> &mut Curve {
+        match quantity {
+            Quantity::Temp => &mut self.temp,
+            Quantity::Humidity => &mut self.humidity,
+            Quantity::Co2 => &mut self.co2,
+            Quantity::Tvoc => &mut self.tvoc,
+            Quantity::Pm2_5 => &mut self.pm2_5,
+        }
+    }
+
+    fn at(&self, day_fraction: f64) -> Reading {
+        Reading {
+            temp_c: self.temp.at(day_fraction),
+            humidity_percent: self.humidity.at(day_fraction),
+            co2_ppm: self.co2.at(day_fraction),
+            tvoc_ppb: self.tvoc.at(day_fraction),
+            pm2_5: self.pm2_5.at(day_fraction),
+        }
+    }
+}
+
+impl Default for Curves {
+    fn default() -> Self {
+        Self {
+            // warmest in the mid-afternoon...
+            temp: Curve::new(22.0, 2.5, 15.0),
+            // ...when relative humidity is lowest.
+            humidity: Curve::new(45.0, 10.0, 3.0),
+            // CO₂ and VOCs build up in the evening, when people are home.
+            co2: Curve::new(800.0, 350.0, 20.0),
+            tvoc: Curve::new(150.0, 100.0, 19.0),
+            // cooking!
+            pm2_5: Curve::new(8.0, 5.0, 18.5),
+        }
+    }
+}
+
+// === impl Curve ===
+
+impl Curve {
+    const fn new(mean: f64, amplitude: f64, peak_hour: f64) -> Self {
+        Self {
+            mean,
+            amplitude,
+            peak_hour,
+        }
+    }
+
+    fn at(&self, day_fraction: f64) -> f64 {
+        let phase = day_fraction - (self.peak_hour / 24.0);
+        self.mean + self.amplitude * (TAU * phase).cos()
+    }
+}
+
+impl FromStr for CurveArg {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (quantity, curve) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("expected QUANTITY=MEAN:AMPLITUDE[:PEAK_HOUR]"))?;
+        let quantity = match quantity.trim() {
+            "temp" => Quantity::Temp,
+            "humidity" => Quantity::Humidity,
+            "co2" => Quantity::Co2,
+            "tvoc" => Quantity::Tvoc,
+            "pm2_5" => Quantity::Pm2_5,
+            q => anyhow::bail!(
+                "unknown quantity {q:?}, expected one of: temp, humidity, co2, tvoc, pm2_5"
+            ),
+        };
+        let mut parts = curve.split(':').map(|part| part.trim().parse::<f64>());
+        let mut next = |name: &str| -> anyhow::Result<Option<f64>> {
+            parts
+                .next()
+                .transpose()
+                .map_err(|e| anyhow::anyhow!("invalid {name}: {e}"))
+        };
+        let mean = next("mean")?.ok_or_else(|| anyhow::anyhow!("missing mean"))?;
+        let amplitude = next("amplitude")?.ok_or_else(|| anyhow::anyhow!("missing amplitude"))?;
+        let peak_hour = next("peak hour")?.unwrap_or(12.0);
+        anyhow::ensure!(
+            (0.0..24.0).contains(&peak_hour),
+            "peak hour must be between 0 and 24"
+        );
+        anyhow::ensure!(next("")?.is_none(), "too many curve parameters");
+        Ok(Self {
+            quantity,
+            curve: Curve::new(mean, amplitude, peak_hour),
+        })
+    }
+}
+
+/// A tiny xorshift PRNG, so that we don't need a dependency on `rand` just to
+/// make some noise.
+#[derive(Debug)]
+struct XorShift(u64);
+
+impl XorShift {
+    fn from_time() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        // the state must be non-zero
+        Self(seed | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+
+    /// Returns approximately normally distributed noise with a mean of 0 and
+    /// a standard deviation of 1.
+    fn noise(&mut self) -> f64 {
+        // Irwin-Hall: the sum of 12 uniform samples in [0, 1) has a variance of 1.
+        let sum: f64 = (0..12)
+            .map(|_| (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64)
+            .sum();
+        sum - 6.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_peaks_at_peak_hour() {
+        let curve = Curve::new(800.0, 400.0, 18.0);
+        assert_eq!(curve.at(18.0 / 24.0), 1200.0);
+        assert!((curve.at(6.0 / 24.0) - 400.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn parse_curve() {
+        let CurveArg { quantity, curve } = "co2=800:400:19".parse().unwrap();
+        assert_eq!(quantity, Quantity::Co2);
+        assert_eq!(curve, Curve::new(800.0, 400.0, 19.0));
+
+        let CurveArg { quantity, curve } = "temp = 21.5:1".parse().unwrap();
+        assert_eq!(quantity, Quantity::Temp);
+        assert_eq!(curve, Curve::new(21.5, 1.0, 12.0));
+
+        assert!("co2=800".parse::<CurveArg>().is_err());
+        assert!("radon=1:1".parse::<CurveArg>().is_err());
+        assert!("co2=800:400:25".parse::<CurveArg>().is_err());
+    }
+}
@@ -1,11 +1,14 @@
 use anyhow::Context;
-use eclss::storage::Store;
-use eclss_api::SensorName;
-use serde::{de::DeserializeOwned, Serialize};
+use eclss::storage::{SensorState, Store};
+use eclss_api::SensorId;
+use serde::Serialize;
+use std::cmp::Ordering;
 use std::future::Future;
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tokio::fs::{File, OpenOptions};
+use tokio::fs::File;
+use tokio::io::AsyncWriteExt;
 
 #[derive(Debug, clap::Parser)]
 pub(super) struct StorageArgs {
@@ -15,11 +18,11 @@ pub(super) struct StorageArgs {
         env = "STATE_DIRECTORY",
         default_value = "/var/lib/eclss"
     )]
-    path: PathBuf,
+    pub(super) path: PathBuf,
 }
 
 impl StorageArgs {
-    pub(super) async fn ensure_state_dir(self) -> anyhow::Result<StateDir> {
+    pub(super) async fn ensure_state_dir(&self) -> anyhow::Result<StateDir> {
         tokio::fs::create_dir_all(&self.path)
             .await
             .with_context(|| {
@@ -29,7 +32,7 @@ impl StorageArgs {
                 )
             })?;
         Ok(StateDir {
-            path: Arc::new(self.path),
+            path: Arc::new(self.path.clone()),
         })
     }
 }
@@ -40,51 +43,320 @@ pub(super) struct StateDir {
 }
 
 impl StateDir {
+    /// Returns the path of a file in the state directory.
+    pub(super) fn file(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
     pub(super) fn sensor_state(
         &self,
-        sensor: SensorName,
+        sensor: SensorId,
     ) -> impl Future<Output = anyhow::Result<StateFile>> + Send + Sync + 'static {
         let path = self.path.join(format!("{sensor}.toml"));
         async move {
-            let file = OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .truncate(false)
-                .open(&path)
-                .await
-                .with_context(|| format!("failed to open {}", path.display()))?;
-            Ok(StateFile { file, path })
+            // if we were killed while saving the sensor's state, the state file
+            // itself is intact, but the incomplete temporary file is left over.
+            let tmp = tmp_path(&path);
+            match tokio::fs::remove_file(&tmp).await {
+                Ok(()) => tracing::debug!(path = %tmp.display(), "removed incomplete state file"),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+                Err(error) => {
+                    return Err(error)
+                        .with_context(|| format!("failed to remove {}", tmp.display()))
+                }
+            }
+            Ok(StateFile { path, newer: false })
         }
     }
 }
 
+/// A sensor's state file.
+///
+/// State files are TOML documents with a `version` key, which is the version
+/// of the format of the rest of the document. State saved in an older format
+/// is [migrated] when it's loaded. If a state file can't be parsed, it's
+/// quarantined by renaming it to `<name>.toml.corrupt.<timestamp>`, and the
+/// sensor starts over without its saved state.
+///
+/// State saved in a newer format, by a newer version of eclssd, is left in
+/// place and isn't overwritten, so that it's still there if eclssd is upgraded
+/// again.
+///
+/// [migrated]: eclss::storage::SensorState::migrate
 pub(super) struct StateFile {
-    file: File,
     path: PathBuf,
+    /// Set if the state file is in a newer format than this version of eclssd
+    /// supports.
+    newer: bool,
+}
+
+/// A sensor's state, tagged with the version of its format.
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    version: u32,
+    #[serde(flatten)]
+    state: &'a T,
+}
+
+/// State files written before the format was versioned are version 1.
+const UNVERSIONED: u32 = 1;
+
+impl StateFile {
+    /// Parses the contents of a state file, migrating it if it's in an older
+    /// format, or returning `None` if it's in a newer one.
+    fn parse<T: SensorState>(&self, buf: &str) -> anyhow::Result<Option<T>> {
+        let mut table = buf.parse::<toml::Table>()?;
+        let version = match table.remove("version") {
+            Some(toml::Value::Integer(version)) => {
+                u32::try_from(version).context("invalid format version")?
+            }
+            Some(version) => anyhow::bail!("invalid format version {version}"),
+            None => UNVERSIONED,
+        };
+        let state = toml::Value::Table(table);
+        match version.cmp(&T::VERSION) {
+            Ordering::Equal => Ok(Some(T::deserialize(state)?)),
+            Ordering::Less => {
+                let state = T::migrate(version, state)?;
+                tracing::info!(
+                    path = %self.path.display(),
+                    "migrated state file from format version {version} to {}",
+                    T::VERSION
+                );
+                Ok(Some(state))
+            }
+            Ordering::Greater => {
+                tracing::warn!(
+                    path = %self.path.display(),
+                    "state file format version {version} is newer than the supported \
+                    version {}, starting over without it",
+                    T::VERSION
+                );
+                Ok(None)
+            }
+        }
+    }
+
+    /// Moves a state file which can't be loaded out of the way, so that it can
+    /// be inspected later.
+    async fn quarantine(&self) -> anyhow::Result<PathBuf> {
+        let mut quarantined = self.path.clone().into_os_string();
+        quarantined.push(format!(".corrupt.{}", crate::unix_now()));
+        let quarantined = PathBuf::from(quarantined);
+        tokio::fs::rename(&self.path, &quarantined)
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to rename {} to {}",
+                    self.path.display(),
+                    quarantined.display()
+                )
+            })?;
+        Ok(quarantined)
+    }
 }
 
 impl Store for StateFile {
     type Error = anyhow::Error;
-    async fn load<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Self::Error> {
-        let mut buf = String::new();
-        tokio::io::AsyncReadExt::read_to_string(&mut self.file, &mut buf)
-            .await
-            .with_context(|| format!("failed to read state file {}", self.path.display()))?;
+    async fn load<T: SensorState>(&mut self) -> Result<Option<T>, Self::Error> {
+        let buf = match tokio::fs::read_to_string(&self.path).await {
+            Ok(buf) => buf,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to read state file {}", self.path.display()))
+            }
+        };
 
-        if buf.is_empty() {
-            return Ok(None);
+        match self.parse(&buf) {
+            Ok(Some(state)) => Ok(Some(state)),
+            Ok(None) => {
+                self.newer = true;
+                Ok(None)
+            }
+            Err(error) => {
+                let quarantined = self.quarantine().await?;
+                tracing::warn!(
+                    path = %self.path.display(),
+                    quarantined = %quarantined.display(),
+                    "failed to load state file, starting over: {error:#}"
+                );
+                Ok(None)
+            }
         }
+    }
 
-        toml::from_str::<T>(&buf)
-            .map(Some)
-            .with_context(|| format!("failed to parse state file {}", self.path.display()))
+    async fn store<T: SensorState>(&mut self, state: &T) -> Result<(), Self::Error> {
+        if self.newer {
+            tracing::debug!(
+                path = %self.path.display(),
+                "not overwriting state file in a newer format"
+            );
+            return Ok(());
+        }
+        let versioned = Versioned {
+            version: T::VERSION,
+            state,
+        };
+        let buf = toml::to_string_pretty(&versioned).context("failed to serialize state")?;
+        write_atomic(&self.path, buf.as_bytes()).await
     }
+}
 
-    async fn store<T: Serialize>(&mut self, state: &T) -> Result<(), Self::Error> {
-        let buf = toml::to_string_pretty(&state).context("failed to serialize state")?;
-        tokio::io::AsyncWriteExt::write_all(&mut self.file, buf.as_bytes())
+/// Replaces the contents of the file at `path` with `buf`.
+///
+/// The new contents are written to a temporary file which is renamed over
+/// `path`, and both are synced to disk, so that the file is left intact if
+/// we're killed or lose power while writing it.
+pub(super) async fn write_atomic(path: &Path, buf: &[u8]) -> anyhow::Result<()> {
+    let tmp = tmp_path(path);
+    let mut file = File::create(&tmp)
+        .await
+        .with_context(|| format!("failed to create {}", tmp.display()))?;
+    file.write_all(buf)
+        .await
+        .with_context(|| format!("failed to write to {}", tmp.display()))?;
+    file.sync_all()
+        .await
+        .with_context(|| format!("failed to sync {}", tmp.display()))?;
+    drop(file);
+    tokio::fs::rename(&tmp, path)
+        .await
+        .with_context(|| format!("failed to rename {} to {}", tmp.display(), path.display()))?;
+    // the rename is only durable once the directory has been synced, too.
+    if let Some(dir) = path.parent() {
+        File::open(dir)
+            .await
+            .with_context(|| format!("failed to open {}", dir.display()))?
+            .sync_all()
             .await
-            .with_context(|| format!("failed to write to state file {}", self.path.display()))
+            .with_context(|| format!("failed to sync {}", dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Returns the path of the temporary file used to write the file at `path`.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Deserializer};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Baseline {
+        co2eq: u16,
+        tvoc: u16,
+    }
+
+    impl SensorState for Baseline {
+        const VERSION: u32 = 2;
+
+        fn migrate<'de, D: Deserializer<'de>>(version: u32, state: D) -> Result<Self, D::Error> {
+            #[derive(Deserialize)]
+            struct V1 {
+                eco2: u16,
+                tvoc: u16,
+            }
+            assert_eq!(version, 1);
+            let V1 { eco2, tvoc } = V1::deserialize(state)?;
+            Ok(Self { co2eq: eco2, tvoc })
+        }
+    }
+
+    /// Returns a new, empty state directory for a test.
+    async fn state_dir(test: &str) -> StateDir {
+        let path = std::env::temp_dir().join(format!("eclssd-{test}-{}", std::process::id()));
+        let _ = tokio::fs::remove_dir_all(&path).await;
+        StorageArgs { path }.ensure_state_dir().await.unwrap()
+    }
+
+    fn sgp30() -> SensorId {
+        SensorId::new(eclss_api::SensorName::Sgp30)
+    }
+
+    #[tokio::test]
+    async fn stores_and_loads_state() {
+        let dir = state_dir("stores-and-loads").await;
+        let mut file = dir.sensor_state(sgp30()).await.unwrap();
+        assert_eq!(file.load::<Baseline>().await.unwrap(), None);
+
+        for tvoc in [1, 2] {
+            let state = Baseline { co2eq: 400, tvoc };
+            file.store(&state).await.unwrap();
+            let mut file = dir.sensor_state(sgp30()).await.unwrap();
+            assert_eq!(file.load::<Baseline>().await.unwrap(), Some(state));
+        }
+
+        let buf = std::fs::read_to_string(dir.file("SGP30.toml")).unwrap();
+        assert_eq!(buf, "version = 2\nco2eq = 400\ntvoc = 2\n");
+        assert!(!dir.file("SGP30.toml.tmp").exists());
+    }
+
+    #[tokio::test]
+    async fn migrates_old_state() {
+        let dir = state_dir("migrates").await;
+        // state files written before the format was versioned are version 1.
+        std::fs::write(dir.file("SGP30.toml"), "eco2 = 400\ntvoc = 3\n").unwrap();
+        let mut file = dir.sensor_state(sgp30()).await.unwrap();
+        assert_eq!(
+            file.load::<Baseline>().await.unwrap(),
+            Some(Baseline {
+                co2eq: 400,
+                tvoc: 3
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn leaves_newer_state() {
+        let dir = state_dir("leaves-newer").await;
+        let buf = "version = 3\nco2eq = 400\ntvoc = 3\n";
+        std::fs::write(dir.file("SGP30.toml"), buf).unwrap();
+        let mut file = dir.sensor_state(sgp30()).await.unwrap();
+        assert_eq!(file.load::<Baseline>().await.unwrap(), None);
+        file.store(&Baseline {
+            co2eq: 400,
+            tvoc: 4,
+        })
+        .await
+        .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.file("SGP30.toml")).unwrap(),
+            buf
+        );
+        let quarantined = std::fs::read_dir(dir.file(""))
+            .unwrap()
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .any(|name| name.contains(".corrupt."));
+        assert!(!quarantined);
+    }
+
+    #[tokio::test]
+    async fn quarantines_corrupt_state() {
+        let corrupt = [
+            ("torn", "co2eq = 400\ntvo"),
+            ("wrong-type", "version = 2\nco2eq = \"400\"\ntvoc = 3\n"),
+        ];
+        for (test, buf) in corrupt {
+            let dir = state_dir(&format!("quarantines-{test}")).await;
+            std::fs::write(dir.file("SGP30.toml"), buf).unwrap();
+            let mut file = dir.sensor_state(sgp30()).await.unwrap();
+            assert_eq!(file.load::<Baseline>().await.unwrap(), None, "{test}");
+            assert!(!dir.file("SGP30.toml").exists(), "{test}");
+
+            let quarantined = std::fs::read_dir(dir.file(""))
+                .unwrap()
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| name.starts_with("SGP30.toml.corrupt."))
+                .collect::<Vec<_>>();
+            assert_eq!(quarantined.len(), 1, "{test}");
+            let contents = std::fs::read_to_string(dir.file(&quarantined[0])).unwrap();
+            assert_eq!(contents, buf, "{test}");
+        }
     }
 }
@@ -0,0 +1,417 @@
+//! Supervision of sensor tasks, so that sensors can be hot-plugged.
+//!
+//! The supervisor starts a task for each enabled sensor, and periodically
+//! checks on them:
+//!
+//! - A sensor which has not responded for two consecutive checks is assumed to
+//!   have been unplugged. Its task is stopped, and it's marked as missing in
+//!   the sensor registry.
+//! - Missing sensors are probed for at every check, and their tasks are
+//!   started again once they are detected.
+//! - If a sensor's task fails, it's restarted after a backoff, unless the
+//!   sensor was never reached, in which case it's marked as missing.
+//! - If sensors were detected automatically at startup, addresses which no
+//!   sensor is using are probed at every check, and any new sensors that are
+//!   found are started. New sensors are named like the sensors detected at
+//!   startup (see [`crate::probe::detected_id`]).
+//!
+//! When the daemon is shutting down, the supervisor signals every sensor task
+//! to put its sensor into a low-power state, and waits for them to finish.
+use crate::{sensors::SensorArgs, storage::StateDir, Bus, GoodDelay};
+use anyhow::Context;
+use eclss::{
+    probe,
+    sensor::{Registry, SensorConfig, SensorId, SensorName, State, Status},
+    Eclss,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time::MissedTickBehavior;
+
+#[derive(Debug, clap::Parser)]
+#[command(next_help_heading = "Sensor Supervision")]
+pub(super) struct SupervisorArgs {
+    /// How often to check for sensors which have been unplugged, and to probe
+    /// for sensors which are missing or have been plugged in.
+    #[clap(
+        long = "rediscover-interval",
+        env = "ECLSS_REDISCOVER_INTERVAL",
+        default_value = "30s",
+        value_parser = humantime::parse_duration,
+    )]
+    rediscover_interval: Duration,
+
+    /// How long to wait for sensors to shut down when the daemon is stopped.
+    #[clap(
+        long = "shutdown-timeout",
+        env = "ECLSS_SHUTDOWN_TIMEOUT",
+        default_value = "10s",
+        value_parser = humantime::parse_duration,
+    )]
+    shutdown_timeout: Duration,
+}
+
+pub(super) struct Supervisor {
+    sensors: HashMap<SensorId, Supervised>,
+    tasks: Tasks,
+    interval: Duration,
+    shutdown_timeout: Duration,
+    /// If sensors were detected automatically, the overrides used to configure
+    /// newly detected sensors.
+    discover: Option<SensorArgs>,
+}
+
+struct Tasks {
+    eclss: &'static Eclss<Bus, 16>,
+    state_dir: StateDir,
+    config: eclss::Config,
+    set: JoinSet<(SensorId, anyhow::Result<()>)>,
+}
+
+struct Supervised {
+    config: SensorConfig<Bus>,
+    task: Task,
+    /// The number of times the sensor's task has been restarted since the
+    /// sensor was last up.
+    restarts: u32,
+}
+
+enum Task {
+    /// The sensor's task is running, or waiting to be restarted.
+    Running {
+        handle: AbortHandle,
+        /// The number of consecutive checks at which the sensor was not
+        /// present.
+        absent_checks: u32,
+    },
+    /// The sensor is not connected to the bus, so it has no task.
+    Missing,
+}
+
+/// The number of consecutive checks at which a sensor must not be present
+/// before it's considered to have been unplugged.
+const ABSENT_CHECKS: u32 = 2;
+
+impl SupervisorArgs {
+    pub(super) fn build(
+        &self,
+        eclss: &'static Eclss<Bus, 16>,
+        state_dir: &StateDir,
+        config: &eclss::Config,
+    ) -> Supervisor {
+        Supervisor {
+            sensors: HashMap::new(),
+            tasks: Tasks {
+                eclss,
+                state_dir: state_dir.clone(),
+                config: config.clone(),
+                set: JoinSet::new(),
+            },
+            interval: self.rediscover_interval,
+            shutdown_timeout: self.shutdown_timeout,
+            discover: None,
+        }
+    }
+}
+
+impl Supervisor {
+    /// Starts supervising `sensor`.
+    pub(super) fn start(&mut self, sensor: SensorId, config: SensorConfig<Bus>) {
+        let handle = self.tasks.spawn(sensor, config, Duration::ZERO);
+        self.sensors.insert(
+            sensor,
+            Supervised {
+                config,
+                task: Task::Running {
+                    handle,
+                    absent_checks: 0,
+                },
+                restarts: 0,
+            },
+        );
+    }
+
+    /// Probe for new sensors on the default bus at every check, configuring
+    /// them using `overrides`.
+    pub(super) fn discover_new_sensors(&mut self, overrides: SensorArgs) {
+        self.discover = Some(overrides);
+    }
+
+    /// Supervises sensors until `shutdown` completes, and then shuts them
+    /// down.
+    ///
+    /// This returns an error if a sensor task panics.
+    pub(super) async fn run(mut self, shutdown: impl Future<Output = ()>) -> anyhow::Result<()> {
+        let mut shutdown = std::pin::pin!(shutdown);
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // the first tick completes immediately, and the sensors have only
+        // just been started.
+        interval.tick().await;
+        loop {
+            let joined = tokio::select! {
+                _ = &mut shutdown => break,
+                _ = interval.tick() => None,
+                Some(joined) = self.tasks.set.join_next() => Some(joined),
+            };
+            match joined {
+                None => self.check().await,
+                Some(Ok((sensor, result))) => self.exited(sensor, result),
+                // tasks are only cancelled when their sensor is missing.
+                Some(Err(error)) if error.is_cancelled() => {}
+                Some(Err(error)) => return Err(error).context("a sensor task panicked"),
+            }
+        }
+
+        self.shutdown().await;
+        Ok(())
+    }
+
+    /// Signals all sensor tasks to shut down, and waits for them to finish.
+    async fn shutdown(&mut self) {
+        let timeout = self.shutdown_timeout;
+        tracing::info!(?timeout, "shutting down sensors...");
+        self.tasks.eclss.shutdown();
+        let set = &mut self.tasks.set;
+        let finished = tokio::time::timeout(timeout, async {
+            while let Some(joined) = set.join_next().await {
+                match joined {
+                    Ok((sensor, Ok(()))) => tracing::debug!(%sensor, "{sensor} stopped"),
+                    Ok((sensor, Err(error))) => {
+                        tracing::warn!(%sensor, "{sensor} failed while shutting down: {error:#}")
+                    }
+                    Err(error) if error.is_cancelled() => {}
+                    Err(error) => {
+                        tracing::warn!("a sensor task panicked while shutting down: {error}")
+                    }
+                }
+            }
+        })
+        .await;
+        if finished.is_err() {
+            tracing::warn!(
+                remaining = self.tasks.set.len(),
+                "sensors did not shut down within {timeout:?}"
+            );
+        }
+    }
+
+    async fn check(&mut self) {
+        let eclss = self.tasks.eclss;
+        let mut found = Vec::new();
+        for (&sensor, supervised) in &mut self.sensors {
+            match supervised.task {
+                Task::Running {
+                    ref handle,
+                    ref mut absent_checks,
+                } => {
+                    // if the sensor isn't registered, its task hasn't started.
+                    let Some(state) = state(eclss.sensors(), sensor) else {
+                        continue;
+                    };
+                    let status = state.status();
+                    if status == Status::Up {
+                        supervised.restarts = 0;
+                    }
+                    if status.is_present() {
+                        *absent_checks = 0;
+                        continue;
+                    }
+                    *absent_checks += 1;
+                    if *absent_checks >= ABSENT_CHECKS {
+                        tracing::warn!(
+                            %sensor,
+                            "{sensor} is not responding, stopping it until it's detected again"
+                        );
+                        handle.abort();
+                        state.set_missing();
+                        supervised.task = Task::Missing;
+                    }
+                }
+                Task::Missing => {
+                    let Some(address) = expected_address(sensor, &supervised.config) else {
+                        continue;
+                    };
+                    let mut i2c = supervised.config.bus.unwrap_or(eclss.bus());
+                    let detected =
+                        probe::identify(&mut i2c, &mut GoodDelay::default(), address).await;
+                    if detected.is_some_and(|name| same_model(name, sensor.name)) {
+                        tracing::info!(%sensor, "detected {sensor} at {address:#04x}, starting it");
+                        found.push(sensor);
+                    }
+                }
+            }
+        }
+
+        for sensor in found {
+            let supervised = self.sensors.get_mut(&sensor).expect("sensor was found");
+            supervised.restarts = 0;
+            supervised.task = Task::Running {
+                handle: self.tasks.spawn(sensor, supervised.config, Duration::ZERO),
+                absent_checks: 0,
+            };
+        }
+
+        self.discover().await;
+    }
+
+    /// Probes for new sensors at the addresses on the default bus which are not
+    /// used by any supervised sensor.
+    async fn discover(&mut self) {
+        let Some(ref overrides) = self.discover else {
+            return;
+        };
+        let mut i2c = self.tasks.eclss.bus();
+        let mut found = Vec::new();
+        for &address in probe::ADDRESSES {
+            let in_use = self.sensors.iter().any(|(&sensor, supervised)| {
+                supervised.config.bus.is_none()
+                    && expected_address(sensor, &supervised.config) == Some(address)
+            });
+            if in_use {
+                continue;
+            }
+            let Some(name) = probe::identify(&mut i2c, &mut GoodDelay::default(), address).await
+            else {
+                continue;
+            };
+            if !crate::probe::is_enabled(name) {
+                continue;
+            }
+            let sensor = crate::probe::detected_id(name, address);
+            if self.sensors.contains_key(&sensor) {
+                continue;
+            }
+            tracing::info!(%sensor, "new sensor {name} detected at {address:#04x}");
+            found.push((sensor, overrides.detected_config(sensor, address)));
+        }
+
+        for (sensor, config) in found {
+            self.start(sensor, config);
+        }
+    }
+
+    fn exited(&mut self, sensor: SensorId, result: anyhow::Result<()>) {
+        let Some(supervised) = self.sensors.get_mut(&sensor) else {
+            return;
+        };
+        let error = match result {
+            Ok(()) => anyhow::anyhow!("task exited"),
+            Err(error) => error,
+        };
+        match state(self.tasks.eclss.sensors(), sensor) {
+            Some(state) if !state.status().is_present() => {
+                tracing::warn!(
+                    %sensor,
+                    "{sensor} was not found, waiting until it's detected: {error:#}"
+                );
+                state.set_missing();
+                supervised.task = Task::Missing;
+            }
+            _ => {
+                let backoff = self.tasks.backoff(supervised.restarts);
+                supervised.restarts += 1;
+                tracing::warn!(
+                    %sensor,
+                    restart_in = ?backoff,
+                    "{sensor} failed, restarting it: {error:#}"
+                );
+                supervised.task = Task::Running {
+                    handle: self.tasks.spawn(sensor, supervised.config, backoff),
+                    absent_checks: 0,
+                };
+            }
+        }
+    }
+}
+
+impl Tasks {
+    /// Spawns a task running `sensor` after waiting for `delay`.
+    fn spawn(
+        &mut self,
+        sensor: SensorId,
+        config: SensorConfig<Bus>,
+        delay: Duration,
+    ) -> AbortHandle {
+        let eclss = self.eclss;
+        let task = crate::run_sensor(eclss, &self.state_dir, &self.config, config, sensor);
+        self.set.spawn(async move {
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                // if the daemon is shutting down, the sensor task will exit
+                // immediately, so don't hold up shutdown by waiting to start it.
+                _ = eclss.wait_for_shutdown() => {}
+            }
+            (sensor, task.await)
+        })
+    }
+
+    /// Returns how long to wait before restarting a sensor which has been
+    /// restarted `restarts` times since it was last up.
+    fn backoff(&self, restarts: u32) -> Duration {
+        let retries = &self.config.retries;
+        retries
+            .initial_backoff
+            .saturating_mul(1u32 << restarts.min(16))
+            .min(retries.max_backoff)
+    }
+}
+
+fn state(registry: &'static Registry<16>, sensor: SensorId) -> Option<&'static State> {
+    registry
+        .iter()
+        .find(|(id, _)| **id == sensor)
+        .map(|(_, state)| state)
+}
+
+/// Returns the address at which `sensor` is expected.
+fn expected_address(sensor: SensorId, config: &SensorConfig<Bus>) -> Option<u8> {
+    config
+        .address
+        .or_else(|| probe::addresses(sensor.name).first().copied())
+}
+
+/// Returns `true` if a `detected` sensor model can be run as the `expected`
+/// model.
+///
+/// SCD40s and SCD41s are interchangeable, because older SCD4x firmware can't
+/// report which variant it is.
+fn same_model(detected: SensorName, expected: SensorName) -> bool {
+    detected == expected
+        || matches!(
+            (detected, expected),
+            (
+                SensorName::Scd40 | SensorName::Scd41,
+                SensorName::Scd40 | SensorName::Scd41
+            )
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_addresses() {
+        let sht41 = SensorId::new(SensorName::Sht41);
+        assert_eq!(
+            expected_address(sht41, &SensorConfig::default()),
+            Some(0x44)
+        );
+        let config = SensorConfig {
+            address: Some(0x45),
+            ..Default::default()
+        };
+        assert_eq!(expected_address(sht41, &config), Some(0x45));
+    }
+
+    #[test]
+    fn scd4x_variants_are_interchangeable() {
+        assert!(same_model(SensorName::Scd41, SensorName::Scd40));
+        assert!(same_model(SensorName::Scd40, SensorName::Scd41));
+        assert!(same_model(SensorName::Sht41, SensorName::Sht41));
+        assert!(!same_model(SensorName::Scd30, SensorName::Scd41));
+    }
+}
@@ -148,7 +148,7 @@
               description = ''
                 A list of sensors to explicitly enable, or an empty list to enable all supported sensors.
 
-                If this is null, the ECLSS daemon will attempt to use all supported sensors.
+                If this is empty, the ECLSS daemon will scan the I2C bus and use all supported sensors that it detects.
               '';
             };
 
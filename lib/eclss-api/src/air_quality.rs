@@ -0,0 +1,370 @@
+//! Air quality indices computed from raw sensor readings.
+//!
+//! Concentrations in µg/m³, ppm, or ppb don't mean much to most people, so
+//! these translate them into the scales used by public health agencies and
+//! building standards:
+//!
+//! - [`aqi_pm2_5`] and [`aqi_pm10`] compute the [US EPA Air Quality Index][aqi]
+//!   from particulate matter concentrations, and [`AqiCategory`] describes the
+//!   resulting index.
+//! - [`Co2Band`] classifies CO₂ concentrations by how well-ventilated a room
+//!   is, based on the indoor air classes in EN 13779.
+//! - [`IaqCategory`] classifies total volatile organic compound (tVOC)
+//!   concentrations, using the levels recommended by the German Federal
+//!   Environment Agency (UBA), as converted to ppb by Sensirion.
+//!
+//! Each index is computed from a single reading, rather than the long-term
+//! averages the standards specify, so they describe the air right now rather
+//! than a regulatory measurement.
+//!
+//! [aqi]: https://www.airnow.gov/aqi/aqi-basics/
+
+/// A breakpoint in the AQI table: concentrations from `lo` to `hi` map onto
+/// indices from `aqi_lo` to `aqi_hi`.
+struct Breakpoint {
+    lo: f64,
+    hi: f64,
+    aqi_lo: f64,
+    aqi_hi: f64,
+}
+
+const fn bp(lo: f64, hi: f64, aqi_lo: u16, aqi_hi: u16) -> Breakpoint {
+    Breakpoint {
+        lo,
+        hi,
+        aqi_lo: aqi_lo as f64,
+        aqi_hi: aqi_hi as f64,
+    }
+}
+
+/// PM2.5 breakpoints in µg/m³, as revised by the EPA in 2024.
+const PM2_5: &[Breakpoint] = &[
+    bp(0.0, 9.0, 0, 50),
+    bp(9.1, 35.4, 51, 100),
+    bp(35.5, 55.4, 101, 150),
+    bp(55.5, 125.4, 151, 200),
+    bp(125.5, 225.4, 201, 300),
+    bp(225.5, 325.4, 301, 500),
+];
+
+/// PM10 breakpoints in µg/m³.
+const PM10: &[Breakpoint] = &[
+    bp(0.0, 54.0, 0, 50),
+    bp(55.0, 154.0, 51, 100),
+    bp(155.0, 254.0, 101, 150),
+    bp(255.0, 354.0, 151, 200),
+    bp(355.0, 424.0, 201, 300),
+    bp(425.0, 604.0, 301, 500),
+];
+
+/// Returns the US EPA AQI for a PM2.5 concentration in µg/m³.
+///
+/// Concentrations above the top of the AQI table are extrapolated from its
+/// highest breakpoint, so the index may exceed 500.
+pub fn aqi_pm2_5(conc_ug_m3: f64) -> u16 {
+    // The EPA truncates PM2.5 concentrations to one decimal place. Sensors
+    // report `f32`s, so allow for values like 9.2 being stored as 9.1999...
+    let conc = truncate(conc_ug_m3 * 10.0 + EPSILON) / 10.0;
+    aqi(PM2_5, conc)
+}
+
+/// Returns the US EPA AQI for a PM10 concentration in µg/m³.
+///
+/// Concentrations above the top of the AQI table are extrapolated from its
+/// highest breakpoint, so the index may exceed 500.
+pub fn aqi_pm10(conc_ug_m3: f64) -> u16 {
+    // The EPA truncates PM10 concentrations to an integer.
+    aqi(PM10, truncate(conc_ug_m3 + EPSILON))
+}
+
+/// Slop allowed when truncating concentrations which aren't exactly
+/// representable as floats.
+const EPSILON: f64 = 1e-4;
+
+fn aqi(breakpoints: &[Breakpoint], conc: f64) -> u16 {
+    // Truncation leaves gaps between breakpoints (such as 9.0 to 9.1 µg/m³),
+    // so use the first breakpoint whose upper bound is above the
+    // concentration. If there isn't one, extrapolate from the last one.
+    let Some(bp) = breakpoints
+        .iter()
+        .find(|bp| conc <= bp.hi)
+        .or(breakpoints.last())
+    else {
+        return 0;
+    };
+    let conc = if conc < bp.lo { bp.lo } else { conc };
+    let aqi = (bp.aqi_hi - bp.aqi_lo) / (bp.hi - bp.lo) * (conc - bp.lo) + bp.aqi_lo;
+    // round to the nearest integer.
+    truncate(aqi + 0.5) as u16
+}
+
+/// Truncates a non-negative `f64`, without the `std`-only `f64::trunc`.
+/// Negative values (which sensors shouldn't report) are treated as 0.
+fn truncate(value: f64) -> f64 {
+    if value <= 0.0 {
+        return 0.0;
+    }
+    value as u64 as f64
+}
+
+/// The categories of the US EPA Air Quality Index.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub enum AqiCategory {
+    /// AQI 0-50.
+    Good = 1,
+    /// AQI 51-100.
+    Moderate,
+    /// AQI 101-150.
+    UnhealthyForSensitiveGroups,
+    /// AQI 151-200.
+    Unhealthy,
+    /// AQI 201-300.
+    VeryUnhealthy,
+    /// AQI 301 and higher.
+    Hazardous,
+}
+
+impl AqiCategory {
+    /// Returns the category of an AQI value.
+    pub const fn from_aqi(aqi: u16) -> Self {
+        match aqi {
+            0..=50 => Self::Good,
+            51..=100 => Self::Moderate,
+            101..=150 => Self::UnhealthyForSensitiveGroups,
+            151..=200 => Self::Unhealthy,
+            201..=300 => Self::VeryUnhealthy,
+            _ => Self::Hazardous,
+        }
+    }
+
+    /// Returns the EPA's name for this category.
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Good => "Good",
+            Self::Moderate => "Moderate",
+            Self::UnhealthyForSensitiveGroups => "Unhealthy for Sensitive Groups",
+            Self::Unhealthy => "Unhealthy",
+            Self::VeryUnhealthy => "Very Unhealthy",
+            Self::Hazardous => "Hazardous",
+        }
+    }
+
+    /// Returns a short name for this category, for displays without room for
+    /// [`AqiCategory::as_str`].
+    pub const fn short_str(&self) -> &'static str {
+        match self {
+            Self::UnhealthyForSensitiveGroups => "USG",
+            _ => self.as_str(),
+        }
+    }
+}
+
+impl core::fmt::Display for AqiCategory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// How well-ventilated a room is, judging by its CO₂ concentration.
+///
+/// These bands assume outdoor air at about 420 ppm, and follow the EN 13779
+/// indoor air classes: `Excellent` and `Good` correspond to IDA 1 and IDA 2,
+/// `Fair` to IDA 3, and `Poor` to IDA 4.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub enum Co2Band {
+    /// Up to 800 ppm.
+    Excellent = 1,
+    /// 801-1000 ppm.
+    Good,
+    /// 1001-1400 ppm.
+    Fair,
+    /// 1401-2000 ppm. Most people notice stuffiness, and ventilation is
+    /// inadequate.
+    Poor,
+    /// Over 2000 ppm. Headaches and drowsiness are likely.
+    Bad,
+}
+
+impl Co2Band {
+    /// Returns the band of a CO₂ concentration in ppm.
+    pub fn from_ppm(co2_ppm: f64) -> Self {
+        if co2_ppm <= 800.0 {
+            Self::Excellent
+        } else if co2_ppm <= 1000.0 {
+            Self::Good
+        } else if co2_ppm <= 1400.0 {
+            Self::Fair
+        } else if co2_ppm <= 2000.0 {
+            Self::Poor
+        } else {
+            Self::Bad
+        }
+    }
+
+    /// Returns the band with the given [level](Self::level), if there is one.
+    pub const fn from_level(level: u8) -> Option<Self> {
+        match level {
+            1 => Some(Self::Excellent),
+            2 => Some(Self::Good),
+            3 => Some(Self::Fair),
+            4 => Some(Self::Poor),
+            5 => Some(Self::Bad),
+            _ => None,
+        }
+    }
+
+    /// Returns the band's level, from 1 (excellent) to 5 (bad), as reported
+    /// by the `co2_comfort_band` metric.
+    pub const fn level(&self) -> u8 {
+        *self as u8
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Excellent => "Excellent",
+            Self::Good => "Good",
+            Self::Fair => "Fair",
+            Self::Poor => "Poor",
+            Self::Bad => "Bad",
+        }
+    }
+}
+
+impl core::fmt::Display for Co2Band {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Indoor air quality, judging by the concentration of total volatile organic
+/// compounds (tVOC).
+///
+/// These are the UBA's tVOC levels 1-5. Sensirion converts them from µg/m³ to
+/// ppb assuming a typical indoor mixture of VOCs.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub enum IaqCategory {
+    /// Up to 65 ppb. Target value.
+    Excellent = 1,
+    /// 66-220 ppb. Ventilation is recommended.
+    Good,
+    /// 221-660 ppb. Ventilation is required, and sources of VOCs should be
+    /// found.
+    Moderate,
+    /// 661-2200 ppb. Intensified ventilation is needed.
+    Poor,
+    /// Over 2200 ppb. Use of the room should be limited.
+    Unhealthy,
+}
+
+impl IaqCategory {
+    /// Returns the category of a tVOC concentration in ppb.
+    pub fn from_tvoc_ppb(tvoc_ppb: f64) -> Self {
+        if tvoc_ppb <= 65.0 {
+            Self::Excellent
+        } else if tvoc_ppb <= 220.0 {
+            Self::Good
+        } else if tvoc_ppb <= 660.0 {
+            Self::Moderate
+        } else if tvoc_ppb <= 2200.0 {
+            Self::Poor
+        } else {
+            Self::Unhealthy
+        }
+    }
+
+    /// Returns the category with the given [level](Self::level), if there is
+    /// one.
+    pub const fn from_level(level: u8) -> Option<Self> {
+        match level {
+            1 => Some(Self::Excellent),
+            2 => Some(Self::Good),
+            3 => Some(Self::Moderate),
+            4 => Some(Self::Poor),
+            5 => Some(Self::Unhealthy),
+            _ => None,
+        }
+    }
+
+    /// Returns the category's level, from 1 (excellent) to 5 (unhealthy), as
+    /// reported by the `tvoc_iaq_category` metric.
+    pub const fn level(&self) -> u8 {
+        *self as u8
+    }
+
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Excellent => "Excellent",
+            Self::Good => "Good",
+            Self::Moderate => "Moderate",
+            Self::Poor => "Poor",
+            Self::Unhealthy => "Unhealthy",
+        }
+    }
+}
+
+impl core::fmt::Display for IaqCategory {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pm2_5_breakpoints() {
+        assert_eq!(aqi_pm2_5(0.0), 0);
+        assert_eq!(aqi_pm2_5(9.0), 50);
+        // truncated to 9.0
+        assert_eq!(aqi_pm2_5(9.05), 50);
+        assert_eq!(aqi_pm2_5(9.1), 51);
+        assert_eq!(aqi_pm2_5(35.4), 100);
+        assert_eq!(aqi_pm2_5(35.5), 101);
+        assert_eq!(aqi_pm2_5(12.0), 56);
+        assert_eq!(aqi_pm2_5(325.4), 500);
+        assert!(aqi_pm2_5(400.0) > 500);
+        assert_eq!(aqi_pm2_5(-1.0), 0);
+    }
+
+    #[test]
+    fn pm10_breakpoints() {
+        assert_eq!(aqi_pm10(54.9), 50);
+        assert_eq!(aqi_pm10(55.0), 51);
+        assert_eq!(aqi_pm10(154.0), 100);
+        assert_eq!(aqi_pm10(604.0), 500);
+    }
+
+    #[test]
+    fn aqi_categories() {
+        assert_eq!(AqiCategory::from_aqi(aqi_pm2_5(5.0)), AqiCategory::Good);
+        assert_eq!(
+            AqiCategory::from_aqi(aqi_pm2_5(40.0)),
+            AqiCategory::UnhealthyForSensitiveGroups
+        );
+        assert_eq!(AqiCategory::from_aqi(501), AqiCategory::Hazardous);
+    }
+
+    #[test]
+    fn levels_round_trip() {
+        for level in 1..=5 {
+            assert_eq!(Co2Band::from_level(level).unwrap().level(), level);
+            assert_eq!(IaqCategory::from_level(level).unwrap().level(), level);
+        }
+        assert_eq!(Co2Band::from_level(0), None);
+        assert_eq!(IaqCategory::from_level(6), None);
+    }
+
+    #[test]
+    fn co2_bands_and_iaq_categories() {
+        assert_eq!(Co2Band::from_ppm(420.0), Co2Band::Excellent);
+        assert_eq!(Co2Band::from_ppm(1200.0), Co2Band::Fair);
+        assert_eq!(Co2Band::from_ppm(2500.0), Co2Band::Bad);
+        assert_eq!(IaqCategory::from_tvoc_ppb(30.0), IaqCategory::Excellent);
+        assert_eq!(IaqCategory::from_tvoc_ppb(500.0), IaqCategory::Moderate);
+        assert_eq!(IaqCategory::from_tvoc_ppb(3000.0), IaqCategory::Unhealthy);
+    }
+}
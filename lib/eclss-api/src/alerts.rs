@@ -0,0 +1,92 @@
+//! Types returned by the `/alerts.json` endpoint.
+use crate::SensorId;
+use serde::{Deserialize, Serialize};
+use std::string::String;
+
+/// An alert which is currently pending or firing.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct Alert {
+    /// The name of the rule which triggered this alert.
+    pub rule: String,
+    /// The metric the rule applies to, as named in `/metrics.json`.
+    pub metric: String,
+    /// The particle diameter the rule applies to, in µm, if the metric is a
+    /// particulate matter metric.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diameter: Option<String>,
+    /// The sensor the rule applies to, or `None` if the rule applies to an
+    /// aggregate of all sensors.
+    pub sensor: Option<SensorId>,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// The most recently observed value of the metric.
+    pub value: f64,
+    pub state: AlertState,
+    /// When the alert entered its current state, as a Unix timestamp in
+    /// seconds.
+    pub since: u64,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug, strum::Display))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "fmt", strum(serialize_all = "lowercase"))]
+pub enum AlertState {
+    /// The alert's condition is true, but has not yet been true for the
+    /// rule's hold duration.
+    Pending,
+    /// The alert's condition has been true for at least the rule's hold
+    /// duration.
+    Firing,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug, strum::Display))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "fmt", strum(serialize_all = "lowercase"))]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+/// How to combine readings of a metric from multiple sensors.
+#[derive(Copy, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug, strum::Display))]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "fmt", strum(serialize_all = "lowercase"))]
+pub enum Aggregate {
+    #[default]
+    Mean,
+    Min,
+    Max,
+}
+
+impl Comparison {
+    /// Returns `true` if `value` is beyond `threshold` in this direction.
+    #[must_use]
+    pub fn matches(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Above => value > threshold,
+            Self::Below => value < threshold,
+        }
+    }
+}
+
+impl Aggregate {
+    /// Combines `values`, returning `None` if there are no values.
+    #[must_use]
+    pub fn apply(self, values: impl IntoIterator<Item = f64>) -> Option<f64> {
+        let mut values = values.into_iter();
+        let first = values.next()?;
+        let value = match self {
+            Self::Mean => {
+                let (sum, n) = values.fold((first, 1), |(sum, n), v| (sum + v, n + 1));
+                sum / n as f64
+            }
+            Self::Min => values.fold(first, f64::min),
+            Self::Max => values.fold(first, f64::max),
+        };
+        Some(value)
+    }
+}
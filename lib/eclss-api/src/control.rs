@@ -0,0 +1,53 @@
+//! Types used by the sensor control endpoints, `/sensors/{sensor}/calibrate`
+//! and `/sensors/{sensor}/asc`.
+//!
+//! The `/sensors/{sensor}/reset`, `/sensors/{sensor}/reset-baseline`, and
+//! `/sensors/{sensor}/clean-fan` endpoints take no request body, and respond
+//! with `204 No Content` on success.
+use crate::SensorId;
+use core::ops::RangeInclusive;
+use serde::{Deserialize, Serialize};
+
+/// The range of reference CO₂ concentrations, in ppm, which CO₂ sensors may be
+/// recalibrated to.
+pub const REFERENCE_PPM: RangeInclusive<u16> = 400..=2000;
+
+/// Request body for `POST /sensors/{sensor}/calibrate`, which performs a
+/// forced recalibration of a CO₂ sensor.
+///
+/// The sensor should have been running for at least a few minutes in air with
+/// a known, stable CO₂ concentration (such as outdoors, where it's about 420
+/// ppm) before it's recalibrated.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct CalibrateRequest {
+    /// The CO₂ concentration that the sensor is currently exposed to, in ppm.
+    pub reference_ppm: u16,
+}
+
+/// Response body for `POST /sensors/{sensor}/calibrate`.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct CalibrateResponse {
+    pub sensor: SensorId,
+    /// The correction applied to the sensor's CO₂ readings, in ppm, if the
+    /// sensor reports it.
+    pub correction_ppm: Option<i16>,
+}
+
+/// Request body for `POST /sensors/{sensor}/asc`, which enables or disables a
+/// CO₂ sensor's automatic self-calibration.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct AscRequest {
+    pub enabled: bool,
+}
+
+/// Response body for `POST /sensors/{sensor}/asc`.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct AscResponse {
+    pub sensor: SensorId,
+    /// Whether automatic self-calibration is now enabled.
+    pub enabled: bool,
+}
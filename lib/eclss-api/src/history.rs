@@ -0,0 +1,39 @@
+//! Types returned by the `/history.json` endpoint.
+use crate::SensorId;
+use serde::{Deserialize, Serialize};
+use std::{string::String, vec::Vec};
+
+/// Downsampled readings of one metric, from one or more sensors.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct History {
+    /// The name of the metric, as it appears in `/metrics.json` (e.g.
+    /// `co2_ppm`).
+    pub metric: String,
+    /// The interval between samples, in seconds.
+    pub step_secs: u64,
+    pub series: Vec<Series>,
+}
+
+/// Downsampled readings of a metric from a single sensor.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct Series {
+    pub sensor: SensorId,
+    /// The particle diameter of a particulate matter metric's readings, in
+    /// µm (e.g. `"2.5"`), or `None` for other metrics.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diameter: Option<String>,
+    /// Samples, in ascending order by timestamp.
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct Sample {
+    /// The start of the interval this sample covers, as a Unix timestamp in
+    /// seconds.
+    pub timestamp: u64,
+    /// The mean value of the metric over the interval.
+    pub value: f64,
+}
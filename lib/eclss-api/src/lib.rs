@@ -2,34 +2,221 @@
 use core::time::Duration;
 use serde::{Deserialize, Serialize};
 
-pub const MAX_SENSORS: usize = 16;
+pub mod air_quality;
+#[cfg(feature = "std")]
+pub mod alerts;
+pub mod control;
+#[cfg(feature = "std")]
+pub mod history;
+pub mod schema;
 
-#[derive(Clone, PartialEq, Serialize, Deserialize)]
+/// The maximum number of instances of each sensor model on a node.
+pub const MAX_INSTANCES: usize = 4;
+
+/// The maximum number of sensors on a node: [`MAX_INSTANCES`] of each of the
+/// [`SensorName::MODELS`], plus the [`SensorName::Fused`] pseudo-sensor.
+pub const MAX_SENSORS: usize = SensorName::MODELS.len() * MAX_INSTANCES + 1;
+
+/// The maximum number of particulate matter measurements of each kind in a
+/// [`Metrics`] response. Particulate sensors report several diameters each:
+/// the PMSA003I reports up to six, and the SEN55 four.
+pub const MAX_PARTICULATE_MEASUREMENTS: usize = (6 + 4) * MAX_INSTANCES;
+
+/// The maximum number of device status measurements in a [`Metrics`]
+/// response. Sensors which report their status report every one of the
+/// [`DeviceStatus::FLAGS`].
+pub const MAX_DEVICE_STATUS_MEASUREMENTS: usize = DeviceStatus::FLAGS.len() * MAX_INSTANCES;
+
+/// The response body of `/metrics.json`.
+///
+/// Each field holds the readings of the [`schema::Metric`] of the same name.
+/// Use [`Metrics::iter`] to visit every metric along with its schema, rather
+/// than accessing fields by name, to display metrics without hard-coding each
+/// one.
+///
+/// Metrics which a node doesn't report (such as those added in newer versions
+/// of the schema) are empty.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "fmt", derive(Debug))]
+#[serde(default)]
 pub struct Metrics {
-    pub abs_humidity_grams_m3: heapless::Vec<Measurement, MAX_SENSORS>,
-    pub rel_humidity_percent: heapless::Vec<Measurement, MAX_SENSORS>,
+    /// The [`schema::VERSION`] of the metrics schema used by the node, or 0 if
+    /// the node doesn't report a schema version.
+    pub schema_version: u32,
     pub temp_c: heapless::Vec<Measurement, MAX_SENSORS>,
     pub co2_ppm: heapless::Vec<Measurement, MAX_SENSORS>,
     pub eco2_ppm: heapless::Vec<Measurement, MAX_SENSORS>,
+    pub rel_humidity_percent: heapless::Vec<Measurement, MAX_SENSORS>,
+    pub abs_humidity_grams_m3: heapless::Vec<Measurement, MAX_SENSORS>,
+    pub pressure_hpa: heapless::Vec<Measurement, MAX_SENSORS>,
+    pub gas_resistance: heapless::Vec<Measurement, MAX_SENSORS>,
     pub tvoc_ppb: heapless::Vec<Measurement, MAX_SENSORS>,
     pub tvoc_iaq_index: heapless::Vec<Measurement, MAX_SENSORS>,
     pub nox_iaq_index: heapless::Vec<Measurement, MAX_SENSORS>,
-    pub pressure_hpa: heapless::Vec<Measurement, MAX_SENSORS>,
+    /// US EPA Air Quality Index, computed from each particulate sensor's PM2.5
+    /// and PM10 concentrations. See [`air_quality::aqi_pm2_5`].
+    pub aqi: heapless::Vec<Measurement, MAX_SENSORS>,
+    /// CO₂ comfort band [levels](air_quality::Co2Band::level), from 1 to 5.
+    pub co2_comfort_band: heapless::Vec<Measurement, MAX_SENSORS>,
+    /// tVOC indoor air quality category
+    /// [levels](air_quality::IaqCategory::level), from 1 to 5.
+    pub tvoc_iaq_category: heapless::Vec<Measurement, MAX_SENSORS>,
+    /// Particulate matter mass concentrations, in µg/m³.
+    ///
+    /// Sensors which distinguish them report concentrations under the actual
+    /// ("environmental") atmospheric conditions here.
+    pub pm_concentration_ug_m3: heapless::Vec<ParticulateMeasurement, MAX_PARTICULATE_MEASUREMENTS>,
+    /// Particulate matter mass concentrations under standard atmospheric
+    /// conditions, in µg/m³.
+    ///
+    /// Only sensors which distinguish standard from environmental
+    /// concentrations, such as the PMSA003I, report these.
+    pub pm_concentration_standard_ug_m3:
+        heapless::Vec<ParticulateMeasurement, MAX_PARTICULATE_MEASUREMENTS>,
+    /// Counts of particles larger than each diameter in 0.1L of air.
+    pub pm_count: heapless::Vec<ParticulateMeasurement, MAX_PARTICULATE_MEASUREMENTS>,
+    /// Number concentrations of particles up to each diameter, in particles
+    /// per cm³.
+    pub pm_number_concentration_cm3:
+        heapless::Vec<ParticulateMeasurement, MAX_PARTICULATE_MEASUREMENTS>,
+    /// Conditions reported by sensors' self-diagnostics: 1 if the condition is
+    /// present, and 0 if it isn't.
+    pub device_status: heapless::Vec<DeviceStatusMeasurement, MAX_DEVICE_STATUS_MEASUREMENTS>,
     pub sensor_errors: heapless::Vec<Measurement, MAX_SENSORS>,
+    pub sensor_reset_count: heapless::Vec<Measurement, MAX_SENSORS>,
+    /// The time since each sensor was last polled successfully, in seconds.
+    pub reading_age_secs: heapless::Vec<Measurement, MAX_SENSORS>,
     pub location: Option<heapless::String<64>>,
 }
 
+/// The readings of one metric in a [`Metrics`] response, as returned by
+/// [`Metrics::iter`].
+///
+/// Which variant a metric's readings are depends on its [`schema::Labels`].
+#[derive(Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub enum Readings<'metrics> {
+    Sensor(&'metrics [Measurement]),
+    Diameter(&'metrics [ParticulateMeasurement]),
+    DeviceStatusFlag(&'metrics [DeviceStatusMeasurement]),
+}
+
 #[derive(Clone, PartialEq, Serialize, Deserialize)]
 #[cfg_attr(feature = "fmt", derive(Debug))]
 pub struct Measurement {
     pub value: f64,
-    pub sensor: SensorName,
+    pub sensor: SensorId,
+    /// When the sensor was last polled successfully, as a Unix timestamp in
+    /// seconds, if the node has a clock and this is a sensor reading.
+    pub timestamp: Option<u64>,
+}
+
+/// A particulate matter measurement for particles of one diameter.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct ParticulateMeasurement {
+    pub value: f64,
+    pub sensor: SensorId,
+    /// The particle diameter in µm, such as `"2.5"` for PM2.5.
+    pub diameter: heapless::String<8>,
+    pub timestamp: Option<u64>,
+}
+
+/// Whether a condition reported by a sensor's self-diagnostics is present.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct DeviceStatusMeasurement {
+    /// 1 if the condition is present, and 0 if it isn't.
+    pub value: f64,
+    pub sensor: SensorId,
+    /// One of the [`DeviceStatus::FLAGS`].
+    pub flag: heapless::String<24>,
     pub timestamp: Option<u64>,
 }
 
+// === impl Metrics ===
+
+impl Metrics {
+    /// Returns an iterator over every metric in the [schema](schema::ALL),
+    /// along with its readings, in schema order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static schema::Metric, Readings<'_>)> {
+        use Readings::{DeviceStatusFlag, Diameter, Sensor};
+        [
+            (&schema::TEMP_C, Sensor(&self.temp_c)),
+            (&schema::CO2_PPM, Sensor(&self.co2_ppm)),
+            (&schema::ECO2_PPM, Sensor(&self.eco2_ppm)),
+            (
+                &schema::REL_HUMIDITY_PERCENT,
+                Sensor(&self.rel_humidity_percent),
+            ),
+            (
+                &schema::ABS_HUMIDITY_GRAMS_M3,
+                Sensor(&self.abs_humidity_grams_m3),
+            ),
+            (&schema::PRESSURE_HPA, Sensor(&self.pressure_hpa)),
+            (&schema::GAS_RESISTANCE, Sensor(&self.gas_resistance)),
+            (&schema::TVOC_PPB, Sensor(&self.tvoc_ppb)),
+            (&schema::TVOC_IAQ_INDEX, Sensor(&self.tvoc_iaq_index)),
+            (&schema::NOX_IAQ_INDEX, Sensor(&self.nox_iaq_index)),
+            (&schema::AQI, Sensor(&self.aqi)),
+            (&schema::CO2_COMFORT_BAND, Sensor(&self.co2_comfort_band)),
+            (&schema::TVOC_IAQ_CATEGORY, Sensor(&self.tvoc_iaq_category)),
+            (
+                &schema::PM_CONCENTRATION,
+                Diameter(&self.pm_concentration_ug_m3),
+            ),
+            (
+                &schema::PM_CONCENTRATION_STANDARD,
+                Diameter(&self.pm_concentration_standard_ug_m3),
+            ),
+            (&schema::PM_COUNT, Diameter(&self.pm_count)),
+            (
+                &schema::PM_NUMBER_CONCENTRATION,
+                Diameter(&self.pm_number_concentration_cm3),
+            ),
+            (
+                &schema::DEVICE_STATUS,
+                DeviceStatusFlag(&self.device_status),
+            ),
+            (&schema::SENSOR_ERRORS, Sensor(&self.sensor_errors)),
+            (
+                &schema::SENSOR_RESET_COUNT,
+                Sensor(&self.sensor_reset_count),
+            ),
+            (&schema::READING_AGE, Sensor(&self.reading_age_secs)),
+        ]
+        .into_iter()
+    }
+}
+
+// === impl Readings ===
+
+impl Readings<'_> {
+    /// Returns the number of readings.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Sensor(readings) => readings.len(),
+            Self::Diameter(readings) => readings.len(),
+            Self::DeviceStatusFlag(readings) => readings.len(),
+        }
+    }
+
+    /// Returns `true` if there are no readings of this metric.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
 #[derive(
-    Copy, Clone, PartialEq, Eq, Serialize, Deserialize, strum::IntoStaticStr, strum::EnumString,
+    Copy,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    strum::IntoStaticStr,
+    strum::EnumString,
 )]
 #[cfg_attr(feature = "fmt", derive(Debug, strum::Display))]
 #[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
@@ -48,6 +235,26 @@ pub enum SensorName {
     Sht41,
     Sgp30,
     Sen55,
+    /// Not a physical sensor: readings combined from every sensor which
+    /// measures the same quantity.
+    #[cfg_attr(feature = "clap", value(skip))]
+    Fused,
+}
+
+impl SensorName {
+    /// Every physical sensor model, excluding the [`SensorName::Fused`]
+    /// pseudo-sensor.
+    pub const MODELS: [SensorName; 9] = [
+        Self::Bme680,
+        Self::Ens160,
+        Self::Pmsa003i,
+        Self::Scd30,
+        Self::Scd40,
+        Self::Scd41,
+        Self::Sht41,
+        Self::Sgp30,
+        Self::Sen55,
+    ];
 }
 
 #[cfg(feature = "tinymetrics")]
@@ -57,6 +264,217 @@ impl tinymetrics::FmtLabels for SensorName {
     }
 }
 
+/// Identifies an individual sensor on a node.
+///
+/// A node may have more than one sensor of the same model (for example, two
+/// SHT41s at different heights in the same room), which are distinguished by
+/// an [`InstanceName`]. A sensor without an instance name is formatted and
+/// serialized as its model name (e.g. `SHT41`), and a named instance is
+/// formatted as `MODEL:INSTANCE` (e.g. `SHT41:ceiling`).
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct SensorId {
+    /// The sensor's model.
+    pub name: SensorName,
+    /// The name of this instance of the sensor model, if there is more than
+    /// one.
+    pub instance: Option<InstanceName>,
+}
+
+/// The name of one of several sensors of the same model on a node.
+///
+/// Instance names are between 1 and [`InstanceName::MAX_LEN`] ASCII letters,
+/// digits, `-` or `_`, so that they may be used in metric labels, MQTT
+/// topics, and file names.
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+pub struct InstanceName {
+    len: u8,
+    buf: [u8; InstanceName::MAX_LEN],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ParseSensorIdError {
+    UnknownSensor,
+    EmptyInstance,
+    InstanceTooLong,
+    InvalidInstance,
+}
+
+// === impl SensorId ===
+
+impl SensorId {
+    /// Returns the ID of the only sensor of the model `name`.
+    pub const fn new(name: SensorName) -> Self {
+        Self {
+            name,
+            instance: None,
+        }
+    }
+
+    /// Returns the ID of the instance of the model `name` called `instance`.
+    pub const fn with_instance(name: SensorName, instance: InstanceName) -> Self {
+        Self {
+            name,
+            instance: Some(instance),
+        }
+    }
+
+    /// Returns `true` if this is the [`SensorName::Fused`] pseudo-sensor.
+    ///
+    /// The fused readings are estimated from every other sensor's readings,
+    /// so they should be preferred over those readings when a single value is
+    /// wanted, and skipped when aggregating or recording every sensor's
+    /// readings, which would otherwise count the same measurements twice.
+    pub const fn is_fused(&self) -> bool {
+        matches!(self.name, SensorName::Fused)
+    }
+}
+
+impl From<SensorName> for SensorId {
+    fn from(name: SensorName) -> Self {
+        Self::new(name)
+    }
+}
+
+impl core::str::FromStr for SensorId {
+    type Err = ParseSensorIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, instance) = match s.split_once(':') {
+            Some((name, instance)) => (name, Some(instance.parse::<InstanceName>()?)),
+            None => (s, None),
+        };
+        let name = name
+            .parse::<SensorName>()
+            .map_err(|_| ParseSensorIdError::UnknownSensor)?;
+        Ok(Self { name, instance })
+    }
+}
+
+impl core::fmt::Display for SensorId {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name: &'static str = self.name.into();
+        match self.instance {
+            Some(ref instance) => write!(f, "{name}:{instance}"),
+            None => f.pad(name),
+        }
+    }
+}
+
+impl Serialize for SensorId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use core::fmt::Write;
+        // long enough for the longest model name, a `:`, and an instance name.
+        let mut buf = heapless::String::<32>::new();
+        write!(buf, "{self}").map_err(|_| serde::ser::Error::custom("sensor ID too long"))?;
+        serializer.serialize_str(&buf)
+    }
+}
+
+impl<'de> Deserialize<'de> for SensorId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = SensorId;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.write_str("a sensor name, optionally followed by `:` and an instance name")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                s.parse().map_err(E::custom)
+            }
+        }
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+/// Formats the `sensor` label exactly as [`SensorName`] does, so that the
+/// series for a sensor without an instance name are the same as if it were
+/// labeled by its model alone. Named instances add a `sensor_instance` label.
+#[cfg(feature = "tinymetrics")]
+impl tinymetrics::FmtLabels for SensorId {
+    fn fmt_labels(&self, f: &mut impl core::fmt::Write) -> core::fmt::Result {
+        self.name.fmt_labels(f)?;
+        if let Some(ref instance) = self.instance {
+            write!(f, ",sensor_instance=\"{instance}\"")?;
+        }
+        Ok(())
+    }
+}
+
+// === impl InstanceName ===
+
+impl InstanceName {
+    /// The maximum length of an instance name, in bytes.
+    pub const MAX_LEN: usize = 16;
+
+    pub fn as_str(&self) -> &str {
+        // instance names are validated to be ASCII when they are constructed.
+        core::str::from_utf8(&self.buf[..self.len as usize]).unwrap_or_default()
+    }
+}
+
+impl core::str::FromStr for InstanceName {
+    type Err = ParseSensorIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(ParseSensorIdError::EmptyInstance);
+        }
+        if s.len() > Self::MAX_LEN {
+            return Err(ParseSensorIdError::InstanceTooLong);
+        }
+        if !s
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        {
+            return Err(ParseSensorIdError::InvalidInstance);
+        }
+        let mut buf = [0; Self::MAX_LEN];
+        buf[..s.len()].copy_from_slice(s.as_bytes());
+        Ok(Self {
+            len: s.len() as u8,
+            buf,
+        })
+    }
+}
+
+impl core::fmt::Display for InstanceName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+#[cfg(feature = "fmt")]
+impl core::fmt::Debug for InstanceName {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+// === impl ParseSensorIdError ===
+
+impl core::fmt::Display for ParseSensorIdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnknownSensor => f.write_str("unknown sensor model"),
+            Self::EmptyInstance => f.write_str("sensor instance names may not be empty"),
+            Self::InstanceTooLong => write!(
+                f,
+                "sensor instance names may not be longer than {} characters",
+                InstanceName::MAX_LEN
+            ),
+            Self::InvalidInstance => f.write_str(
+                "sensor instance names may only contain ASCII letters, digits, `-`, and `_`",
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseSensorIdError {}
+
 // #[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 // #[cfg_attr(feature = "fmt", derive(Debug))]
 // #[serde(rename_all = "UPPERCASE")]
@@ -75,6 +493,10 @@ pub struct SensorState {
     status: SensorStatus,
     found: bool,
     poll_interval: Duration,
+    #[serde(default)]
+    device_status: DeviceStatus,
+    #[serde(default)]
+    compensation: CompensationSources,
 }
 
 /// Represents the status of an I2C sensor.
@@ -106,6 +528,133 @@ pub enum SensorStatus {
     OtherI2cError,
 }
 
+/// Conditions reported by a sensor's own self-diagnostics.
+///
+/// These add detail to a sensor's [`SensorStatus`]: a sensor with a failed
+/// laser may still respond to every poll, but its readings shouldn't be
+/// trusted. Sensors which don't report their device status never set any of
+/// these.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct DeviceStatus {
+    /// The fan's speed is out of its target range.
+    pub fan_speed_warning: bool,
+    /// The fan is running its cleaning cycle.
+    pub fan_cleaning: bool,
+    /// The fan is stopped, or failed to reach its target speed.
+    pub fan_failure: bool,
+    /// The laser current is out of range.
+    pub laser_failure: bool,
+    /// The gas (VOC and NOx) sensing element failed.
+    pub gas_sensor_error: bool,
+    /// Communication with the temperature and humidity sensing element failed.
+    pub rht_error: bool,
+}
+
+/// Where a sensor's environmental compensation inputs came from.
+///
+/// Some sensors correct their readings for the temperature, humidity, or
+/// pressure of the air, as measured by other sensors. Inputs which a sensor
+/// doesn't use, or for which no source had a current reading, are `None`.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct CompensationSources {
+    pub temp: Option<CompensationSource>,
+    pub humidity: Option<CompensationSource>,
+    pub pressure: Option<CompensationSource>,
+}
+
+/// The source of one of a sensor's environmental compensation inputs.
+#[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum CompensationSource {
+    /// A single sensor's reading, which may be the fused estimate.
+    Sensor(SensorId),
+    /// The mean of every healthy sensor's readings, excluding outliers.
+    Mean,
+}
+
+// === impl SensorState ===
+
+impl SensorState {
+    /// Returns the sensor's current status.
+    pub fn status(&self) -> SensorStatus {
+        self.status
+    }
+
+    /// Returns `true` if the sensor has been initialized, and has not since
+    /// been marked as missing.
+    pub fn found(&self) -> bool {
+        self.found
+    }
+
+    /// Returns the interval at which the sensor is polled.
+    pub fn poll_interval(&self) -> Duration {
+        self.poll_interval
+    }
+
+    /// Returns the conditions reported by the sensor's self-diagnostics.
+    pub fn device_status(&self) -> DeviceStatus {
+        self.device_status
+    }
+
+    /// Returns where the sensor's compensation inputs came from, as of its
+    /// most recent poll.
+    pub fn compensation(&self) -> CompensationSources {
+        self.compensation
+    }
+}
+
+// === impl CompensationSource ===
+
+impl core::fmt::Display for CompensationSource {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Sensor(sensor) => core::fmt::Display::fmt(sensor, f),
+            Self::Mean => f.pad("mean"),
+        }
+    }
+}
+
+// === impl DeviceStatus ===
+
+impl DeviceStatus {
+    /// The name of each condition, as used in metric labels. These are the
+    /// same as the field names.
+    pub const FLAGS: [&'static str; 6] = [
+        "fan_speed_warning",
+        "fan_cleaning",
+        "fan_failure",
+        "laser_failure",
+        "gas_sensor_error",
+        "rht_error",
+    ];
+
+    /// Returns whether each condition is set, in the same order as
+    /// [`DeviceStatus::FLAGS`].
+    pub fn flags(&self) -> [bool; 6] {
+        [
+            self.fan_speed_warning,
+            self.fan_cleaning,
+            self.fan_failure,
+            self.laser_failure,
+            self.gas_sensor_error,
+            self.rht_error,
+        ]
+    }
+
+    /// Returns `true` if any condition indicating a hardware fault is set.
+    ///
+    /// The fan cleaning cycle is expected, and a fan speed warning usually
+    /// clears by itself, so neither is considered a fault.
+    pub fn is_fault(&self) -> bool {
+        self.fan_failure || self.laser_failure || self.gas_sensor_error || self.rht_error
+    }
+}
+
+// === impl SensorStatus ===
+
 impl SensorStatus {
     pub fn from_u8(u: u8) -> Self {
         match u {
@@ -147,6 +696,7 @@ mod tests {
         ("SHT41", SensorName::Sht41),
         ("SGP30", SensorName::Sgp30),
         ("SEN55", SensorName::Sen55),
+        ("FUSED", SensorName::Fused),
     ];
 
     #[test]
@@ -169,4 +719,232 @@ mod tests {
             assert_eq!(s, name.to_string());
         }
     }
+
+    #[test]
+    fn sensor_id_round_trips() {
+        for &(s, name) in SENSOR_KINDS {
+            let id = s.parse::<SensorId>().unwrap();
+            assert_eq!(id, SensorId::new(name));
+            assert_eq!(id.to_string(), s);
+
+            let named = format!("{s}:ceiling");
+            let id = named.to_ascii_lowercase().parse::<SensorId>().unwrap();
+            assert_eq!(id.name, name);
+            assert_eq!(id.instance.unwrap().as_str(), "ceiling");
+            assert_eq!(id.to_string(), named);
+        }
+    }
+
+    #[test]
+    fn sensor_id_serde() {
+        let measurement = Measurement {
+            value: 1.0,
+            sensor: SensorId::new(SensorName::Sht41),
+            timestamp: None,
+        };
+        let json = serde_json::to_string(&measurement).unwrap();
+        assert_eq!(json, r#"{"value":1.0,"sensor":"SHT41","timestamp":null}"#);
+
+        let id = serde_json::from_str::<SensorId>(r#""SHT41:floor""#).unwrap();
+        assert_eq!(id, "SHT41:floor".parse().unwrap());
+        assert_eq!(serde_json::to_string(&id).unwrap(), r#""SHT41:floor""#);
+    }
+
+    #[test]
+    fn metrics_with_particulates() {
+        let metrics = serde_json::json!({
+            "abs_humidity_grams_m3": [],
+            "rel_humidity_percent": [],
+            "temp_c": [],
+            "co2_ppm": [],
+            "eco2_ppm": [],
+            "tvoc_ppb": [],
+            "tvoc_iaq_index": [],
+            "nox_iaq_index": [],
+            "pressure_hpa": [],
+            "sensor_errors": [],
+            "pm_concentration_ug_m3": [
+                { "sensor": "PMSA003I", "diameter": "2.5", "value": 12.0 },
+                { "sensor": "SEN55", "diameter": "2.5", "value": 10.5 },
+            ],
+            "pm_concentration_standard_ug_m3": [
+                { "sensor": "PMSA003I", "diameter": "2.5", "value": 11.0 },
+            ],
+            "pm_count": [
+                { "sensor": "PMSA003I", "diameter": "0.3", "value": 420.0 },
+            ],
+            "location": null,
+        });
+        let metrics = serde_json::from_value::<Metrics>(metrics).unwrap();
+        assert_eq!(metrics.pm_concentration_ug_m3.len(), 2);
+        let standard = &metrics.pm_concentration_standard_ug_m3[0];
+        assert_eq!(standard.sensor, SensorId::new(SensorName::Pmsa003i));
+        assert_eq!(standard.diameter, "2.5");
+        assert_eq!(standard.value, 11.0);
+        assert_eq!(metrics.pm_count[0].diameter, "0.3");
+
+        // responses from daemons which don't report particulates are still
+        // accepted.
+        let metrics = serde_json::from_str::<Metrics>(
+            r#"{"abs_humidity_grams_m3":[],"rel_humidity_percent":[],"temp_c":[],
+            "co2_ppm":[],"eco2_ppm":[],"tvoc_ppb":[],"tvoc_iaq_index":[],
+            "nox_iaq_index":[],"pressure_hpa":[],"sensor_errors":[],"location":null}"#,
+        )
+        .unwrap();
+        assert!(metrics.pm_concentration_ug_m3.is_empty());
+        assert!(metrics.pm_count.is_empty());
+    }
+
+    #[test]
+    fn full_metrics_round_trip() {
+        // every sensor a node may have: `MAX_INSTANCES` of each model, and
+        // the fused pseudo-sensor.
+        let sensors = SensorName::MODELS
+            .iter()
+            .flat_map(|&name| {
+                (0..MAX_INSTANCES).map(move |i| {
+                    SensorId::with_instance(name, format!("sensor{i}").parse().unwrap())
+                })
+            })
+            .chain([SensorId::new(SensorName::Fused)])
+            .collect::<Vec<_>>();
+        assert_eq!(sensors.len(), MAX_SENSORS);
+
+        let readings = sensors
+            .iter()
+            .map(|&sensor| Measurement {
+                value: 1.0,
+                sensor,
+                timestamp: Some(1_700_000_000),
+            })
+            .collect::<heapless::Vec<_, MAX_SENSORS>>();
+        let particulates = sensors
+            .iter()
+            .cycle()
+            .take(MAX_PARTICULATE_MEASUREMENTS)
+            .map(|&sensor| ParticulateMeasurement {
+                value: 2.5,
+                sensor,
+                diameter: "10.0".try_into().unwrap(),
+                timestamp: Some(1_700_000_000),
+            })
+            .collect::<heapless::Vec<_, MAX_PARTICULATE_MEASUREMENTS>>();
+        let device_status = sensors
+            .iter()
+            .cycle()
+            .zip(DeviceStatus::FLAGS.iter().cycle())
+            .take(MAX_DEVICE_STATUS_MEASUREMENTS)
+            .map(|(&sensor, &flag)| DeviceStatusMeasurement {
+                value: 1.0,
+                sensor,
+                flag: flag.try_into().unwrap(),
+                timestamp: Some(1_700_000_000),
+            })
+            .collect::<heapless::Vec<_, MAX_DEVICE_STATUS_MEASUREMENTS>>();
+
+        let metrics = Metrics {
+            schema_version: schema::VERSION,
+            temp_c: readings.clone(),
+            co2_ppm: readings.clone(),
+            eco2_ppm: readings.clone(),
+            rel_humidity_percent: readings.clone(),
+            abs_humidity_grams_m3: readings.clone(),
+            pressure_hpa: readings.clone(),
+            gas_resistance: readings.clone(),
+            tvoc_ppb: readings.clone(),
+            tvoc_iaq_index: readings.clone(),
+            nox_iaq_index: readings.clone(),
+            aqi: readings.clone(),
+            co2_comfort_band: readings.clone(),
+            tvoc_iaq_category: readings.clone(),
+            pm_concentration_ug_m3: particulates.clone(),
+            pm_concentration_standard_ug_m3: particulates.clone(),
+            pm_count: particulates.clone(),
+            pm_number_concentration_cm3: particulates,
+            device_status,
+            sensor_errors: readings.clone(),
+            sensor_reset_count: readings.clone(),
+            reading_age_secs: readings,
+            location: Some("the-moon".try_into().unwrap()),
+        };
+        let json = serde_json::to_string(&metrics).unwrap();
+        let parsed = serde_json::from_str::<Metrics>(&json).unwrap();
+        assert!(parsed == metrics);
+    }
+
+    #[test]
+    fn metrics_fields_match_schema() {
+        let metrics = Metrics::default();
+        let json = serde_json::to_value(&metrics).unwrap();
+        let json = json.as_object().unwrap();
+        for metric in schema::ALL {
+            assert!(json.contains_key(metric.name), "{}", metric.name);
+        }
+        // every field other than the schema version and location is a metric
+        assert_eq!(json.len(), schema::ALL.len() + 2);
+
+        let names = metrics.iter().map(|(metric, _)| metric.name);
+        assert!(names.eq(schema::ALL.iter().map(|metric| metric.name)));
+    }
+
+    #[test]
+    fn device_status_flags_match_fields() {
+        let status = DeviceStatus {
+            fan_cleaning: true,
+            laser_failure: true,
+            ..Default::default()
+        };
+        let json = serde_json::to_value(status).unwrap();
+        for (flag, set) in DeviceStatus::FLAGS.iter().zip(status.flags()) {
+            assert_eq!(json[flag], set, "{flag}");
+        }
+        assert!(status.is_fault());
+    }
+
+    #[test]
+    fn sensor_state_without_device_status() {
+        let state = serde_json::from_str::<SensorState>(
+            r#"{"status":"Up","found":true,"poll_interval":{"secs":1,"nanos":0}}"#,
+        )
+        .unwrap();
+        assert_eq!(state.device_status(), DeviceStatus::default());
+        assert_eq!(state.compensation(), CompensationSources::default());
+    }
+
+    #[test]
+    fn compensation_sources_json() {
+        let sources = CompensationSources {
+            humidity: Some(CompensationSource::Sensor("SHT41:ceiling".parse().unwrap())),
+            pressure: Some(CompensationSource::Mean),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(sources).unwrap();
+        assert_eq!(json["temp"], serde_json::Value::Null);
+        assert_eq!(json["humidity"]["sensor"], "SHT41:ceiling");
+        assert_eq!(json["pressure"], "mean");
+        assert_eq!(
+            serde_json::from_value::<CompensationSources>(json).unwrap(),
+            sources
+        );
+    }
+
+    #[test]
+    fn invalid_sensor_ids() {
+        assert_eq!(
+            "SHT42".parse::<SensorId>(),
+            Err(ParseSensorIdError::UnknownSensor)
+        );
+        assert_eq!(
+            "SHT41:".parse::<SensorId>(),
+            Err(ParseSensorIdError::EmptyInstance)
+        );
+        assert_eq!(
+            "SHT41:a-very-long-instance-name".parse::<SensorId>(),
+            Err(ParseSensorIdError::InstanceTooLong)
+        );
+        assert_eq!(
+            "SHT41:up stairs".parse::<SensorId>(),
+            Err(ParseSensorIdError::InvalidInstance)
+        );
+    }
 }
@@ -0,0 +1,372 @@
+//! The schema of the metrics exported by an ECLSS node.
+//!
+//! Every metric served at `/metrics` (in the Prometheus text format) and
+//! `/metrics.json` is described by a [`Metric`] in [`ALL`]. Clients should use
+//! these to label and format readings, rather than hard-coding units and
+//! names, so that new sensors' metrics show up without changes to each
+//! client. The schema is also served as JSON at `/schema.json`.
+use serde::Serialize;
+
+/// The version of the metrics schema.
+///
+/// This is incremented whenever a metric is renamed or removed, or its unit or
+/// labels change. Adding a metric doesn't change the version, so clients
+/// should ignore metrics they don't know about.
+pub const VERSION: u32 = 1;
+
+/// Describes a metric exported by an ECLSS node.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+pub struct Metric {
+    /// The metric's name in `/metrics.json`, which is also the name of its
+    /// field in [`Metrics`](crate::Metrics) and the name used by
+    /// `/history.json` and alert rules.
+    pub name: &'static str,
+    /// The metric's name in the Prometheus exposition at `/metrics`.
+    pub prometheus_name: &'static str,
+    /// A description of the metric.
+    pub help: &'static str,
+    /// The metric's unit, if it has one.
+    pub unit: Option<Unit>,
+    pub kind: Kind,
+    /// The labels which distinguish readings of this metric.
+    pub labels: Labels,
+}
+
+/// Whether a metric's value may go up and down, or only up.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    Gauge,
+    Counter,
+}
+
+/// The labels which distinguish readings of a metric.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum Labels {
+    /// Readings are labeled with their sensor, as a
+    /// [`Measurement`](crate::Measurement).
+    Sensor,
+    /// Readings are labeled with their sensor and a particle diameter, as a
+    /// [`ParticulateMeasurement`](crate::ParticulateMeasurement).
+    Diameter,
+    /// Readings are labeled with their sensor and one of the
+    /// [`DeviceStatus::FLAGS`](crate::DeviceStatus::FLAGS), as a
+    /// [`DeviceStatusMeasurement`](crate::DeviceStatusMeasurement).
+    DeviceStatusFlag,
+}
+
+/// The unit of a metric.
+#[derive(Copy, Clone, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "fmt", derive(Debug))]
+#[serde(rename_all = "snake_case")]
+pub enum Unit {
+    Celsius,
+    Ppm,
+    Ppb,
+    Percent,
+    GramsPerCubicMeter,
+    Hectopascals,
+    Ohms,
+    MicrogramsPerCubicMeter,
+    /// Particles per 0.1L of air.
+    ParticlesPerDeciliter,
+    ParticlesPerCubicCentimeter,
+    /// An Indoor Air Quality (IAQ) index from 0-500.
+    IaqIndex,
+    /// A US EPA Air Quality Index.
+    Aqi,
+    Seconds,
+}
+
+// === impl Unit ===
+
+impl Unit {
+    /// Returns the unit's symbol, for displaying alongside readings, or `None`
+    /// if the unit is an index which is shown without one.
+    pub const fn symbol(&self) -> Option<&'static str> {
+        match self {
+            Self::Celsius => Some("°C"),
+            Self::Ppm => Some("ppm"),
+            Self::Ppb => Some("ppb"),
+            Self::Percent => Some("%"),
+            Self::GramsPerCubicMeter => Some("g/m³"),
+            Self::Hectopascals => Some("hPa"),
+            Self::Ohms => Some("Ω"),
+            Self::MicrogramsPerCubicMeter => Some("µg/m³"),
+            Self::ParticlesPerDeciliter => Some("particles/0.1L"),
+            Self::ParticlesPerCubicCentimeter => Some("particles/cm³"),
+            Self::Seconds => Some("s"),
+            Self::IaqIndex | Self::Aqi => None,
+        }
+    }
+
+    /// Returns the unit as it appears in the Prometheus exposition.
+    pub const fn prometheus(&self) -> &'static str {
+        match self {
+            Self::Celsius => "celcius",
+            Self::Ppm => "ppm",
+            Self::Ppb => "ppb",
+            Self::Percent => "percent",
+            Self::GramsPerCubicMeter => "g/m^3",
+            Self::Hectopascals => "hPa",
+            Self::Ohms => "Ohms",
+            Self::MicrogramsPerCubicMeter => "ug/m^3",
+            Self::ParticlesPerDeciliter => "particulates per 0.1L",
+            Self::ParticlesPerCubicCentimeter => "particles/cm^3",
+            Self::IaqIndex => "IAQ index",
+            Self::Aqi => "AQI",
+            Self::Seconds => "seconds",
+        }
+    }
+}
+
+// === metrics ===
+
+const fn gauge(
+    name: &'static str,
+    prometheus_name: &'static str,
+    help: &'static str,
+    unit: Option<Unit>,
+    labels: Labels,
+) -> Metric {
+    Metric {
+        name,
+        prometheus_name,
+        help,
+        unit,
+        kind: Kind::Gauge,
+        labels,
+    }
+}
+
+pub const TEMP_C: Metric = gauge(
+    "temp_c",
+    "temperature_degrees_celcius",
+    "Temperature in degrees Celcius.",
+    Some(Unit::Celsius),
+    Labels::Sensor,
+);
+
+pub const CO2_PPM: Metric = gauge(
+    "co2_ppm",
+    "co2_ppm",
+    "CO2 in parts per million (ppm).",
+    Some(Unit::Ppm),
+    Labels::Sensor,
+);
+
+pub const ECO2_PPM: Metric = gauge(
+    "eco2_ppm",
+    "eco2_ppm",
+    "VOC equivalent CO2 (eCO2) calculated by a tVOC sensor, in parts per million (ppm).",
+    Some(Unit::Ppm),
+    Labels::Sensor,
+);
+
+pub const REL_HUMIDITY_PERCENT: Metric = gauge(
+    "rel_humidity_percent",
+    "humidity_percent",
+    "Relative humidity (RH) percentage.",
+    Some(Unit::Percent),
+    Labels::Sensor,
+);
+
+pub const ABS_HUMIDITY_GRAMS_M3: Metric = gauge(
+    "abs_humidity_grams_m3",
+    "absolute_humidity_grams_m3",
+    "Absolute humidity in grams per cubic meter.",
+    Some(Unit::GramsPerCubicMeter),
+    Labels::Sensor,
+);
+
+pub const PRESSURE_HPA: Metric = gauge(
+    "pressure_hpa",
+    "pressure_hpa",
+    "Barometric pressure, in hectopascals (hPa).",
+    Some(Unit::Hectopascals),
+    Labels::Sensor,
+);
+
+pub const GAS_RESISTANCE: Metric = gauge(
+    "gas_resistance",
+    "gas_resistance_ohms",
+    "BME680 VOC sensor resistance, in Ohms.",
+    Some(Unit::Ohms),
+    Labels::Sensor,
+);
+
+pub const TVOC_PPB: Metric = gauge(
+    "tvoc_ppb",
+    "tvoc_ppb",
+    "Total Volatile Organic Compounds (VOC) in parts per billion (ppb)",
+    Some(Unit::Ppb),
+    Labels::Sensor,
+);
+
+pub const TVOC_IAQ_INDEX: Metric = gauge(
+    "tvoc_iaq_index",
+    "tvoc_iaq_index",
+    "Total Volatile Organic Compounds (VOC) Indoor Air Quality (IAQ) Index from 0-500",
+    Some(Unit::IaqIndex),
+    Labels::Sensor,
+);
+
+pub const NOX_IAQ_INDEX: Metric = gauge(
+    "nox_iaq_index",
+    "nox_iaq_index",
+    "Nitrogen Oxides (NOx) Indoor Air Quality (IAQ) Index from 0-500",
+    Some(Unit::IaqIndex),
+    Labels::Sensor,
+);
+
+pub const AQI: Metric = gauge(
+    "aqi",
+    "us_epa_aqi",
+    "US EPA Air Quality Index (AQI) computed from PM2.5 and PM10 concentrations",
+    Some(Unit::Aqi),
+    Labels::Sensor,
+);
+
+pub const CO2_COMFORT_BAND: Metric = gauge(
+    "co2_comfort_band",
+    "co2_comfort_band",
+    "CO2 comfort band, from 1 (excellent, up to 800 ppm) to 5 (bad, over 2000 ppm)",
+    None,
+    Labels::Sensor,
+);
+
+pub const TVOC_IAQ_CATEGORY: Metric = gauge(
+    "tvoc_iaq_category",
+    "tvoc_iaq_category",
+    "Indoor Air Quality (IAQ) category computed from tVOC, from 1 (excellent) to 5 (unhealthy)",
+    None,
+    Labels::Sensor,
+);
+
+pub const PM_CONCENTRATION: Metric = gauge(
+    "pm_concentration_ug_m3",
+    "pm_concentration_ug_m3",
+    "Particulate matter concentration in ug/m^3",
+    Some(Unit::MicrogramsPerCubicMeter),
+    Labels::Diameter,
+);
+
+pub const PM_CONCENTRATION_STANDARD: Metric = gauge(
+    "pm_concentration_standard_ug_m3",
+    "pm_concentration_standard_ug_m3",
+    "Particulate matter concentration under standard atmospheric conditions in ug/m^3",
+    Some(Unit::MicrogramsPerCubicMeter),
+    Labels::Diameter,
+);
+
+pub const PM_COUNT: Metric = gauge(
+    "pm_count",
+    "pm_count",
+    "Particulate matter count per 0.1L of air.",
+    Some(Unit::ParticlesPerDeciliter),
+    Labels::Diameter,
+);
+
+pub const PM_NUMBER_CONCENTRATION: Metric = gauge(
+    "pm_number_concentration_cm3",
+    "pm_number_concentration_cm3",
+    "Number concentration of particulate matter up to each diameter, in particles per cm^3.",
+    Some(Unit::ParticlesPerCubicCentimeter),
+    Labels::Diameter,
+);
+
+pub const DEVICE_STATUS: Metric = gauge(
+    "device_status",
+    "sensor_device_status",
+    "Conditions reported by a sensor's self-diagnostics (1 if present, 0 if not)",
+    None,
+    Labels::DeviceStatusFlag,
+);
+
+pub const SENSOR_ERRORS: Metric = Metric {
+    name: "sensor_errors",
+    prometheus_name: "sensor_error_count",
+    help: "Count of I2C errors that occurred while talking to a sensor",
+    unit: None,
+    kind: Kind::Counter,
+    labels: Labels::Sensor,
+};
+
+pub const SENSOR_RESET_COUNT: Metric = Metric {
+    name: "sensor_reset_count",
+    prometheus_name: "sensor_reset_count",
+    help: "The number of times a sensor was reset successfully",
+    unit: None,
+    kind: Kind::Counter,
+    labels: Labels::Sensor,
+};
+
+pub const READING_AGE: Metric = gauge(
+    "reading_age_secs",
+    "sensor_reading_age_seconds",
+    "Time since a sensor was last polled successfully, in seconds",
+    Some(Unit::Seconds),
+    Labels::Sensor,
+);
+
+/// Every metric exported by an ECLSS node, in the order they're exported.
+pub const ALL: &[Metric] = &[
+    TEMP_C,
+    CO2_PPM,
+    ECO2_PPM,
+    REL_HUMIDITY_PERCENT,
+    ABS_HUMIDITY_GRAMS_M3,
+    PRESSURE_HPA,
+    GAS_RESISTANCE,
+    TVOC_PPB,
+    TVOC_IAQ_INDEX,
+    NOX_IAQ_INDEX,
+    AQI,
+    CO2_COMFORT_BAND,
+    TVOC_IAQ_CATEGORY,
+    PM_CONCENTRATION,
+    PM_CONCENTRATION_STANDARD,
+    PM_COUNT,
+    PM_NUMBER_CONCENTRATION,
+    DEVICE_STATUS,
+    SENSOR_ERRORS,
+    SENSOR_RESET_COUNT,
+    READING_AGE,
+];
+
+/// Returns the metric with the given [`Metric::name`], if there is one.
+pub fn get(name: &str) -> Option<&'static Metric> {
+    ALL.iter().find(|metric| metric.name == name)
+}
+
+// === impl Metric ===
+
+impl Metric {
+    /// Returns the symbol of the metric's unit, if it has one. See
+    /// [`Unit::symbol`].
+    pub const fn unit_symbol(&self) -> Option<&'static str> {
+        match self.unit {
+            Some(unit) => unit.symbol(),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_unique() {
+        for (i, metric) in ALL.iter().enumerate() {
+            for other in &ALL[i + 1..] {
+                assert_ne!(metric.name, other.name);
+                assert_ne!(metric.prometheus_name, other.prometheus_name);
+            }
+        }
+    }
+}
@@ -17,16 +17,42 @@ pub struct TraceArgs {
     /// This is intended for use in environments where timestamps are added by
     /// an external logging system, such as when running as a systemd service or
     /// in a container runtime.
-    #[clap(long, env = "ECLSS_LOG_NO_TIMESTAMPS")]
+    #[clap(long, env = "ECLSS_LOG_NO_TIMESTAMPS", overrides_with = "timestamps")]
     no_timestamps: bool,
 
+    /// Enable timestamps in trace events, overriding `--no-timestamps`.
+    #[clap(long, overrides_with = "no_timestamps")]
+    timestamps: bool,
+
     /// If true, disable ANSI formatting escape codes in tracing output.
-    #[clap(long, env = "NO_COLOR")]
+    #[clap(long, env = "NO_COLOR", overrides_with = "color")]
     no_color: bool,
+
+    /// Enable ANSI formatting escape codes in tracing output, overriding
+    /// `--no-color`.
+    #[clap(long, overrides_with = "no_color")]
+    color: bool,
+}
+
+/// Tracing settings loaded from a configuration file.
+#[cfg(feature = "serde")]
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TraceConfig {
+    /// Tracing-subscriber filter configuration, as in `--trace`.
+    filter: Option<String>,
+    /// Trace output format, as in `--trace-format`.
+    format: Option<TraceFormat>,
+    /// Whether to include timestamps in trace events.
+    timestamps: Option<bool>,
+    /// Whether to use ANSI formatting escape codes in tracing output.
+    color: Option<bool>,
 }
 
 #[derive(clap::ValueEnum, Debug, Copy, Clone, Default)]
 #[clap(rename_all = "lower")]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum TraceFormat {
     /// Human-readable text logging format.
     #[default]
@@ -44,6 +70,46 @@ impl TraceArgs {
         self.trace_init_with_default_format(TraceFormat::default())
     }
 
+    /// Sets any settings in `config` which were not set on the command line or
+    /// by environment variables, according to `matches`.
+    #[cfg(feature = "serde")]
+    pub fn merge_config(
+        &mut self,
+        config: TraceConfig,
+        matches: &clap::ArgMatches,
+    ) -> anyhow::Result<()> {
+        use clap::parser::ValueSource;
+        let from_cli = |id: &str| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        };
+        if let Some(filter) = config.filter.filter(|_| !from_cli("filter")) {
+            self.filter = filter
+                .parse()
+                .with_context(|| format!("invalid trace filter {filter:?}"))?;
+        }
+        if let Some(format) = config.format.filter(|_| !from_cli("format")) {
+            self.format = Some(format);
+        }
+        if let Some(timestamps) = config
+            .timestamps
+            .filter(|_| !from_cli("no_timestamps") && !from_cli("timestamps"))
+        {
+            self.no_timestamps = !timestamps;
+            self.timestamps = timestamps;
+        }
+        if let Some(color) = config
+            .color
+            .filter(|_| !from_cli("no_color") && !from_cli("color"))
+        {
+            self.no_color = !color;
+            self.color = color;
+        }
+        Ok(())
+    }
+
     pub fn trace_init_with_default_format(&self, default: TraceFormat) {
         if let Some(format) = self.format {
             match self.init_format(format) {
@@ -58,6 +124,18 @@ impl TraceArgs {
             .expect("default format must initialize")
     }
 
+    /// Returns `true` if timestamps are disabled. `--timestamps` takes
+    /// precedence over `ECLSS_LOG_NO_TIMESTAMPS`.
+    fn no_timestamps(&self) -> bool {
+        self.no_timestamps && !self.timestamps
+    }
+
+    /// Returns `true` if ANSI formatting is disabled. `--color` takes
+    /// precedence over `NO_COLOR`.
+    fn no_color(&self) -> bool {
+        self.no_color && !self.color
+    }
+
     fn init_format(&self, format: TraceFormat) -> anyhow::Result<()> {
         use tracing_subscriber::prelude::*;
         match format {
@@ -83,7 +161,7 @@ impl TraceArgs {
                     .with_thread_ids(true);
 
                 let registry = tracing_subscriber::registry().with(self.filter.clone());
-                if self.no_timestamps {
+                if self.no_timestamps() {
                     registry.with(fmt.without_time()).init();
                 } else {
                     registry.with(fmt).init();
@@ -93,9 +171,9 @@ impl TraceArgs {
                 let registry = tracing_subscriber::registry().with(self.filter.clone());
                 let fmt = tracing_subscriber::fmt::layer()
                     .with_thread_ids(true)
-                    .with_ansi(!self.no_color)
+                    .with_ansi(!self.no_color())
                     .pretty();
-                if self.no_timestamps {
+                if self.no_timestamps() {
                     registry.with(fmt.without_time()).init();
                 } else {
                     registry.with(fmt).init();
@@ -105,8 +183,8 @@ impl TraceArgs {
                 let registry = tracing_subscriber::registry().with(self.filter.clone());
                 let fmt = tracing_subscriber::fmt::layer()
                     .with_thread_ids(true)
-                    .with_ansi(!self.no_color);
-                if self.no_timestamps {
+                    .with_ansi(!self.no_color());
+                if self.no_timestamps() {
                     registry.with(fmt.without_time()).init();
                 } else {
                     registry.with(fmt).init();
@@ -0,0 +1,23 @@
+//! The `/alerts.json` endpoint.
+//!
+//! `eclss-axum` does not evaluate alert rules itself; instead, the
+//! application provides an [`AlertSource`] which reports the currently active
+//! alerts.
+use axum::{extract::State, response::Json, routing::get, Router};
+use eclss_api::alerts::Alert;
+
+/// A source of active alerts.
+pub trait AlertSource: Send + Sync + 'static {
+    /// Returns all alerts which are currently pending or firing.
+    fn active_alerts(&self) -> Vec<Alert>;
+}
+
+pub fn routes(source: &'static impl AlertSource) -> Router {
+    Router::new()
+        .route("/alerts.json", get(get_alerts))
+        .with_state(source as &'static dyn AlertSource)
+}
+
+async fn get_alerts(State(source): State<&'static dyn AlertSource>) -> Json<Vec<Alert>> {
+    Json(source.active_alerts())
+}
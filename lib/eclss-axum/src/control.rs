@@ -0,0 +1,172 @@
+//! The sensor control endpoints:
+//!
+//! - `POST /sensors/{sensor}/calibrate`: forced CO₂ recalibration.
+//! - `POST /sensors/{sensor}/asc`: enable or disable automatic self-calibration.
+//! - `POST /sensors/{sensor}/reset`: reset and re-initialize the sensor.
+//! - `POST /sensors/{sensor}/reset-baseline`: discard the sensor's learned
+//!   baseline calibration.
+//! - `POST /sensors/{sensor}/clean-fan`: start the sensor's fan cleaning.
+//!
+//! Commands are sent to the sensor's task through its [`Commands`] channel,
+//! and the response is returned once the sensor has executed them.
+//!
+//! [`Commands`]: eclss::sensor::Commands
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::post,
+    Router,
+};
+use eclss::sensor::{self, Command, CommandError, Registry};
+use eclss_api::{
+    control::{AscRequest, AscResponse, CalibrateRequest, CalibrateResponse, REFERENCE_PPM},
+    SensorId,
+};
+use std::time::Duration;
+
+/// How long to wait for a sensor to execute a command.
+///
+/// Commands are executed between polls, so this must be longer than the
+/// slowest sensor's poll.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub(crate) fn routes<const SENSORS: usize>(sensors: &'static Registry<SENSORS>) -> Router {
+    Router::new()
+        .route("/sensors/:sensor/calibrate", post(post_calibrate))
+        .route("/sensors/:sensor/asc", post(post_asc))
+        .route("/sensors/:sensor/reset", post(post_reset))
+        .route("/sensors/:sensor/reset-baseline", post(post_reset_baseline))
+        .route("/sensors/:sensor/clean-fan", post(post_clean_fan))
+        .with_state(sensors)
+}
+
+async fn post_calibrate<const SENSORS: usize>(
+    State(sensors): State<&'static Registry<SENSORS>>,
+    Path(sensor): Path<SensorId>,
+    Json(CalibrateRequest { reference_ppm }): Json<CalibrateRequest>,
+) -> Response {
+    if !REFERENCE_PPM.contains(&reference_ppm) {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "reference CO₂ concentration must be between {} and {} ppm",
+                REFERENCE_PPM.start(),
+                REFERENCE_PPM.end()
+            ),
+        )
+            .into_response();
+    }
+    let command = Command::ForcedRecalibration { reference_ppm };
+    match send(sensors, sensor, command).await {
+        Ok(sensor::Response::Recalibrated { correction_ppm }) => Json(CalibrateResponse {
+            sensor,
+            correction_ppm,
+        })
+        .into_response(),
+        Ok(response) => unexpected(sensor, command, response),
+        Err(error) => error,
+    }
+}
+
+async fn post_asc<const SENSORS: usize>(
+    State(sensors): State<&'static Registry<SENSORS>>,
+    Path(sensor): Path<SensorId>,
+    Json(AscRequest { enabled }): Json<AscRequest>,
+) -> Response {
+    let command = Command::SetAutomaticSelfCalibration { enabled };
+    match send(sensors, sensor, command).await {
+        Ok(sensor::Response::AutomaticSelfCalibration { enabled }) => {
+            Json(AscResponse { sensor, enabled }).into_response()
+        }
+        Ok(response) => unexpected(sensor, command, response),
+        Err(error) => error,
+    }
+}
+
+async fn post_reset<const SENSORS: usize>(
+    State(sensors): State<&'static Registry<SENSORS>>,
+    Path(sensor): Path<SensorId>,
+) -> Response {
+    post_done(sensors, sensor, Command::Reset).await
+}
+
+async fn post_reset_baseline<const SENSORS: usize>(
+    State(sensors): State<&'static Registry<SENSORS>>,
+    Path(sensor): Path<SensorId>,
+) -> Response {
+    post_done(sensors, sensor, Command::ResetBaseline).await
+}
+
+async fn post_clean_fan<const SENSORS: usize>(
+    State(sensors): State<&'static Registry<SENSORS>>,
+    Path(sensor): Path<SensorId>,
+) -> Response {
+    post_done(sensors, sensor, Command::CleanFan).await
+}
+
+/// Sends a `command` which has no result to `sensor`, responding with
+/// `204 No Content` if it succeeds.
+async fn post_done<const SENSORS: usize>(
+    sensors: &'static Registry<SENSORS>,
+    sensor: SensorId,
+    command: Command,
+) -> Response {
+    match send(sensors, sensor, command).await {
+        Ok(sensor::Response::Done) => StatusCode::NO_CONTENT.into_response(),
+        Ok(response) => unexpected(sensor, command, response),
+        Err(error) => error,
+    }
+}
+
+/// Sends `command` to `sensor`, returning an error response if it fails.
+async fn send<const SENSORS: usize>(
+    sensors: &'static Registry<SENSORS>,
+    sensor: SensorId,
+    command: Command,
+) -> Result<sensor::Response, Response> {
+    let Some(state) = sensors
+        .iter()
+        .find(|(id, _)| **id == sensor)
+        .map(|(_, state)| state)
+    else {
+        return Err((StatusCode::NOT_FOUND, format!("no sensor {sensor}")).into_response());
+    };
+    let status = state.status();
+    if !status.is_present() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("{sensor} is not connected ({status})"),
+        )
+            .into_response());
+    }
+
+    let error = match tokio::time::timeout(COMMAND_TIMEOUT, state.commands().send(command)).await {
+        Ok(Ok(response)) => return Ok(response),
+        Ok(Err(CommandError::Unsupported)) => (
+            StatusCode::BAD_REQUEST,
+            format!("{sensor} does not support {command}"),
+        ),
+        Ok(Err(CommandError::Busy)) => (
+            StatusCode::CONFLICT,
+            format!("another command is already in progress on {sensor}"),
+        ),
+        Ok(Err(CommandError::Sensor(status))) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("{sensor} failed to execute {command} ({status})"),
+        ),
+        Err(_) => (
+            StatusCode::GATEWAY_TIMEOUT,
+            format!("{sensor} did not execute {command} within {COMMAND_TIMEOUT:?}"),
+        ),
+    };
+    Err(error.into_response())
+}
+
+fn unexpected(sensor: SensorId, command: Command, response: sensor::Response) -> Response {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("{sensor} returned an unexpected response to {command}: {response:?}"),
+    )
+        .into_response()
+}
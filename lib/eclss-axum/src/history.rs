@@ -0,0 +1,77 @@
+//! The `/history.json` endpoint.
+//!
+//! `eclss-axum` does not record history itself; instead, the application
+//! provides a [`HistorySource`] which answers [`HistoryQuery`]s.
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use eclss_api::{history::History, SensorId};
+use serde::{de, Deserialize, Deserializer};
+use std::time::Duration;
+
+/// A source of downsampled historical readings.
+pub trait HistorySource: Send + Sync + 'static {
+    /// Returns the history for the requested metric, or `None` if the
+    /// metric is not recorded.
+    fn history(&self, query: &HistoryQuery) -> Option<History>;
+}
+
+/// Query parameters for `/history.json`.
+///
+/// For example: `/history.json?metric=co2_ppm&since=1h&step=1m`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct HistoryQuery {
+    /// The metric to return, as named in `/metrics.json`.
+    pub metric: String,
+    /// If present, only return readings from this sensor.
+    #[serde(default)]
+    pub sensor: Option<SensorId>,
+    /// If present, only return readings of a particulate matter metric for
+    /// this particle diameter, in µm (e.g. `2.5`).
+    #[serde(default)]
+    pub diameter: Option<String>,
+    /// How far back to return readings for. If this is not present, all
+    /// retained readings are returned.
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub since: Option<Duration>,
+    /// The interval between returned samples. If this is not present, the
+    /// resolution at which readings are recorded is used.
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub step: Option<Duration>,
+}
+
+pub fn routes(source: &'static impl HistorySource) -> Router {
+    Router::new()
+        .route("/history.json", get(get_history))
+        .with_state(source as &'static dyn HistorySource)
+}
+
+async fn get_history(
+    State(source): State<&'static dyn HistorySource>,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    match source.history(&query) {
+        Some(history) => Json(history).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            format!("no history for metric {:?}", query.metric),
+        )
+            .into_response(),
+    }
+}
+
+fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let Some(s) = Option::<String>::deserialize(deserializer)? else {
+        return Ok(None);
+    };
+    humantime::parse_duration(&s)
+        .map(Some)
+        .map_err(de::Error::custom)
+}
@@ -7,8 +7,13 @@ use axum::{
     Router,
 };
 use eclss::{sensor::Registry, Eclss, SensorMetrics};
+use eclss_api::schema;
 use std::sync::Arc;
 
+pub mod alerts;
+mod control;
+pub mod history;
+
 #[derive(Clone)]
 struct AppState<const SENSORS: usize> {
     metrics: &'static SensorMetrics,
@@ -24,12 +29,14 @@ pub fn app<I, const SENSORS: usize>(
         .route("/metrics", get(get_metrics))
         .route("/metrics.json", get(get_metrics_json))
         .route("/sensors.json", get(get_sensors))
+        .route("/schema.json", get(get_schema))
         .route("/", get(index))
         .with_state(AppState {
             metrics: eclss.metrics(),
             sensors: eclss.sensors(),
             location,
         })
+        .merge(control::routes(eclss.sensors()))
         .fallback(not_found)
 }
 
@@ -65,6 +72,19 @@ async fn get_sensors<const SENSORS: usize>(
     Json(sensors)
 }
 
+#[derive(serde::Serialize)]
+struct SchemaResponse {
+    version: u32,
+    metrics: &'static [schema::Metric],
+}
+
+async fn get_schema() -> Json<SchemaResponse> {
+    Json(SchemaResponse {
+        version: schema::VERSION,
+        metrics: schema::ALL,
+    })
+}
+
 async fn index() -> Html<&'static str> {
     Html(
         "<!DOCTYPE html>\
@@ -78,6 +98,9 @@ async fn index() -> Html<&'static str> {
                 <li><a href=\"/metrics\">Metrics (Prometheus)</a></li>\
                 <li><a href=\"/metrics.json\">Metrics (JSON)</a></li>\
                 <li><a href=\"/sensors.json\">Sensors (JSON)</a></li>\
+                <li><a href=\"/schema.json\">Metrics schema (JSON)</a></li>\
+                <li><a href=\"/history.json?metric=co2_ppm&amp;since=1h\">CO₂ history (JSON)</a></li>\
+                <li><a href=\"/alerts.json\">Active alerts (JSON)</a></li>\
             </ul>\
         </body>\
         </html>",
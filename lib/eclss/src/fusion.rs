@@ -0,0 +1,340 @@
+//! Sensor fusion: combining several sensors' readings of the same quantity
+//! into a single best estimate.
+//!
+//! Many sensors measure the same quantities: the SCD41, SHT41, BME680 and
+//! SEN55 all report temperature and humidity, for example. Rather than leaving
+//! each consumer to average them, [`Eclss::run_fusion`] periodically computes a
+//! weighted mean of each [`Quantity`], and records it as the [`FUSED`]
+//! pseudo-sensor's reading of the same metric.
+//!
+//! Sensors whose [`Status`] isn't [`Status::Up`], or whose readings are stale,
+//! are excluded from the estimate. When three or more sensors report a
+//! quantity, readings too far from the median are rejected as outliers.
+use crate::{metrics::Gauge, sensor::Status, Eclss, SensorMetrics};
+use core::{fmt, str::FromStr, time::Duration};
+use eclss_api::{air_quality::Co2Band, SensorId, SensorName};
+use embedded_hal_async::delay::DelayNs;
+use tinymetrics::GaugeFamily;
+
+/// The sensor ID of fused readings.
+pub const FUSED: SensorId = SensorId::new(SensorName::Fused);
+
+/// The maximum number of sensors whose readings of a quantity are combined.
+pub(crate) const MAX_INPUTS: usize = 32;
+
+/// Sensor fusion configuration.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// How often to compute fused estimates.
+    pub interval: Duration,
+
+    /// Per-sensor weights. A sensor's readings count towards the estimate in
+    /// proportion to its weight, so a sensor which is known to be less
+    /// accurate may be given a lower weight, and a weight of 0 excludes a
+    /// sensor entirely. Sensors without a weight have a weight of 1.
+    ///
+    /// If more than one weight matches a sensor, the last one is used.
+    ///
+    /// Readings are combined after each sensor's own
+    /// [`Calibration`](crate::sensor::Calibration) has been applied, so a
+    /// sensor which reads hot should be calibrated, rather than given a lower
+    /// weight.
+    pub weights: &'static [Adjustment],
+}
+
+/// A weight for one sensor's readings of one or all quantities.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Adjustment {
+    pub sensor: SensorId,
+    /// The quantity this adjustment applies to, or `None` if it applies to all
+    /// of the sensor's readings.
+    pub quantity: Option<Quantity>,
+    pub value: f64,
+}
+
+/// A quantity measured by more than one sensor model.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Quantity {
+    /// Temperature, in °C.
+    Temp,
+    /// Relative humidity, in percent.
+    Humidity,
+    /// CO₂ concentration, in ppm.
+    Co2,
+    /// Barometric pressure, in hPa.
+    Pressure,
+}
+
+/// Returned when parsing an unknown [`Quantity`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct UnknownQuantity;
+
+/// The fused sensor's gauges.
+struct Gauges {
+    temp_c: &'static Gauge,
+    rel_humidity_percent: &'static Gauge,
+    abs_humidity_grams_m3: &'static Gauge,
+    co2_ppm: &'static Gauge,
+    co2_comfort_band: &'static Gauge,
+    pressure_hpa: &'static Gauge,
+    last_reading: &'static Gauge,
+}
+
+impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
+    /// Periodically computes fused estimates of each [`Quantity`], until the
+    /// ECLSS is [shut down](Eclss::shutdown).
+    ///
+    /// The fused readings only become [stale](Eclss::with_max_reading_age) if
+    /// a quantity which could previously be estimated no longer can be, such
+    /// as when every CO₂ sensor has failed.
+    pub async fn run_fusion(
+        &'static self,
+        config: Config,
+        mut delay: impl DelayNs,
+    ) -> Result<(), &'static str> {
+        let metrics = &self.metrics;
+        let gauges =
+            Gauges::register(metrics).ok_or("insufficient space in metrics for fused readings")?;
+        // the quantities which have been estimated before.
+        let mut estimated = [false; Quantity::ALL.len()];
+
+        loop {
+            let interval = delay.delay_ms(config.interval.as_millis() as u32);
+            if self.wait_unless_shutdown(interval).await {
+                return Ok(());
+            }
+
+            let now = metrics.now();
+            let temp = self.estimate(&metrics.temp_c, Quantity::Temp, &config, now);
+            let humidity = self.estimate(
+                &metrics.rel_humidity_percent,
+                Quantity::Humidity,
+                &config,
+                now,
+            );
+            let co2 = self.estimate(&metrics.co2_ppm, Quantity::Co2, &config, now);
+            let pressure = self.estimate(&metrics.pressure_hpa, Quantity::Pressure, &config, now);
+
+            if let Some(temp) = temp {
+                gauges.temp_c.set_value(temp);
+            }
+            if let Some(humidity) = humidity {
+                gauges.rel_humidity_percent.set_value(humidity);
+            }
+            if let (Some(temp), Some(humidity)) = (temp, humidity) {
+                let abs_humidity = crate::sensor::absolute_humidity(temp as f32, humidity as f32);
+                gauges.abs_humidity_grams_m3.set_value(abs_humidity.into());
+            }
+            if let Some(co2) = co2 {
+                gauges.co2_ppm.set_value(co2);
+                let band = Co2Band::from_ppm(co2);
+                gauges.co2_comfort_band.set_value(band.level().into());
+            }
+            if let Some(pressure) = pressure {
+                gauges.pressure_hpa.set_value(pressure);
+            }
+
+            let mut complete = true;
+            for (estimated, estimate) in estimated.iter_mut().zip([temp, humidity, co2, pressure]) {
+                if estimate.is_some() {
+                    *estimated = true;
+                } else if *estimated {
+                    complete = false;
+                }
+            }
+            if complete && estimated.contains(&true) {
+                metrics.record_reading(gauges.last_reading);
+            } else if !complete {
+                debug!("not all previously fused quantities could be estimated");
+            }
+        }
+    }
+
+    /// Returns the fused estimate of `quantity` from the readings in `family`,
+    /// or `None` if no sensors' readings can be used.
+    fn estimate<const N: usize>(
+        &self,
+        family: &GaugeFamily<'static, N, SensorId>,
+        quantity: Quantity,
+        config: &Config,
+        now: Option<u64>,
+    ) -> Option<f64> {
+        let mut readings = [(0.0, 0.0); MAX_INPUTS];
+        let mut len = 0;
+        for (&sensor, gauge) in family.metrics().iter() {
+            if sensor.is_fused()
+                || !gauge.has_been_recorded()
+                || !self.is_up(&sensor)
+                || self.metrics.is_stale(&sensor, now)
+            {
+                continue;
+            }
+            let weight = config.weight(sensor, quantity);
+            if weight <= 0.0 || len == MAX_INPUTS {
+                continue;
+            }
+            readings[len] = (gauge.value(), weight);
+            len += 1;
+        }
+        weighted_mean(&mut readings[..len], quantity.tolerance())
+    }
+
+    fn is_up(&self, sensor: &SensorId) -> bool {
+        self.sensors
+            .iter()
+            .find(|(id, _)| *id == sensor)
+            .is_some_and(|(_, state)| state.status() == Status::Up)
+    }
+}
+
+/// Returns the weighted mean of `readings`, which are `(value, weight)` pairs,
+/// excluding outliers, or `None` if there are no readings.
+///
+/// If there are at least three readings, a reading is an outlier if it differs
+/// from the median by more than `tolerance`, or by more than three times the
+/// (normalized) median absolute deviation, whichever is greater.
+pub(crate) fn weighted_mean(readings: &mut [(f64, f64)], tolerance: f64) -> Option<f64> {
+    if readings.is_empty() {
+        return None;
+    }
+
+    let mut max_deviation = f64::INFINITY;
+    let mut median = 0.0;
+    if readings.len() >= 3 {
+        readings.sort_unstable_by(|(a, _), (b, _)| a.total_cmp(b));
+        median = middle(readings.iter().map(|&(value, _)| value), readings.len());
+        let mut deviations = [0.0; MAX_INPUTS];
+        for (deviation, &(value, _)) in deviations.iter_mut().zip(readings.iter()) {
+            *deviation = (value - median).abs();
+        }
+        let deviations = &mut deviations[..readings.len()];
+        deviations.sort_unstable_by(f64::total_cmp);
+        // scaled so that it estimates the standard deviation of normally
+        // distributed readings.
+        let mad = 1.4826 * middle(deviations.iter().copied(), deviations.len());
+        max_deviation = tolerance.max(3.0 * mad);
+    }
+
+    let (sum, weights) = readings
+        .iter()
+        .filter(|(value, _)| (value - median).abs() <= max_deviation)
+        .fold((0.0, 0.0), |(sum, weights), &(value, weight)| {
+            (sum + value * weight, weights + weight)
+        });
+    if weights > 0.0 {
+        Some(sum / weights)
+    } else {
+        None
+    }
+}
+
+/// Returns the median of `len` sorted `values`.
+fn middle(mut values: impl Iterator<Item = f64>, len: usize) -> f64 {
+    let lower = values.nth((len - 1) / 2).unwrap_or(0.0);
+    if len.is_multiple_of(2) {
+        (lower + values.next().unwrap_or(lower)) / 2.0
+    } else {
+        lower
+    }
+}
+
+// === impl Config ===
+
+impl Config {
+    /// Returns the weight of `sensor`'s readings of `quantity`.
+    #[must_use]
+    pub fn weight(&self, sensor: SensorId, quantity: Quantity) -> f64 {
+        Adjustment::find(self.weights, sensor, quantity).unwrap_or(1.0)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10),
+            weights: &[],
+        }
+    }
+}
+
+// === impl Adjustment ===
+
+impl Adjustment {
+    fn find(adjustments: &[Self], sensor: SensorId, quantity: Quantity) -> Option<f64> {
+        adjustments
+            .iter()
+            .rev()
+            .find(|adjustment| {
+                adjustment.sensor == sensor && adjustment.quantity.is_none_or(|q| q == quantity)
+            })
+            .map(|adjustment| adjustment.value)
+    }
+}
+
+// === impl Quantity ===
+
+impl Quantity {
+    pub const ALL: [Self; 4] = [Self::Temp, Self::Humidity, Self::Co2, Self::Pressure];
+
+    #[must_use]
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Temp => "temp",
+            Self::Humidity => "humidity",
+            Self::Co2 => "co2",
+            Self::Pressure => "pressure",
+        }
+    }
+
+    /// Readings which differ from the median by less than this are never
+    /// rejected as outliers.
+    pub(crate) const fn tolerance(&self) -> f64 {
+        match self {
+            Self::Temp => 1.5,
+            Self::Humidity => 8.0,
+            Self::Co2 => 150.0,
+            Self::Pressure => 3.0,
+        }
+    }
+}
+
+impl FromStr for Quantity {
+    type Err = UnknownQuantity;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|quantity| quantity.as_str().eq_ignore_ascii_case(s))
+            .ok_or(UnknownQuantity)
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl fmt::Display for UnknownQuantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("unknown quantity, expected one of: temp, humidity, co2, pressure")
+    }
+}
+
+impl core::error::Error for UnknownQuantity {}
+
+// === impl Gauges ===
+
+impl Gauges {
+    fn register(metrics: &'static SensorMetrics) -> Option<Self> {
+        Some(Self {
+            temp_c: metrics.temp_c.register(FUSED)?,
+            rel_humidity_percent: metrics.rel_humidity_percent.register(FUSED)?,
+            abs_humidity_grams_m3: metrics.abs_humidity_grams_m3.register(FUSED)?,
+            co2_ppm: metrics.co2_ppm.register(FUSED)?,
+            co2_comfort_band: metrics.co2_comfort_band.register(FUSED)?,
+            pressure_hpa: metrics.pressure_hpa.register(FUSED)?,
+            last_reading: metrics.register_reading(FUSED)?,
+        })
+    }
+}
@@ -1,40 +1,54 @@
 #![cfg_attr(not(feature = "std"), no_std)]
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::time::Duration;
 use embedded_hal::i2c;
 use embedded_hal_async::i2c::I2c;
-use maitake_sync::Mutex;
+use maitake_sync::{Mutex, WaitQueue};
 
 #[macro_use]
 mod trace;
 
 pub use self::metrics::SensorMetrics;
 pub mod error;
+pub mod fusion;
 pub mod metrics;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod probe;
 pub mod retry;
 pub mod sensor;
+#[cfg(all(feature = "serde", feature = "std"))]
+mod serde_duration;
 pub mod storage;
 
-pub struct Eclss<I, const SENSORS: usize> {
+pub struct Eclss<I: 'static, const SENSORS: usize> {
     pub(crate) metrics: SensorMetrics,
     pub(crate) i2c: SharedBus<I>,
     pub(crate) sensors: sensor::Registry<SENSORS>,
+    shutting_down: AtomicBool,
+    /// Closed when the ECLSS is shut down, waking any sensor tasks which are
+    /// waiting to poll their sensors.
+    shutdown: WaitQueue,
 }
 
 /// Global ECLSS configuration.
+///
+/// When deserialized, missing fields are set to their default values, and
+/// durations are human-readable strings, such as `"30s"`.
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
 #[non_exhaustive]
 pub struct Config {
     /// Maximum number of attempts to initialize a sensor.
     ///
-    /// If this argument is present, sensor initialization will permanently fail
-    /// after this many attempts. Otherwise, the ECLSS daemon will continue to
-    /// retry sensor initialization indefinitely.
-    ///
-    /// Use this setting if the daemon should fail to start up if some expected
-    /// sensors are missing. Do not use this setting if you intend to hot-plug
-    /// sensors.
+    /// If this argument is present, a sensor which fails to initialize this
+    /// many times in a row is considered missing, and the ECLSS daemon stops
+    /// trying to initialize it until it is detected on the bus again.
+    /// Otherwise, the ECLSS daemon will continue to retry sensor initialization
+    /// indefinitely.
     #[cfg_attr(feature = "clap", clap(long))]
     pub max_init_attempts: Option<usize>,
 
@@ -57,22 +71,107 @@ pub struct Config {
             value_parser = humantime::parse_duration,
         ),
     )]
+    #[cfg_attr(
+        all(feature = "serde", feature = "std"),
+        serde(with = "serde_duration")
+    )]
     pub log_reading_interval: Duration,
 
+    /// How often to run the fan cleaning cycle of sensors with fans, such as
+    /// the SEN55. Set to 0 to disable automatic fan cleaning.
+    ///
+    /// This counts the time the sensor spends measuring, and is saved across
+    /// restarts, unlike the sensor's own cleaning interval, which starts over
+    /// whenever it loses power.
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long,
+            default_value = "7days",
+            value_parser = humantime::parse_duration,
+        ),
+    )]
+    #[cfg_attr(
+        all(feature = "serde", feature = "std"),
+        serde(with = "serde_duration")
+    )]
+    pub fan_cleaning_interval: Duration,
+
+    /// Sensor readings older than this are considered stale.
+    ///
+    /// Stale readings are withheld from `/metrics.json`, history, alerts and
+    /// MQTT, so that a sensor which stops responding doesn't keep reporting its
+    /// last value. They're still included in the Prometheus metrics, but the
+    /// `sensor_reading_age_seconds` metric can be used to detect them. If
+    /// this is not set, readings never become stale.
+    #[cfg_attr(
+        feature = "clap",
+        clap(
+            long,
+            value_parser = humantime::parse_duration,
+        ),
+    )]
+    #[cfg_attr(
+        all(feature = "serde", feature = "std"),
+        serde(with = "serde_duration::option")
+    )]
+    pub max_reading_age: Option<Duration>,
+
     /// Retry configuration.
     #[cfg_attr(feature = "clap", clap(flatten))]
     pub retries: retry::RetryConfig,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_init_attempts: None,
+            abs_humidity_interval: 1,
+            log_reading_interval: Duration::from_secs(30),
+            fan_cleaning_interval: Duration::from_secs(7 * 24 * 60 * 60),
+            max_reading_age: None,
+            retries: retry::RetryConfig::default(),
+        }
+    }
+}
+
 impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
     pub const fn new(i2c: I) -> Self {
         Self {
             metrics: SensorMetrics::new(),
             i2c: SharedBus::new(i2c),
             sensors: sensor::Registry::new(),
+            shutting_down: AtomicBool::new(false),
+            shutdown: WaitQueue::new(),
         }
     }
 
+    /// Sets the clock used to timestamp sensor readings, which returns the
+    /// current time as a Unix timestamp in seconds.
+    ///
+    /// With the `std` feature, the system clock is used by default. Otherwise,
+    /// readings are not timestamped, and never become stale, unless a clock is
+    /// provided.
+    pub const fn with_clock(mut self, clock: fn() -> u64) -> Self {
+        self.metrics.clock = Some(clock);
+        self
+    }
+
+    /// Sets the age after which a sensor's readings are considered stale. See
+    /// [`Config::max_reading_age`].
+    ///
+    /// Stale readings are withheld from the serialized metrics and from
+    /// [`SensorMetrics::for_each_reading`] and
+    /// [`SensorMetrics::for_each_pm_reading`], so that a sensor which has
+    /// stopped responding doesn't keep reporting its last value. The
+    /// Prometheus metrics still include them, along with
+    /// [`SensorMetrics::reading_age`], which may be used to detect stale
+    /// readings. If this is `None`, readings are never considered stale.
+    pub const fn with_max_reading_age(mut self, max_age: Option<Duration>) -> Self {
+        self.metrics.max_reading_age = max_age;
+        self
+    }
+
     pub fn sensors(&self) -> &sensor::Registry<SENSORS> {
         &self.sensors
     }
@@ -80,20 +179,101 @@ impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
     pub fn metrics(&self) -> &SensorMetrics {
         &self.metrics
     }
+
+    /// Returns the I²C bus that sensors are attached to by default.
+    pub fn bus(&self) -> &SharedBus<I> {
+        &self.i2c
+    }
+
+    /// Signals all sensor tasks to shut down.
+    ///
+    /// Each task started by [`Eclss::run_sensor`] finishes its current poll,
+    /// puts its sensor into a low-power state using [`Sensor::shutdown`], and
+    /// then returns. Sensor tasks started after this is called return
+    /// immediately.
+    ///
+    /// [`Sensor::shutdown`]: sensor::Sensor::shutdown
+    pub fn shutdown(&self) {
+        self.shutting_down.store(true, Ordering::Release);
+        self.shutdown.close();
+    }
+
+    /// Returns `true` if [`Eclss::shutdown`] has been called.
+    #[must_use]
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Acquire)
+    }
+
+    /// Waits until [`Eclss::shutdown`] is called.
+    pub async fn wait_for_shutdown(&self) {
+        // the wait queue is only ever closed, never woken, so this completes
+        // once the ECLSS is shut down.
+        let _ = self.shutdown.wait().await;
+    }
+}
+
+/// An I²C bus shared between multiple sensors.
+///
+/// A `SharedBus` is either a bus which owns an I²C peripheral, created with
+/// [`SharedBus::new`], or a channel of a [`Tca9548a`] multiplexer attached to
+/// such a bus, created with [`Tca9548a::channel`]. Transactions on all
+/// multiplexer channels are serialized by the lock on the bus that the
+/// multiplexer is attached to.
+#[derive(Debug)]
+pub struct SharedBus<I: 'static>(Bus<I>);
+
+/// A TCA9548A 8-channel I²C multiplexer, attached to a [`SharedBus`].
+///
+/// Sensors with fixed addresses may be attached to different channels of a
+/// multiplexer, so that more than one of them can share a bus.
+#[derive(Debug)]
+pub struct Tca9548a<I: 'static> {
+    bus: &'static Mutex<Root<I>>,
+    address: u8,
+}
+
+/// Errors returned by [`Tca9548a`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MuxError {
+    /// The requested channel does not exist.
+    InvalidChannel(u8),
+    /// The multiplexer is attached to a channel of another multiplexer, which
+    /// is not supported.
+    Nested,
+}
+
+#[derive(Debug)]
+enum Bus<I: 'static> {
+    Root(Mutex<Root<I>>),
+    Channel {
+        bus: &'static Mutex<Root<I>>,
+        mux: u8,
+        channel: u8,
+    },
 }
 
 #[derive(Debug)]
-pub struct SharedBus<I>(Mutex<I>);
+struct Root<I> {
+    i2c: I,
+    /// The address of a multiplexer which may have a channel enabled.
+    ///
+    /// An enabled channel is connected to the bus, so it must be disabled
+    /// before a transaction that isn't meant for a device on that channel.
+    selected_mux: Option<u8>,
+}
 
 impl<I> SharedBus<I> {
     pub const fn new(i2c: I) -> Self {
-        SharedBus(Mutex::new(i2c))
+        SharedBus(Bus::Root(Mutex::new(Root {
+            i2c,
+            selected_mux: None,
+        })))
     }
 }
 
 impl<I, A> I2c<A> for &'_ SharedBus<I>
 where
-    I: I2c<A>,
+    I: I2c<A> + I2c,
     A: i2c::AddressMode,
 {
     async fn transaction(
@@ -101,7 +281,21 @@ where
         address: A,
         operations: &mut [i2c::Operation<'_>],
     ) -> Result<(), <Self as i2c::ErrorType>::Error> {
-        self.0.lock().await.transaction(address, operations).await
+        let mut root = match self.0 {
+            Bus::Root(ref bus) => {
+                let mut root = bus.lock().await;
+                root.deselect().await?;
+                root
+            }
+            Bus::Channel { bus, mux, channel } => {
+                // select the channel while holding the lock, so that another
+                // channel can't be selected before our transaction.
+                let mut root = bus.lock().await;
+                root.select(mux, channel).await?;
+                root
+            }
+        };
+        I2c::<A>::transaction(&mut root.i2c, address, operations).await
     }
 }
 
@@ -111,3 +305,81 @@ where
 {
     type Error = I::Error;
 }
+
+// === impl Root ===
+
+impl<I: I2c> Root<I> {
+    async fn select(&mut self, mux: u8, channel: u8) -> Result<(), I::Error> {
+        if self.selected_mux != Some(mux) {
+            self.deselect().await?;
+        }
+        // even if this channel was the last one selected, write the control
+        // register again, in case the multiplexer has been reset since.
+        self.selected_mux = Some(mux);
+        self.i2c.write(mux, &[1 << channel]).await
+    }
+
+    async fn deselect(&mut self) -> Result<(), I::Error> {
+        if let Some(mux) = self.selected_mux {
+            self.i2c.write(mux, &[0]).await?;
+            self.selected_mux = None;
+        }
+        Ok(())
+    }
+}
+
+// === impl Tca9548a ===
+
+impl<I> Tca9548a<I> {
+    /// The default I²C address of a TCA9548A, with its address pins tied low.
+    pub const DEFAULT_ADDRESS: u8 = 0x70;
+
+    /// The number of channels on a TCA9548A.
+    pub const CHANNELS: u8 = 8;
+
+    /// Returns a multiplexer at `address` on `bus`.
+    ///
+    /// # Errors
+    ///
+    /// If `bus` is a channel of another multiplexer.
+    pub fn new(bus: &'static SharedBus<I>, address: u8) -> Result<Self, MuxError> {
+        match bus.0 {
+            Bus::Root(ref bus) => Ok(Self { bus, address }),
+            Bus::Channel { .. } => Err(MuxError::Nested),
+        }
+    }
+
+    /// Returns a bus for the devices attached to `channel` of this
+    /// multiplexer.
+    ///
+    /// # Errors
+    ///
+    /// If `channel` is not between 0 and 7.
+    pub fn channel(&self, channel: u8) -> Result<SharedBus<I>, MuxError> {
+        if channel >= Self::CHANNELS {
+            return Err(MuxError::InvalidChannel(channel));
+        }
+        Ok(SharedBus(Bus::Channel {
+            bus: self.bus,
+            mux: self.address,
+            channel,
+        }))
+    }
+}
+
+// === impl MuxError ===
+
+impl fmt::Display for MuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidChannel(channel) => write!(
+                f,
+                "invalid TCA9548A channel {channel} (channels are numbered 0-{})",
+                Tca9548a::<()>::CHANNELS - 1
+            ),
+            Self::Nested => f.write_str("nested I²C multiplexers are not supported"),
+        }
+    }
+}
+
+impl core::error::Error for MuxError {}
@@ -1,44 +1,72 @@
 pub use tinymetrics::{Counter, Gauge};
 
 use core::fmt;
-use eclss_api::SensorName;
+use core::time::Duration;
+use eclss_api::{schema, DeviceStatus, SensorId};
 use tinymetrics::{CounterFamily, FmtLabels, GaugeFamily, MetricBuilder, MetricFamily};
 
+/// Every metric exported by an ECLSS node.
+///
+/// Each family is described by the [`schema::Metric`] of the same name, which
+/// determines its name, help text, and unit in the Prometheus exposition, and
+/// its name when serialized.
 #[derive(Debug)]
-#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SensorMetrics {
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub temp_c: GaugeFamily<'static, TEMP_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub co2_ppm: GaugeFamily<'static, CO2_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub eco2_ppm: GaugeFamily<'static, ECO2_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub rel_humidity_percent: GaugeFamily<'static, HUMIDITY_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub abs_humidity_grams_m3: GaugeFamily<'static, HUMIDITY_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub pressure_hpa: GaugeFamily<'static, PRESSURE_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub gas_resistance: GaugeFamily<'static, VOC_RESISTANCE_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub tvoc_ppb: GaugeFamily<'static, TVOC_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub tvoc_iaq_index: GaugeFamily<'static, TVOC_IAQ_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub nox_iaq_index: GaugeFamily<'static, NOX_IAQ_METRICS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    #[serde(skip)]
+    pub temp_c: GaugeFamily<'static, TEMP_METRICS, SensorId>,
+    pub co2_ppm: GaugeFamily<'static, CO2_METRICS, SensorId>,
+    pub eco2_ppm: GaugeFamily<'static, ECO2_METRICS, SensorId>,
+    pub rel_humidity_percent: GaugeFamily<'static, HUMIDITY_METRICS, SensorId>,
+    pub abs_humidity_grams_m3: GaugeFamily<'static, HUMIDITY_METRICS, SensorId>,
+    pub pressure_hpa: GaugeFamily<'static, PRESSURE_METRICS, SensorId>,
+    pub gas_resistance: GaugeFamily<'static, VOC_RESISTANCE_METRICS, SensorId>,
+    pub tvoc_ppb: GaugeFamily<'static, TVOC_METRICS, SensorId>,
+    pub tvoc_iaq_index: GaugeFamily<'static, TVOC_IAQ_METRICS, SensorId>,
+    pub nox_iaq_index: GaugeFamily<'static, NOX_IAQ_METRICS, SensorId>,
+    /// US EPA Air Quality Index, computed from PM2.5 and PM10 concentrations.
+    pub aqi: GaugeFamily<'static, AQI_METRICS, SensorId>,
+    /// [`Co2Band`](eclss_api::air_quality::Co2Band) levels, computed from CO₂
+    /// concentrations.
+    pub co2_comfort_band: GaugeFamily<'static, CO2_METRICS, SensorId>,
+    /// [`IaqCategory`](eclss_api::air_quality::IaqCategory) levels, computed
+    /// from tVOC concentrations.
+    pub tvoc_iaq_category: GaugeFamily<'static, TVOC_METRICS, SensorId>,
     pub pm_conc: GaugeFamily<'static, PM_CONC_METRICS, DiameterLabel>,
-    // #[cfg_attr(feature = "serde", serde(serialize_with =
-    // "serialize_metric"))]
-    #[serde(skip)]
+    /// Particulate matter concentrations under standard atmospheric
+    /// conditions, as reported by the PMSA003I alongside the environmental
+    /// concentrations in `pm_conc`.
+    pub pm_conc_standard: GaugeFamily<'static, PM_CONC_STANDARD_METRICS, DiameterLabel>,
     pub pm_count: GaugeFamily<'static, PM_COUNT_METRICS, DiameterLabel>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub sensor_errors: CounterFamily<'static, SENSORS, SensorName>,
-    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_metric"))]
-    pub sensor_reset_count: CounterFamily<'static, SENSORS, SensorName>,
+    pub pm_number_conc: GaugeFamily<'static, PM_NUMBER_CONC_METRICS, DiameterLabel>,
+    pub device_status: GaugeFamily<'static, DEVICE_STATUS_METRICS, DeviceStatusLabel>,
+    pub sensor_errors: CounterFamily<'static, SENSORS, SensorId>,
+    pub sensor_reset_count: CounterFamily<'static, SENSORS, SensorId>,
+    /// The time since each sensor was last polled successfully. This is
+    /// updated whenever metrics are formatted or serialized.
+    pub reading_age: GaugeFamily<'static, SENSORS, SensorId>,
+    /// The Unix timestamp (in seconds) of each sensor's last successful poll.
+    /// This isn't exported, but is used to timestamp readings and compute
+    /// [`SensorMetrics::reading_age`].
+    last_reading: GaugeFamily<'static, SENSORS, SensorId>,
+    /// Returns the current Unix timestamp in seconds, if there is a clock.
+    pub(crate) clock: Option<fn() -> u64>,
+    /// Readings older than this are withheld, if it's set.
+    pub(crate) max_reading_age: Option<Duration>,
 }
+
+/// Builds a metric family with the name, help text, and unit of a
+/// [`schema::Metric`].
+macro_rules! family {
+    ($metric:expr, $label:ty, $metrics:expr) => {{
+        const METRIC: schema::Metric = $metric;
+        let builder = MetricBuilder::new(METRIC.prometheus_name).with_help(METRIC.help);
+        let builder = match METRIC.unit {
+            Some(unit) => builder.with_unit(unit.prometheus()),
+            None => builder,
+        };
+        builder.build_labeled::<_, $label, $metrics>()
+    }};
+}
+
 macro_rules! count_features {
     ($($feature:literal),*) => {{
         let mut n = 0;
@@ -49,96 +77,183 @@ macro_rules! count_features {
     }}
 
 }
+#[cfg(feature = "std")]
+const DEFAULT_CLOCK: Option<fn() -> u64> = Some(system_clock);
+#[cfg(not(feature = "std"))]
+const DEFAULT_CLOCK: Option<fn() -> u64> = None;
+
+pub use eclss_api::MAX_INSTANCES;
+
+// Metrics which are estimated by sensor fusion have an additional slot for the
+// `FUSED` pseudo-sensor.
 pub const TEMP_METRICS: usize =
-    count_features!("scd30", "scd40", "scd41", "bme680", "sht41", "sen55");
-pub const CO2_METRICS: usize = count_features!("scd30", "scd40", "scd41");
-pub const ECO2_METRICS: usize = count_features!("sgp30", "bme680", "ens160");
+    count_features!("scd30", "scd40", "scd41", "bme680", "sht41", "sen55") * MAX_INSTANCES + 1;
+pub const CO2_METRICS: usize = count_features!("scd30", "scd40", "scd41") * MAX_INSTANCES + 1;
+pub const ECO2_METRICS: usize = count_features!("sgp30", "bme680", "ens160") * MAX_INSTANCES;
 pub const HUMIDITY_METRICS: usize =
-    count_features!("bme680", "scd40", "scd41", "scd30", "sht41", "sen55");
-pub const PRESSURE_METRICS: usize = count_features!("bme680");
-pub const VOC_RESISTANCE_METRICS: usize = count_features!("bme680");
-pub const TVOC_METRICS: usize = count_features!("sgp30", "bme680", "ens160");
+    count_features!("bme680", "scd40", "scd41", "scd30", "sht41", "sen55") * MAX_INSTANCES + 1;
+pub const PRESSURE_METRICS: usize = count_features!("bme680") * MAX_INSTANCES + 1;
+pub const VOC_RESISTANCE_METRICS: usize = count_features!("bme680") * MAX_INSTANCES;
+pub const TVOC_METRICS: usize = count_features!("sgp30", "bme680", "ens160") * MAX_INSTANCES;
 // IAQ from 1-500
-pub const TVOC_IAQ_METRICS: usize = count_features!("sen55", "bme680", "sgp40");
-pub const NOX_IAQ_METRICS: usize = count_features!("sen55");
-pub const PM_CONC_METRICS: usize =
+pub const TVOC_IAQ_METRICS: usize = count_features!("sen55", "bme680", "sgp40") * MAX_INSTANCES;
+pub const NOX_IAQ_METRICS: usize = count_features!("sen55") * MAX_INSTANCES;
+pub const AQI_METRICS: usize = count_features!("pmsa003i", "sen55") * MAX_INSTANCES;
+pub const PM_CONC_METRICS: usize = (
     // PMSA003I exposes three particulate concentration metrics
     (count_features!("pmsa003i") * 3)
     // SEN5x sensors expose 4 particulate concentration metrics
-    + (count_features!("sen55") * 4);
-pub const PM_COUNT_METRICS: usize = count_features!("pmsa003i") * 6;
+    + (count_features!("sen55") * 4)
+) * MAX_INSTANCES;
+// Only the PMSA003I distinguishes standard from environmental concentrations
+pub const PM_CONC_STANDARD_METRICS: usize = count_features!("pmsa003i") * 3 * MAX_INSTANCES;
+pub const PM_COUNT_METRICS: usize = count_features!("pmsa003i") * 6 * MAX_INSTANCES;
+// SEN5x sensors expose 5 particulate number concentration metrics
+pub const PM_NUMBER_CONC_METRICS: usize = count_features!("sen55") * 5 * MAX_INSTANCES;
+pub const DEVICE_STATUS_METRICS: usize =
+    count_features!("sen55") * DeviceStatus::FLAGS.len() * MAX_INSTANCES;
 pub const SENSORS: usize = count_features!(
     "scd30", "scd40", "scd41", "sen55", "sgp30", "bme680", "ens160", "sht41", "pmsa003i"
-);
+) * MAX_INSTANCES
+    + 1;
+
+// Every metric must fit in the API's `Metrics` response, so that clients can
+// read the metrics of a node with every sensor it supports.
+const _: () = {
+    use eclss_api::{MAX_DEVICE_STATUS_MEASUREMENTS, MAX_PARTICULATE_MEASUREMENTS, MAX_SENSORS};
+    assert!(SENSORS <= MAX_SENSORS);
+    assert!(TEMP_METRICS <= MAX_SENSORS);
+    assert!(CO2_METRICS <= MAX_SENSORS);
+    assert!(ECO2_METRICS <= MAX_SENSORS);
+    assert!(HUMIDITY_METRICS <= MAX_SENSORS);
+    assert!(PRESSURE_METRICS <= MAX_SENSORS);
+    assert!(VOC_RESISTANCE_METRICS <= MAX_SENSORS);
+    assert!(TVOC_METRICS <= MAX_SENSORS);
+    assert!(TVOC_IAQ_METRICS <= MAX_SENSORS);
+    assert!(NOX_IAQ_METRICS <= MAX_SENSORS);
+    assert!(AQI_METRICS <= MAX_SENSORS);
+    assert!(PM_CONC_METRICS <= MAX_PARTICULATE_MEASUREMENTS);
+    assert!(PM_CONC_STANDARD_METRICS <= MAX_PARTICULATE_MEASUREMENTS);
+    assert!(PM_COUNT_METRICS <= MAX_PARTICULATE_MEASUREMENTS);
+    assert!(PM_NUMBER_CONC_METRICS <= MAX_PARTICULATE_MEASUREMENTS);
+    assert!(DEVICE_STATUS_METRICS <= MAX_DEVICE_STATUS_MEASUREMENTS);
+};
 
 #[derive(Debug, Eq, PartialEq, serde::Serialize)]
 pub struct DiameterLabel {
     pub diameter: &'static str,
-    pub sensor: SensorName,
+    pub sensor: SensorId,
+}
+
+#[derive(Debug, Eq, PartialEq, serde::Serialize)]
+pub struct DeviceStatusLabel {
+    /// One of the [`DeviceStatus::FLAGS`].
+    pub flag: &'static str,
+    pub sensor: SensorId,
 }
 
 impl SensorMetrics {
     pub const fn new() -> Self {
         Self {
-            temp_c: MetricBuilder::new("temperature_degrees_celcius")
-                .with_help("Temperature in degrees Celcius.")
-                .with_unit("celcius")
-                .build_labeled::<_, SensorName, TEMP_METRICS>(),
-            co2_ppm: MetricBuilder::new("co2_ppm")
-                .with_help("CO2 in parts per million (ppm).")
-                .with_unit("ppm")
-                .build_labeled::<_, SensorName, CO2_METRICS>(),
-            eco2_ppm: MetricBuilder::new("eco2_ppm")
-                .with_help("VOC equivalent CO2 (eCO2) calculated by a tVOC sensor, in parts per million (ppm).")
-                .with_unit("ppm")
-                .build_labeled::<_, SensorName, ECO2_METRICS>(),
-            rel_humidity_percent: MetricBuilder::new("humidity_percent")
-                .with_help("Relative humidity (RH) percentage.")
-                .with_unit("percent")
-                .build_labeled::<_, SensorName, HUMIDITY_METRICS>(),
-            abs_humidity_grams_m3: MetricBuilder::new("absolute_humidity_grams_m3")
-                .with_help("Absolute humidity in grams per cubic meter.")
-                .with_unit("g/m^3")
-                .build_labeled::<_, SensorName, HUMIDITY_METRICS>(),
-            pressure_hpa: MetricBuilder::new("pressure_hpa")
-                .with_help("Barometric pressure, in hectopascals (hPa).")
-                .with_unit("hPa")
-                .build_labeled::<_, SensorName, PRESSURE_METRICS>(),
-            gas_resistance: MetricBuilder::new("gas_resistance_ohms")
-                .with_help("BME680 VOC sensor resistance, in Ohms.")
-                .with_unit("Ohms")
-                .build_labeled::<_, SensorName, VOC_RESISTANCE_METRICS>(),
-            tvoc_ppb: MetricBuilder::new("tvoc_ppb")
-                .with_help("Total Volatile Organic Compounds (VOC) in parts per billion (ppb)")
-                .with_unit("ppb")
-                .build_labeled::<_, SensorName, TVOC_METRICS>(),
-            tvoc_iaq_index: MetricBuilder::new("tvoc_iaq_index")
-                .with_help("Total Volatile Organic Compounds (VOC) Indoor Air Quality (IAQ) Index from 0-500")
-                .with_unit("IAQ index")
-                .build_labeled::<_, SensorName, TVOC_IAQ_METRICS>(),
-            nox_iaq_index: MetricBuilder::new("nox_iaq_index")
-                .with_help("Nitrogen Oxides (NOx) Indoor Air Quality (IAQ) Index from 0-500")
-                .with_unit("IAQ index")
-                .build_labeled::<_, SensorName, NOX_IAQ_METRICS>(),
-            pm_conc: MetricBuilder::new("pm_concentration_ug_m3")
-                .with_help("Particulate matter concentration in ug/m^3")
-                .with_unit("ug/m^3")
-                .build_labeled::<_, DiameterLabel, PM_CONC_METRICS>(),
-            pm_count: MetricBuilder::new("pm_count")
-                .with_help("Particulate matter count per 0.1L of air.")
-                .with_unit("particulates per 0.1L")
-                .build_labeled::<_, DiameterLabel, PM_COUNT_METRICS>(),
-            sensor_errors: MetricBuilder::new("sensor_error_count")
-                .with_help("Count of I2C errors that occurred while talking to a sensor")
-                .build_labeled::<_, SensorName, SENSORS>(),
-
-            sensor_reset_count: MetricBuilder::new("sensor_reset_count")
-                .with_help("The number of times a sensor was reset successfully")
-                .build_labeled::<_, SensorName, SENSORS>(),
+            temp_c: family!(schema::TEMP_C, SensorId, TEMP_METRICS),
+            co2_ppm: family!(schema::CO2_PPM, SensorId, CO2_METRICS),
+            eco2_ppm: family!(schema::ECO2_PPM, SensorId, ECO2_METRICS),
+            rel_humidity_percent: family!(schema::REL_HUMIDITY_PERCENT, SensorId, HUMIDITY_METRICS),
+            abs_humidity_grams_m3: family!(
+                schema::ABS_HUMIDITY_GRAMS_M3,
+                SensorId,
+                HUMIDITY_METRICS
+            ),
+            pressure_hpa: family!(schema::PRESSURE_HPA, SensorId, PRESSURE_METRICS),
+            gas_resistance: family!(schema::GAS_RESISTANCE, SensorId, VOC_RESISTANCE_METRICS),
+            tvoc_ppb: family!(schema::TVOC_PPB, SensorId, TVOC_METRICS),
+            tvoc_iaq_index: family!(schema::TVOC_IAQ_INDEX, SensorId, TVOC_IAQ_METRICS),
+            nox_iaq_index: family!(schema::NOX_IAQ_INDEX, SensorId, NOX_IAQ_METRICS),
+            aqi: family!(schema::AQI, SensorId, AQI_METRICS),
+            co2_comfort_band: family!(schema::CO2_COMFORT_BAND, SensorId, CO2_METRICS),
+            tvoc_iaq_category: family!(schema::TVOC_IAQ_CATEGORY, SensorId, TVOC_METRICS),
+            pm_conc: family!(schema::PM_CONCENTRATION, DiameterLabel, PM_CONC_METRICS),
+            pm_conc_standard: family!(
+                schema::PM_CONCENTRATION_STANDARD,
+                DiameterLabel,
+                PM_CONC_STANDARD_METRICS
+            ),
+            pm_count: family!(schema::PM_COUNT, DiameterLabel, PM_COUNT_METRICS),
+            pm_number_conc: family!(
+                schema::PM_NUMBER_CONCENTRATION,
+                DiameterLabel,
+                PM_NUMBER_CONC_METRICS
+            ),
+            device_status: family!(
+                schema::DEVICE_STATUS,
+                DeviceStatusLabel,
+                DEVICE_STATUS_METRICS
+            ),
+            sensor_errors: family!(schema::SENSOR_ERRORS, SensorId, SENSORS),
+            sensor_reset_count: family!(schema::SENSOR_RESET_COUNT, SensorId, SENSORS),
+            reading_age: family!(schema::READING_AGE, SensorId, SENSORS),
+            last_reading: MetricBuilder::new("sensor_last_reading_timestamp_seconds")
+                .build_labeled::<_, SensorId, SENSORS>(),
+            clock: DEFAULT_CLOCK,
+            max_reading_age: None,
+        }
+    }
+
+    /// Returns the current time as a Unix timestamp in seconds, if there is a
+    /// clock.
+    pub fn now(&self) -> Option<u64> {
+        self.clock.map(|clock| clock())
+    }
+
+    /// Returns the Unix timestamp (in seconds) of `sensor`'s last successful
+    /// poll, if it has been polled successfully since there was a clock.
+    pub fn last_reading(&self, sensor: &SensorId) -> Option<u64> {
+        self.last_reading
+            .metrics()
+            .iter()
+            .find(|(id, gauge)| *id == sensor && gauge.has_been_recorded())
+            .map(|(_, gauge)| gauge.value() as u64)
+    }
+
+    /// Returns `true` if `sensor`'s readings are older than the maximum
+    /// reading age, as of `now`.
+    pub fn is_stale(&self, sensor: &SensorId, now: Option<u64>) -> bool {
+        let (Some(max_age), Some(now)) = (self.max_reading_age, now) else {
+            return false;
+        };
+        self.last_reading(sensor)
+            .is_some_and(|last| now.saturating_sub(last) > max_age.as_secs())
+    }
+
+    /// Registers the gauges recording when `sensor` was last polled, returning
+    /// the gauge which [`SensorMetrics::record_reading`] should be called
+    /// with.
+    pub(crate) fn register_reading(&self, sensor: SensorId) -> Option<&Gauge> {
+        self.reading_age.register(sensor)?;
+        self.last_reading.register(sensor)
+    }
+
+    /// Records that the sensor whose `last_reading` gauge this is has just
+    /// been polled successfully.
+    pub(crate) fn record_reading(&self, last_reading: &Gauge) {
+        if let Some(now) = self.now() {
+            last_reading.set_value(now as f64);
+        }
+    }
+
+    /// Updates [`SensorMetrics::reading_age`], returning the current time.
+    fn update_reading_ages(&self) -> Option<u64> {
+        let now = self.now()?;
+        for (sensor, gauge) in self.reading_age.metrics().iter() {
+            if let Some(last) = self.last_reading(sensor) {
+                gauge.set_value(now.saturating_sub(last) as f64);
+            }
         }
+        Some(now)
     }
 
     pub fn fmt_metrics(&self, f: &mut impl fmt::Write) -> fmt::Result {
+        self.update_reading_ages();
         self.temp_c.fmt_metric(f)?;
         self.co2_ppm.fmt_metric(f)?;
         self.eco2_ppm.fmt_metric(f)?;
@@ -149,12 +264,164 @@ impl SensorMetrics {
         self.tvoc_ppb.fmt_metric(f)?;
         self.tvoc_iaq_index.fmt_metric(f)?;
         self.nox_iaq_index.fmt_metric(f)?;
+        self.aqi.fmt_metric(f)?;
+        self.co2_comfort_band.fmt_metric(f)?;
+        self.tvoc_iaq_category.fmt_metric(f)?;
         self.pm_conc.fmt_metric(f)?;
+        self.pm_conc_standard.fmt_metric(f)?;
         self.pm_count.fmt_metric(f)?;
+        self.pm_number_conc.fmt_metric(f)?;
+        self.device_status.fmt_metric(f)?;
         self.sensor_errors.fmt_metric(f)?;
         self.sensor_reset_count.fmt_metric(f)?;
+        self.reading_age.fmt_metric(f)?;
         Ok(())
     }
+
+    /// The names of the per-sensor gauges visited by
+    /// [`SensorMetrics::for_each_reading`]. These are the
+    /// [`schema::Metric::name`]s used when serializing `SensorMetrics`.
+    pub const READINGS: &'static [&'static str] = &[
+        schema::TEMP_C.name,
+        schema::CO2_PPM.name,
+        schema::ECO2_PPM.name,
+        schema::REL_HUMIDITY_PERCENT.name,
+        schema::ABS_HUMIDITY_GRAMS_M3.name,
+        schema::PRESSURE_HPA.name,
+        schema::GAS_RESISTANCE.name,
+        schema::TVOC_PPB.name,
+        schema::TVOC_IAQ_INDEX.name,
+        schema::NOX_IAQ_INDEX.name,
+        schema::AQI.name,
+        schema::CO2_COMFORT_BAND.name,
+        schema::TVOC_IAQ_CATEGORY.name,
+    ];
+
+    /// The names of the particulate matter gauges visited by
+    /// [`SensorMetrics::for_each_pm_reading`]. These are the
+    /// [`schema::Metric::name`]s used when serializing `SensorMetrics`.
+    pub const PM_READINGS: &'static [&'static str] = &[
+        schema::PM_CONCENTRATION.name,
+        schema::PM_CONCENTRATION_STANDARD.name,
+        schema::PM_COUNT.name,
+        schema::PM_NUMBER_CONCENTRATION.name,
+    ];
+
+    /// Calls `f` with the name, sensor, and current value of every per-sensor
+    /// gauge which has been recorded, unless it's [stale].
+    ///
+    /// Metric names are the same as in [`SensorMetrics::READINGS`].
+    ///
+    /// [stale]: crate::Eclss::with_max_reading_age
+    pub fn for_each_reading(&self, mut f: impl FnMut(&'static str, SensorId, f64)) {
+        fn visit<const N: usize>(
+            name: &'static str,
+            family: &GaugeFamily<'static, N, SensorId>,
+            stale: &impl Fn(&SensorId) -> bool,
+            f: &mut impl FnMut(&'static str, SensorId, f64),
+        ) {
+            for (&sensor, gauge) in family.metrics().iter() {
+                if gauge.has_been_recorded() && !stale(&sensor) {
+                    f(name, sensor, gauge.value());
+                }
+            }
+        }
+
+        let now = self.now();
+        let stale = |sensor: &SensorId| self.is_stale(sensor, now);
+
+        visit(schema::TEMP_C.name, &self.temp_c, &stale, &mut f);
+        visit(schema::CO2_PPM.name, &self.co2_ppm, &stale, &mut f);
+        visit(schema::ECO2_PPM.name, &self.eco2_ppm, &stale, &mut f);
+        visit(
+            schema::REL_HUMIDITY_PERCENT.name,
+            &self.rel_humidity_percent,
+            &stale,
+            &mut f,
+        );
+        visit(
+            schema::ABS_HUMIDITY_GRAMS_M3.name,
+            &self.abs_humidity_grams_m3,
+            &stale,
+            &mut f,
+        );
+        visit(
+            schema::PRESSURE_HPA.name,
+            &self.pressure_hpa,
+            &stale,
+            &mut f,
+        );
+        visit(
+            schema::GAS_RESISTANCE.name,
+            &self.gas_resistance,
+            &stale,
+            &mut f,
+        );
+        visit(schema::TVOC_PPB.name, &self.tvoc_ppb, &stale, &mut f);
+        visit(
+            schema::TVOC_IAQ_INDEX.name,
+            &self.tvoc_iaq_index,
+            &stale,
+            &mut f,
+        );
+        visit(
+            schema::NOX_IAQ_INDEX.name,
+            &self.nox_iaq_index,
+            &stale,
+            &mut f,
+        );
+        visit(schema::AQI.name, &self.aqi, &stale, &mut f);
+        visit(
+            schema::CO2_COMFORT_BAND.name,
+            &self.co2_comfort_band,
+            &stale,
+            &mut f,
+        );
+        visit(
+            schema::TVOC_IAQ_CATEGORY.name,
+            &self.tvoc_iaq_category,
+            &stale,
+            &mut f,
+        );
+    }
+
+    /// Calls `f` with the name, label, and current value of every particulate
+    /// matter gauge which has been recorded, unless it's [stale].
+    ///
+    /// Metric names are the same as in [`SensorMetrics::PM_READINGS`].
+    ///
+    /// [stale]: crate::Eclss::with_max_reading_age
+    pub fn for_each_pm_reading(&self, mut f: impl FnMut(&'static str, &DiameterLabel, f64)) {
+        let now = self.now();
+        for (label, gauge) in self.pm_conc.metrics().iter() {
+            if gauge.has_been_recorded() && !self.is_stale(&label.sensor, now) {
+                f(schema::PM_CONCENTRATION.name, label, gauge.value());
+            }
+        }
+        for (label, gauge) in self.pm_conc_standard.metrics().iter() {
+            if gauge.has_been_recorded() && !self.is_stale(&label.sensor, now) {
+                f(schema::PM_CONCENTRATION_STANDARD.name, label, gauge.value());
+            }
+        }
+        for (label, gauge) in self.pm_count.metrics().iter() {
+            if gauge.has_been_recorded() && !self.is_stale(&label.sensor, now) {
+                f(schema::PM_COUNT.name, label, gauge.value());
+            }
+        }
+        for (label, gauge) in self.pm_number_conc.metrics().iter() {
+            if gauge.has_been_recorded() && !self.is_stale(&label.sensor, now) {
+                f(schema::PM_NUMBER_CONCENTRATION.name, label, gauge.value());
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn system_clock() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
 }
 
 impl Default for SensorMetrics {
@@ -174,34 +441,241 @@ impl fmt::Display for SensorMetrics {
 impl FmtLabels for DiameterLabel {
     fn fmt_labels(&self, writer: &mut impl core::fmt::Write) -> core::fmt::Result {
         let Self { diameter, sensor } = self;
-        write!(writer, "diameter=\"{diameter}\",sensor=\"{sensor}\"")
+        write!(writer, "diameter=\"{diameter}\",")?;
+        sensor.fmt_labels(writer)
     }
 }
 
+impl FmtLabels for DeviceStatusLabel {
+    fn fmt_labels(&self, writer: &mut impl core::fmt::Write) -> core::fmt::Result {
+        let Self { flag, sensor } = self;
+        write!(writer, "flag=\"{flag}\",")?;
+        sensor.fmt_labels(writer)
+    }
+}
+
+// === impl Serialize ===
+
 #[cfg(feature = "serde")]
-fn serialize_metric<S, M, const METRICS: usize>(
-    metric: &MetricFamily<M, METRICS, SensorName>,
-    serializer: S,
-) -> Result<S::Ok, S::Error>
+impl serde::Serialize for SensorMetrics {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let times = ReadingTimes {
+            metrics: self,
+            now: self.update_reading_ages(),
+        };
+        let mut map = serializer.serialize_map(Some(schema::ALL.len() + 1))?;
+        map.serialize_entry("schema_version", &schema::VERSION)?;
+        map.serialize_entry(
+            schema::TEMP_C.name,
+            &Readings::readings(&self.temp_c, times),
+        )?;
+        map.serialize_entry(
+            schema::CO2_PPM.name,
+            &Readings::readings(&self.co2_ppm, times),
+        )?;
+        map.serialize_entry(
+            schema::ECO2_PPM.name,
+            &Readings::readings(&self.eco2_ppm, times),
+        )?;
+        map.serialize_entry(
+            schema::REL_HUMIDITY_PERCENT.name,
+            &Readings::readings(&self.rel_humidity_percent, times),
+        )?;
+        map.serialize_entry(
+            schema::ABS_HUMIDITY_GRAMS_M3.name,
+            &Readings::readings(&self.abs_humidity_grams_m3, times),
+        )?;
+        map.serialize_entry(
+            schema::PRESSURE_HPA.name,
+            &Readings::readings(&self.pressure_hpa, times),
+        )?;
+        map.serialize_entry(
+            schema::GAS_RESISTANCE.name,
+            &Readings::readings(&self.gas_resistance, times),
+        )?;
+        map.serialize_entry(
+            schema::TVOC_PPB.name,
+            &Readings::readings(&self.tvoc_ppb, times),
+        )?;
+        map.serialize_entry(
+            schema::TVOC_IAQ_INDEX.name,
+            &Readings::readings(&self.tvoc_iaq_index, times),
+        )?;
+        map.serialize_entry(
+            schema::NOX_IAQ_INDEX.name,
+            &Readings::readings(&self.nox_iaq_index, times),
+        )?;
+        map.serialize_entry(schema::AQI.name, &Readings::readings(&self.aqi, times))?;
+        map.serialize_entry(
+            schema::CO2_COMFORT_BAND.name,
+            &Readings::readings(&self.co2_comfort_band, times),
+        )?;
+        map.serialize_entry(
+            schema::TVOC_IAQ_CATEGORY.name,
+            &Readings::readings(&self.tvoc_iaq_category, times),
+        )?;
+        map.serialize_entry(
+            schema::PM_CONCENTRATION.name,
+            &Readings::readings(&self.pm_conc, times),
+        )?;
+        map.serialize_entry(
+            schema::PM_CONCENTRATION_STANDARD.name,
+            &Readings::readings(&self.pm_conc_standard, times),
+        )?;
+        map.serialize_entry(
+            schema::PM_COUNT.name,
+            &Readings::readings(&self.pm_count, times),
+        )?;
+        map.serialize_entry(
+            schema::PM_NUMBER_CONCENTRATION.name,
+            &Readings::readings(&self.pm_number_conc, times),
+        )?;
+        map.serialize_entry(
+            schema::DEVICE_STATUS.name,
+            &Readings::readings(&self.device_status, times),
+        )?;
+        map.serialize_entry(
+            schema::SENSOR_ERRORS.name,
+            &Readings::sensor(&self.sensor_errors),
+        )?;
+        map.serialize_entry(
+            schema::SENSOR_RESET_COUNT.name,
+            &Readings::sensor(&self.sensor_reset_count),
+        )?;
+        map.serialize_entry(
+            schema::READING_AGE.name,
+            &Readings::sensor(&self.reading_age),
+        )?;
+        map.end()
+    }
+}
+
+/// Serializes the recorded metrics in a family as a sequence of objects with
+/// the metric's labels and its `value`, as expected by
+/// [`eclss_api::Measurement`] and friends.
+#[cfg(feature = "serde")]
+struct Readings<'a, M, const METRICS: usize, L> {
+    family: &'a MetricFamily<'static, M, METRICS, L>,
+    /// Set for sensor readings, which are timestamped and withheld once
+    /// they're stale. Metrics describing the sensors themselves, such as error
+    /// counts, are always included.
+    times: Option<ReadingTimes<'a>>,
+}
+
+#[cfg(feature = "serde")]
+#[derive(Copy, Clone)]
+struct ReadingTimes<'a> {
+    metrics: &'a SensorMetrics,
+    now: Option<u64>,
+}
+
+/// Labels which are serialized as fields of each reading.
+#[cfg(feature = "serde")]
+trait SerializeLabels {
+    fn sensor(&self) -> &SensorId;
+
+    fn serialize_labels<S: serde::ser::SerializeMap>(&self, map: &mut S) -> Result<(), S::Error>;
+}
+
+#[cfg(feature = "serde")]
+impl<'a, M, const METRICS: usize, L> Readings<'a, M, METRICS, L> {
+    fn readings(family: &'a MetricFamily<'static, M, METRICS, L>, times: ReadingTimes<'a>) -> Self {
+        Self {
+            family,
+            times: Some(times),
+        }
+    }
+
+    fn sensor(family: &'a MetricFamily<'static, M, METRICS, L>) -> Self {
+        Self {
+            family,
+            times: None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<M, const METRICS: usize, L> serde::Serialize for Readings<'_, M, METRICS, L>
 where
-    S: serde::Serializer,
     M: tinymetrics::Metric + serde::Serialize,
+    L: SerializeLabels,
 {
-    use serde::ser::SerializeSeq;
-    let metrics = metric.metrics();
-    let mut seq = serializer.serialize_seq(Some(metrics.len()))?;
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{SerializeMap, SerializeSeq};
 
-    for (sensor, value) in metrics.iter() {
-        if !value.has_been_recorded() {
-            continue;
-        }
-        #[derive(serde::Serialize)]
-        struct SerializeMetric<'metric, M> {
-            sensor: &'metric SensorName,
+        struct Reading<'metric, M, L> {
+            labels: &'metric L,
             value: &'metric M,
+            timestamp: Option<u64>,
+        }
+
+        impl<M: serde::Serialize, L: SerializeLabels> serde::Serialize for Reading<'_, M, L> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut map = serializer.serialize_map(None)?;
+                self.labels.serialize_labels(&mut map)?;
+                map.serialize_entry("value", self.value)?;
+                map.serialize_entry("timestamp", &self.timestamp)?;
+                map.end()
+            }
         }
-        seq.serialize_element(&SerializeMetric { sensor, value })?;
+
+        let metrics = self.family.metrics();
+        let mut seq = serializer.serialize_seq(None)?;
+        for (labels, value) in metrics.iter() {
+            if !value.has_been_recorded() {
+                continue;
+            }
+            let timestamp = match self.times {
+                Some(ReadingTimes { metrics, now }) => {
+                    if metrics.is_stale(labels.sensor(), now) {
+                        continue;
+                    }
+                    metrics.last_reading(labels.sensor())
+                }
+                None => None,
+            };
+            seq.serialize_element(&Reading {
+                labels,
+                value,
+                timestamp,
+            })?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SerializeLabels for SensorId {
+    fn sensor(&self) -> &SensorId {
+        self
+    }
+
+    fn serialize_labels<S: serde::ser::SerializeMap>(&self, map: &mut S) -> Result<(), S::Error> {
+        map.serialize_entry("sensor", self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SerializeLabels for DiameterLabel {
+    fn sensor(&self) -> &SensorId {
+        &self.sensor
     }
 
-    seq.end()
+    fn serialize_labels<S: serde::ser::SerializeMap>(&self, map: &mut S) -> Result<(), S::Error> {
+        map.serialize_entry("sensor", &self.sensor)?;
+        map.serialize_entry("diameter", self.diameter)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl SerializeLabels for DeviceStatusLabel {
+    fn sensor(&self) -> &SensorId {
+        &self.sensor
+    }
+
+    fn serialize_labels<S: serde::ser::SerializeMap>(&self, map: &mut S) -> Result<(), S::Error> {
+        map.serialize_entry("sensor", &self.sensor)?;
+        map.serialize_entry("flag", self.flag)
+    }
 }
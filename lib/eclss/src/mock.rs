@@ -0,0 +1,336 @@
+//! A scriptable fake I²C bus, for exercising sensor drivers without hardware.
+//!
+//! [`MockI2c`] implements [`embedded_hal_async::i2c::I2c`] by dispatching each
+//! transaction to an emulated [`Device`] attached at the target address.
+//! Emulations of the SCD41, SGP30, SHT41, PMSA003I and SEN55 register
+//! protocols are provided, which are complete enough to drive the real
+//! [`Sensor`](crate::sensor::Sensor) implementations in this crate, along
+//! with a [`Tca9548a`] I²C multiplexer for testing sensors behind a mux.
+//!
+//! Faults (NAKs, bus errors, and corrupted responses) can be injected into
+//! individual transactions using [`MockI2c::inject`], in order to test error
+//! handling and the retry/reset behavior of [`Eclss::run_sensor`].
+//!
+//! This module requires the "mock" feature flag.
+//!
+//! [`Eclss::run_sensor`]: crate::Eclss::run_sensor
+use core::{
+    any::Any,
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use embedded_hal::i2c::{self, ErrorKind, NoAcknowledgeSource, Operation, SevenBitAddress};
+use embedded_hal_async::{delay::DelayNs, i2c::I2c};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+};
+
+mod pmsa003i;
+mod scd41;
+mod sen55;
+mod sensirion;
+mod sgp30;
+mod sht41;
+mod tca9548a;
+
+pub use self::{
+    pmsa003i::Pmsa003i, scd41::Scd41, sen55::Sen55, sgp30::Sgp30, sht41::Sht41, tca9548a::Tca9548a,
+};
+
+/// A fake I²C bus with emulated [`Device`]s attached to it.
+///
+/// Cloning a `MockI2c` returns a new handle to the same bus, so that a test
+/// can keep a handle to script faults and update device readings after the
+/// bus has been moved into an [`Eclss`](crate::Eclss).
+#[derive(Clone, Default)]
+pub struct MockI2c {
+    bus: Arc<Mutex<Bus>>,
+}
+
+/// An emulated I²C device.
+pub trait Device: Any + Send {
+    /// Handles a write of `bytes` to the device.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ErrorKind>;
+
+    /// Handles a read from the device into `buf`.
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), ErrorKind>;
+}
+
+/// A fault to inject into an I²C transaction.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Fault {
+    /// The device does not acknowledge its address.
+    Nak,
+    /// A bus error occurs during the transaction.
+    BusError,
+    /// The transaction completes, but the last byte read from the device is
+    /// corrupted, so that the response fails CRC or checksum validation.
+    Corrupt,
+}
+
+/// Errors returned by [`MockI2c`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MockError(ErrorKind);
+
+/// A [`DelayNs`] implementation which doesn't actually wait, but yields once
+/// to the executor, so that a test can step through a sensor task.
+///
+/// The total amount of time that *would* have elapsed is tracked, and can be
+/// read using [`MockDelay::elapsed`].
+#[derive(Clone, Debug, Default)]
+pub struct MockDelay {
+    elapsed_ns: Arc<AtomicU64>,
+}
+
+#[derive(Default)]
+struct Bus {
+    devices: Vec<Attached>,
+}
+
+struct Attached {
+    address: u8,
+    device: Box<dyn Device>,
+    faults: VecDeque<Fault>,
+}
+
+struct YieldNow(bool);
+
+// === impl MockI2c ===
+
+impl MockI2c {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attaches `device` to the bus at `address`, returning the bus.
+    #[must_use]
+    pub fn with_device(self, address: u8, device: impl Device) -> Self {
+        self.attach(address, device);
+        self
+    }
+
+    /// Attaches `device` to the bus at `address`, replacing any device that was
+    /// previously attached at that address.
+    pub fn attach(&self, address: u8, device: impl Device) {
+        let mut bus = self.lock();
+        bus.devices.retain(|attached| attached.address != address);
+        bus.devices.push(Attached {
+            address,
+            device: Box::new(device),
+            faults: VecDeque::new(),
+        });
+    }
+
+    /// Detaches the device at `address`, as though it was unplugged.
+    ///
+    /// Returns `true` if a device was attached at that address.
+    pub fn detach(&self, address: u8) -> bool {
+        let mut bus = self.lock();
+        let len = bus.devices.len();
+        bus.devices.retain(|attached| attached.address != address);
+        bus.devices.len() != len
+    }
+
+    /// Injects `fault` into the next transaction addressed to `address`.
+    ///
+    /// # Panics
+    ///
+    /// If no device is attached at `address`.
+    pub fn inject(&self, address: u8, fault: Fault) {
+        self.inject_n(address, fault, 1)
+    }
+
+    /// Injects `fault` into the next `n` transactions addressed to `address`.
+    ///
+    /// # Panics
+    ///
+    /// If no device is attached at `address`.
+    pub fn inject_n(&self, address: u8, fault: Fault, n: usize) {
+        let mut bus = self.lock();
+        let attached = bus
+            .get_mut(address)
+            .unwrap_or_else(|| panic!("no mock device attached at {address:#04x}"));
+        attached.faults.extend(core::iter::repeat_n(fault, n));
+    }
+
+    /// Calls `f` with a mutable reference to the device of type `D` attached
+    /// at `address`, returning `None` if there is no such device.
+    pub fn device<D: Device, T>(&self, address: u8, f: impl FnOnce(&mut D) -> T) -> Option<T> {
+        let mut bus = self.lock();
+        let device: &mut dyn Any = bus.get_mut(address)?.device.as_mut();
+        device.downcast_mut::<D>().map(f)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Bus> {
+        // Don't let one panicking test poison the bus for everyone else.
+        self.bus
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl fmt::Debug for MockI2c {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bus = self.lock();
+        let mut list = f.debug_list();
+        for attached in &bus.devices {
+            list.entry(&format_args!("{:#04x}", attached.address));
+        }
+        list.finish()
+    }
+}
+
+impl i2c::ErrorType for MockI2c {
+    type Error = MockError;
+}
+
+impl I2c<SevenBitAddress> for MockI2c {
+    async fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.lock()
+            .transaction(address, operations)
+            .map_err(MockError)
+    }
+}
+
+// === impl Bus ===
+
+impl Bus {
+    fn get_mut(&mut self, address: u8) -> Option<&mut Attached> {
+        self.devices
+            .iter_mut()
+            .find(|attached| attached.address == address)
+    }
+
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), ErrorKind> {
+        let Some(attached) = self.get_mut(address) else {
+            return self.mux_transaction(address, operations);
+        };
+
+        // Corruption can only be injected into a transaction that reads from
+        // the device, so leave it queued for the next read.
+        let reads = operations.iter().any(|op| matches!(op, Operation::Read(_)));
+        let fault = match attached.faults.front() {
+            Some(Fault::Corrupt) if !reads => None,
+            _ => attached.faults.pop_front(),
+        };
+        match fault {
+            Some(Fault::Nak) => return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address)),
+            Some(Fault::BusError) => return Err(ErrorKind::Bus),
+            _ => {}
+        }
+
+        for op in operations.iter_mut() {
+            match op {
+                Operation::Write(bytes) => attached.device.write(bytes)?,
+                Operation::Read(buf) => attached.device.read(buf)?,
+            }
+        }
+
+        if fault == Some(Fault::Corrupt) {
+            let last_read = operations.iter_mut().rev().find_map(|op| match op {
+                Operation::Read(buf) => buf.last_mut(),
+                Operation::Write(_) => None,
+            });
+            if let Some(byte) = last_read {
+                *byte ^= 0xff;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forwards a transaction for an address with no device on this bus to the
+    /// enabled channels of any multiplexers that are attached to it.
+    fn mux_transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), ErrorKind> {
+        for attached in &mut self.devices {
+            let device: &mut dyn Any = attached.device.as_mut();
+            let Some(mux) = device.downcast_mut::<Tca9548a>() else {
+                continue;
+            };
+            for channel in mux.enabled() {
+                let mut bus = channel.lock();
+                if bus.get_mut(address).is_some() {
+                    return bus.transaction(address, operations);
+                }
+            }
+        }
+        Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+    }
+}
+
+// === impl MockError ===
+
+impl MockError {
+    #[must_use]
+    pub fn new(kind: ErrorKind) -> Self {
+        Self(kind)
+    }
+}
+
+impl i2c::Error for MockError {
+    fn kind(&self) -> ErrorKind {
+        self.0
+    }
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "mock I²C error: {}", self.0)
+    }
+}
+
+// === impl MockDelay ===
+
+impl MockDelay {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the total time that this delay (and its clones) would have
+    /// waited for.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.elapsed_ns.load(Ordering::Acquire))
+    }
+}
+
+impl DelayNs for MockDelay {
+    async fn delay_ns(&mut self, ns: u32) {
+        self.elapsed_ns.fetch_add(ns as u64, Ordering::AcqRel);
+        YieldNow(false).await
+    }
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
@@ -0,0 +1,69 @@
+use super::Device;
+use embedded_hal::i2c::ErrorKind;
+
+/// An emulated Plantower PMSA003I particulate sensor.
+#[derive(Debug, Default)]
+pub struct Pmsa003i {
+    /// PM1.0, PM2.5 and PM10.0 concentrations in µg/m³, under environmental
+    /// atmospheric conditions.
+    pub concentrations: [u16; 3],
+    /// PM1.0, PM2.5 and PM10.0 concentrations in µg/m³, under standard
+    /// atmospheric conditions.
+    pub concentrations_standard: [u16; 3],
+    /// Counts of particles >= 0.3, 0.5, 1.0, 2.5, 5.0 and 10.0 µm in 0.1L of
+    /// air.
+    pub counts: [u16; 6],
+    /// The error code byte reported by the sensor.
+    pub error_code: u8,
+}
+
+const MAGIC: u16 = 0x424d;
+const PACKET_LEN: usize = 32;
+
+impl Pmsa003i {
+    /// The PMSA003I's default I²C address.
+    pub const ADDR: u8 = 0x12;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn packet(&self) -> [u8; PACKET_LEN] {
+        let mut words = [0u16; 14];
+        words[0] = MAGIC;
+        // frame length: 2 * 13 data words + 2 checksum bytes
+        words[1] = 28;
+        words[2..5].copy_from_slice(&self.concentrations_standard);
+        words[5..8].copy_from_slice(&self.concentrations);
+        words[8..14].copy_from_slice(&self.counts);
+
+        let mut packet = [0u8; PACKET_LEN];
+        for (bytes, word) in packet.as_chunks_mut::<2>().0.iter_mut().zip(words) {
+            *bytes = word.to_be_bytes();
+        }
+        // byte 28 is the version, and byte 29 is the error code.
+        packet[28] = 0x97;
+        packet[29] = self.error_code;
+        let checksum = packet[..PACKET_LEN - 2]
+            .iter()
+            .map(|&byte| byte as u16)
+            .sum::<u16>();
+        packet[PACKET_LEN - 2..].copy_from_slice(&checksum.to_be_bytes());
+        packet
+    }
+}
+
+impl Device for Pmsa003i {
+    fn write(&mut self, _: &[u8]) -> Result<(), ErrorKind> {
+        // The PMSA003I doesn't have any commands; it just streams readings.
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), ErrorKind> {
+        let packet = self.packet();
+        let len = buf.len().min(PACKET_LEN);
+        buf[..len].copy_from_slice(&packet[..len]);
+        Ok(())
+    }
+}
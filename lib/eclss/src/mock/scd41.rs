@@ -0,0 +1,185 @@
+use super::{
+    sensirion::{self, nak, Response},
+    Device,
+};
+use embedded_hal::i2c::ErrorKind;
+
+/// An emulated Sensirion SCD41 CO₂ sensor.
+#[derive(Debug)]
+pub struct Scd41 {
+    /// The CO₂ concentration reported by the sensor, in ppm.
+    pub co2_ppm: u16,
+    /// The temperature reported by the sensor with its default temperature
+    /// offset, in degrees Celcius.
+    pub temp_c: f32,
+    /// The relative humidity reported by the sensor, as a percentage.
+    pub humidity_percent: f32,
+    /// The sensor's 48-bit serial number.
+    pub serial: u64,
+    /// Whether the sensor's self-test passes.
+    pub self_test_ok: bool,
+    /// Whether automatic self-calibration is enabled.
+    pub automatic_self_calibration: bool,
+    measuring: bool,
+    ambient_pressure_hpa: Option<u16>,
+    temp_offset_c: f32,
+    rsp: Response,
+}
+
+const START_PERIODIC_MEASUREMENT: u16 = 0x21b1;
+const READ_MEASUREMENT: u16 = 0xec05;
+const STOP_PERIODIC_MEASUREMENT: u16 = 0x3f86;
+const GET_DATA_READY_STATUS: u16 = 0xe4b8;
+const SET_AMBIENT_PRESSURE: u16 = 0xe000;
+const GET_SERIAL_NUMBER: u16 = 0x3682;
+const PERFORM_SELF_TEST: u16 = 0x3639;
+const REINIT: u16 = 0x3646;
+const WAKE_UP: u16 = 0x36f6;
+const SET_AUTOMATIC_SELF_CALIBRATION_ENABLED: u16 = 0x2416;
+const GET_AUTOMATIC_SELF_CALIBRATION_ENABLED: u16 = 0x2313;
+const PERFORM_FORCED_RECALIBRATION: u16 = 0x362f;
+const SET_TEMPERATURE_OFFSET: u16 = 0x241d;
+const GET_TEMPERATURE_OFFSET: u16 = 0x2318;
+
+/// The SCD41's factory default temperature offset, in degrees Celcius.
+const DEFAULT_TEMP_OFFSET_C: f32 = 4.0;
+/// Converts temperature offsets to and from the SCD41's representation.
+const TEMP_OFFSET_SCALE: f32 = 65536.0 / 175.0;
+
+impl Scd41 {
+    /// The SCD41's fixed I²C address.
+    pub const ADDR: u8 = 0x62;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            co2_ppm: 600,
+            temp_c: 22.0,
+            humidity_percent: 40.0,
+            serial: 0xbeef_cafe_f00d,
+            self_test_ok: true,
+            // ASC is enabled by default.
+            automatic_self_calibration: true,
+            measuring: false,
+            ambient_pressure_hpa: None,
+            temp_offset_c: DEFAULT_TEMP_OFFSET_C,
+            rsp: Response::default(),
+        }
+    }
+
+    /// Returns `true` if periodic measurement is running.
+    #[must_use]
+    pub fn is_measuring(&self) -> bool {
+        self.measuring
+    }
+
+    /// Returns the most recent ambient pressure compensation value set by the
+    /// host, in hPa.
+    #[must_use]
+    pub fn ambient_pressure_hpa(&self) -> Option<u16> {
+        self.ambient_pressure_hpa
+    }
+
+    /// Returns the temperature offset which the sensor subtracts from its
+    /// temperature readings, in degrees Celcius.
+    ///
+    /// Changing the offset from its default of 4 °C changes the temperature
+    /// reported by the sensor accordingly, until the sensor is reinitialized.
+    #[must_use]
+    pub fn temp_offset_c(&self) -> f32 {
+        self.temp_offset_c
+    }
+}
+
+impl Default for Scd41 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Scd41 {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ErrorKind> {
+        let (command, args) = sensirion::parse_command(bytes)?;
+        self.rsp.clear();
+
+        // While periodic measurement is running, the SCD4x only accepts a
+        // handful of commands, and NAKs everything else.
+        let allowed_while_measuring = matches!(
+            command,
+            READ_MEASUREMENT
+                | STOP_PERIODIC_MEASUREMENT
+                | GET_DATA_READY_STATUS
+                | SET_AMBIENT_PRESSURE
+        );
+        if self.measuring && !allowed_while_measuring {
+            return Err(nak());
+        }
+
+        match command {
+            START_PERIODIC_MEASUREMENT => self.measuring = true,
+            STOP_PERIODIC_MEASUREMENT => self.measuring = false,
+            READ_MEASUREMENT => {
+                let temp_c = self.temp_c + DEFAULT_TEMP_OFFSET_C - self.temp_offset_c;
+                self.rsp.push(self.co2_ppm);
+                self.rsp
+                    .push(sensirion::scaled(temp_c + 45.0, 65536.0 / 175.0));
+                self.rsp
+                    .push(sensirion::scaled(self.humidity_percent, 65536.0 / 100.0));
+            }
+            GET_DATA_READY_STATUS => {
+                // The data is ready if any of the least significant 11 bits
+                // are set.
+                self.rsp.push(if self.measuring { 0x8006 } else { 0x8000 });
+            }
+            SET_AMBIENT_PRESSURE => {
+                let &[pressure] = &args[..] else {
+                    return Err(nak());
+                };
+                self.ambient_pressure_hpa = Some(pressure);
+            }
+            GET_SERIAL_NUMBER => {
+                self.rsp.push((self.serial >> 32) as u16);
+                self.rsp.push((self.serial >> 16) as u16);
+                self.rsp.push(self.serial as u16);
+            }
+            PERFORM_SELF_TEST => self.rsp.push(if self.self_test_ok { 0 } else { 1 }),
+            // settings which haven't been persisted are reset.
+            REINIT => self.temp_offset_c = DEFAULT_TEMP_OFFSET_C,
+            SET_TEMPERATURE_OFFSET => {
+                let &[offset] = &args[..] else {
+                    return Err(nak());
+                };
+                self.temp_offset_c = offset as f32 / TEMP_OFFSET_SCALE;
+            }
+            GET_TEMPERATURE_OFFSET => self
+                .rsp
+                .push(sensirion::scaled(self.temp_offset_c, TEMP_OFFSET_SCALE)),
+            SET_AUTOMATIC_SELF_CALIBRATION_ENABLED => match args[..] {
+                [enabled] => self.automatic_self_calibration = enabled != 0,
+                _ => return Err(nak()),
+            },
+            GET_AUTOMATIC_SELF_CALIBRATION_ENABLED => {
+                self.rsp.push(self.automatic_self_calibration as u16)
+            }
+            PERFORM_FORCED_RECALIBRATION => {
+                let &[reference_ppm] = &args[..] else {
+                    return Err(nak());
+                };
+                // the correction is offset by 0x8000, and subsequent readings
+                // are corrected to the reference concentration.
+                let correction = reference_ppm as i32 - self.co2_ppm as i32;
+                self.rsp.push((correction + 0x8000) as u16);
+                self.co2_ppm = reference_ppm;
+            }
+            // The SCD41 doesn't acknowledge the wake-up command.
+            WAKE_UP => return Err(nak()),
+            _ => return Err(nak()),
+        }
+
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), ErrorKind> {
+        self.rsp.read(buf)
+    }
+}
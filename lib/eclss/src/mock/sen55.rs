@@ -0,0 +1,188 @@
+use super::{
+    sensirion::{self, nak, Response},
+    Device,
+};
+use embedded_hal::i2c::ErrorKind;
+
+/// An emulated Sensirion SEN55 environmental sensor node.
+#[derive(Debug)]
+pub struct Sen55 {
+    /// PM1.0, PM2.5, PM4.0 and PM10.0 mass concentrations, in µg/m³.
+    pub pm_conc: [f32; 4],
+    /// PM0.5, PM1.0, PM2.5, PM4.0 and PM10.0 number concentrations, in
+    /// particles/cm³.
+    pub pm_count: [f32; 5],
+    /// Typical particle size, in µm.
+    pub typical_particle_size: f32,
+    /// The temperature reported by the sensor, in degrees Celcius.
+    pub temp_c: f32,
+    /// The relative humidity reported by the sensor, as a percentage.
+    pub humidity_percent: f32,
+    /// The VOC index reported by the sensor.
+    pub voc_index: f32,
+    /// The NOx index reported by the sensor.
+    pub nox_index: f32,
+    /// The value of the device status register.
+    pub device_status: u32,
+    /// The sensor's own automatic fan cleaning interval, in seconds, or 0 if
+    /// it's disabled.
+    pub auto_cleaning_interval: u32,
+    /// The product name reported by the sensor.
+    pub product_name: &'static str,
+    /// The temperature compensation warm start parameter.
+    pub warm_start: u16,
+    /// The VOC algorithm state.
+    pub voc_state: [u16; 4],
+    measuring: bool,
+    fan_cleanings: usize,
+    rsp: Response,
+}
+
+const START_MEASUREMENT: u16 = 0x0021;
+const START_MEASUREMENT_RHT_GAS: u16 = 0x0037;
+const STOP_MEASUREMENT: u16 = 0x0104;
+const READ_DATA_READY: u16 = 0x0202;
+const READ_MEASURED_VALUES: u16 = 0x03c4;
+const READ_MEASURED_PM_VALUES: u16 = 0x0413;
+const START_FAN_CLEANING: u16 = 0x5607;
+const AUTO_CLEANING_INTERVAL: u16 = 0x8004;
+const WARM_START_PARAMETER: u16 = 0x60c6;
+const VOC_ALGORITHM_STATE: u16 = 0x6181;
+const READ_PRODUCT_NAME: u16 = 0xd014;
+const READ_SERIAL_NUMBER: u16 = 0xd033;
+const READ_FIRMWARE_VERSION: u16 = 0xd100;
+const READ_DEVICE_STATUS: u16 = 0xd206;
+const CLEAR_DEVICE_STATUS: u16 = 0xd210;
+const DEVICE_RESET: u16 = 0xd304;
+
+impl Sen55 {
+    /// The SEN5x's fixed I²C address.
+    pub const ADDR: u8 = 0x69;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            pm_conc: [2.0, 3.5, 4.0, 4.5],
+            pm_count: [10.0, 12.0, 13.0, 13.5, 14.0],
+            typical_particle_size: 0.6,
+            temp_c: 22.0,
+            humidity_percent: 40.0,
+            voc_index: 100.0,
+            nox_index: 1.0,
+            device_status: 0,
+            auto_cleaning_interval: 604_800,
+            product_name: "SEN55",
+            warm_start: 0,
+            voc_state: [0; 4],
+            measuring: false,
+            fan_cleanings: 0,
+            rsp: Response::default(),
+        }
+    }
+
+    /// Returns `true` if the sensor is in measurement mode.
+    #[must_use]
+    pub fn is_measuring(&self) -> bool {
+        self.measuring
+    }
+
+    /// Returns the number of times the fan cleaning cycle has been started.
+    #[must_use]
+    pub fn fan_cleanings(&self) -> usize {
+        self.fan_cleanings
+    }
+
+    fn push_pm_conc(&mut self) {
+        for pm in self.pm_conc {
+            self.rsp.push(sensirion::scaled(pm, 10.0));
+        }
+    }
+}
+
+impl Default for Sen55 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Sen55 {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ErrorKind> {
+        let (command, args) = sensirion::parse_command(bytes)?;
+        self.rsp.clear();
+        match command {
+            START_MEASUREMENT | START_MEASUREMENT_RHT_GAS => self.measuring = true,
+            STOP_MEASUREMENT => self.measuring = false,
+            READ_DATA_READY => self.rsp.push(self.measuring as u16),
+            READ_MEASURED_VALUES if self.measuring => {
+                self.push_pm_conc();
+                self.rsp
+                    .push(sensirion::scaled_signed(self.humidity_percent, 100.0));
+                self.rsp.push(sensirion::scaled_signed(self.temp_c, 200.0));
+                self.rsp
+                    .push(sensirion::scaled_signed(self.voc_index, 10.0));
+                self.rsp
+                    .push(sensirion::scaled_signed(self.nox_index, 10.0));
+            }
+            READ_MEASURED_VALUES => {
+                // Values are invalid when not in measurement mode.
+                for _ in 0..4 {
+                    self.rsp.push(0xffff);
+                }
+                for _ in 0..4 {
+                    self.rsp.push(0x7fff);
+                }
+            }
+            READ_MEASURED_PM_VALUES => {
+                self.push_pm_conc();
+                for count in self.pm_count {
+                    self.rsp.push(sensirion::scaled(count, 10.0));
+                }
+                self.rsp
+                    .push(sensirion::scaled(self.typical_particle_size, 1000.0));
+            }
+            START_FAN_CLEANING if self.measuring => self.fan_cleanings += 1,
+            AUTO_CLEANING_INTERVAL => match args[..] {
+                [] => {
+                    self.rsp.push((self.auto_cleaning_interval >> 16) as u16);
+                    self.rsp.push(self.auto_cleaning_interval as u16);
+                }
+                [hi, lo] => self.auto_cleaning_interval = (u32::from(hi) << 16) | u32::from(lo),
+                _ => return Err(nak()),
+            },
+            WARM_START_PARAMETER => match args[..] {
+                [] => self.rsp.push(self.warm_start),
+                [param] => self.warm_start = param,
+                _ => return Err(nak()),
+            },
+            VOC_ALGORITHM_STATE => match args[..] {
+                [] => {
+                    for word in self.voc_state {
+                        self.rsp.push(word);
+                    }
+                }
+                [a, b, c, d] if !self.measuring => self.voc_state = [a, b, c, d],
+                _ => return Err(nak()),
+            },
+            READ_PRODUCT_NAME => {
+                let mut name = [0u8; 32];
+                let len = self.product_name.len().min(name.len() - 1);
+                name[..len].copy_from_slice(&self.product_name.as_bytes()[..len]);
+                self.rsp.push_bytes(&name);
+            }
+            READ_SERIAL_NUMBER => self.rsp.push_bytes(b"MOCKSEN55\0"),
+            READ_FIRMWARE_VERSION => self.rsp.push(0x0200),
+            READ_DEVICE_STATUS => {
+                self.rsp.push((self.device_status >> 16) as u16);
+                self.rsp.push(self.device_status as u16);
+            }
+            CLEAR_DEVICE_STATUS => self.device_status = 0,
+            DEVICE_RESET => self.measuring = false,
+            _ => return Err(nak()),
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), ErrorKind> {
+        self.rsp.read(buf)
+    }
+}
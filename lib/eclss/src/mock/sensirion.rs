@@ -0,0 +1,133 @@
+//! Helpers for emulating the Sensirion I²C command protocol.
+//!
+//! Sensirion sensors (the SCDxx, SGPxx, SHTxx and SENxx families) share a
+//! common wire protocol: the host writes a 16-bit command (or an 8-bit one, for
+//! the SHT4x), optionally followed by 16-bit argument words, and then reads
+//! back 16-bit response words. Every word on the wire is followed by a CRC-8
+//! checksum byte.
+use embedded_hal::i2c::{ErrorKind, NoAcknowledgeSource};
+
+/// The Sensirion CRC-8 checksum (polynomial 0x31, initialized to 0xFF).
+pub(super) fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xff;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Parses a 16-bit command and its CRC-protected argument words.
+///
+/// If the argument words are malformed or fail CRC validation, the device
+/// NAKs the data, as a real sensor would.
+pub(super) fn parse_command(bytes: &[u8]) -> Result<(u16, Vec<u16>), ErrorKind> {
+    let [hi, lo, args @ ..] = bytes else {
+        return Err(nak());
+    };
+    let (args, []) = args.as_chunks::<3>() else {
+        return Err(nak());
+    };
+    let args = args
+        .iter()
+        .map(|&[hi, lo, crc]| {
+            if crc8(&[hi, lo]) != crc {
+                return Err(nak());
+            }
+            Ok(u16::from_be_bytes([hi, lo]))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok((u16::from_be_bytes([*hi, *lo]), args))
+}
+
+/// Returns the error for a command or argument that the device NAKs.
+pub(super) fn nak() -> ErrorKind {
+    ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)
+}
+
+/// Encodes `f` as a fixed-point value scaled by `scale`, saturating at the
+/// bounds of a `u16`.
+pub(super) fn scaled(f: f32, scale: f32) -> u16 {
+    (f * scale).round().clamp(0.0, u16::MAX as f32) as u16
+}
+
+/// Encodes `f` as a signed fixed-point value scaled by `scale`, saturating at
+/// the bounds of an `i16`.
+pub(super) fn scaled_signed(f: f32, scale: f32) -> u16 {
+    (f * scale).round().clamp(i16::MIN as f32, i16::MAX as f32) as i16 as u16
+}
+
+/// A pending response to a command.
+#[derive(Debug, Default)]
+pub(super) struct Response {
+    bytes: Vec<u8>,
+}
+
+impl Response {
+    pub(super) fn clear(&mut self) {
+        self.bytes.clear();
+    }
+
+    /// Appends a word (and its CRC) to the response.
+    pub(super) fn push(&mut self, word: u16) {
+        let bytes = word.to_be_bytes();
+        self.bytes.extend_from_slice(&bytes);
+        self.bytes.push(crc8(&bytes));
+    }
+
+    /// Appends a sequence of bytes to the response, packed into words.
+    pub(super) fn push_bytes(&mut self, bytes: &[u8]) {
+        for word in bytes.chunks(2) {
+            let lo = word.get(1).copied().unwrap_or(0);
+            self.push(u16::from_be_bytes([word[0], lo]));
+        }
+    }
+
+    /// Reads the response into `buf`.
+    ///
+    /// If the host reads more words than the command returns, the remainder
+    /// is padded with zero words. If no command with a response is pending,
+    /// the device does not acknowledge the read.
+    pub(super) fn read(&mut self, buf: &mut [u8]) -> Result<(), ErrorKind> {
+        if self.bytes.is_empty() {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        while self.bytes.len() < buf.len() {
+            self.push(0);
+        }
+        buf.copy_from_slice(&self.bytes[..buf.len()]);
+        self.bytes.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc8_matches_datasheet() {
+        // Example from the Sensirion datasheets: CRC(0xBEEF) = 0x92
+        assert_eq!(crc8(&[0xbe, 0xef]), 0x92);
+    }
+
+    #[test]
+    fn parse_command_with_args() {
+        let word = 0x1234u16.to_be_bytes();
+        let bytes = [0xe0, 0x00, word[0], word[1], crc8(&word)];
+        assert_eq!(parse_command(&bytes), Ok((0xe000, vec![0x1234])));
+    }
+
+    #[test]
+    fn parse_command_bad_crc() {
+        let word = 0x1234u16.to_be_bytes();
+        let bytes = [0xe0, 0x00, word[0], word[1], crc8(&word) ^ 1];
+        assert_eq!(parse_command(&bytes), Err(nak()));
+    }
+}
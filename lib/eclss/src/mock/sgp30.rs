@@ -0,0 +1,134 @@
+use super::{
+    sensirion::{self, nak, Response},
+    Device,
+};
+use embedded_hal::i2c::ErrorKind;
+
+/// An emulated Sensirion SGP30 tVOC/eCO₂ sensor.
+#[derive(Debug)]
+pub struct Sgp30 {
+    /// The total VOC concentration reported by the sensor, in ppb.
+    pub tvoc_ppb: u16,
+    /// The equivalent CO₂ concentration reported by the sensor, in ppm.
+    pub co2eq_ppm: u16,
+    /// The raw H₂ signal reported by the sensor.
+    pub h2: u16,
+    /// The raw ethanol signal reported by the sensor.
+    pub ethanol: u16,
+    /// The sensor's current baseline values, as `(co2eq, tvoc)`.
+    pub baseline: (u16, u16),
+    /// The sensor's serial number.
+    pub serial: [u16; 3],
+    /// Whether the sensor's self-test passes.
+    pub self_test_ok: bool,
+    humidity: Option<u16>,
+    initialized: bool,
+    rsp: Response,
+}
+
+const GET_SERIAL_ID: u16 = 0x3682;
+const INIT_AIR_QUALITY: u16 = 0x2003;
+const MEASURE_AIR_QUALITY: u16 = 0x2008;
+const GET_BASELINE: u16 = 0x2015;
+const SET_BASELINE: u16 = 0x201e;
+const SET_HUMIDITY: u16 = 0x2061;
+const MEASURE_TEST: u16 = 0x2032;
+const GET_FEATURE_SET: u16 = 0x202f;
+const MEASURE_RAW_SIGNALS: u16 = 0x2050;
+
+/// Product type 0 (SGP30), product version 0x22.
+const FEATURE_SET: u16 = 0x0022;
+const SELF_TEST_OK: u16 = 0xd400;
+
+impl Sgp30 {
+    /// The SGP30's fixed I²C address.
+    pub const ADDR: u8 = 0x58;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            tvoc_ppb: 0,
+            co2eq_ppm: 400,
+            h2: 13_000,
+            ethanol: 18_000,
+            baseline: (0x8973, 0x8aae),
+            serial: [0x0000, 0x0123, 0x4567],
+            self_test_ok: true,
+            humidity: None,
+            initialized: false,
+            rsp: Response::default(),
+        }
+    }
+
+    /// Returns `true` if the air quality algorithm has been initialized.
+    #[must_use]
+    pub fn is_initialized(&self) -> bool {
+        self.initialized
+    }
+
+    /// Returns the absolute humidity compensation value most recently set by
+    /// the host, in the sensor's 8.8 fixed-point g/m³ format.
+    #[must_use]
+    pub fn humidity(&self) -> Option<u16> {
+        self.humidity
+    }
+}
+
+impl Default for Sgp30 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Sgp30 {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ErrorKind> {
+        let (command, args) = sensirion::parse_command(bytes)?;
+        self.rsp.clear();
+        match command {
+            GET_SERIAL_ID => {
+                for word in self.serial {
+                    self.rsp.push(word);
+                }
+            }
+            INIT_AIR_QUALITY => self.initialized = true,
+            MEASURE_AIR_QUALITY => {
+                self.rsp.push(self.co2eq_ppm);
+                self.rsp.push(self.tvoc_ppb);
+            }
+            GET_BASELINE => {
+                let (co2eq, tvoc) = self.baseline;
+                self.rsp.push(co2eq);
+                self.rsp.push(tvoc);
+            }
+            SET_BASELINE => {
+                // Note that the baseline words are written in the reverse
+                // order from how they are read.
+                let &[tvoc, co2eq] = &args[..] else {
+                    return Err(nak());
+                };
+                self.baseline = (co2eq, tvoc);
+            }
+            SET_HUMIDITY => {
+                let &[humidity] = &args[..] else {
+                    return Err(nak());
+                };
+                // A humidity value of 0 disables humidity compensation.
+                self.humidity = Some(humidity).filter(|&h| h != 0);
+            }
+            MEASURE_TEST => self
+                .rsp
+                .push(if self.self_test_ok { SELF_TEST_OK } else { 0 }),
+            GET_FEATURE_SET => self.rsp.push(FEATURE_SET),
+            MEASURE_RAW_SIGNALS => {
+                self.rsp.push(self.h2);
+                self.rsp.push(self.ethanol);
+            }
+            _ => return Err(nak()),
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), ErrorKind> {
+        self.rsp.read(buf)
+    }
+}
@@ -0,0 +1,75 @@
+use super::{
+    sensirion::{self, nak, Response},
+    Device,
+};
+use embedded_hal::i2c::ErrorKind;
+
+/// An emulated Sensirion SHT41 temperature and humidity sensor.
+#[derive(Debug)]
+pub struct Sht41 {
+    /// The temperature reported by the sensor, in degrees Celcius.
+    pub temp_c: f32,
+    /// The relative humidity reported by the sensor, as a percentage.
+    pub humidity_percent: f32,
+    /// The sensor's 32-bit serial number.
+    pub serial: u32,
+    rsp: Response,
+}
+
+const MEASURE_HIGH_PRECISION: u8 = 0xfd;
+const MEASURE_MEDIUM_PRECISION: u8 = 0xf6;
+const MEASURE_LOW_PRECISION: u8 = 0xe0;
+const READ_SERIAL: u8 = 0x89;
+const SOFT_RESET: u8 = 0x94;
+
+impl Sht41 {
+    /// The default I²C address of the SHT41-A.
+    pub const ADDR: u8 = 0x44;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            temp_c: 22.0,
+            humidity_percent: 40.0,
+            serial: 0x1234_5678,
+            rsp: Response::default(),
+        }
+    }
+}
+
+impl Default for Sht41 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Device for Sht41 {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ErrorKind> {
+        // Unlike most Sensirion sensors, the SHT4x uses 8-bit commands.
+        let &[command] = bytes else {
+            return Err(nak());
+        };
+        self.rsp.clear();
+        match command {
+            MEASURE_HIGH_PRECISION | MEASURE_MEDIUM_PRECISION | MEASURE_LOW_PRECISION => {
+                self.rsp
+                    .push(sensirion::scaled(self.temp_c + 45.0, 65535.0 / 175.0));
+                self.rsp.push(sensirion::scaled(
+                    self.humidity_percent + 6.0,
+                    65535.0 / 125.0,
+                ));
+            }
+            READ_SERIAL => {
+                self.rsp.push((self.serial >> 16) as u16);
+                self.rsp.push(self.serial as u16);
+            }
+            SOFT_RESET => {}
+            _ => return Err(nak()),
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), ErrorKind> {
+        self.rsp.read(buf)
+    }
+}
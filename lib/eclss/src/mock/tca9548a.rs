@@ -0,0 +1,63 @@
+use super::{sensirion::nak, Device, MockI2c};
+use embedded_hal::i2c::ErrorKind;
+
+/// An emulated TCA9548A 8-channel I²C multiplexer.
+///
+/// Each channel is a [`MockI2c`] bus, returned by [`Tca9548a::channel`].
+/// Devices attached to a channel are reachable from the bus the multiplexer
+/// is attached to only while that channel is enabled.
+#[derive(Clone, Debug, Default)]
+pub struct Tca9548a {
+    channels: [MockI2c; 8],
+    control: u8,
+}
+
+impl Tca9548a {
+    /// The default I²C address of the TCA9548A.
+    pub const ADDR: u8 = 0x70;
+
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a handle to the bus for `channel`.
+    ///
+    /// # Panics
+    ///
+    /// If `channel` is not between 0 and 7.
+    #[must_use]
+    pub fn channel(&self, channel: u8) -> MockI2c {
+        self.channels[channel as usize].clone()
+    }
+
+    /// Returns the multiplexer's control register, in which bit `n` is set if
+    /// channel `n` is enabled.
+    #[must_use]
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    pub(super) fn enabled(&self) -> impl Iterator<Item = &MockI2c> + '_ {
+        self.channels
+            .iter()
+            .enumerate()
+            .filter(|&(n, _)| self.control & (1 << n) != 0)
+            .map(|(_, channel)| channel)
+    }
+}
+
+impl Device for Tca9548a {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), ErrorKind> {
+        let &[control] = bytes else {
+            return Err(nak());
+        };
+        self.control = control;
+        Ok(())
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<(), ErrorKind> {
+        buf.fill(self.control);
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+//! Detecting which sensors are connected to an I²C bus.
+//!
+//! [`probe`] scans the I²C addresses used by the supported sensor models, and
+//! fingerprints any device found at one of those addresses by reading its
+//! identity registers: the serial number or feature set of a Sensirion sensor,
+//! the part ID of an ENS160, the chip ID of a BME680, or the frame header of a
+//! PMSA003I. A device is only reported if it responds the way the sensor model
+//! which uses that address would, so unrelated devices which happen to share
+//! an address are not mistaken for sensors.
+//!
+//! Probing doesn't depend on the sensor drivers, so it can detect sensor models
+//! whose feature flags are not enabled.
+use crate::sensor::SensorName;
+use embedded_hal_async::{delay::DelayNs, i2c::I2c};
+
+/// A sensor found by [`probe`].
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Detected {
+    /// The sensor's model.
+    pub name: SensorName,
+    /// The sensor's I²C address.
+    pub address: u8,
+}
+
+/// The I²C addresses scanned by [`probe`].
+pub const ADDRESSES: &[u8] = &[
+    PMSA003I_ADDR,
+    0x44, // SHT4x-A
+    0x45, // SHT4x-B
+    0x46, // SHT4x-C
+    0x52, // ENS160, with ADDR pulled low
+    0x53, // ENS160
+    SGP30_ADDR,
+    SCD30_ADDR,
+    SCD4X_ADDR,
+    SEN5X_ADDR,
+    0x76, // BME680, with SDO pulled low
+    0x77, // BME680
+];
+
+const PMSA003I_ADDR: u8 = 0x12;
+const SGP30_ADDR: u8 = 0x58;
+const SCD30_ADDR: u8 = 0x61;
+const SCD4X_ADDR: u8 = 0x62;
+const SEN5X_ADDR: u8 = 0x69;
+
+/// Returns the I²C addresses at which [`probe`] looks for the `name` sensor
+/// model. The first address is the model's default address.
+#[must_use]
+pub fn addresses(name: SensorName) -> &'static [u8] {
+    match name {
+        SensorName::Pmsa003i => &[PMSA003I_ADDR],
+        SensorName::Sht41 => &[0x44, 0x45, 0x46],
+        SensorName::Ens160 => &[0x53, 0x52],
+        SensorName::Sgp30 => &[SGP30_ADDR],
+        SensorName::Scd30 => &[SCD30_ADDR],
+        SensorName::Scd40 | SensorName::Scd41 => &[SCD4X_ADDR],
+        SensorName::Sen55 => &[SEN5X_ADDR],
+        SensorName::Bme680 => &[0x77, 0x76],
+        _ => &[],
+    }
+}
+
+/// Scans `i2c` for supported sensors, calling `detected` for each sensor that
+/// is found.
+///
+/// Any sensors which are already running on the bus may be disturbed by
+/// probing, so this should be called before sensors are started.
+pub async fn probe<I, D>(i2c: &mut I, delay: &mut D, mut detected: impl FnMut(Detected))
+where
+    I: I2c,
+    D: DelayNs,
+{
+    for &address in ADDRESSES {
+        if let Some(name) = identify(i2c, delay, address).await {
+            debug!("{name:>8}: detected at {address:#04x}");
+            detected(Detected { name, address });
+        }
+    }
+}
+
+/// Returns the model of the sensor at `address`, or `None` if no supported
+/// sensor responds at that address.
+pub async fn identify<I, D>(i2c: &mut I, delay: &mut D, address: u8) -> Option<SensorName>
+where
+    I: I2c,
+    D: DelayNs,
+{
+    match address {
+        PMSA003I_ADDR => {
+            let mut frame = [0u8; 32];
+            i2c.read(address, &mut frame).await.ok()?;
+            let (data, checksum) = frame.split_at(30);
+            let sum = data.iter().map(|&byte| byte as u16).sum::<u16>();
+            (data[..2] == [0x42, 0x4d] && checksum == sum.to_be_bytes())
+                .then_some(SensorName::Pmsa003i)
+        }
+        0x44..=0x46 => {
+            // READ_SERIAL
+            sensirion_read::<_, _, 2>(i2c, delay, address, &[0x89], 1).await?;
+            Some(SensorName::Sht41)
+        }
+        0x52 | 0x53 => {
+            // PART_ID register, little-endian
+            let mut part_id = [0u8; 2];
+            i2c.write_read(address, &[0x00], &mut part_id).await.ok()?;
+            (u16::from_le_bytes(part_id) == 0x0160).then_some(SensorName::Ens160)
+        }
+        SGP30_ADDR => {
+            // GET_FEATURE_SET; the upper nibble is the product type, which is
+            // 0 for the SGP30.
+            let [features] = sensirion_read(i2c, delay, address, &[0x20, 0x2f], 10).await?;
+            (features >> 12 == 0).then_some(SensorName::Sgp30)
+        }
+        SCD30_ADDR => {
+            // READ_FIRMWARE_VERSION
+            sensirion_read::<_, _, 1>(i2c, delay, address, &[0xd1, 0x00], 3).await?;
+            Some(SensorName::Scd30)
+        }
+        SCD4X_ADDR => identify_scd4x(i2c, delay).await,
+        SEN5X_ADDR => {
+            // READ_PRODUCT_NAME, as a NUL-terminated string packed into words.
+            let name = sensirion_read::<_, _, 3>(i2c, delay, address, &[0xd0, 0x14], 20).await?;
+            let [s, e, n] = name.map(u16::to_be_bytes);
+            (s == *b"SE" && e == *b"N5" && n[0] == b'5').then_some(SensorName::Sen55)
+        }
+        0x76 | 0x77 => {
+            // CHIP_ID register
+            let mut chip_id = [0u8];
+            i2c.write_read(address, &[0xd0], &mut chip_id).await.ok()?;
+            (chip_id == [0x61]).then_some(SensorName::Bme680)
+        }
+        _ => None,
+    }
+}
+
+async fn identify_scd4x<I, D>(i2c: &mut I, delay: &mut D) -> Option<SensorName>
+where
+    I: I2c,
+    D: DelayNs,
+{
+    // The SCD4x only responds to most commands while idle, and it may still be
+    // measuring if the daemon was restarted, so stop periodic measurement
+    // first.
+    i2c.write(SCD4X_ADDR, &[0x3f, 0x86]).await.ok()?;
+    delay.delay_ms(500).await;
+    // GET_SERIAL_NUMBER
+    sensirion_read::<_, _, 3>(i2c, delay, SCD4X_ADDR, &[0x36, 0x82], 1).await?;
+    // GET_SENSOR_VARIANT, whose upper nibble is 0 for the SCD40. Older
+    // firmware doesn't support this command, so assume the sensor is an SCD41
+    // if it fails.
+    match sensirion_read(i2c, delay, SCD4X_ADDR, &[0x20, 0x2f], 1).await {
+        Some([variant]) if variant >> 12 == 0 => Some(SensorName::Scd40),
+        _ => Some(SensorName::Scd41),
+    }
+}
+
+/// Sends a Sensirion `command`, and reads back `N` CRC-protected words after
+/// waiting `delay_ms` for the sensor to execute it.
+async fn sensirion_read<I, D, const N: usize>(
+    i2c: &mut I,
+    delay: &mut D,
+    address: u8,
+    command: &[u8],
+    delay_ms: u32,
+) -> Option<[u16; N]>
+where
+    I: I2c,
+    D: DelayNs,
+{
+    i2c.write(address, command).await.ok()?;
+    delay.delay_ms(delay_ms).await;
+    let mut buf = [[0u8; 3]; N];
+    i2c.read(address, buf.as_flattened_mut()).await.ok()?;
+    let mut words = [0u16; N];
+    for (word, &[hi, lo, crc]) in words.iter_mut().zip(&buf) {
+        if crc8(&[hi, lo]) != crc {
+            return None;
+        }
+        *word = u16::from_be_bytes([hi, lo]);
+    }
+    Some(words)
+}
+
+/// The Sensirion CRC-8 checksum (polynomial 0x31, initialized to 0xFF).
+fn crc8(data: &[u8]) -> u8 {
+    let mut crc: u8 = 0xff;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x31
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
@@ -7,6 +7,7 @@ use embedded_hal_async::delay::DelayNs;
 #[cfg_attr(feature = "clap", derive(clap::Parser))]
 #[cfg_attr(feature = "clap", clap(next_help_heading = "Sensor Retry Settings"))]
 #[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
 pub struct RetryConfig {
     /// Initial value for sensor retry backoffs
     #[cfg_attr(
@@ -17,6 +18,10 @@ pub struct RetryConfig {
             value_parser = humantime::parse_duration,
         )
     )]
+    #[cfg_attr(
+        all(feature = "serde", feature = "std"),
+        serde(with = "crate::serde_duration")
+    )]
     pub initial_backoff: Duration,
 
     /// Maximum backoff duration for sensor retries
@@ -28,9 +33,22 @@ pub struct RetryConfig {
             value_parser = humantime::parse_duration,
         ),
     )]
+    #[cfg_attr(
+        all(feature = "serde", feature = "std"),
+        serde(with = "crate::serde_duration")
+    )]
     pub max_backoff: Duration,
 }
 
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
 impl RetryConfig {
     pub(crate) fn backoff(&self) -> ExpBackoff {
         ExpBackoff::new(self.initial_backoff).with_max(self.max_backoff)
@@ -1,9 +1,19 @@
-use crate::{error::SensorError, Config, Eclss};
+use crate::{error::SensorError, Config, Eclss, SharedBus};
+use core::fmt;
+use core::future::{poll_fn, Future};
 use core::num::Wrapping;
+use core::pin::{pin, Pin};
 use core::sync::atomic::{AtomicBool, Ordering};
+use core::task::Poll;
 use core::time::Duration;
-pub use eclss_api::SensorName;
+pub use eclss_api::{
+    CompensationSource, CompensationSources, DeviceStatus, InstanceName, SensorId, SensorName,
+};
 use embedded_hal_async::delay::DelayNs;
+use maitake_sync::spin::Mutex;
+pub mod calibration;
+pub mod command;
+pub mod compensation;
 mod status;
 
 #[cfg(feature = "bme680")]
@@ -44,6 +54,9 @@ pub mod ens160;
 #[cfg(feature = "ens160")]
 pub use self::ens160::Ens160;
 
+pub use self::calibration::{Calibration, Correction};
+pub use self::command::{Command, CommandError, Commands, Response};
+pub use self::compensation::Compensation;
 pub use self::status::{Status, StatusCell};
 
 use tinymetrics::registry::RegistryMap;
@@ -53,11 +66,128 @@ pub trait Sensor {
     type Error: SensorError;
 
     const NAME: eclss_api::SensorName;
+
+    /// The default interval at which this sensor is polled.
     const POLL_INTERVAL: Duration;
 
     async fn init(&mut self) -> Result<(), Self::Error>;
 
     async fn poll(&mut self) -> Result<(), Self::Error>;
+
+    /// Puts the sensor into a low-power or idle state before the ECLSS shuts
+    /// down.
+    ///
+    /// This is called by [`Eclss::run_sensor`] after [`Eclss::shutdown`], if
+    /// the sensor was successfully initialized. Sensors which measure
+    /// continuously should stop measuring and power down any heaters or fans
+    /// here, and sensors which store calibration state should flush it.
+    ///
+    /// By default, this does nothing.
+    async fn shutdown(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Executes a [`Command`] sent to this sensor through its
+    /// [`State::commands`] channel.
+    ///
+    /// This is called by [`Eclss::run_sensor`] between polls, once the sensor
+    /// has been initialized. A sensor which is left in a bad state by a
+    /// failed command will be reset when it next fails to poll.
+    /// [`Command::Reset`] is handled by `run_sensor` itself, and is never
+    /// passed to this method.
+    ///
+    /// By default, all commands are [unsupported](CommandError::Unsupported).
+    async fn command(&mut self, command: Command) -> Result<Response, CommandError<Self::Error>> {
+        let _ = command;
+        Err(CommandError::Unsupported)
+    }
+
+    /// Returns the conditions reported by this sensor's self-diagnostics, as
+    /// of its most recent poll.
+    ///
+    /// This is recorded in the sensor's [`State`] after each successful poll.
+    /// By default, sensors report no conditions.
+    fn device_status(&self) -> DeviceStatus {
+        DeviceStatus::default()
+    }
+
+    /// Returns where this sensor's environmental compensation inputs came
+    /// from, as of its most recent poll.
+    ///
+    /// This is recorded in the sensor's [`State`] after each successful poll.
+    /// By default, sensors use no compensation inputs.
+    fn compensation(&self) -> CompensationSources {
+        CompensationSources::default()
+    }
+
+    /// Returns the interval at which this sensor should be polled.
+    ///
+    /// By default, this is [`Self::POLL_INTERVAL`], but it may be overridden
+    /// by a sensor's [`SensorConfig`].
+    fn poll_interval(&self) -> Duration {
+        Self::POLL_INTERVAL
+    }
+
+    /// Returns the identity of this sensor.
+    ///
+    /// By default, this is the sensor's [`Self::NAME`] with no instance name,
+    /// but it may be overridden by a sensor's [`SensorConfig`] when more than
+    /// one sensor of the same model is present.
+    fn id(&self) -> SensorId {
+        SensorId::new(Self::NAME)
+    }
+}
+
+/// Configuration for an individual sensor.
+///
+/// Any settings which are `None` use the defaults for that sensor model.
+#[derive(Debug)]
+pub struct SensorConfig<I: 'static> {
+    /// The I²C bus which the sensor is attached to.
+    ///
+    /// If this is `None`, the sensor is attached to the [`Eclss`] instance's
+    /// bus.
+    pub bus: Option<&'static SharedBus<I>>,
+
+    /// The sensor's I²C address.
+    ///
+    /// If this is `None`, the default address for the sensor model (generally,
+    /// the address used by Adafruit's breakout board) is used.
+    pub address: Option<u8>,
+
+    /// How often to poll the sensor.
+    ///
+    /// If this is `None`, the sensor's default [`Sensor::POLL_INTERVAL`] is
+    /// used.
+    pub poll_interval: Option<Duration>,
+
+    /// The name of this instance of the sensor model.
+    ///
+    /// This must be set to distinguish between multiple sensors of the same
+    /// model. Sensors without an instance name are labeled by their model
+    /// alone.
+    pub instance: Option<InstanceName>,
+
+    /// Corrections applied to the sensor's temperature and humidity readings.
+    ///
+    /// By default, readings are not corrected.
+    pub calibration: Calibration,
+
+    /// Which sensors to take temperature, humidity, and pressure readings
+    /// from, for sensors which compensate their own readings for them.
+    ///
+    /// By default, the fused estimate is used, or the mean of every healthy
+    /// sensor's readings if sensor fusion isn't running.
+    pub compensation: Compensation,
+}
+
+/// Returned when a [`SensorConfig`] sets an I²C address which the sensor does
+/// not support.
+#[derive(Copy, Clone, Debug)]
+pub struct InvalidAddress {
+    sensor: SensorName,
+    address: u8,
+    supported: &'static [u8],
 }
 
 impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
@@ -67,7 +197,7 @@ impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
             name = "sensor",
             level = tracing::Level::INFO,
             skip(self, delay, sensor, config),
-            fields(sensor = %S::NAME)
+            fields(sensor = %sensor.id())
         )
     )]
     pub async fn run_sensor<S>(
@@ -80,17 +210,25 @@ impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
         S: Sensor,
         S::Error: core::fmt::Display,
     {
+        if self.is_shutting_down() {
+            return Ok(());
+        }
+
+        let id = sensor.id();
         let State {
             status,
+            found,
             backoff,
             poll_interval,
-            ..
+            commands,
+            device_status,
+            compensation,
         } = self
             .sensors
             .get_or_register(
-                S::NAME,
+                id,
                 State {
-                    poll_interval: S::POLL_INTERVAL,
+                    poll_interval: sensor.poll_interval(),
                     backoff: config.retries.backoff(),
                     ..Default::default()
                 },
@@ -99,14 +237,23 @@ impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
         let errors = self
             .metrics
             .sensor_errors
-            .register(S::NAME)
+            .register(id)
             .ok_or("insufficient space in sensor errors metric")?;
         let resets = self
             .metrics
             .sensor_reset_count
-            .register(S::NAME)
+            .register(id)
             .ok_or("insufficient space in sensor reset count metric")?;
+        let last_reading = self
+            .metrics
+            .register_reading(id)
+            .ok_or("insufficient space in reading age metric")?;
         let mut has_come_up = false;
+        // set while the sensor is initialized, and cleared when it's reset.
+        let mut initialized = false;
+        // set when the sensor is being reset by a `Command::Reset`, which is
+        // completed once the sensor has been initialized again.
+        let mut resetting = false;
         'initialize: loop {
             let mut attempts = 0;
             let what_are_we_doing = if has_come_up { "initialize" } else { "reset" };
@@ -117,39 +264,76 @@ impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
                 status.set_status(error.as_status());
                 errors.fetch_add(1);
                 attempts += 1;
+                if resetting {
+                    commands.complete(Err(CommandError::Sensor(error.as_status())));
+                    resetting = false;
+                }
                 warn!(
                     %error,
-                    "failed to {what_are_we_doing} {} (attempt {attempts}): {error}",
-                    S::NAME
+                    "failed to {what_are_we_doing} {id} (attempt {attempts}): {error}"
                 );
 
                 if Some(attempts) == config.max_init_attempts {
-                    error!(
-                        "Giving up on {} after {attempts} attempts to {what_are_we_doing}",
-                        S::NAME
-                    );
+                    error!("Giving up on {id} after {attempts} attempts to {what_are_we_doing}");
                     return Err("failed to initialize sensor after maximum attempts");
                 }
 
-                backoff.wait(&mut delay).await;
+                if self.wait_unless_shutdown(backoff.wait(&mut delay)).await {
+                    break 'initialize;
+                }
             }
 
             backoff.reset();
+            initialized = true;
+            found.store(true, Ordering::Release);
             if has_come_up {
                 resets.fetch_add(1);
-                info!("successfully reset {}", S::NAME);
+                info!("successfully reset {id}");
+                if resetting {
+                    commands.complete(Ok(Response::Done));
+                    resetting = false;
+                }
             } else {
-                info!("initialized {}", S::NAME);
+                info!("initialized {id}");
                 has_come_up = true;
             }
 
             loop {
-                delay.delay_ms(poll_interval.as_millis() as u32).await;
+                {
+                    // execute any commands sent while waiting to poll the sensor.
+                    let mut poll_delay = pin!(delay.delay_ms(poll_interval.as_millis() as u32));
+                    loop {
+                        let command =
+                            match self.wait_for_command(poll_delay.as_mut(), commands).await {
+                                Wake::Elapsed => break,
+                                Wake::Shutdown => break 'initialize,
+                                Wake::Command(Command::Reset) => {
+                                    info!("resetting {id}...");
+                                    resetting = true;
+                                    initialized = false;
+                                    continue 'initialize;
+                                }
+                                Wake::Command(command) => command,
+                            };
+                        info!("executing {command} on {id}");
+                        let result = sensor.command(command).await.map_err(|error| match error {
+                            CommandError::Sensor(error) => {
+                                errors.fetch_add(1);
+                                warn!(%error, "failed to execute {command} on {id}: {error}");
+                                CommandError::Sensor(error.as_status())
+                            }
+                            CommandError::Unsupported => CommandError::Unsupported,
+                            CommandError::Busy => CommandError::Busy,
+                        });
+                        commands.complete(result);
+                    }
+                }
+
                 while let Err(error) = sensor.poll().await {
                     warn!(
                         %error,
                         retry_in = ?backoff.current(),
-                        "failed to poll {}, retrying: {error}", S::NAME
+                        "failed to poll {id}, retrying: {error}"
                     );
                     status.set_status(error.as_status());
                     errors.fetch_add(1);
@@ -157,23 +341,89 @@ impl<I, const SENSORS: usize> Eclss<I, { SENSORS }> {
                     if error.should_reset() {
                         tracing::info!(
                             %error,
-                            "attempting to clear {} error by resetting...",
-                            S::NAME,
+                            "attempting to clear {id} error by resetting...",
                         );
+                        initialized = false;
                         continue 'initialize;
-                    } else {
-                        backoff.wait(&mut delay).await;
+                    } else if self.wait_unless_shutdown(backoff.wait(&mut delay)).await {
+                        break 'initialize;
                     }
                 }
                 status.set_status(Status::Up);
+                self.metrics.record_reading(last_reading);
+                *device_status.lock() = sensor.device_status();
+                *compensation.lock() = sensor.compensation();
             }
         }
+
+        // don't try to shut down a sensor that never came up, as it's
+        // probably not there, or one which failed to reset, as that error
+        // has already been logged.
+        if initialized {
+            info!("shutting down {id}...");
+            match sensor.shutdown().await {
+                Ok(()) => info!("shut down {id}"),
+                Err(error) => {
+                    errors.fetch_add(1);
+                    warn!(%error, "failed to shut down {id}: {error}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Waits for `wait` to complete, unless the ECLSS is shut down or a
+    /// command is sent to the sensor first.
+    ///
+    /// `wait` is not consumed, so that it may be waited on again after a
+    /// command is executed.
+    async fn wait_for_command(
+        &self,
+        mut wait: Pin<&mut impl Future<Output = ()>>,
+        commands: &Commands,
+    ) -> Wake {
+        let mut command = pin!(commands.recv());
+        let mut shutdown = pin!(self.wait_for_shutdown());
+        poll_fn(|cx| {
+            if shutdown.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(Wake::Shutdown);
+            }
+            if let Poll::Ready(command) = command.as_mut().poll(cx) {
+                return Poll::Ready(Wake::Command(command));
+            }
+            wait.as_mut().poll(cx).map(|()| Wake::Elapsed)
+        })
+        .await
+    }
+
+    /// Waits for `wait` to complete, unless the ECLSS is shut down first.
+    ///
+    /// Returns `true` if the ECLSS is shutting down.
+    pub(crate) async fn wait_unless_shutdown(&self, wait: impl Future<Output = ()>) -> bool {
+        let mut wait = pin!(wait);
+        let mut shutdown = pin!(self.wait_for_shutdown());
+        poll_fn(|cx| {
+            if shutdown.as_mut().poll(cx).is_ready() {
+                return Poll::Ready(true);
+            }
+            wait.as_mut().poll(cx).map(|()| false)
+        })
+        .await
     }
 }
 
-pub type Registry<const N: usize> = RegistryMap<SensorName, State, { N }>;
+pub type Registry<const N: usize> = RegistryMap<SensorId, State, { N }>;
+
+/// Returned by `Eclss::wait_for_command`.
+enum Wake {
+    Elapsed,
+    Command(Command),
+    Shutdown,
+}
 
 pub(crate) struct PollCount {
+    interval: Duration,
     polls: Wrapping<u32>,
     abs_humidity_interval: u32,
     log_info_interval: u32,
@@ -192,6 +442,7 @@ impl Config {
         };
 
         PollCount {
+            interval: poll_interval,
             polls: Wrapping(0),
             abs_humidity_interval: self.abs_humidity_interval,
             log_info_interval,
@@ -200,6 +451,10 @@ impl Config {
 }
 
 impl PollCount {
+    pub(crate) fn interval(&self) -> Duration {
+        self.interval
+    }
+
     pub(crate) fn add(&mut self) {
         self.polls += 1;
     }
@@ -213,6 +468,96 @@ impl PollCount {
     }
 }
 
+// === impl SensorConfig ===
+
+impl<I> SensorConfig<I> {
+    pub(crate) fn bus<const SENSORS: usize>(
+        &self,
+        eclss: &'static Eclss<I, SENSORS>,
+    ) -> &'static SharedBus<I> {
+        self.bus.unwrap_or(&eclss.i2c)
+    }
+
+    /// Returns the configured address, or `default` if none is configured.
+    ///
+    /// This is used for sensors whose drivers accept any address.
+    pub(crate) fn address_or(&self, default: u8) -> u8 {
+        self.address.unwrap_or(default)
+    }
+
+    /// Returns the configured address if it is one of the `supported`
+    /// addresses, or the first supported address if none is configured.
+    pub(crate) fn address_in(
+        &self,
+        sensor: SensorName,
+        supported: &'static [u8],
+    ) -> Result<u8, InvalidAddress> {
+        match self.address {
+            None => Ok(supported[0]),
+            Some(address) if supported.contains(&address) => Ok(address),
+            Some(address) => Err(InvalidAddress {
+                sensor,
+                address,
+                supported,
+            }),
+        }
+    }
+
+    pub(crate) fn poll_interval(&self, default: Duration) -> Duration {
+        self.poll_interval.unwrap_or(default)
+    }
+
+    pub(crate) fn id(&self, name: SensorName) -> SensorId {
+        SensorId {
+            name,
+            instance: self.instance,
+        }
+    }
+}
+
+impl<I> Default for SensorConfig<I> {
+    fn default() -> Self {
+        Self {
+            bus: None,
+            address: None,
+            poll_interval: None,
+            instance: None,
+            calibration: Calibration::IDENTITY,
+            compensation: Compensation::AUTOMATIC,
+        }
+    }
+}
+
+impl<I> Clone for SensorConfig<I> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<I> Copy for SensorConfig<I> {}
+
+// === impl InvalidAddress ===
+
+impl fmt::Display for InvalidAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            sensor,
+            address,
+            supported,
+        } = self;
+        write!(
+            f,
+            "{sensor} does not support I²C address {address:#04x} (supported addresses:"
+        )?;
+        for address in *supported {
+            write!(f, " {address:#04x}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl core::error::Error for InvalidAddress {}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct State {
@@ -223,6 +568,59 @@ pub struct State {
     poll_interval: Duration,
     #[cfg_attr(feature = "serde", serde(skip))]
     backoff: crate::retry::ExpBackoff,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    commands: Commands,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_locked"))]
+    device_status: Mutex<DeviceStatus>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_locked"))]
+    compensation: Mutex<CompensationSources>,
+}
+
+impl State {
+    /// Returns the current status of this sensor.
+    #[must_use]
+    pub fn status(&self) -> Status {
+        self.status.status()
+    }
+
+    /// Returns `true` if this sensor has been initialized, and has not since
+    /// been marked as missing.
+    #[must_use]
+    pub fn found(&self) -> bool {
+        self.found.load(Ordering::Acquire)
+    }
+
+    /// Records that this sensor is no longer connected to the bus.
+    ///
+    /// This should be called when a sensor's task is stopped because the sensor
+    /// has disappeared. If the sensor is detected again, it will be marked as
+    /// found once its task initializes it.
+    pub fn set_missing(&self) {
+        self.found.store(false, Ordering::Release);
+        self.status.set_status(Status::NoAcknowledge);
+        *self.device_status.lock() = DeviceStatus::default();
+        *self.compensation.lock() = CompensationSources::default();
+    }
+
+    /// Returns the conditions reported by this sensor's self-diagnostics, as
+    /// of its most recent successful poll.
+    #[must_use]
+    pub fn device_status(&self) -> DeviceStatus {
+        *self.device_status.lock()
+    }
+
+    /// Returns where this sensor's compensation inputs came from, as of its
+    /// most recent successful poll.
+    #[must_use]
+    pub fn compensation(&self) -> CompensationSources {
+        *self.compensation.lock()
+    }
+
+    /// Returns the channel for sending [`Command`]s to this sensor.
+    #[must_use]
+    pub fn commands(&self) -> &Commands {
+        &self.commands
+    }
 }
 
 impl Default for State {
@@ -232,6 +630,9 @@ impl Default for State {
             found: AtomicBool::new(false),
             poll_interval: Duration::from_secs(2),
             backoff: crate::retry::ExpBackoff::default(),
+            commands: Commands::new(),
+            device_status: Mutex::new(DeviceStatus::default()),
+            compensation: Mutex::new(CompensationSources::default()),
         }
     }
 }
@@ -245,15 +646,21 @@ fn serialize_atomic_bool<S: serde::Serializer>(
     found.load(Ordering::Relaxed).serialize(serializer)
 }
 
+#[cfg(feature = "serde")]
+fn serialize_locked<S: serde::Serializer, T: serde::Serialize>(
+    value: &Mutex<T>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value.lock().serialize(serializer)
+}
+
 /// Given a temperature in Celcius and a relative humidity percentage, returns
 /// an absolute humidity in grams/m^3.
 // TODO(eliza): can we avoid some of the float math?
 pub(crate) fn absolute_humidity(temp_c: f32, rel_humidity_percent: f32) -> f32 {
     // first, determine the saturation vapor pressure (`P_sat`) at `temp_c`
-    // degrees --- the pressure when the relative humidity is 100%. we compute
-    // this using a variant of the Magnus-Tetens formula:
-    // (see https://doi.org/10.1175/1520-0493(1980)108%3C1046:TCOEPT%3E2.0.CO;2)
-    let p_sat = 6.112 * ((17.64 * temp_c) / (temp_c + 243.5)).exp();
+    // degrees --- the pressure when the relative humidity is 100%.
+    let p_sat = saturation_vapor_pressure(temp_c);
     // using `P_sat`, the pressure at 100% RH, we can compute `P`, the pressure
     // at the given relative humidity percentage, by multiplying:
     //     P = P_sat * (rel_humidity_percent / 100)
@@ -264,3 +671,19 @@ pub(crate) fn absolute_humidity(temp_c: f32, rel_humidity_percent: f32) -> f32 {
     (p_sat * rel_humidity_percent * 2.1674) / (273.15 + temp_c)
     // see https://carnotcycle.wordpress.com/2012/08/04/how-to-convert-relative-humidity-to-absolute-humidity/
 }
+
+/// Returns the saturation vapor pressure of water at `temp_c` degrees Celcius,
+/// in hPa.
+pub(crate) fn saturation_vapor_pressure(temp_c: f32) -> f32 {
+    // we compute this using a variant of the Magnus-Tetens formula:
+    // (see https://doi.org/10.1175/1520-0493(1980)108%3C1046:TCOEPT%3E2.0.CO;2)
+    6.112 * ((17.64 * temp_c) / (temp_c + 243.5)).exp()
+}
+
+/// Given PM2.5 and PM10 concentrations in µg/m³, returns the US EPA Air
+/// Quality Index, which is the higher of the two pollutants' indices.
+#[cfg(any(feature = "pmsa003i", feature = "sen55"))]
+pub(crate) fn aqi(pm2_5: f32, pm10_0: f32) -> u16 {
+    use eclss_api::air_quality::{aqi_pm10, aqi_pm2_5};
+    aqi_pm2_5(pm2_5.into()).max(aqi_pm10(pm10_0.into()))
+}
@@ -1,24 +1,51 @@
 use crate::{
     error::{Context, EclssError, SensorError},
     metrics::Gauge,
-    sensor::{PollCount, Sensor},
+    sensor::{Calibration, InvalidAddress, PollCount, Sensor, SensorConfig},
+    storage::{self, SaveInterval, SensorState, Store},
     SharedBus,
 };
-use bosch_bme680::{AsyncBme680, BmeError, MeasurmentData as MeasurementData};
+use bosch_bme680::{AsyncBme680, BmeError, DeviceAddress, MeasurmentData as MeasurementData};
 use core::fmt;
-use eclss_api::SensorName;
+use eclss_api::{SensorId, SensorName};
 use embedded_hal_async::{
     delay::DelayNs,
     i2c::{self, Error as _, I2c},
 };
-pub struct Bme680<I: 'static, D> {
-    sensor: AsyncBme680<&'static SharedBus<I>, D>,
+pub struct Bme680<I: 'static, D, S = ()> {
+    /// The driver is constructed when the sensor is initialized, as it
+    /// calculates the gas sensor heater's resistance from the ambient
+    /// temperature.
+    sensor: Option<AsyncBme680<&'static SharedBus<I>, D>>,
+    bus: &'static SharedBus<I>,
+    /// The sensor's I²C address, either `0x76` or `0x77`.
+    address: u8,
+    delay: D,
+    /// The most recently measured temperature, in whole °C.
+    ambient_temp: Option<i32>,
+    calibration: Calibration,
     temp: &'static Gauge,
     rel_humidity: &'static Gauge,
     abs_humidity: &'static Gauge,
     pressure: &'static Gauge,
     gas_resistance: &'static Gauge,
+    id: SensorId,
     polls: PollCount,
+    saves: SaveInterval,
+    loaded: bool,
+    store: S,
+}
+
+/// BME680 state which is saved across restarts.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct StoredState {
+    /// The ambient temperature used to calculate the gas sensor heater's
+    /// resistance, in °C.
+    ambient_temp_c: i32,
+}
+
+impl SensorState for StoredState {
+    const VERSION: u32 = 1;
 }
 
 impl<I, D> Bme680<I, D>
@@ -31,21 +58,80 @@ where
         config: &crate::Config,
         delay: D,
     ) -> Self {
+        Self::new_with_config(eclss, config, SensorConfig::default(), delay)
+            .expect("default sensor config must be valid")
+    }
+
+    /// Returns a new BME680 using the provided per-sensor configuration.
+    ///
+    /// The BME680 may be configured with the primary address (`0x76`) or the
+    /// secondary address (`0x77`).
+    pub fn new_with_config<const SENSORS: usize>(
+        eclss: &'static crate::Eclss<I, { SENSORS }>,
+        config: &crate::Config,
+        sensor_config: SensorConfig<I>,
+        delay: D,
+    ) -> Result<Self, InvalidAddress> {
         let metrics = &eclss.metrics;
+        let id = sensor_config.id(NAME);
 
         // the default I2C address of the Adafruit BME680 breakout board
         // is the "secondary" address, 0x77.
-        let address = bosch_bme680::DeviceAddress::Secondary;
-        // TODO(eliza): get this from an ambient measurement...
-        let ambient_temp = 20;
-        Self {
-            sensor: AsyncBme680::new(&eclss.i2c, address, delay, ambient_temp),
-            temp: metrics.temp_c.register(NAME).unwrap(),
-            pressure: metrics.pressure_hpa.register(NAME).unwrap(),
-            rel_humidity: metrics.rel_humidity_percent.register(NAME).unwrap(),
-            abs_humidity: metrics.abs_humidity_grams_m3.register(NAME).unwrap(),
-            gas_resistance: metrics.gas_resistance.register(NAME).unwrap(),
-            polls: config.poll_counter(POLL_INTERVAL),
+        let address = sensor_config.address_in(NAME, &[0x77, 0x76])?;
+        let poll_interval = sensor_config.poll_interval(POLL_INTERVAL);
+        Ok(Self {
+            id,
+            sensor: None,
+            bus: sensor_config.bus(eclss),
+            address,
+            delay,
+            ambient_temp: None,
+            calibration: sensor_config.calibration,
+            temp: metrics.temp_c.register(id).unwrap(),
+            pressure: metrics.pressure_hpa.register(id).unwrap(),
+            rel_humidity: metrics.rel_humidity_percent.register(id).unwrap(),
+            abs_humidity: metrics.abs_humidity_grams_m3.register(id).unwrap(),
+            gas_resistance: metrics.gas_resistance.register(id).unwrap(),
+            polls: config.poll_counter(poll_interval),
+            saves: SaveInterval::new(poll_interval),
+            loaded: false,
+            store: (),
+        })
+    }
+
+    /// Returns this sensor with the ambient temperature used by its gas
+    /// sensor heater saved to `store`.
+    pub fn with_storage<S: Store>(self, store: S) -> Bme680<I, D, S> {
+        Bme680 {
+            sensor: self.sensor,
+            bus: self.bus,
+            address: self.address,
+            delay: self.delay,
+            ambient_temp: self.ambient_temp,
+            calibration: self.calibration,
+            temp: self.temp,
+            rel_humidity: self.rel_humidity,
+            abs_humidity: self.abs_humidity,
+            pressure: self.pressure,
+            gas_resistance: self.gas_resistance,
+            id: self.id,
+            polls: self.polls,
+            saves: self.saves,
+            loaded: self.loaded,
+            store,
+        }
+    }
+}
+
+impl<I, D, S> Bme680<I, D, S>
+where
+    S: Store,
+    S::Error: fmt::Display,
+{
+    async fn save_state(&mut self) {
+        if let Some(ambient_temp_c) = self.ambient_temp {
+            let state = StoredState { ambient_temp_c };
+            storage::save(&mut self.store, self.id, &state).await;
         }
     }
 }
@@ -55,12 +141,16 @@ pub struct Error<E: embedded_hal::i2c::ErrorType>(BmeError<E>);
 
 const NAME: SensorName = SensorName::Bme680;
 const POLL_INTERVAL: core::time::Duration = core::time::Duration::from_secs(2);
+/// The ambient temperature assumed when the BME680 has never been measured.
+const DEFAULT_AMBIENT_TEMP: i32 = 20;
 
-impl<I, D> Sensor for Bme680<I, D>
+impl<I, D, S> Sensor for Bme680<I, D, S>
 where
     I: I2c + 'static,
     I::Error: core::fmt::Display,
-    D: DelayNs,
+    D: DelayNs + Clone,
+    S: Store + 'static,
+    S::Error: core::fmt::Display,
 {
     const NAME: SensorName = SensorName::Bme680;
     const POLL_INTERVAL: core::time::Duration = POLL_INTERVAL;
@@ -68,19 +158,60 @@ where
     type Error = EclssError<Error<&'static SharedBus<I>>>;
 
     async fn init(&mut self) -> Result<(), Self::Error> {
+        if !self.loaded {
+            self.loaded = true;
+            if let Some(StoredState { ambient_temp_c }) =
+                storage::load(&mut self.store, self.id).await
+            {
+                info!("{NAME:>8}: loaded saved ambient temperature: {ambient_temp_c}°C");
+                self.ambient_temp.get_or_insert(ambient_temp_c);
+            }
+        }
+
+        let ambient_temp = self.ambient_temp.unwrap_or(DEFAULT_AMBIENT_TEMP);
         let config = bosch_bme680::Configuration::default();
+        let address = match self.address {
+            0x76 => DeviceAddress::Primary,
+            _ => DeviceAddress::Secondary,
+        };
         self.sensor
+            .insert(AsyncBme680::new(
+                self.bus,
+                address,
+                self.delay.clone(),
+                ambient_temp,
+            ))
             .initialize(&config)
             .await
             .context("error initializing BME680")?;
-        info!("{NAME:>8}: initialized with config: {config:?}");
+        info!(
+            "{NAME:>8}: initialized with config: {config:?}, ambient temperature: {ambient_temp}°C"
+        );
+        Ok(())
+    }
+
+    async fn shutdown(&mut self) -> Result<(), Self::Error> {
+        // The BME680 is only powered up while it's measuring, so there's
+        // nothing to do here other than saving its state.
+        self.save_state().await;
         Ok(())
     }
 
+    fn poll_interval(&self) -> core::time::Duration {
+        self.polls.interval()
+    }
+
+    fn id(&self) -> SensorId {
+        self.id
+    }
+
     async fn poll(&mut self) -> Result<(), Self::Error> {
+        let Some(ref mut sensor) = self.sensor else {
+            return Err(BmeError::Uninitialized).context("BME680 has not been initialized");
+        };
         let mut timeouts = 0;
         let data = loop {
-            match self.sensor.measure().await {
+            match sensor.measure().await {
                 Ok(data) => break data,
                 // don't get into long backoffs on timeouts...
                 Err(BmeError::MeasuringTimeOut) if timeouts < 5 => {
@@ -101,6 +232,8 @@ where
             gas_resistance,
         } = data;
         self.polls.add();
+        let (temperature, humidity) = self.calibration.correct(temperature, humidity);
+        self.ambient_temp = Some(temperature as i32);
 
         // pretty sure the `bosch-bme680` library is off by a factor of 100 when
         // representing pressures as hectopascals...
@@ -136,6 +269,10 @@ where
             }
         }
 
+        if self.saves.poll() {
+            self.save_state().await;
+        }
+
         Ok(())
     }
 }
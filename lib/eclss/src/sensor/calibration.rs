@@ -0,0 +1,124 @@
+//! Per-sensor corrections to temperature and humidity readings.
+//!
+//! Sensors in an enclosure often read a little hot, because they are warmed by
+//! their own electronics (or by their neighbours'). A [`Calibration`] corrects
+//! a sensor's readings before they are recorded in the
+//! [`SensorMetrics`](crate::SensorMetrics), so that every consumer of those
+//! metrics sees the same corrected values.
+
+/// A linear correction to a reading, which is multiplied by `scale`, and then
+/// added to `offset`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Correction {
+    pub offset: f32,
+    pub scale: f32,
+}
+
+/// Corrections to a sensor's temperature and relative humidity readings.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Calibration {
+    /// The correction applied to temperature readings, in °C.
+    pub temp: Correction,
+    /// The correction applied to relative humidity readings, in percent.
+    ///
+    /// This is applied after the relative humidity has been adjusted for the
+    /// corrected temperature.
+    pub humidity: Correction,
+}
+
+// === impl Correction ===
+
+impl Correction {
+    /// A correction which leaves readings unchanged.
+    pub const IDENTITY: Self = Self {
+        offset: 0.0,
+        scale: 1.0,
+    };
+
+    /// Returns a correction which adds `offset` to readings.
+    #[must_use]
+    pub const fn offset(offset: f32) -> Self {
+        Self { offset, scale: 1.0 }
+    }
+
+    /// Returns the corrected value of `reading`.
+    #[must_use]
+    pub fn apply(&self, reading: f32) -> f32 {
+        reading * self.scale + self.offset
+    }
+
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+}
+
+impl Default for Correction {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+// === impl Calibration ===
+
+impl Calibration {
+    /// A calibration which leaves readings unchanged.
+    pub const IDENTITY: Self = Self {
+        temp: Correction::IDENTITY,
+        humidity: Correction::IDENTITY,
+    };
+
+    #[must_use]
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    /// Returns the corrected temperature and relative humidity, given a
+    /// sensor's temperature and relative humidity readings.
+    ///
+    /// Relative humidity depends on temperature: a sensor which reads hot
+    /// also reads dry, because the air around it is warmer than the air in
+    /// the room. So, the relative humidity is first recomputed for the
+    /// corrected temperature, assuming the amount of water vapor in the air
+    /// is the same, and then the humidity correction is applied.
+    #[must_use]
+    pub fn correct(&self, temp_c: f32, rel_humidity_percent: f32) -> (f32, f32) {
+        let corrected_temp = self.temp.apply(temp_c);
+        let mut rel_humidity = rel_humidity_percent;
+        if !self.temp.is_identity() {
+            rel_humidity *= super::saturation_vapor_pressure(temp_c)
+                / super::saturation_vapor_pressure(corrected_temp);
+        }
+        let rel_humidity = self.humidity.apply(rel_humidity).clamp(0.0, 100.0);
+        (corrected_temp, rel_humidity)
+    }
+
+    /// Returns the corrected temperature and relative humidity, when a sensor
+    /// may only report one of the two.
+    ///
+    /// If only the relative humidity is known, it can't be recomputed for the
+    /// corrected temperature, so only the humidity correction is applied.
+    #[must_use]
+    pub fn correct_partial(
+        &self,
+        temp_c: Option<f32>,
+        rel_humidity_percent: Option<f32>,
+    ) -> (Option<f32>, Option<f32>) {
+        match (temp_c, rel_humidity_percent) {
+            (Some(temp_c), Some(rel_humidity)) => {
+                let (temp_c, rel_humidity) = self.correct(temp_c, rel_humidity);
+                (Some(temp_c), Some(rel_humidity))
+            }
+            (temp_c, rel_humidity) => (
+                temp_c.map(|temp_c| self.temp.apply(temp_c)),
+                rel_humidity.map(|rh| self.humidity.apply(rh).clamp(0.0, 100.0)),
+            ),
+        }
+    }
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
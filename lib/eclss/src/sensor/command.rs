@@ -0,0 +1,226 @@
+//! Commands sent to running sensors.
+//!
+//! A sensor is owned by the task running it, so other tasks can't use the
+//! sensor directly, e.g. to recalibrate it. Instead, they send a [`Command`]
+//! through the [`Commands`] channel in the sensor's [`State`], and
+//! [`Eclss::run_sensor`] passes it to [`Sensor::command`] between polls.
+//!
+//! [`State`]: super::State
+//! [`Eclss::run_sensor`]: crate::Eclss::run_sensor
+//! [`Sensor::command`]: super::Sensor::command
+use super::Status;
+use core::fmt;
+use maitake_sync::{spin::Mutex, WaitQueue};
+
+/// A command sent to a running sensor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Command {
+    /// Recalibrate the sensor's CO₂ readings, given the CO₂ concentration it's
+    /// currently exposed to, in ppm.
+    ForcedRecalibration { reference_ppm: u16 },
+    /// Enable or disable the sensor's automatic self-calibration.
+    SetAutomaticSelfCalibration { enabled: bool },
+    /// Reset the sensor, and initialize it again.
+    ///
+    /// This is handled by [`Eclss::run_sensor`], rather than by the sensor,
+    /// in the same way as a sensor error which can be cleared by a reset.
+    ///
+    /// [`Eclss::run_sensor`]: crate::Eclss::run_sensor
+    Reset,
+    /// Discard the sensor's learned baseline calibration, so that it
+    /// calibrates itself again from scratch.
+    ResetBaseline,
+    /// Run the sensor's fan at full speed to blow out accumulated dust.
+    CleanFan,
+}
+
+/// The result of a successful [`Command`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Response {
+    /// The sensor was recalibrated.
+    Recalibrated {
+        /// The correction applied to the sensor's CO₂ readings, in ppm, if
+        /// the sensor reports it.
+        correction_ppm: Option<i16>,
+    },
+    /// The sensor's automatic self-calibration was enabled or disabled.
+    AutomaticSelfCalibration { enabled: bool },
+    /// The command was executed, and has no other result.
+    Done,
+}
+
+/// Errors returned by a [`Command`].
+///
+/// Errors returned by [`Commands::send`] carry the [`Status`] corresponding
+/// to the sensor's error, which is logged by the sensor's task.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandError<E = Status> {
+    /// The sensor does not support this command.
+    Unsupported,
+    /// Another command is already being sent to the sensor.
+    Busy,
+    /// The sensor returned an error while executing the command.
+    Sensor(E),
+}
+
+/// A channel for sending [`Command`]s to a running sensor.
+///
+/// Only one command may be sent to a sensor at a time.
+pub struct Commands {
+    slot: Mutex<Slot>,
+    /// Woken when a command is sent.
+    sent: WaitQueue,
+    /// Woken when a command has been executed.
+    done: WaitQueue,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Slot {
+    Idle,
+    Pending(Command),
+    Running,
+    Done(Result<Response, CommandError>),
+}
+
+/// Returns the channel to idle if a [`Commands::send`] future is dropped
+/// before the command completes.
+struct Abandon<'a>(&'a Commands);
+
+// === impl Command ===
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ForcedRecalibration { reference_ppm } => {
+                write!(f, "forced recalibration to {reference_ppm} ppm")
+            }
+            Self::SetAutomaticSelfCalibration { enabled: true } => {
+                f.write_str("enable automatic self-calibration")
+            }
+            Self::SetAutomaticSelfCalibration { enabled: false } => {
+                f.write_str("disable automatic self-calibration")
+            }
+            Self::Reset => f.write_str("reset"),
+            Self::ResetBaseline => f.write_str("baseline reset"),
+            Self::CleanFan => f.write_str("fan cleaning"),
+        }
+    }
+}
+
+// === impl CommandError ===
+
+impl<E: fmt::Display> fmt::Display for CommandError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unsupported => f.write_str("command not supported by this sensor"),
+            Self::Busy => f.write_str("another command is already in progress"),
+            Self::Sensor(error) => write!(f, "sensor error: {error}"),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> core::error::Error for CommandError<E> {}
+
+// === impl Commands ===
+
+impl Commands {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            slot: Mutex::new(Slot::Idle),
+            sent: WaitQueue::new(),
+            done: WaitQueue::new(),
+        }
+    }
+
+    /// Sends `command` to the sensor, and waits for it to be executed.
+    ///
+    /// Commands are executed between polls, so this waits for any poll which
+    /// is in progress to complete. If the sensor's task is not running, this
+    /// waits until it's started and the sensor is initialized, so callers
+    /// will generally want to time out. If this future is dropped before the
+    /// command is executed, the command is cancelled.
+    ///
+    /// # Errors
+    ///
+    /// - [`CommandError::Busy`] if another command is being sent to the sensor.
+    /// - [`CommandError::Unsupported`] if the sensor doesn't support `command`.
+    /// - [`CommandError::Sensor`] if the sensor failed to execute `command`.
+    pub async fn send(&self, command: Command) -> Result<Response, CommandError> {
+        {
+            let mut slot = self.slot.lock();
+            if !matches!(*slot, Slot::Idle) {
+                return Err(CommandError::Busy);
+            }
+            *slot = Slot::Pending(command);
+        }
+        let abandon = Abandon(self);
+        self.sent.wake();
+
+        loop {
+            {
+                let mut slot = self.slot.lock();
+                if let Slot::Done(result) = *slot {
+                    *slot = Slot::Idle;
+                    core::mem::forget(abandon);
+                    return result;
+                }
+            }
+            // the queue is never closed.
+            let _ = self.done.wait().await;
+        }
+    }
+
+    /// Waits for a command to be sent.
+    ///
+    /// This is cancel-safe: a command is only taken from the channel when
+    /// this future completes.
+    pub(crate) async fn recv(&self) -> Command {
+        loop {
+            {
+                let mut slot = self.slot.lock();
+                if let Slot::Pending(command) = *slot {
+                    *slot = Slot::Running;
+                    return command;
+                }
+            }
+            // the queue is never closed.
+            let _ = self.sent.wait().await;
+        }
+    }
+
+    /// Completes the command most recently returned by [`Commands::recv`].
+    pub(crate) fn complete(&self, result: Result<Response, CommandError>) {
+        {
+            let mut slot = self.slot.lock();
+            // if the sender gave up on the command, there's no one to tell.
+            if !matches!(*slot, Slot::Running) {
+                return;
+            }
+            *slot = Slot::Done(result);
+        }
+        self.done.wake();
+    }
+}
+
+impl Default for Commands {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Commands {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Commands")
+            .field("slot", &*self.slot.lock())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Abandon<'_> {
+    fn drop(&mut self) {
+        *self.0.slot.lock() = Slot::Idle;
+    }
+}
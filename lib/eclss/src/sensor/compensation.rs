@@ -0,0 +1,241 @@
+//! Environmental compensation inputs for gas sensors.
+//!
+//! Some sensors correct their readings for conditions they can't measure
+//! themselves: the SGP30 for absolute humidity, the ENS160 for temperature and
+//! relative humidity, and the SCD4x for barometric pressure. These inputs are
+//! taken from other sensors' readings, as configured by each sensor's
+//! [`Compensation`].
+//!
+//! A source is only used if its [`Status`] is [`Status::Up`] and its readings
+//! aren't stale, so that a sensor which has failed doesn't keep feeding its
+//! last reading to the sensors which depend on it. The source which was used
+//! for each input is reported in the sensor's [`State`](super::State).
+use super::{Registry, Status};
+use crate::{
+    fusion::{self, Quantity},
+    Eclss, SensorMetrics,
+};
+use eclss_api::SensorId;
+use tinymetrics::GaugeFamily;
+
+pub use eclss_api::{CompensationSource, CompensationSources};
+
+/// Which sensors a sensor takes its compensation inputs from.
+///
+/// Each input has a list of source sensors, in order of preference: the first
+/// source with a current reading is used. If a list is empty, the
+/// [fused](fusion::FUSED) estimate is used if there is one, or the mean of
+/// every healthy sensor's readings if sensor fusion isn't running. If none of
+/// the sources in a non-empty list have a current reading, that input isn't
+/// updated, rather than falling back to other sensors which weren't chosen.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Compensation {
+    /// Sources of temperature readings.
+    pub temp: &'static [SensorId],
+    /// Sources of humidity readings.
+    pub humidity: &'static [SensorId],
+    /// Sources of barometric pressure readings.
+    pub pressure: &'static [SensorId],
+}
+
+/// Resolves a sensor's compensation inputs, and records which source was used
+/// for each of them.
+pub(crate) struct Inputs {
+    id: SensorId,
+    config: Compensation,
+    metrics: &'static SensorMetrics,
+    sensors: &'static dyn Statuses,
+    sources: CompensationSources,
+}
+
+/// Looks up sensors' statuses, regardless of the size of the sensor registry.
+trait Statuses: Sync {
+    fn status(&self, sensor: &SensorId) -> Option<Status>;
+}
+
+/// Absolute humidity readings which differ from the median by less than this
+/// (in g/m³) are never rejected as outliers.
+const ABS_HUMIDITY_TOLERANCE: f64 = 2.0;
+
+// === impl Compensation ===
+
+impl Compensation {
+    /// Uses the fused estimate, or the mean of every healthy sensor's
+    /// readings, for every input.
+    pub const AUTOMATIC: Self = Self {
+        temp: &[],
+        humidity: &[],
+        pressure: &[],
+    };
+
+    /// Returns the sources of `quantity` readings, in order of preference.
+    #[must_use]
+    pub fn sources(&self, quantity: Quantity) -> &'static [SensorId] {
+        match quantity {
+            Quantity::Temp => self.temp,
+            Quantity::Humidity => self.humidity,
+            Quantity::Pressure => self.pressure,
+            Quantity::Co2 => &[],
+        }
+    }
+}
+
+// === impl Inputs ===
+
+impl Inputs {
+    pub(crate) fn new<I, const SENSORS: usize>(
+        eclss: &'static Eclss<I, { SENSORS }>,
+        id: SensorId,
+        config: Compensation,
+    ) -> Self {
+        Self {
+            id,
+            config,
+            metrics: &eclss.metrics,
+            sensors: &eclss.sensors,
+            sources: CompensationSources::default(),
+        }
+    }
+
+    /// Returns the temperature to compensate for, in °C.
+    pub(crate) fn temp_c(&mut self) -> Option<f64> {
+        let metrics = self.metrics;
+        self.resolve(&metrics.temp_c, Quantity::Temp, Quantity::Temp.tolerance())
+    }
+
+    /// Returns the relative humidity to compensate for, in percent.
+    pub(crate) fn rel_humidity_percent(&mut self) -> Option<f64> {
+        let metrics = self.metrics;
+        self.resolve(
+            &metrics.rel_humidity_percent,
+            Quantity::Humidity,
+            Quantity::Humidity.tolerance(),
+        )
+    }
+
+    /// Returns the absolute humidity to compensate for, in g/m³.
+    pub(crate) fn abs_humidity_grams_m3(&mut self) -> Option<f64> {
+        let metrics = self.metrics;
+        self.resolve(
+            &metrics.abs_humidity_grams_m3,
+            Quantity::Humidity,
+            ABS_HUMIDITY_TOLERANCE,
+        )
+    }
+
+    /// Returns the barometric pressure to compensate for, in hPa.
+    pub(crate) fn pressure_hpa(&mut self) -> Option<f64> {
+        let metrics = self.metrics;
+        self.resolve(
+            &metrics.pressure_hpa,
+            Quantity::Pressure,
+            Quantity::Pressure.tolerance(),
+        )
+    }
+
+    /// Returns the source of each input, as of when it was last resolved.
+    pub(crate) fn sources(&self) -> CompensationSources {
+        self.sources
+    }
+
+    fn resolve<const N: usize>(
+        &mut self,
+        family: &GaugeFamily<'static, N, SensorId>,
+        quantity: Quantity,
+        tolerance: f64,
+    ) -> Option<f64> {
+        let now = self.metrics.now();
+        let configured = self.config.sources(quantity);
+        let resolved = if configured.is_empty() {
+            self.reading(family, fusion::FUSED, now)
+                .map(|value| (value, CompensationSource::Sensor(fusion::FUSED)))
+                .or_else(|| {
+                    self.mean(family, tolerance, now)
+                        .map(|value| (value, CompensationSource::Mean))
+                })
+        } else {
+            configured.iter().find_map(|&sensor| {
+                self.reading(family, sensor, now)
+                    .map(|value| (value, CompensationSource::Sensor(sensor)))
+            })
+        };
+
+        let source = resolved.map(|(_, source)| source);
+        let used = match quantity {
+            Quantity::Temp => &mut self.sources.temp,
+            Quantity::Humidity => &mut self.sources.humidity,
+            Quantity::Pressure | Quantity::Co2 => &mut self.sources.pressure,
+        };
+        if *used != source {
+            match source {
+                Some(source) => info!("{:>8}: compensating for {quantity} from {source}", self.id),
+                None if !configured.is_empty() => warn!(
+                    "{:>8}: no configured {quantity} compensation source has a current \
+                    reading, not compensating for {quantity}",
+                    self.id
+                ),
+                None => debug!("{:>8}: no {quantity} readings to compensate for", self.id),
+            }
+            *used = source;
+        }
+        resolved.map(|(value, _)| value)
+    }
+
+    /// Returns `sensor`'s current reading in `family`, or `None` if it has no
+    /// reading, or its reading can't be trusted.
+    fn reading<const N: usize>(
+        &self,
+        family: &GaugeFamily<'static, N, SensorId>,
+        sensor: SensorId,
+        now: Option<u64>,
+    ) -> Option<f64> {
+        if !self.is_usable(&sensor, now) {
+            return None;
+        }
+        family
+            .metrics()
+            .iter()
+            .find(|(id, gauge)| **id == sensor && gauge.has_been_recorded())
+            .map(|(_, gauge)| gauge.value())
+    }
+
+    /// Returns the mean of every usable sensor's readings in `family`,
+    /// excluding outliers.
+    fn mean<const N: usize>(
+        &self,
+        family: &GaugeFamily<'static, N, SensorId>,
+        tolerance: f64,
+        now: Option<u64>,
+    ) -> Option<f64> {
+        let mut readings = [(0.0, 1.0); fusion::MAX_INPUTS];
+        let mut len = 0;
+        for (sensor, gauge) in family.metrics().iter() {
+            if len == fusion::MAX_INPUTS
+                || *sensor == fusion::FUSED
+                || !gauge.has_been_recorded()
+                || !self.is_usable(sensor, now)
+            {
+                continue;
+            }
+            readings[len].0 = gauge.value();
+            len += 1;
+        }
+        fusion::weighted_mean(&mut readings[..len], tolerance)
+    }
+
+    fn is_usable(&self, sensor: &SensorId, now: Option<u64>) -> bool {
+        // the fused sensor isn't polled, so it has no status.
+        let is_up = *sensor == fusion::FUSED || self.sensors.status(sensor) == Some(Status::Up);
+        *sensor != self.id && is_up && !self.metrics.is_stale(sensor, now)
+    }
+}
+
+// === impl Statuses ===
+
+impl<const N: usize> Statuses for Registry<N> {
+    fn status(&self, sensor: &SensorId) -> Option<Status> {
+        self.iter()
+            .find(|(id, _)| *id == sensor)
+            .map(|(_, state)| state.status())
+    }
+}
@@ -1,23 +1,43 @@
 use crate::{
     error::{Context, EclssError, SensorError},
-    metrics::{Gauge, HUMIDITY_METRICS, TEMP_METRICS},
-    sensor::{PollCount, Sensor},
+    metrics::Gauge,
+    sensor::{
+        compensation::{CompensationSources, Inputs},
+        InvalidAddress, PollCount, Sensor, SensorConfig,
+    },
+    storage::{self, SensorState, Store},
     SharedBus,
 };
 use core::fmt;
-use eclss_api::SensorName;
+use eclss_api::{air_quality::IaqCategory, SensorId, SensorName};
 
 use embedded_hal::i2c;
 use embedded_hal_async::{delay::DelayNs, i2c::I2c};
 
-pub struct Ens160<I: 'static, D> {
+pub struct Ens160<I: 'static, D, S = ()> {
     sensor: ens160::Ens160<&'static SharedBus<I>>,
     tvoc: &'static Gauge,
+    tvoc_iaq_category: &'static Gauge,
     eco2: &'static Gauge,
-    temp: &'static tinymetrics::GaugeFamily<'static, TEMP_METRICS, SensorName>,
-    rel_humidity: &'static tinymetrics::GaugeFamily<'static, HUMIDITY_METRICS, SensorName>,
+    /// The sources of the temperature and humidity which readings are
+    /// compensated for.
+    compensation: Inputs,
     delay: D,
+    id: SensorId,
     polls: PollCount,
+    state: Option<StoredState>,
+    store: S,
+}
+
+/// ENS160 state which is saved across restarts.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize)]
+struct StoredState {
+    /// Whether the sensor has completed its initial startup phase.
+    initial_startup_complete: bool,
+}
+
+impl SensorState for StoredState {
+    const VERSION: u32 = 1;
 }
 
 #[derive(Debug)]
@@ -26,8 +46,8 @@ pub enum Ens160Error<E> {
     Invalid,
 }
 
-// I2C address of the Adafruit breakout board.
-// TODO(eliza): allow configuring this to support other ENS160 parts...
+// I2C address of the Adafruit breakout board. This can be overridden by the
+// sensor's `SensorConfig` to support other ENS160 parts.
 const ADAFRUIT_ENS160_ADDR: u8 = 0x53;
 const SECOND_MS: u32 = 1_000;
 // The ENS160 sensor has a 3-minute warmup period when powered on, so we check
@@ -46,26 +66,68 @@ where
         config: &crate::Config,
         delay: D,
     ) -> Self {
+        Self::new_with_config(eclss, config, SensorConfig::default(), delay)
+            .expect("default sensor config must be valid")
+    }
+
+    pub fn new_with_config<const SENSORS: usize>(
+        eclss: &'static crate::Eclss<I, { SENSORS }>,
+        config: &crate::Config,
+        sensor_config: SensorConfig<I>,
+        delay: D,
+    ) -> Result<Self, InvalidAddress> {
         let metrics = &eclss.metrics;
-        Self {
-            sensor: ens160::Ens160::new(&eclss.i2c, ADAFRUIT_ENS160_ADDR),
-            tvoc: metrics.tvoc_ppb.register(NAME).unwrap(),
-            eco2: metrics.eco2_ppm.register(NAME).unwrap(),
-            temp: &metrics.temp_c,
-            rel_humidity: &metrics.rel_humidity_percent,
-            polls: config.poll_counter(POLL_INTERVAL),
+        let id = sensor_config.id(NAME);
+        let address = sensor_config.address_or(ADAFRUIT_ENS160_ADDR);
+        Ok(Self {
+            id,
+            sensor: ens160::Ens160::new(sensor_config.bus(eclss), address),
+            tvoc: metrics.tvoc_ppb.register(id).unwrap(),
+            tvoc_iaq_category: metrics.tvoc_iaq_category.register(id).unwrap(),
+            eco2: metrics.eco2_ppm.register(id).unwrap(),
+            compensation: Inputs::new(eclss, id, sensor_config.compensation),
+            polls: config.poll_counter(sensor_config.poll_interval(POLL_INTERVAL)),
             delay,
+            state: None,
+            store: (),
+        })
+    }
+
+    /// Returns this sensor with whether it has completed its initial startup
+    /// phase saved to `store`.
+    pub fn with_storage<S: Store>(self, store: S) -> Ens160<I, D, S> {
+        Ens160 {
+            sensor: self.sensor,
+            tvoc: self.tvoc,
+            tvoc_iaq_category: self.tvoc_iaq_category,
+            eco2: self.eco2,
+            compensation: self.compensation,
+            delay: self.delay,
+            id: self.id,
+            polls: self.polls,
+            state: self.state,
+            store,
         }
     }
 }
 
 const NAME: SensorName = SensorName::Ens160;
 
-impl<I, D> Sensor for Ens160<I, D>
+impl<I, D, S> Ens160<I, D, S> {
+    fn initial_startup_complete(&self) -> bool {
+        self.state
+            .as_ref()
+            .is_some_and(|state| state.initial_startup_complete)
+    }
+}
+
+impl<I, D, S> Sensor for Ens160<I, D, S>
 where
     I: I2c + 'static,
     I::Error: core::fmt::Display,
     D: DelayNs,
+    S: Store + 'static,
+    S::Error: core::fmt::Display,
 {
     const NAME: SensorName = NAME;
     const POLL_INTERVAL: core::time::Duration = POLL_INTERVAL;
@@ -91,6 +153,12 @@ where
             .await
             .context("error setting ENS160 to operational mode")?;
 
+        if self.state.is_none() {
+            let state = storage::load(&mut self.store, self.id).await;
+            self.state = Some(state.unwrap_or_default());
+        }
+        let initial_startup_complete = self.initial_startup_complete();
+
         // The ENS160 sensor has a 3-minute warmup period when powered on, so
         // wait for it to fully come up before starting to poll it.
         // In addition, the sensor requires a 1-hour initial startup phase the
@@ -110,6 +178,22 @@ where
             match validity {
                 ens160::Validity::NormalOperation => {
                     info!("{NAME:>8}: is ready");
+                    if !initial_startup_complete {
+                        let state = StoredState {
+                            initial_startup_complete: true,
+                        };
+                        storage::save(&mut self.store, self.id, &state).await;
+                        self.state = Some(state);
+                    }
+                    return Ok(());
+                }
+                // If this sensor has completed its initial startup phase
+                // before, don't wait an hour for it to do so again.
+                ens160::Validity::InitStartupPhase if initial_startup_complete => {
+                    info!(
+                        "{NAME:>8}: is reporting its initial startup phase, but \
+                        has already completed it"
+                    );
                     return Ok(());
                 }
                 ens160::Validity::WarmupPhase => {
@@ -140,8 +224,28 @@ where
         }
     }
 
+    async fn shutdown(&mut self) -> Result<(), Self::Error> {
+        // Deep sleep mode turns off the ENS160's hotplates.
+        self.sensor
+            .deep_sleep()
+            .await
+            .context("error setting ENS160 to deep sleep mode")
+    }
+
+    fn poll_interval(&self) -> core::time::Duration {
+        self.polls.interval()
+    }
+
+    fn id(&self) -> SensorId {
+        self.id
+    }
+
+    fn compensation(&self) -> CompensationSources {
+        self.compensation.sources()
+    }
+
     async fn poll(&mut self) -> Result<(), Self::Error> {
-        if let Some(avg_temp) = self.temp.mean() {
+        if let Some(avg_temp) = self.compensation.temp_c() {
             // per the docs: Unit is scaled by 100. For example, a temperature
             // value of 2550 should be used for 25.50 °C.
             let integer = avg_temp.trunc() as i16 * 100;
@@ -154,17 +258,15 @@ where
                 .context("error setting current temperature for ENS160")?;
         }
 
-        if let Some(avg_rh) = self.rel_humidity.mean() {
-            // per the docs: Unit is scaled by 100. For example, a temperature
-            // value of 2550 should be used for 25.50 °C.
-            let integer = avg_rh.trunc() as i16 * 100;
-            let fractional = (avg_rh.fract() * 100.0) as i16;
-            let temp = integer + fractional;
-            debug!("{NAME:>8}: setting relative humidity compensation to {temp} ({avg_rh}%)");
+        if let Some(avg_rh) = self.compensation.rel_humidity_percent() {
+            // per the docs: Unit is scaled by 100. For example, a relative
+            // humidity value of 5000 should be used for 50.00%.
+            let hum = (avg_rh.clamp(0.0, 100.0) * 100.0) as u16;
+            debug!("{NAME:>8}: setting relative humidity compensation to {hum} ({avg_rh}%)");
             self.sensor
-                .set_temp(temp)
+                .set_hum(hum)
                 .await
-                .context("error setting current temperature for ENS160")?;
+                .context("error setting current relative humidity for ENS160")?;
         }
 
         let status = self
@@ -176,6 +278,9 @@ where
         match status.validity_flag() {
             // we are in operating mode. read the sensor!
             ens160::Validity::NormalOperation => {}
+            // the sensor has completed its initial startup phase on a previous
+            // run, so its readings are valid.
+            ens160::Validity::InitStartupPhase if self.initial_startup_complete() => {}
             ens160::Validity::InvalidOutput => {
                 warn!("{NAME:>8}: invalid output!");
                 return Err(Ens160Error::Invalid.into());
@@ -199,6 +304,8 @@ where
             debug!("{NAME:>8}: TVOC: {tvoc} ppb",);
         }
         self.tvoc.set_value(tvoc.into());
+        let category = IaqCategory::from_tvoc_ppb(tvoc.into());
+        self.tvoc_iaq_category.set_value(category.level().into());
 
         let eco2 = self
             .sensor
@@ -1,18 +1,23 @@
 use crate::{
     metrics::{DiameterLabel, Gauge},
-    sensor::{PollCount, Sensor, SensorError},
+    sensor::{InvalidAddress, PollCount, Sensor, SensorConfig, SensorError},
     SharedBus,
 };
-use eclss_api::SensorName;
+use eclss_api::{SensorId, SensorName};
 use embedded_hal::i2c;
 use embedded_hal_async::i2c::I2c;
 
 pub struct Pmsa003i<I: 'static> {
     sensor: pmsa003i::Pmsa003i<&'static SharedBus<I>>,
+    id: SensorId,
     polls: PollCount,
     pm2_5: &'static Gauge,
     pm1_0: &'static Gauge,
     pm10_0: &'static Gauge,
+    pm1_0_standard: &'static Gauge,
+    pm2_5_standard: &'static Gauge,
+    pm10_0_standard: &'static Gauge,
+    aqi: &'static Gauge,
     particles_0_3um: &'static Gauge,
     particles_0_5um: &'static Gauge,
     particles_1_0um: &'static Gauge,
@@ -26,26 +31,40 @@ impl<I> Pmsa003i<I> {
         eclss: &'static crate::Eclss<I, { SENSORS }>,
         config: &crate::Config,
     ) -> Self {
+        Self::new_with_config(eclss, config, SensorConfig::default())
+            .expect("default sensor config must be valid")
+    }
+
+    pub fn new_with_config<const SENSORS: usize>(
+        eclss: &'static crate::Eclss<I, { SENSORS }>,
+        config: &crate::Config,
+        sensor_config: SensorConfig<I>,
+    ) -> Result<Self, InvalidAddress> {
         let metrics = &eclss.metrics;
-        const fn diameter(diameter: &'static str) -> DiameterLabel {
-            DiameterLabel {
-                diameter,
-                sensor: NAME,
-            }
-        }
-        Self {
-            polls: config.poll_counter(POLL_INTERVAL),
-            sensor: pmsa003i::Pmsa003i::new(&eclss.i2c),
+        let id = sensor_config.id(NAME);
+        let diameter = |diameter: &'static str| DiameterLabel {
+            diameter,
+            sensor: id,
+        };
+        let address = sensor_config.address_or(pmsa003i::DEFAULT_I2C_ADDR);
+        Ok(Self {
+            id,
+            polls: config.poll_counter(sensor_config.poll_interval(POLL_INTERVAL)),
+            sensor: pmsa003i::Pmsa003i::with_addr(sensor_config.bus(eclss), address),
             pm2_5: metrics.pm_conc.register(diameter("2.5")).unwrap(),
             pm1_0: metrics.pm_conc.register(diameter("1.0")).unwrap(),
             pm10_0: metrics.pm_conc.register(diameter("10.0")).unwrap(),
+            pm1_0_standard: metrics.pm_conc_standard.register(diameter("1.0")).unwrap(),
+            pm2_5_standard: metrics.pm_conc_standard.register(diameter("2.5")).unwrap(),
+            pm10_0_standard: metrics.pm_conc_standard.register(diameter("10.0")).unwrap(),
+            aqi: metrics.aqi.register(id).unwrap(),
             particles_0_3um: metrics.pm_count.register(diameter("0.3")).unwrap(),
             particles_0_5um: metrics.pm_count.register(diameter("0.5")).unwrap(),
             particles_1_0um: metrics.pm_count.register(diameter("1.0")).unwrap(),
             particles_2_5um: metrics.pm_count.register(diameter("2.5")).unwrap(),
             particles_5_0um: metrics.pm_count.register(diameter("5.0")).unwrap(),
             particles_10_0um: metrics.pm_count.register(diameter("10.0")).unwrap(),
-        }
+        })
     }
 }
 
@@ -67,6 +86,14 @@ where
         Ok(())
     }
 
+    fn poll_interval(&self) -> core::time::Duration {
+        self.polls.interval()
+    }
+
+    fn id(&self) -> SensorId {
+        self.id
+    }
+
     async fn poll(&mut self) -> Result<(), Self::Error> {
         let pmsa003i::Reading {
             concentrations,
@@ -90,7 +117,14 @@ where
                 )+
             }
         }
-        set_metrics!(concentrations => pm1_0, pm2_5, pm10_0);
+        set_metrics!(concentrations =>
+            pm1_0,
+            pm2_5,
+            pm10_0,
+            pm1_0_standard,
+            pm2_5_standard,
+            pm10_0_standard
+        );
         set_metrics!(counts =>
             particles_0_3um,
             particles_0_5um,
@@ -100,6 +134,10 @@ where
             particles_10_0um
         );
 
+        let aqi = super::aqi(concentrations.pm2_5.into(), concentrations.pm10_0.into());
+        debug!("{NAME:>8}: AQI: {aqi}");
+        self.aqi.set_value(aqi.into());
+
         self.polls.add();
         Ok(())
     }
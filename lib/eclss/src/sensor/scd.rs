@@ -1,11 +1,15 @@
 use crate::{
     error::SensorError,
-    metrics::{Gauge, PRESSURE_METRICS},
-    sensor::PollCount,
+    metrics::Gauge,
+    sensor::{
+        compensation::{Compensation, Inputs},
+        Calibration, Correction, PollCount,
+    },
+    storage::{self, SensorState, Store},
 };
 use core::fmt;
 use core::time::Duration;
-use eclss_api::SensorName;
+use eclss_api::{air_quality::Co2Band, SensorId};
 
 use embedded_hal::i2c;
 
@@ -26,6 +30,9 @@ pub use self::scd41::Scd41;
 pub enum ScdError<E> {
     Libscd(libscd::error::Error<E>),
     SelfTest,
+    /// The sensor rejected a forced recalibration, because it hadn't been
+    /// measuring for long enough beforehand.
+    ForcedRecalibration,
 }
 
 struct Shared {
@@ -33,32 +40,92 @@ struct Shared {
     rel_humidity: &'static Gauge,
     abs_humidity: &'static Gauge,
     co2_ppm: &'static Gauge,
-    pressure: &'static tinymetrics::GaugeFamily<'static, PRESSURE_METRICS, SensorName>,
+    co2_comfort_band: &'static Gauge,
+    /// The source of the ambient pressure which readings are compensated for.
+    compensation: Inputs,
     polls: PollCount,
-    name: SensorName,
+    id: SensorId,
+    /// Corrections applied to temperature and humidity readings.
+    calibration: Calibration,
+    /// Set if the temperature offset in `calibration` has been written to the
+    /// sensor's temperature offset register, so it's already been applied to
+    /// the sensor's readings.
+    device_temp_offset: bool,
+    /// The saved automatic self-calibration setting, once it's been loaded.
+    automatic_self_calibration: Option<bool>,
+}
+
+/// SCD calibration settings which are saved across restarts.
+///
+/// These are saved by the ECLSS, rather than persisted to the sensor's EEPROM,
+/// which has limited write endurance.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct StoredCalibration {
+    /// Whether automatic self-calibration is enabled.
+    automatic_self_calibration: bool,
+}
+
+impl SensorState for StoredCalibration {
+    const VERSION: u32 = 1;
 }
 
 impl Shared {
     fn new<I, const SENSORS: usize>(
         eclss: &'static crate::Eclss<I, { SENSORS }>,
         config: &crate::Config,
-        name: SensorName,
+        id: SensorId,
+        calibration: Calibration,
+        compensation: Compensation,
         poll_interval: Duration,
     ) -> Self {
         let metrics = &eclss.metrics;
         Self {
-            temp_c: metrics.temp_c.register(name).unwrap(),
-            rel_humidity: metrics.rel_humidity_percent.register(name).unwrap(),
-            abs_humidity: metrics.abs_humidity_grams_m3.register(name).unwrap(),
-            co2_ppm: metrics.co2_ppm.register(name).unwrap(),
-            pressure: &metrics.pressure_hpa,
+            temp_c: metrics.temp_c.register(id).unwrap(),
+            rel_humidity: metrics.rel_humidity_percent.register(id).unwrap(),
+            abs_humidity: metrics.abs_humidity_grams_m3.register(id).unwrap(),
+            co2_ppm: metrics.co2_ppm.register(id).unwrap(),
+            co2_comfort_band: metrics.co2_comfort_band.register(id).unwrap(),
+            compensation: Inputs::new(eclss, id, compensation),
             polls: config.poll_counter(poll_interval),
-            name,
+            id,
+            calibration,
+            device_temp_offset: false,
+            automatic_self_calibration: None,
+        }
+    }
+
+    /// Returns the saved automatic self-calibration setting, loading it from
+    /// `store` if it hasn't been loaded yet.
+    async fn saved_asc<S>(&mut self, store: &mut S) -> Option<bool>
+    where
+        S: Store,
+        S::Error: fmt::Display,
+    {
+        if self.automatic_self_calibration.is_none() {
+            self.automatic_self_calibration = storage::load(store, self.id).await.map(
+                |StoredCalibration {
+                     automatic_self_calibration,
+                 }| automatic_self_calibration,
+            );
         }
+        self.automatic_self_calibration
+    }
+
+    /// Saves the automatic self-calibration setting to `store`.
+    async fn save_asc<S>(&mut self, store: &mut S, automatic_self_calibration: bool)
+    where
+        S: Store,
+        S::Error: fmt::Display,
+    {
+        self.automatic_self_calibration = Some(automatic_self_calibration);
+        let calibration = StoredCalibration {
+            automatic_self_calibration,
+        };
+        storage::save(store, self.id, &calibration).await;
     }
 
-    fn pressure_pascals(&self) -> Option<u32> {
-        let pressure_hpa = self.pressure.mean()?;
+    fn pressure_pascals(&mut self) -> Option<u32> {
+        let pressure_hpa = self.compensation.pressure_hpa()?;
         let pressure_pascals = (pressure_hpa * 100.0) as u32;
         // Valid pressure compensation values per the SCDxx datasheet.
         const VALID_PRESSURES: core::ops::Range<u32> = 70_000..120_000;
@@ -69,22 +136,62 @@ impl Shared {
         }
     }
 
+    /// Returns the temperature offset to write to an SCD4x's temperature
+    /// offset register, given the offset it's currently configured with, or
+    /// `None` if the calibrated offset must be applied in software.
+    ///
+    /// The SCD4x subtracts its temperature offset (4 °C by default) from its
+    /// temperature readings. The offset can only be written to the sensor if
+    /// the temperature isn't also scaled, and if the resulting offset is in the
+    /// range the sensor supports.
+    #[cfg(any(feature = "scd40", feature = "scd41"))]
+    fn scd4x_temp_offset(&self, current: f32) -> Option<f32> {
+        // Temperature offsets supported by the SCD4x, per the datasheet.
+        const VALID_OFFSETS: core::ops::RangeInclusive<f32> = 0.0..=20.0;
+        let Correction { offset, scale } = self.calibration.temp;
+        if scale != 1.0 {
+            return None;
+        }
+        let device_offset = current - offset;
+        if VALID_OFFSETS.contains(&device_offset) {
+            Some(device_offset)
+        } else {
+            warn!(
+                "{}: temperature offset of {device_offset}°C is out of range, \
+                correcting temperatures in software",
+                self.id
+            );
+            None
+        }
+    }
+
     fn record_measurement(&mut self, co2: u16, temperature: f32, humidity: f32) {
+        let calibration = if self.device_temp_offset {
+            Calibration {
+                temp: Correction::IDENTITY,
+                ..self.calibration
+            }
+        } else {
+            self.calibration
+        };
+        let (temperature, humidity) = calibration.correct(temperature, humidity);
         if self.polls.should_log_info() {
             info!(
                 "{:>8}: Temp: {temperature:>3.2}°C, Humidity: {humidity:>3.2}%, \
                 CO₂: {co2:>4} ppm",
-                self.name
+                self.id
             );
         } else {
             debug!(
                 "{:>8}: Temp: {temperature}°C, Humidity: {humidity}%, \
                 CO₂: {co2} ppm",
-                self.name
+                self.id
             );
         }
 
         self.co2_ppm.set_value(co2.into());
+        let band = Co2Band::from_ppm(co2.into());
+        self.co2_comfort_band.set_value(band.level().into());
         self.temp_c.set_value(temperature.into());
         self.rel_humidity.set_value(humidity.into());
 
@@ -92,12 +199,9 @@ impl Shared {
             let abs_humidity = super::absolute_humidity(temperature, humidity);
             self.abs_humidity.set_value(abs_humidity.into());
             if self.polls.should_log_info() {
-                info!(
-                    "{:>8}: Absolute humidity: {abs_humidity:3.2} g/m³",
-                    self.name
-                );
+                info!("{:>8}: Absolute humidity: {abs_humidity:3.2} g/m³", self.id);
             } else {
-                debug!("{:>8}: Absolute humidity: {abs_humidity} g/m³", self.name);
+                debug!("{:>8}: Absolute humidity: {abs_humidity} g/m³", self.id);
             }
         }
 
@@ -126,6 +230,9 @@ impl<E: fmt::Display> fmt::Display for ScdError<E> {
                 f.write_str("not allowed when periodic measurement is running")
             }
             Self::SelfTest => f.write_str("self-test validation failed"),
+            Self::ForcedRecalibration => {
+                f.write_str("sensor must be measuring for 3 minutes before recalibration")
+            }
         }
     }
 }
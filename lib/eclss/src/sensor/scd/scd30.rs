@@ -1,18 +1,20 @@
 use super::{ScdError, Shared};
 use crate::{
     error::{Context, EclssError},
-    sensor::Sensor,
+    sensor::{Command, CommandError, InvalidAddress, Response, Sensor, SensorConfig},
+    storage::Store,
     SharedBus,
 };
-use eclss_api::SensorName;
+use eclss_api::{SensorId, SensorName};
 use embedded_hal::i2c;
 use embedded_hal_async::{delay::DelayNs, i2c::I2c};
 use libscd::asynchronous::scd30;
 
-pub struct Scd30<I: 'static, D> {
+pub struct Scd30<I: 'static, D, S = ()> {
     sensor: scd30::Scd30<&'static SharedBus<I>, D>,
     delay: D,
     state: Shared,
+    store: S,
 }
 
 impl<I, D> Scd30<I, D>
@@ -25,22 +27,60 @@ where
         config: &crate::Config,
         delay: D,
     ) -> Self {
-        Self {
-            sensor: scd30::Scd30::new(&eclss.i2c, delay.clone()),
-            state: Shared::new(eclss, config, NAME, POLL_INTERVAL),
+        Self::new_with_config(eclss, config, SensorConfig::default(), delay)
+            .expect("default sensor config must be valid")
+    }
+
+    /// Returns a new SCD30 using the provided per-sensor configuration.
+    ///
+    /// The SCD30's I²C address is fixed, so the configured address must be
+    /// `0x61` if one is set.
+    pub fn new_with_config<const SENSORS: usize>(
+        eclss: &'static crate::Eclss<I, { SENSORS }>,
+        config: &crate::Config,
+        sensor_config: SensorConfig<I>,
+        delay: D,
+    ) -> Result<Self, InvalidAddress> {
+        sensor_config.address_in(NAME, &[ADDRESS])?;
+        let poll_interval = sensor_config.poll_interval(POLL_INTERVAL);
+        Ok(Self {
+            sensor: scd30::Scd30::new(sensor_config.bus(eclss), delay.clone()),
+            state: Shared::new(
+                eclss,
+                config,
+                sensor_config.id(NAME),
+                sensor_config.calibration,
+                sensor_config.compensation,
+                poll_interval,
+            ),
             delay,
+            store: (),
+        })
+    }
+
+    /// Returns this sensor with its calibration settings saved to `store`.
+    pub fn with_storage<S: Store>(self, store: S) -> Scd30<I, D, S> {
+        Scd30 {
+            sensor: self.sensor,
+            delay: self.delay,
+            state: self.state,
+            store,
         }
     }
 }
 
 const NAME: SensorName = SensorName::Scd30;
+// The SCD30's I²C address is fixed.
+const ADDRESS: u8 = 0x61;
 const POLL_INTERVAL: core::time::Duration = core::time::Duration::from_secs(2);
 
-impl<I, D> Sensor for Scd30<I, D>
+impl<I, D, S> Sensor for Scd30<I, D, S>
 where
     I: I2c + 'static,
     I::Error: i2c::Error,
     D: DelayNs,
+    S: Store + 'static,
+    S::Error: core::fmt::Display,
 {
     const NAME: SensorName = NAME;
     const POLL_INTERVAL: core::time::Duration = POLL_INTERVAL;
@@ -67,6 +107,24 @@ where
             .await
             .context("error setting SCD30 measurement interval")?;
 
+        // Restore the saved automatic self-calibration setting, or save the
+        // sensor's current setting if none was saved.
+        match self.state.saved_asc(&mut self.store).await {
+            Some(enabled) => self
+                .sensor
+                .enable_automatic_self_calibration(enabled)
+                .await
+                .context("error setting SCD30 automatic self-calibration")?,
+            None => {
+                let enabled = self
+                    .sensor
+                    .get_automatic_self_calibration()
+                    .await
+                    .context("error reading SCD30 automatic self-calibration")?;
+                self.state.save_asc(&mut self.store, enabled).await;
+            }
+        }
+
         self.sensor
             // TODO(calculate ambient pressure hPa here
             .start_continuous_measurement(1001)
@@ -76,6 +134,51 @@ where
         Ok(())
     }
 
+    async fn shutdown(&mut self) -> Result<(), Self::Error> {
+        self.sensor
+            .stop_continuous_measurement()
+            .await
+            .context("error stopping SCD30 continuous measurement")
+    }
+
+    async fn command(&mut self, command: Command) -> Result<Response, CommandError<Self::Error>> {
+        // unlike the SCD4x, the SCD30 accepts these commands while it's
+        // measuring.
+        match command {
+            Command::ForcedRecalibration { reference_ppm } => {
+                let recalibrated: Result<(), Self::Error> = self
+                    .sensor
+                    .set_forced_recalibration_value(reference_ppm)
+                    .await
+                    .context("error performing SCD30 forced recalibration");
+                recalibrated.map_err(CommandError::Sensor)?;
+                // the SCD30 doesn't report the correction it applied.
+                Ok(Response::Recalibrated {
+                    correction_ppm: None,
+                })
+            }
+            Command::SetAutomaticSelfCalibration { enabled } => {
+                let set: Result<(), Self::Error> = self
+                    .sensor
+                    .enable_automatic_self_calibration(enabled)
+                    .await
+                    .context("error setting SCD30 automatic self-calibration");
+                set.map_err(CommandError::Sensor)?;
+                self.state.save_asc(&mut self.store, enabled).await;
+                Ok(Response::AutomaticSelfCalibration { enabled })
+            }
+            _ => Err(CommandError::Unsupported),
+        }
+    }
+
+    fn poll_interval(&self) -> core::time::Duration {
+        self.state.polls.interval()
+    }
+
+    fn id(&self) -> SensorId {
+        self.state.id
+    }
+
     async fn poll(&mut self) -> Result<(), Self::Error> {
         while !self
             .sensor
@@ -91,7 +194,7 @@ where
             humidity,
         } = self
             .sensor
-            .measurement()
+            .read_measurement()
             .await
             .context("error reading SCD30 measurement")?;
         self.state.record_measurement(co2, temperature, humidity);
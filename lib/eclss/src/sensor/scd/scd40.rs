@@ -1,19 +1,23 @@
 use super::{ScdError, Shared};
 use crate::{
     error::{Context, EclssError},
-    sensor::Sensor,
+    sensor::{
+        Command, CommandError, CompensationSources, InvalidAddress, Response, Sensor, SensorConfig,
+    },
+    storage::Store,
     SharedBus,
 };
 
-use eclss_api::SensorName;
+use eclss_api::{SensorId, SensorName};
 use embedded_hal::i2c;
 use embedded_hal_async::{delay::DelayNs, i2c::I2c};
 use libscd::asynchronous::scd4x;
 
-pub struct Scd40<I: 'static, D> {
+pub struct Scd40<I: 'static, D, S = ()> {
     sensor: scd4x::Scd40<&'static SharedBus<I>, D>,
     state: Shared,
     delay: D,
+    store: S,
 }
 
 impl<I, D> Scd40<I, D>
@@ -26,23 +30,121 @@ where
         config: &crate::Config,
         delay: D,
     ) -> Self {
-        Self {
-            sensor: scd4x::Scd40::new(&eclss.i2c, delay.clone()),
-            state: Shared::new(eclss, config, NAME, POLL_INTERVAL),
+        Self::new_with_config(eclss, config, SensorConfig::default(), delay)
+            .expect("default sensor config must be valid")
+    }
+
+    /// Returns a new SCD40 using the provided per-sensor configuration.
+    ///
+    /// The SCD40's I²C address is fixed, so the configured address must be
+    /// `0x62` if one is set.
+    pub fn new_with_config<const SENSORS: usize>(
+        eclss: &'static crate::Eclss<I, { SENSORS }>,
+        config: &crate::Config,
+        sensor_config: SensorConfig<I>,
+        delay: D,
+    ) -> Result<Self, InvalidAddress> {
+        sensor_config.address_in(NAME, &[ADDRESS])?;
+        let poll_interval = sensor_config.poll_interval(POLL_INTERVAL);
+        Ok(Self {
+            sensor: scd4x::Scd40::new(sensor_config.bus(eclss), delay.clone()),
+            state: Shared::new(
+                eclss,
+                config,
+                sensor_config.id(NAME),
+                sensor_config.calibration,
+                sensor_config.compensation,
+                poll_interval,
+            ),
             delay,
+            store: (),
+        })
+    }
+
+    /// Returns this sensor with its calibration settings saved to `store`.
+    pub fn with_storage<S: Store>(self, store: S) -> Scd40<I, D, S> {
+        Scd40 {
+            sensor: self.sensor,
+            state: self.state,
+            delay: self.delay,
+            store,
         }
     }
 }
 
+impl<I, D, S> Scd40<I, D, S>
+where
+    I: I2c + 'static,
+    I::Error: i2c::Error,
+    D: DelayNs,
+    S: Store + 'static,
+    S::Error: core::fmt::Display,
+{
+    /// Recalibrates the SCD40 to `reference_ppm`, returning the correction
+    /// applied to its readings.
+    ///
+    /// The SCD40 only accepts this command while it's idle, so periodic
+    /// measurement is stopped and then restarted.
+    async fn forced_recalibration(
+        &mut self,
+        reference_ppm: u16,
+    ) -> Result<i16, EclssError<ScdError<I::Error>>> {
+        self.sensor
+            .stop_periodic_measurement()
+            .await
+            .context("error stopping SCD40 periodic measurement")?;
+        let correction = self
+            .sensor
+            .perform_forced_recalibration(reference_ppm)
+            .await
+            .context("error performing SCD40 forced recalibration");
+        // restart measurement even if recalibration failed.
+        self.sensor
+            .start_periodic_measurement()
+            .await
+            .context("error starting SCD40 periodic measurement")?;
+        correction?
+            .ok_or(ScdError::ForcedRecalibration)
+            .context("SCD40 forced recalibration failed")
+    }
+
+    /// Enables or disables automatic self-calibration, and saves the setting.
+    async fn set_automatic_self_calibration(
+        &mut self,
+        enabled: bool,
+    ) -> Result<(), EclssError<ScdError<I::Error>>> {
+        self.sensor
+            .stop_periodic_measurement()
+            .await
+            .context("error stopping SCD40 periodic measurement")?;
+        let set = self
+            .sensor
+            .enable_automatic_self_calibration(enabled)
+            .await
+            .context("error setting SCD40 automatic self-calibration");
+        self.sensor
+            .start_periodic_measurement()
+            .await
+            .context("error starting SCD40 periodic measurement")?;
+        set?;
+        self.state.save_asc(&mut self.store, enabled).await;
+        Ok(())
+    }
+}
+
 const NAME: SensorName = SensorName::Scd40;
 
+// The SCD40's I²C address is fixed.
+const ADDRESS: u8 = 0x62;
 const POLL_INTERVAL: core::time::Duration = core::time::Duration::from_secs(5);
 
-impl<I, D> Sensor for Scd40<I, D>
+impl<I, D, S> Sensor for Scd40<I, D, S>
 where
     I: I2c + 'static,
     I::Error: i2c::Error,
     D: DelayNs,
+    S: Store + 'static,
+    S::Error: core::fmt::Display,
 {
     const NAME: SensorName = NAME;
     const POLL_INTERVAL: core::time::Duration = POLL_INTERVAL;
@@ -73,6 +175,43 @@ where
             Err(ScdError::SelfTest).context("SCD40 self test failed")?;
         }
 
+        // Write the calibrated temperature offset to the sensor, which also
+        // compensates its humidity readings for it.
+        self.state.device_temp_offset = false;
+        if !self.state.calibration.temp.is_identity() {
+            let current = self
+                .sensor
+                .get_temperature_offset()
+                .await
+                .context("error reading SCD40 temperature offset")?;
+            if let Some(offset) = self.state.scd4x_temp_offset(current) {
+                self.sensor
+                    .set_temperature_offset(offset)
+                    .await
+                    .context("error setting SCD40 temperature offset")?;
+                self.state.device_temp_offset = true;
+                debug!("set SCD40 temperature offset to {offset}°C");
+            }
+        }
+
+        // Restore the saved automatic self-calibration setting, or save the
+        // sensor's current setting if none was saved.
+        match self.state.saved_asc(&mut self.store).await {
+            Some(enabled) => self
+                .sensor
+                .enable_automatic_self_calibration(enabled)
+                .await
+                .context("error setting SCD40 automatic self-calibration")?,
+            None => {
+                let enabled = self
+                    .sensor
+                    .get_automatic_self_calibration()
+                    .await
+                    .context("error reading SCD40 automatic self-calibration")?;
+                self.state.save_asc(&mut self.store, enabled).await;
+            }
+        }
+
         self.sensor
             .start_periodic_measurement()
             .await
@@ -81,6 +220,46 @@ where
         Ok(())
     }
 
+    async fn shutdown(&mut self) -> Result<(), Self::Error> {
+        self.sensor
+            .stop_periodic_measurement()
+            .await
+            .context("error stopping SCD40 periodic measurement")
+    }
+
+    async fn command(&mut self, command: Command) -> Result<Response, CommandError<Self::Error>> {
+        match command {
+            Command::ForcedRecalibration { reference_ppm } => {
+                let correction_ppm = self
+                    .forced_recalibration(reference_ppm)
+                    .await
+                    .map_err(CommandError::Sensor)?;
+                Ok(Response::Recalibrated {
+                    correction_ppm: Some(correction_ppm),
+                })
+            }
+            Command::SetAutomaticSelfCalibration { enabled } => {
+                self.set_automatic_self_calibration(enabled)
+                    .await
+                    .map_err(CommandError::Sensor)?;
+                Ok(Response::AutomaticSelfCalibration { enabled })
+            }
+            _ => Err(CommandError::Unsupported),
+        }
+    }
+
+    fn poll_interval(&self) -> core::time::Duration {
+        self.state.polls.interval()
+    }
+
+    fn id(&self) -> SensorId {
+        self.state.id
+    }
+
+    fn compensation(&self) -> CompensationSources {
+        self.state.compensation.sources()
+    }
+
     async fn poll(&mut self) -> Result<(), Self::Error> {
         while !self
             .sensor
@@ -100,6 +279,12 @@ where
             .await
             .context("error reading SCD40 measurement")?;
         self.state.record_measurement(co2, temperature, humidity);
+        if let Some(pressure) = self.state.pressure_pascals() {
+            self.sensor
+                .set_ambient_pressure(pressure)
+                .await
+                .context("error setting SCD40 ambient pressure")?;
+        }
         Ok(())
     }
 }
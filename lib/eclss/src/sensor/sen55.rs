@@ -1,11 +1,16 @@
 use crate::{
     error::{Context, EclssError, SensorError},
-    metrics::{DiameterLabel, Gauge},
-    sensor::{PollCount, Sensor},
-    SharedBus,
+    metrics::{DeviceStatusLabel, DiameterLabel, Gauge},
+    sensor::{
+        Calibration, Command, CommandError, InvalidAddress, PollCount, Response, Sensor,
+        SensorConfig,
+    },
+    storage::{self, SaveInterval, SensorState, Store},
+    SensorMetrics, SharedBus,
 };
 use core::time::Duration;
-use eclss_api::SensorName;
+use eclss_api::{DeviceStatus, SensorId, SensorName};
+use serde::de::{self, Deserialize, Deserializer};
 
 use embedded_hal_async::{
     delay::DelayNs,
@@ -13,20 +18,93 @@ use embedded_hal_async::{
 };
 use sensor_sen5x::{Error as Sen5xError, ParticulateMode, Sen5xAsync};
 
-pub struct Sen55<I: 'static, D> {
+pub struct Sen55<I: 'static, D, S = ()> {
     sensor: Sen5xAsync<&'static SharedBus<I>>,
     rel_humidity: &'static Gauge,
     abs_humidity: &'static Gauge,
     temp: &'static Gauge,
+    calibration: Calibration,
     pm1_0: &'static Gauge,
     pm2_5: &'static Gauge,
     pm4_0: &'static Gauge,
     pm10_0: &'static Gauge,
+    nc0_5: &'static Gauge,
+    nc1_0: &'static Gauge,
+    nc2_5: &'static Gauge,
+    nc4_0: &'static Gauge,
+    nc10_0: &'static Gauge,
     nox_index: &'static Gauge,
     voc_index: &'static Gauge,
+    aqi: &'static Gauge,
+    /// One gauge for each of the [`DeviceStatus::FLAGS`].
+    device_status_flags: [&'static Gauge; DeviceStatus::FLAGS.len()],
+    device_status: DeviceStatus,
     delay: D,
     last_warm_start_param: Option<u16>,
+    voc_algorithm_state: Option<[u8; 8]>,
+    /// When `voc_algorithm_state` was read from the sensor, as a Unix
+    /// timestamp in seconds.
+    voc_algorithm_read_at: Option<u64>,
+    fan_cleaning_interval: Duration,
+    /// Measurement time since the fan cleaning cycle last ran.
+    since_fan_cleaning: Duration,
+    id: SensorId,
+    metrics: &'static SensorMetrics,
     polls: PollCount,
+    saves: SaveInterval,
+    loaded: bool,
+    store: S,
+}
+
+/// SEN55 state which is saved across restarts.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct StoredState {
+    /// The temperature compensation warm start parameter.
+    warm_start: Option<u16>,
+    /// The VOC algorithm state.
+    ///
+    /// Sensirion only recommends restoring this after a short interruption,
+    /// so it's saved when the sensor is shut down, as well as periodically,
+    /// and only restored if it was saved recently.
+    voc_algorithm: Option<[u8; 8]>,
+    /// When the VOC algorithm state was read from the sensor, as a Unix
+    /// timestamp in seconds, if there was a clock.
+    #[serde(default)]
+    voc_algorithm_saved_at: Option<u64>,
+    /// Seconds of measurement since the fan cleaning cycle last ran.
+    ///
+    /// There's no wall clock to timestamp cleanings with, so this counts
+    /// measurement time, like the sensor's own cleaning interval.
+    since_fan_cleaning_secs: u64,
+}
+
+impl SensorState for StoredState {
+    const VERSION: u32 = 2;
+
+    fn migrate<'de, D: Deserializer<'de>>(version: u32, state: D) -> Result<Self, D::Error> {
+        /// Version 1 didn't record when the fan was last cleaned.
+        #[derive(serde::Deserialize)]
+        struct V1 {
+            warm_start: Option<u16>,
+            voc_algorithm: Option<[u8; 8]>,
+        }
+
+        if version != 1 {
+            return Err(de::Error::custom(format_args!(
+                "can't migrate {NAME} state from format version {version}"
+            )));
+        }
+        let V1 {
+            warm_start,
+            voc_algorithm,
+        } = V1::deserialize(state)?;
+        Ok(Self {
+            warm_start,
+            voc_algorithm,
+            voc_algorithm_saved_at: None,
+            since_fan_cleaning_secs: 0,
+        })
+    }
 }
 
 impl<I, D> Sen55<I, D>
@@ -39,39 +117,234 @@ where
         config: &crate::Config,
         delay: D,
     ) -> Self {
+        Self::new_with_config(eclss, config, SensorConfig::default(), delay)
+            .expect("default sensor config must be valid")
+    }
+
+    /// Returns a new SEN55 using the provided per-sensor configuration.
+    ///
+    /// The SEN55's I²C address is fixed, so the configured address must be
+    /// `0x69` if one is set.
+    pub fn new_with_config<const SENSORS: usize>(
+        eclss: &'static crate::Eclss<I, { SENSORS }>,
+        config: &crate::Config,
+        sensor_config: SensorConfig<I>,
+        delay: D,
+    ) -> Result<Self, InvalidAddress> {
+        sensor_config.address_in(NAME, &[ADDRESS])?;
         let metrics = &eclss.metrics;
-        const fn diameter(diameter: &'static str) -> DiameterLabel {
-            DiameterLabel {
-                diameter,
-                sensor: NAME,
-            }
-        }
-        Self {
-            sensor: Sen5xAsync::new(&eclss.i2c),
-            rel_humidity: metrics.rel_humidity_percent.register(NAME).unwrap(),
-            abs_humidity: metrics.abs_humidity_grams_m3.register(NAME).unwrap(),
-            temp: metrics.temp_c.register(NAME).unwrap(),
+        let id = sensor_config.id(NAME);
+        let poll_interval = sensor_config.poll_interval(POLL_INTERVAL);
+        let diameter = |diameter: &'static str| DiameterLabel {
+            diameter,
+            sensor: id,
+        };
+        let device_status_flags = DeviceStatus::FLAGS.map(|flag| {
+            metrics
+                .device_status
+                .register(DeviceStatusLabel { flag, sensor: id })
+                .unwrap()
+        });
+        Ok(Self {
+            id,
+            sensor: Sen5xAsync::new(sensor_config.bus(eclss)),
+            rel_humidity: metrics.rel_humidity_percent.register(id).unwrap(),
+            abs_humidity: metrics.abs_humidity_grams_m3.register(id).unwrap(),
+            temp: metrics.temp_c.register(id).unwrap(),
+            calibration: sensor_config.calibration,
             pm1_0: metrics.pm_conc.register(diameter("1.0")).unwrap(),
             pm2_5: metrics.pm_conc.register(diameter("2.5")).unwrap(),
             pm4_0: metrics.pm_conc.register(diameter("4.0")).unwrap(),
             pm10_0: metrics.pm_conc.register(diameter("10.0")).unwrap(),
-            nox_index: metrics.nox_iaq_index.register(NAME).unwrap(),
-            voc_index: metrics.tvoc_iaq_index.register(NAME).unwrap(),
+            nc0_5: metrics.pm_number_conc.register(diameter("0.5")).unwrap(),
+            nc1_0: metrics.pm_number_conc.register(diameter("1.0")).unwrap(),
+            nc2_5: metrics.pm_number_conc.register(diameter("2.5")).unwrap(),
+            nc4_0: metrics.pm_number_conc.register(diameter("4.0")).unwrap(),
+            nc10_0: metrics.pm_number_conc.register(diameter("10.0")).unwrap(),
+            nox_index: metrics.nox_iaq_index.register(id).unwrap(),
+            voc_index: metrics.tvoc_iaq_index.register(id).unwrap(),
+            aqi: metrics.aqi.register(id).unwrap(),
+            device_status_flags,
+            device_status: DeviceStatus::default(),
             delay,
-            polls: config.poll_counter(POLL_INTERVAL),
+            polls: config.poll_counter(poll_interval),
+            saves: SaveInterval::new(poll_interval),
             last_warm_start_param: None,
+            voc_algorithm_state: None,
+            voc_algorithm_read_at: None,
+            fan_cleaning_interval: config.fan_cleaning_interval,
+            since_fan_cleaning: Duration::ZERO,
+            metrics,
+            loaded: false,
+            store: (),
+        })
+    }
+
+    /// Returns this sensor with its warm start parameter, VOC algorithm state,
+    /// and fan cleaning schedule saved to `store`.
+    pub fn with_storage<S: Store>(self, store: S) -> Sen55<I, D, S> {
+        Sen55 {
+            sensor: self.sensor,
+            rel_humidity: self.rel_humidity,
+            abs_humidity: self.abs_humidity,
+            temp: self.temp,
+            calibration: self.calibration,
+            pm1_0: self.pm1_0,
+            pm2_5: self.pm2_5,
+            pm4_0: self.pm4_0,
+            pm10_0: self.pm10_0,
+            nc0_5: self.nc0_5,
+            nc1_0: self.nc1_0,
+            nc2_5: self.nc2_5,
+            nc4_0: self.nc4_0,
+            nc10_0: self.nc10_0,
+            nox_index: self.nox_index,
+            voc_index: self.voc_index,
+            aqi: self.aqi,
+            device_status_flags: self.device_status_flags,
+            device_status: self.device_status,
+            delay: self.delay,
+            last_warm_start_param: self.last_warm_start_param,
+            voc_algorithm_state: self.voc_algorithm_state,
+            voc_algorithm_read_at: self.voc_algorithm_read_at,
+            fan_cleaning_interval: self.fan_cleaning_interval,
+            since_fan_cleaning: self.since_fan_cleaning,
+            id: self.id,
+            metrics: self.metrics,
+            polls: self.polls,
+            saves: self.saves,
+            loaded: self.loaded,
+            store,
         }
     }
 }
 
+impl<I, D, S> Sen55<I, D, S>
+where
+    I: I2c,
+    I::Error: core::fmt::Display,
+    D: DelayNs,
+    S: Store,
+    S::Error: core::fmt::Display,
+{
+    async fn load_state(&mut self) {
+        if self.loaded {
+            return;
+        }
+        self.loaded = true;
+
+        let Some(StoredState {
+            warm_start,
+            voc_algorithm,
+            voc_algorithm_saved_at,
+            since_fan_cleaning_secs,
+        }) = storage::load(&mut self.store, self.id).await
+        else {
+            return;
+        };
+        info!("{NAME:>8}: loaded saved state");
+        // a warm start parameter read since the sensor task started is more
+        // recent than the saved one.
+        self.last_warm_start_param = self.last_warm_start_param.or(warm_start);
+        if voc_algorithm.is_some() {
+            let age = self
+                .metrics
+                .now()
+                .zip(voc_algorithm_saved_at)
+                .map(|(now, saved_at)| now.saturating_sub(saved_at));
+            match age {
+                Some(age) if age <= VOC_ALGORITHM_STATE_MAX_AGE.as_secs() => {
+                    self.voc_algorithm_state = voc_algorithm;
+                    self.voc_algorithm_read_at = voc_algorithm_saved_at;
+                }
+                Some(age) => info!("{NAME:>8}: not restoring VOC algorithm state saved {age}s ago"),
+                None => {
+                    info!("{NAME:>8}: not restoring VOC algorithm state, as its age is unknown")
+                }
+            }
+        }
+        self.since_fan_cleaning += Duration::from_secs(since_fan_cleaning_secs);
+    }
+
+    async fn save_state(&mut self) {
+        match self.sensor.read_voc_algorithm_state(&mut self.delay).await {
+            Ok(state) => {
+                self.voc_algorithm_state = Some(state);
+                self.voc_algorithm_read_at = self.metrics.now();
+            }
+            Err(error) => warn!("{NAME:>8}: error reading VOC algorithm state: {error}"),
+        }
+        let state = StoredState {
+            warm_start: self.last_warm_start_param,
+            voc_algorithm: self.voc_algorithm_state,
+            voc_algorithm_saved_at: self.voc_algorithm_read_at,
+            since_fan_cleaning_secs: self.since_fan_cleaning.as_secs(),
+        };
+        storage::save(&mut self.store, self.id, &state).await;
+    }
+
+    /// Starts the fan cleaning cycle, and saves the time it was run.
+    async fn clean_fan(&mut self) -> Result<(), EclssError<Sen5xError<I::Error>>> {
+        // Fan cleaning takes about 10 seconds. The sensor doesn't report new
+        // data until it's done, so polls just skip it.
+        self.sensor
+            .start_fan_cleaning(&mut self.delay)
+            .await
+            .context("failed to start SEN5x fan cleaning")?;
+        info!("{NAME:>8}: started fan cleaning");
+        self.since_fan_cleaning = Duration::ZERO;
+        self.save_state().await;
+        Ok(())
+    }
+
+    fn record_device_status(&mut self, register: u32) {
+        let status = DeviceStatus {
+            fan_speed_warning: register & STATUS_FAN_SPEED_WARNING != 0,
+            fan_cleaning: register & STATUS_FAN_CLEANING != 0,
+            fan_failure: register & STATUS_FAN_FAILURE != 0,
+            laser_failure: register & STATUS_LASER_FAILURE != 0,
+            gas_sensor_error: register & STATUS_GAS_SENSOR_ERROR != 0,
+            rht_error: register & STATUS_RHT_ERROR != 0,
+        };
+        if status != self.device_status {
+            if status.is_fault() {
+                warn!("{NAME:>8}: device status: {status:?} ({register:#010x})");
+            } else {
+                info!("{NAME:>8}: device status: {status:?} ({register:#010x})");
+            }
+        }
+        for (gauge, set) in self.device_status_flags.iter().zip(status.flags()) {
+            gauge.set_value(f64::from(u8::from(set)));
+        }
+        self.device_status = status;
+    }
+}
+
 const NAME: SensorName = SensorName::Sen55;
+// The SEN55's I²C address is fixed.
+const ADDRESS: u8 = 0x69;
 const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Sensirion recommends only restoring the VOC algorithm state after an
+/// interruption of up to 10 minutes; after longer ones, the algorithm should
+/// start over.
+const VOC_ALGORITHM_STATE_MAX_AGE: Duration = Duration::from_secs(10 * 60);
 
-impl<I, D> Sensor for Sen55<I, D>
+// Device status register bits, from the SEN5x datasheet. Error bits stay set
+// until the register is cleared or the sensor is reset.
+const STATUS_FAN_SPEED_WARNING: u32 = 1 << 21;
+const STATUS_FAN_CLEANING: u32 = 1 << 19;
+const STATUS_GAS_SENSOR_ERROR: u32 = 1 << 7;
+const STATUS_RHT_ERROR: u32 = 1 << 6;
+const STATUS_LASER_FAILURE: u32 = 1 << 5;
+const STATUS_FAN_FAILURE: u32 = 1 << 4;
+
+impl<I, D, S> Sensor for Sen55<I, D, S>
 where
     I: I2c + 'static,
     I::Error: core::fmt::Display,
     D: DelayNs,
+    S: Store + 'static,
+    S::Error: core::fmt::Display,
 {
     const NAME: SensorName = NAME;
     const POLL_INTERVAL: Duration = POLL_INTERVAL;
@@ -91,6 +364,8 @@ where
         let name = product_name.as_str();
         info!("Connected to {name}...");
 
+        self.load_state().await;
+
         if let Some(param) = self.last_warm_start_param {
             info!("Setting {name} warm start param to {param}");
             self.sensor
@@ -99,6 +374,21 @@ where
                 .context("failed to set SEN5x warm start parameter")?;
         }
 
+        if let Some(state) = self.voc_algorithm_state {
+            info!("Restoring {name} VOC algorithm state");
+            self.sensor
+                .set_voc_algorithm_state(&mut self.delay, state)
+                .await
+                .context("failed to set SEN5x VOC algorithm state")?;
+        }
+
+        // Fan cleaning is scheduled by `poll`, so that the schedule survives
+        // power loss, which resets the sensor's own cleaning interval.
+        self.sensor
+            .set_auto_cleaning_interval(&mut self.delay, 0)
+            .await
+            .context("failed to disable SEN5x automatic fan cleaning")?;
+
         self.sensor
             .start_measurement(ParticulateMode::Enabled, &mut self.delay)
             .await
@@ -109,6 +399,38 @@ where
         Ok(())
     }
 
+    async fn shutdown(&mut self) -> Result<(), Self::Error> {
+        self.save_state().await;
+        // stopping measurement returns the SEN55 to idle mode, turning off
+        // its fan and laser.
+        self.sensor
+            .stop_measurement(&mut self.delay)
+            .await
+            .context("failed to stop SEN5x measurement")
+    }
+
+    async fn command(&mut self, command: Command) -> Result<Response, CommandError<Self::Error>> {
+        match command {
+            Command::CleanFan => {
+                self.clean_fan().await.map_err(CommandError::Sensor)?;
+                Ok(Response::Done)
+            }
+            _ => Err(CommandError::Unsupported),
+        }
+    }
+
+    fn device_status(&self) -> DeviceStatus {
+        self.device_status
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.polls.interval()
+    }
+
+    fn id(&self) -> SensorId {
+        self.id
+    }
+
     async fn poll(&mut self) -> Result<(), Self::Error> {
         let ready = self
             .sensor
@@ -121,8 +443,9 @@ where
             .read_measurements(&mut self.delay)
             .await
             .context("failed to read SEN5x measurement data")?;
-        let temp = measurement.temp_c();
-        let rel_humidity = measurement.relative_humidity();
+        let (temp, rel_humidity) = self
+            .calibration
+            .correct_partial(measurement.temp_c(), measurement.relative_humidity());
         let voc_index = measurement.voc_index();
         let nox_index = measurement.nox_index();
         let pm1_0 = measurement.pm1_0();
@@ -145,6 +468,18 @@ where
         }
 
         if ready {
+            let pm = self
+                .sensor
+                .read_pm_values(&mut self.delay)
+                .await
+                .context("failed to read SEN5x PM values")?;
+            let nc0_5 = pm.nc0_5();
+            let nc1_0 = pm.nc1_0();
+            let nc2_5 = pm.nc2_5();
+            let nc4_0 = pm.nc4_0();
+            let nc10_0 = pm.nc10_0();
+            debug!("{NAME:>8}: NC0.5: {nc0_5:?}, NC1.0: {nc1_0:?}, NC2.5: {nc2_5:?}, NC4.0: {nc4_0:?}, NC10.0: {nc10_0:?}");
+
             macro_rules! update_metrics {
                 ($($name:ident),+) => {
                     $(
@@ -163,9 +498,20 @@ where
                 pm1_0,
                 pm2_5,
                 pm4_0,
-                pm10_0
+                pm10_0,
+                nc0_5,
+                nc1_0,
+                nc2_5,
+                nc4_0,
+                nc10_0
             );
 
+            if let (Some(pm2_5), Some(pm10_0)) = (pm2_5, pm10_0) {
+                let aqi = super::aqi(pm2_5, pm10_0);
+                debug!("{NAME:>8}: AQI: {aqi}");
+                self.aqi.set_value(aqi.into());
+            }
+
             if let (Some(temp), Some(humidity)) = (temp, rel_humidity) {
                 if self.polls.should_calc_abs_humidity() {
                     let abs_humidity = super::absolute_humidity(temp, humidity);
@@ -189,6 +535,27 @@ where
             Err(error) => warn!("{NAME:>8}: error reading warm start parameter: {error}"),
         }
 
+        match self.sensor.read_device_status(&mut self.delay).await {
+            Ok(register) => self.record_device_status(register),
+            Err(error) => warn!("{NAME:>8}: error reading device status: {error}"),
+        }
+
+        self.since_fan_cleaning += self.polls.interval();
+        if !self.fan_cleaning_interval.is_zero()
+            && self.since_fan_cleaning >= self.fan_cleaning_interval
+        {
+            info!(
+                "{NAME:>8}: fan last cleaned {}s ago, cleaning it...",
+                self.since_fan_cleaning.as_secs()
+            );
+            // cleaning saves the state, so there's no need to save it again.
+            return self.clean_fan().await;
+        }
+
+        if self.saves.poll() {
+            self.save_state().await;
+        }
+
         Ok(())
     }
 }
@@ -1,13 +1,17 @@
 use crate::{
     error::{Context, EclssError, SensorError},
-    metrics::{Gauge, HUMIDITY_METRICS},
-    sensor::{PollCount, Sensor},
-    storage::Store,
+    metrics::Gauge,
+    sensor::{
+        compensation::{CompensationSources, Inputs},
+        Command, CommandError, InvalidAddress, PollCount, Response, Sensor, SensorConfig,
+    },
+    storage::{self, SaveInterval, SensorState, Store},
     SharedBus,
 };
 use core::fmt;
 use core::time::Duration;
-use eclss_api::SensorName;
+use eclss_api::{air_quality::IaqCategory, SensorId, SensorName};
+use serde::de::{Deserialize, Deserializer};
 
 use embedded_hal_async::{
     delay::DelayNs,
@@ -18,11 +22,18 @@ use sgp30::{Baseline, Sgp30Async};
 pub struct Sgp30<I: 'static, D, S = ()> {
     sensor: Sgp30Async<&'static SharedBus<I>, D>,
     tvoc: &'static Gauge,
+    tvoc_iaq_category: &'static Gauge,
     eco2: &'static Gauge,
-    abs_humidity: &'static tinymetrics::GaugeFamily<'static, HUMIDITY_METRICS, SensorName>,
+    /// The source of the absolute humidity which readings are compensated for.
+    compensation: Inputs,
     calibration_polls: u32,
     last_good_baseline: Option<sgp30::Baseline>,
+    /// Set once the stored baseline has been loaded, or discarded by a
+    /// baseline reset, so that it's not restored when the sensor is reset.
+    baseline_loaded: bool,
+    id: SensorId,
     polls: PollCount,
+    saves: SaveInterval,
     store: S,
 }
 
@@ -44,6 +55,32 @@ struct StoredBaseline {
     tvoc: u16,
 }
 
+/// SGP30 state which is saved across restarts.
+#[derive(Debug, PartialEq, Eq, Clone, serde::Serialize, serde::Deserialize)]
+struct StoredState {
+    /// The last good baseline, or `None` if the baseline was reset and the
+    /// sensor hasn't recalibrated since.
+    #[serde(default)]
+    baseline: Option<StoredBaseline>,
+}
+
+impl SensorState for StoredState {
+    const VERSION: u32 = 2;
+
+    fn migrate<'de, D: Deserializer<'de>>(version: u32, state: D) -> Result<Self, D::Error> {
+        // Version 1 was just the baseline, which couldn't be cleared.
+        if version != 1 {
+            return Err(serde::de::Error::custom(format_args!(
+                "can't migrate {NAME} state from format version {version}"
+            )));
+        }
+        let baseline = StoredBaseline::deserialize(state)?;
+        Ok(Self {
+            baseline: Some(baseline),
+        })
+    }
+}
+
 impl From<Baseline> for StoredBaseline {
     fn from(Baseline { co2eq, tvoc }: Baseline) -> Self {
         Self { co2eq, tvoc }
@@ -66,29 +103,55 @@ where
         config: &crate::Config,
         delay: D,
     ) -> Self {
+        Self::new_with_config(eclss, config, SensorConfig::default(), delay)
+            .expect("default sensor config must be valid")
+    }
+
+    /// Returns a new SGP30 using the provided per-sensor configuration.
+    ///
+    /// Note that the SGP30's baseline calibration algorithm expects the sensor
+    /// to be polled every second, so overriding the poll interval is not
+    /// recommended.
+    pub fn new_with_config<const SENSORS: usize>(
+        eclss: &'static crate::Eclss<I, { SENSORS }>,
+        config: &crate::Config,
+        sensor_config: SensorConfig<I>,
+        delay: D,
+    ) -> Result<Self, InvalidAddress> {
         let metrics = &eclss.metrics;
-        Self {
-            sensor: Sgp30Async::new(&eclss.i2c, ADAFRUIT_SGP30_ADDR, delay),
-            tvoc: metrics.tvoc_ppb.register(NAME).unwrap(),
-            eco2: metrics.eco2_ppm.register(NAME).unwrap(),
-            abs_humidity: &metrics.abs_humidity_grams_m3,
+        let id = sensor_config.id(NAME);
+        let address = sensor_config.address_or(ADAFRUIT_SGP30_ADDR);
+        let poll_interval = sensor_config.poll_interval(POLL_INTERVAL);
+        Ok(Self {
+            id,
+            sensor: Sgp30Async::new(sensor_config.bus(eclss), address, delay),
+            tvoc: metrics.tvoc_ppb.register(id).unwrap(),
+            tvoc_iaq_category: metrics.tvoc_iaq_category.register(id).unwrap(),
+            eco2: metrics.eco2_ppm.register(id).unwrap(),
+            compensation: Inputs::new(eclss, id, sensor_config.compensation),
             calibration_polls: 0,
             last_good_baseline: None,
+            baseline_loaded: false,
             store: (),
-            polls: config.poll_counter(POLL_INTERVAL),
-        }
+            polls: config.poll_counter(poll_interval),
+            saves: SaveInterval::new(poll_interval),
+        })
     }
 
     pub fn with_storage<S: Store>(self, store: S) -> Sgp30<I, D, S> {
         Sgp30 {
             sensor: self.sensor,
             tvoc: self.tvoc,
+            tvoc_iaq_category: self.tvoc_iaq_category,
             eco2: self.eco2,
-            abs_humidity: self.abs_humidity,
+            compensation: self.compensation,
             calibration_polls: self.calibration_polls,
             last_good_baseline: self.last_good_baseline,
+            baseline_loaded: self.baseline_loaded,
+            id: self.id,
             store,
             polls: self.polls,
+            saves: self.saves,
         }
     }
 }
@@ -111,20 +174,38 @@ where
     S::Error: core::fmt::Display,
 {
     async fn refresh_baseline(&mut self) {
-        if self.last_good_baseline.is_some() {
+        if self.baseline_loaded {
             return;
         }
+        self.baseline_loaded = true;
 
-        match self.store.load::<StoredBaseline>().await {
-            Ok(Some(baseline)) => {
-                let baseline = baseline.into();
-                info!("{NAME:>8}: loaded baseline from storage: {baseline:?}");
-                self.last_good_baseline = Some(baseline);
-            }
-            Ok(None) => {}
-            Err(error) => warn!("error loading {NAME} baseline from storage: {error}"),
+        let state = storage::load::<_, StoredState>(&mut self.store, self.id).await;
+        if let Some(baseline) = state.and_then(|state| state.baseline) {
+            let baseline = baseline.into();
+            info!("{NAME:>8}: loaded baseline from storage: {baseline:?}");
+            self.last_good_baseline = Some(baseline);
         }
     }
+
+    /// Saves the last good baseline, so that it can be restored when the
+    /// sensor is restarted.
+    async fn save_baseline(&mut self) {
+        let Some(baseline) = self.last_good_baseline.clone() else {
+            return;
+        };
+        let stored = StoredState {
+            baseline: Some(StoredBaseline::from(baseline)),
+        };
+        storage::save(&mut self.store, self.id, &stored).await;
+    }
+
+    /// Discards the stored baseline, so that it's not restored when the
+    /// sensor is restarted before it has recalibrated.
+    async fn clear_baseline(&mut self) {
+        self.last_good_baseline = None;
+        self.baseline_loaded = true;
+        storage::save(&mut self.store, self.id, &StoredState { baseline: None }).await;
+    }
 }
 
 impl<I, D, S> Sensor for Sgp30<I, D, S>
@@ -179,8 +260,58 @@ where
         Ok(())
     }
 
+    async fn shutdown(&mut self) -> Result<(), Self::Error> {
+        // The SGP30 has no low-power mode, but save its current baseline so
+        // that it doesn't have to recalibrate from scratch when it's started
+        // again. The baseline isn't valid until calibration completes.
+        if self.calibration_polls <= 15 {
+            return Ok(());
+        }
+        let baseline = self
+            .sensor
+            .get_baseline()
+            .await
+            .context("error reading SGP30 baseline")?;
+        self.last_good_baseline = Some(baseline);
+        self.save_baseline().await;
+        Ok(())
+    }
+
+    async fn command(&mut self, command: Command) -> Result<Response, CommandError<Self::Error>> {
+        match command {
+            Command::ResetBaseline => {
+                // Re-initializing the IAQ algorithm discards its baseline. The
+                // stored baseline is discarded too, and the new baseline is
+                // stored once it's calibrated.
+                let reset: Result<(), Self::Error> = self
+                    .sensor
+                    .force_init()
+                    .await
+                    .context("error initializing SGP30");
+                reset.map_err(CommandError::Sensor)?;
+                info!("{NAME:>8}: baseline reset, recalibrating...");
+                self.clear_baseline().await;
+                self.calibration_polls = 0;
+                Ok(Response::Done)
+            }
+            _ => Err(CommandError::Unsupported),
+        }
+    }
+
+    fn poll_interval(&self) -> Duration {
+        self.polls.interval()
+    }
+
+    fn id(&self) -> SensorId {
+        self.id
+    }
+
+    fn compensation(&self) -> CompensationSources {
+        self.compensation.sources()
+    }
+
     async fn poll(&mut self) -> Result<(), Self::Error> {
-        let abs_h = self.abs_humidity.mean().and_then(|abs_h| {
+        let abs_h = self.compensation.abs_humidity_grams_m3().and_then(|abs_h| {
             match sgp30::Humidity::from_f32(abs_h as f32) {
                 Ok(h) => Some(h),
                 Err(error) => {
@@ -259,17 +390,16 @@ where
         }
 
         self.tvoc.set_value(tvoc_ppb as f64);
+        let category = IaqCategory::from_tvoc_ppb(tvoc_ppb.into());
+        self.tvoc_iaq_category.set_value(category.level().into());
         self.eco2.set_value(co2eq_ppm as f64);
 
         if let Some(baseline) = baseline {
-            if self.last_good_baseline.as_ref() != Some(&baseline) {
-                trace!("{NAME:>8}: new basaeline: {baseline:?}");
-                let stored = StoredBaseline::from(baseline.clone());
-                self.last_good_baseline = Some(baseline);
-                if let Err(error) = self.store.store(&stored).await {
-                    warn!("error loading {NAME} baseline from storage: {error}")
-                }
-            }
+            trace!("{NAME:>8}: new baseline: {baseline:?}");
+            self.last_good_baseline = Some(baseline);
+        }
+        if self.saves.poll() {
+            self.save_baseline().await;
         }
 
         self.polls.add();
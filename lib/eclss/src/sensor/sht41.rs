@@ -1,11 +1,11 @@
 use crate::{
     error::{Context, EclssError, SensorError},
     metrics::Gauge,
-    sensor::Sensor,
+    sensor::{Calibration, InvalidAddress, Sensor, SensorConfig},
     SharedBus,
 };
 use core::{fmt, time::Duration};
-use eclss_api::SensorName;
+use eclss_api::{SensorId, SensorName};
 use embedded_hal_async::{
     delay::DelayNs,
     i2c::{self, I2c},
@@ -22,10 +22,13 @@ pub struct Sht41<I: 'static, D> {
     rel_humidity: &'static Gauge,
     abs_humidity: &'static Gauge,
     precision: Precision,
+    calibration: Calibration,
+    id: SensorId,
     polls: PollCount,
     delay: D,
 }
 
+#[derive(Debug)]
 pub struct Sht4xError<E>(sht4x::Error<E>);
 
 const NAME: SensorName = SensorName::Sht41;
@@ -40,20 +43,41 @@ where
         config: &crate::Config,
         delay: D,
     ) -> Self {
+        Self::new_with_config(eclss, config, SensorConfig::default(), delay)
+            .expect("default sensor config must be valid")
+    }
+
+    /// Returns a new SHT41 using the provided per-sensor configuration.
+    ///
+    /// The SHT41 may be configured with addresses `0x44`, `0x45`, or `0x46`,
+    /// depending on the part number.
+    pub fn new_with_config<const SENSORS: usize>(
+        eclss: &'static crate::Eclss<I, { SENSORS }>,
+        config: &crate::Config,
+        sensor_config: SensorConfig<I>,
+        delay: D,
+    ) -> Result<Self, InvalidAddress> {
         let metrics = &eclss.metrics;
-        // This is the default I2C address of the Adafruit breakout board.
-        // TODO(eliza): make this configurable
-        let address = sht4x::Address::Address0x44;
-
-        Self {
-            sensor: Sht4xAsync::new_with_address(&eclss.i2c, address),
-            temp: metrics.temp_c.register(NAME).unwrap(),
-            rel_humidity: metrics.rel_humidity_percent.register(NAME).unwrap(),
-            abs_humidity: metrics.abs_humidity_grams_m3.register(NAME).unwrap(),
-            polls: config.poll_counter(POLL_INTERVAL),
+        let id = sensor_config.id(NAME);
+        // The first address is the default I2C address of the Adafruit
+        // breakout board.
+        let address = match sensor_config.address_in(NAME, &[0x44, 0x45, 0x46])? {
+            0x45 => sht4x::Address::Address0x45,
+            0x46 => sht4x::Address::Address0x46,
+            _ => sht4x::Address::Address0x44,
+        };
+
+        Ok(Self {
+            id,
+            sensor: Sht4xAsync::new_with_address(sensor_config.bus(eclss), address),
+            temp: metrics.temp_c.register(id).unwrap(),
+            rel_humidity: metrics.rel_humidity_percent.register(id).unwrap(),
+            abs_humidity: metrics.abs_humidity_grams_m3.register(id).unwrap(),
+            polls: config.poll_counter(sensor_config.poll_interval(POLL_INTERVAL)),
             precision: Precision::Medium,
+            calibration: sensor_config.calibration,
             delay,
-        }
+        })
     }
 
     pub fn with_precision(self, precision: Precision) -> Self {
@@ -82,6 +106,14 @@ where
         Ok(())
     }
 
+    fn poll_interval(&self) -> Duration {
+        self.polls.interval()
+    }
+
+    fn id(&self) -> SensorId {
+        self.id
+    }
+
     async fn poll(&mut self) -> Result<(), Self::Error> {
         let reading = self
             .sensor
@@ -89,10 +121,12 @@ where
             .await
             .context("error reading SHT41 measurement")?;
 
-        let temp = reading.temperature_celsius().to_num::<f64>();
-        let rel_humidity = reading.humidity_percent().to_num::<f64>();
-        self.temp.set_value(temp);
-        self.rel_humidity.set_value(rel_humidity);
+        let (temp, rel_humidity) = self.calibration.correct(
+            reading.temperature_celsius().to_num::<f32>(),
+            reading.humidity_percent().to_num::<f32>(),
+        );
+        self.temp.set_value(temp.into());
+        self.rel_humidity.set_value(rel_humidity.into());
         if self.polls.should_log_info() {
             info!("{NAME:>8}: Temp: {temp:>3.2}°C, Humidity: {rel_humidity:>3.2}%");
         } else {
@@ -100,7 +134,7 @@ where
         }
 
         if self.polls.should_calc_abs_humidity() {
-            let abs_humidity = super::absolute_humidity(temp as f32, rel_humidity as f32);
+            let abs_humidity = super::absolute_humidity(temp, rel_humidity);
             self.abs_humidity.set_value(abs_humidity.into());
             if self.polls.should_log_info() {
                 info!("{NAME:>8}: Absolute humidity: {abs_humidity:02.2} g/m³");
@@ -1,20 +1,114 @@
-use serde::{de::DeserializeOwned, Serialize};
+use core::fmt;
+use core::time::Duration;
+use eclss_api::SensorId;
+use serde::{
+    de::{self, DeserializeOwned, Deserializer},
+    Serialize,
+};
+
+/// Calibration state which a sensor persists across restarts.
+///
+/// Sensors which learn calibration state over time save it to a [`Store`], so
+/// that restarting the sensor doesn't mean learning it all over again.
+pub trait SensorState: Serialize + DeserializeOwned {
+    /// The version of this state's stored format.
+    ///
+    /// This must be incremented whenever the format changes, so that a
+    /// [`Store`] doesn't load state saved in an older format as if it were in
+    /// the current one.
+    const VERSION: u32;
+
+    /// Deserializes state which was saved in an older `version` of its format.
+    ///
+    /// When the format changes, this should be implemented to convert state
+    /// saved in previous versions, so that it survives the upgrade. By
+    /// default, state saved in older formats is discarded.
+    fn migrate<'de, D: Deserializer<'de>>(version: u32, state: D) -> Result<Self, D::Error> {
+        let _ = state;
+        Err(de::Error::custom(format_args!(
+            "can't migrate state from format version {version} to version {}",
+            Self::VERSION
+        )))
+    }
+}
 
 #[allow(async_fn_in_trait)]
 pub trait Store {
     type Error;
-    async fn load<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Self::Error>;
-    async fn store<T: Serialize>(&mut self, value: &T) -> Result<(), Self::Error>;
+    async fn load<T: SensorState>(&mut self) -> Result<Option<T>, Self::Error>;
+    async fn store<T: SensorState>(&mut self, value: &T) -> Result<(), Self::Error>;
 }
 
 impl Store for () {
     type Error = core::convert::Infallible;
 
-    async fn load<T: DeserializeOwned>(&mut self) -> Result<Option<T>, Self::Error> {
+    async fn load<T: SensorState>(&mut self) -> Result<Option<T>, Self::Error> {
         Ok(None)
     }
 
-    async fn store<T: Serialize>(&mut self, _: &T) -> Result<(), Self::Error> {
+    async fn store<T: SensorState>(&mut self, _: &T) -> Result<(), Self::Error> {
         Ok(())
     }
 }
+
+/// Loads `sensor`'s state from `store`.
+///
+/// Errors are logged rather than returned, as a sensor can always start over
+/// without its saved state.
+pub(crate) async fn load<S, T>(store: &mut S, sensor: SensorId) -> Option<T>
+where
+    S: Store,
+    S::Error: fmt::Display,
+    T: SensorState,
+{
+    match store.load::<T>().await {
+        Ok(state) => state,
+        Err(error) => {
+            warn!("{sensor}: error loading saved state: {error}");
+            None
+        }
+    }
+}
+
+/// Saves `sensor`'s `state` to `store`, logging any error.
+pub(crate) async fn save<S, T>(store: &mut S, sensor: SensorId, state: &T)
+where
+    S: Store,
+    S::Error: fmt::Display,
+    T: SensorState,
+{
+    match store.store(state).await {
+        Ok(()) => trace!("{sensor}: saved state"),
+        Err(error) => warn!("{sensor}: error saving state: {error}"),
+    }
+}
+
+/// How often sensors whose state changes continuously save it, in addition to
+/// saving it when they're shut down.
+const SAVE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Counts polls between periodic saves of a sensor's state.
+pub(crate) struct SaveInterval {
+    polls: u32,
+    every: u32,
+}
+
+impl SaveInterval {
+    pub(crate) fn new(poll_interval: Duration) -> Self {
+        let every = SAVE_INTERVAL.as_millis() / poll_interval.as_millis().max(1);
+        Self {
+            polls: 0,
+            every: every.clamp(1, u32::MAX as u128) as u32,
+        }
+    }
+
+    /// Records a poll, returning `true` if the sensor's state should be saved.
+    pub(crate) fn poll(&mut self) -> bool {
+        self.polls += 1;
+        if self.polls >= self.every {
+            self.polls = 0;
+            return true;
+        }
+        false
+    }
+}
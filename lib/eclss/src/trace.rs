@@ -1,7 +1,7 @@
 #[cfg(feature = "tracing")]
 macro_rules! trace {
     ($($arg:tt)*) => {
-        tracing::trace!($($arg)*);
+        tracing::trace!($($arg)*)
     };
 }
 
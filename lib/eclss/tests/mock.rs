@@ -0,0 +1,1249 @@
+//! Integration tests driving the real sensor drivers and `Eclss::run_sensor`
+//! against the emulated sensors in `eclss::mock`.
+use eclss::{
+    fusion,
+    mock::{self, Fault, MockDelay, MockI2c},
+    probe::{self, Detected},
+    sensor::{
+        self, Calibration, Command, CommandError, Compensation, CompensationSource, Correction,
+        Response, Sensor, SensorConfig, SensorId, SensorName, Status,
+    },
+    storage::{SensorState, Store},
+    Config, Eclss, SensorMetrics, SharedBus, Tca9548a,
+};
+use futures::{executor::block_on, poll};
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::rc::Rc;
+use std::task::Poll;
+use std::time::Duration;
+use tinymetrics::{CounterFamily, GaugeFamily};
+
+fn eclss(bus: &MockI2c) -> &'static Eclss<MockI2c, 16> {
+    Box::leak(Box::new(Eclss::new(bus.clone())))
+}
+
+fn gauge<const N: usize>(
+    family: &GaugeFamily<'static, N, SensorId>,
+    sensor: impl Into<SensorId>,
+) -> f64 {
+    let sensor = sensor.into();
+    family
+        .metrics()
+        .iter()
+        .find(|(name, _)| **name == sensor)
+        .map(|(_, gauge)| gauge.value())
+        .unwrap_or_else(|| panic!("no gauge for {sensor}"))
+}
+
+fn counter<const N: usize>(
+    family: &CounterFamily<'static, N, SensorId>,
+    sensor: impl Into<SensorId>,
+) -> u64 {
+    let sensor = sensor.into();
+    family
+        .metrics()
+        .iter()
+        .find(|(name, _)| **name == sensor)
+        .map(|(_, counter)| counter.value())
+        .unwrap_or(0)
+}
+
+/// A `Store` which keeps a sensor's state in memory, as JSON.
+#[derive(Clone, Default)]
+struct MemStore(Rc<RefCell<Option<serde_json::Value>>>);
+
+impl MemStore {
+    fn get(&self) -> Option<serde_json::Value> {
+        self.0.borrow().clone()
+    }
+
+    fn set(&self, value: serde_json::Value) {
+        *self.0.borrow_mut() = Some(value);
+    }
+}
+
+impl Store for MemStore {
+    type Error = serde_json::Error;
+
+    async fn load<T: SensorState>(&mut self) -> Result<Option<T>, Self::Error> {
+        self.get().map(serde_json::from_value).transpose()
+    }
+
+    async fn store<T: SensorState>(&mut self, value: &T) -> Result<(), Self::Error> {
+        self.set(serde_json::to_value(value)?);
+        Ok(())
+    }
+}
+
+fn status<I>(eclss: &Eclss<I, 16>, sensor: impl Into<SensorId>) -> Status {
+    let sensor = sensor.into();
+    eclss
+        .sensors()
+        .iter()
+        .find(|(name, _)| **name == sensor)
+        .map(|(_, state)| state.status())
+        .unwrap_or(Status::Unknown)
+}
+
+/// A boxed `Eclss::run_sensor` task.
+type SensorTask = Pin<Box<dyn Future<Output = Result<(), &'static str>>>>;
+
+/// Polls a `run_sensor` `task` until `sensor` is up.
+async fn run_until_up<I>(
+    eclss: &Eclss<I, 16>,
+    sensor: impl Into<SensorId>,
+    task: &mut (impl Future<Output = Result<(), &'static str>> + Unpin),
+) {
+    let sensor = sensor.into();
+    let mut steps = 0;
+    while status(eclss, sensor) != Status::Up {
+        assert!(poll!(&mut *task).is_pending(), "{sensor} task exited");
+        steps += 1;
+        assert!(steps < 1000, "{sensor} never came up");
+    }
+}
+
+#[cfg(feature = "scd41")]
+#[test]
+fn scd41_init_and_poll() {
+    let mut scd41 = mock::Scd41::new();
+    scd41.co2_ppm = 1200;
+    scd41.temp_c = 25.0;
+    scd41.humidity_percent = 50.0;
+    let bus = MockI2c::new().with_device(mock::Scd41::ADDR, scd41);
+    let eclss = eclss(&bus);
+
+    let mut sensor = sensor::Scd41::new(eclss, &Config::default(), MockDelay::new());
+    block_on(async {
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+    });
+
+    let measuring = bus.device(mock::Scd41::ADDR, |scd41: &mut mock::Scd41| {
+        scd41.is_measuring()
+    });
+    assert_eq!(measuring, Some(true));
+    let metrics = eclss.metrics();
+    assert_eq!(gauge(&metrics.co2_ppm, SensorName::Scd41), 1200.0);
+    assert!((gauge(&metrics.temp_c, SensorName::Scd41) - 25.0).abs() < 0.1);
+    assert!((gauge(&metrics.rel_humidity_percent, SensorName::Scd41) - 50.0).abs() < 0.1);
+    // 1200 ppm is in the "fair" band.
+    assert_eq!(gauge(&metrics.co2_comfort_band, SensorName::Scd41), 3.0);
+}
+
+#[cfg(feature = "scd41")]
+#[test]
+fn scd41_temperature_offset_calibration() {
+    let poll = |calibration: Calibration| {
+        let mut scd41 = mock::Scd41::new();
+        scd41.temp_c = 25.0;
+        let bus = MockI2c::new().with_device(mock::Scd41::ADDR, scd41);
+        let eclss = eclss(&bus);
+        let sensor_config = SensorConfig {
+            calibration,
+            ..Default::default()
+        };
+        let mut sensor = sensor::Scd41::new_with_config(
+            eclss,
+            &Config::default(),
+            sensor_config,
+            MockDelay::new(),
+        )
+        .expect("SCD41 config should be valid");
+        block_on(async {
+            sensor.init().await.expect("init should succeed");
+            sensor.poll().await.expect("poll should succeed");
+        });
+        let offset = bus
+            .device(mock::Scd41::ADDR, |scd41: &mut mock::Scd41| {
+                scd41.temp_offset_c()
+            })
+            .unwrap();
+        (gauge(&eclss.metrics().temp_c, SensorName::Scd41), offset)
+    };
+
+    // offsets are written to the sensor's temperature offset register, which
+    // the sensor subtracts from its readings.
+    let (temp_c, offset) = poll(Calibration {
+        temp: Correction::offset(-2.0),
+        ..Calibration::IDENTITY
+    });
+    assert!((offset - 6.0).abs() < 0.01, "temperature offset: {offset}");
+    assert!((temp_c - 23.0).abs() < 0.1, "temperature: {temp_c}");
+
+    // scaled temperatures are corrected in software.
+    let (temp_c, offset) = poll(Calibration {
+        temp: Correction {
+            offset: -2.0,
+            scale: 1.1,
+        },
+        ..Calibration::IDENTITY
+    });
+    assert!((offset - 4.0).abs() < 0.01, "temperature offset: {offset}");
+    assert!((temp_c - 25.5).abs() < 0.1, "temperature: {temp_c}");
+}
+
+#[cfg(feature = "scd41")]
+#[test]
+fn scd41_saves_and_restores_asc() {
+    let bus = MockI2c::new().with_device(mock::Scd41::ADDR, mock::Scd41::new());
+    let eclss = eclss(&bus);
+    let config = Config::default();
+    let store = MemStore::default();
+
+    // if no setting was saved, the sensor's current setting is saved.
+    let mut sensor =
+        sensor::Scd41::new(eclss, &config, MockDelay::new()).with_storage(store.clone());
+    block_on(sensor.init()).expect("init should succeed");
+    assert_eq!(
+        store.get(),
+        Some(serde_json::json!({ "automatic_self_calibration": true }))
+    );
+
+    // otherwise, the saved setting is restored.
+    store.set(serde_json::json!({ "automatic_self_calibration": false }));
+    let mut sensor =
+        sensor::Scd41::new(eclss, &config, MockDelay::new()).with_storage(store.clone());
+    block_on(sensor.init()).expect("init should succeed");
+    let asc = bus.device(mock::Scd41::ADDR, |scd41: &mut mock::Scd41| {
+        scd41.automatic_self_calibration
+    });
+    assert_eq!(asc, Some(false));
+}
+
+#[cfg(feature = "sht41")]
+#[test]
+fn sht41_alternate_address_and_bus() {
+    let mut sht41 = mock::Sht41::new();
+    sht41.temp_c = 30.0;
+    // the SHT41-B's address is 0x45, and it's on a different bus from the
+    // `Eclss` instance's bus.
+    let other_bus: &'static SharedBus<MockI2c> = Box::leak(Box::new(SharedBus::new(
+        MockI2c::new().with_device(0x45, sht41),
+    )));
+    let eclss = eclss(&MockI2c::new());
+    let sensor_config = SensorConfig {
+        bus: Some(other_bus),
+        address: Some(0x45),
+        poll_interval: Some(Duration::from_secs(10)),
+        ..Default::default()
+    };
+
+    let mut sensor =
+        sensor::Sht41::new_with_config(eclss, &Config::default(), sensor_config, MockDelay::new())
+            .expect("0x45 is a valid SHT41 address");
+    assert_eq!(sensor.poll_interval(), Duration::from_secs(10));
+    block_on(async {
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+    });
+    let temp = gauge(&eclss.metrics().temp_c, SensorName::Sht41);
+    assert!((temp - 30.0).abs() < 0.1);
+}
+
+#[cfg(feature = "sht41")]
+#[test]
+fn sht41_calibration_recomputes_humidity() {
+    let mut sht41 = mock::Sht41::new();
+    sht41.temp_c = 25.0;
+    sht41.humidity_percent = 40.0;
+    let bus = MockI2c::new().with_device(0x44, sht41);
+    let eclss = eclss(&bus);
+    let sensor_config = SensorConfig {
+        calibration: Calibration {
+            temp: Correction::offset(-3.0),
+            humidity: Correction::offset(1.0),
+        },
+        ..Default::default()
+    };
+    let mut sensor =
+        sensor::Sht41::new_with_config(eclss, &Config::default(), sensor_config, MockDelay::new())
+            .expect("SHT41 config should be valid");
+    block_on(async {
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+    });
+
+    let metrics = eclss.metrics();
+    let temp_c = gauge(&metrics.temp_c, SensorName::Sht41);
+    assert!((temp_c - 22.0).abs() < 0.1, "temperature: {temp_c}");
+    // the same amount of water vapor is a higher relative humidity in cooler
+    // air: 40% at 25 °C is about 47.9% at 22 °C, before the humidity offset is
+    // added.
+    let rel_humidity = gauge(&metrics.rel_humidity_percent, SensorName::Sht41);
+    assert!(
+        (rel_humidity - 48.9).abs() < 0.3,
+        "relative humidity: {rel_humidity}"
+    );
+    // absolute humidity is derived from the corrected readings.
+    let abs_humidity = gauge(&metrics.abs_humidity_grams_m3, SensorName::Sht41);
+    assert!(
+        (abs_humidity - 9.5).abs() < 0.2,
+        "absolute humidity: {abs_humidity}"
+    );
+}
+
+#[cfg(feature = "sht41")]
+#[test]
+fn sht41_multiple_instances() {
+    let mut floor = mock::Sht41::new();
+    floor.temp_c = 18.0;
+    let mut ceiling = mock::Sht41::new();
+    ceiling.temp_c = 24.0;
+    let bus = MockI2c::new()
+        .with_device(0x44, floor)
+        .with_device(0x45, ceiling);
+    let eclss = eclss(&bus);
+
+    let floor = "SHT41:floor".parse::<SensorId>().unwrap();
+    let ceiling = "SHT41:ceiling".parse::<SensorId>().unwrap();
+    let mut sensors = [(floor, 0x44), (ceiling, 0x45)].map(|(id, address)| {
+        let sensor_config = SensorConfig {
+            address: Some(address),
+            instance: id.instance,
+            ..Default::default()
+        };
+        sensor::Sht41::new_with_config(eclss, &Config::default(), sensor_config, MockDelay::new())
+            .expect("SHT41 config should be valid")
+    });
+    assert_eq!(sensors[0].id(), floor);
+    assert_eq!(sensors[1].id(), ceiling);
+    block_on(async {
+        for sensor in &mut sensors {
+            sensor.init().await.expect("init should succeed");
+            sensor.poll().await.expect("poll should succeed");
+        }
+    });
+
+    let metrics = eclss.metrics();
+    assert!((gauge(&metrics.temp_c, floor) - 18.0).abs() < 0.1);
+    assert!((gauge(&metrics.temp_c, ceiling) - 24.0).abs() < 0.1);
+    let exposition = metrics.to_string();
+    assert!(
+        exposition.contains(r#"sensor="SHT41",sensor_instance="ceiling""#),
+        "{exposition}"
+    );
+}
+
+#[cfg(feature = "sht41")]
+#[test]
+fn sht41s_behind_mux() {
+    let mux = mock::Tca9548a::new();
+    let mut left = mock::Sht41::new();
+    left.temp_c = 18.0;
+    mux.channel(0).attach(mock::Sht41::ADDR, left);
+    let mut right = mock::Sht41::new();
+    right.temp_c = 24.0;
+    mux.channel(3).attach(mock::Sht41::ADDR, right);
+    let bus = MockI2c::new().with_device(mock::Tca9548a::ADDR, mux);
+    let eclss = eclss(&bus);
+
+    let tca9548a = Tca9548a::new(eclss.bus(), Tca9548a::<MockI2c>::DEFAULT_ADDRESS)
+        .expect("the Eclss bus is not a mux channel");
+    assert!(tca9548a.channel(8).is_err());
+    let left = "SHT41:left".parse::<SensorId>().unwrap();
+    let right = "SHT41:right".parse::<SensorId>().unwrap();
+    let mut sensors = [(left, 0), (right, 3)].map(|(id, channel)| {
+        let channel: &'static SharedBus<MockI2c> =
+            Box::leak(Box::new(tca9548a.channel(channel).unwrap()));
+        let sensor_config = SensorConfig {
+            bus: Some(channel),
+            instance: id.instance,
+            ..Default::default()
+        };
+        sensor::Sht41::new_with_config(eclss, &Config::default(), sensor_config, MockDelay::new())
+            .expect("SHT41 config should be valid")
+    });
+    block_on(async {
+        for sensor in &mut sensors {
+            sensor.init().await.expect("init should succeed");
+            sensor.poll().await.expect("poll should succeed");
+        }
+    });
+
+    let metrics = eclss.metrics();
+    assert!((gauge(&metrics.temp_c, left) - 18.0).abs() < 0.1);
+    assert!((gauge(&metrics.temp_c, right) - 24.0).abs() < 0.1);
+    let control = bus.device(mock::Tca9548a::ADDR, |mux: &mut mock::Tca9548a| {
+        mux.control()
+    });
+    assert_eq!(control, Some(1 << 3));
+}
+
+/// A sensor model run by `run_sensor_polls_and_shuts_down`.
+#[cfg(any(feature = "sht41", feature = "scd41", feature = "sen55"))]
+struct RunCase {
+    name: SensorName,
+    /// Returns a bus with the emulated sensor attached.
+    bus: fn() -> MockI2c,
+    /// Returns a task running the sensor's driver.
+    run: fn(&'static Eclss<MockI2c, 16>, MemStore) -> SensorTask,
+    /// Returns whether the emulated sensor is measuring, for sensors which
+    /// must be stopped on shutdown.
+    measuring: Option<fn(&MockI2c) -> Option<bool>>,
+    /// Whether the sensor's state is saved on shutdown.
+    saves_state: bool,
+    /// Checks the sensor's readings.
+    check: fn(&SensorMetrics),
+}
+
+#[cfg(any(feature = "sht41", feature = "scd41", feature = "sen55"))]
+#[test]
+fn run_sensor_polls_and_shuts_down() {
+    let cases = [
+        #[cfg(feature = "sht41")]
+        RunCase {
+            name: SensorName::Sht41,
+            bus: || {
+                let mut sht41 = mock::Sht41::new();
+                sht41.temp_c = 23.5;
+                sht41.humidity_percent = 55.0;
+                MockI2c::new().with_device(mock::Sht41::ADDR, sht41)
+            },
+            run: |eclss, _| {
+                let config = Config::default();
+                let sensor = sensor::Sht41::new(eclss, &config, MockDelay::new());
+                Box::pin(eclss.run_sensor(sensor, config, MockDelay::new()))
+            },
+            measuring: None,
+            saves_state: false,
+            check: |metrics| {
+                assert!((gauge(&metrics.temp_c, SensorName::Sht41) - 23.5).abs() < 0.1);
+                assert!(
+                    (gauge(&metrics.rel_humidity_percent, SensorName::Sht41) - 55.0).abs() < 0.1
+                );
+            },
+        },
+        #[cfg(feature = "scd41")]
+        RunCase {
+            name: SensorName::Scd41,
+            bus: || {
+                let mut scd41 = mock::Scd41::new();
+                scd41.co2_ppm = 800;
+                MockI2c::new().with_device(mock::Scd41::ADDR, scd41)
+            },
+            run: |eclss, _| {
+                let config = Config::default();
+                let sensor = sensor::Scd41::new(eclss, &config, MockDelay::new());
+                Box::pin(eclss.run_sensor(sensor, config, MockDelay::new()))
+            },
+            measuring: Some(|bus| {
+                bus.device(mock::Scd41::ADDR, |scd41: &mut mock::Scd41| {
+                    scd41.is_measuring()
+                })
+            }),
+            saves_state: false,
+            check: |metrics| {
+                assert_eq!(gauge(&metrics.co2_ppm, SensorName::Scd41), 800.0);
+            },
+        },
+        #[cfg(feature = "sen55")]
+        RunCase {
+            name: SensorName::Sen55,
+            bus: || MockI2c::new().with_device(mock::Sen55::ADDR, mock::Sen55::new()),
+            run: |eclss, store| {
+                let config = Config::default();
+                let sensor =
+                    sensor::Sen55::new(eclss, &config, MockDelay::new()).with_storage(store);
+                Box::pin(eclss.run_sensor(sensor, config, MockDelay::new()))
+            },
+            measuring: Some(|bus| {
+                bus.device(mock::Sen55::ADDR, |sen55: &mut mock::Sen55| {
+                    sen55.is_measuring()
+                })
+            }),
+            saves_state: true,
+            check: |metrics| {
+                let pm2_5 = metrics
+                    .pm_conc
+                    .metrics()
+                    .iter()
+                    .find(|(label, _)| label.diameter == "2.5")
+                    .map(|(_, gauge)| gauge.value());
+                assert_eq!(pm2_5, Some(3.5));
+                assert!((gauge(&metrics.temp_c, SensorName::Sen55) - 22.0).abs() < 0.1);
+            },
+        },
+    ];
+
+    for case in cases {
+        let bus = (case.bus)();
+        let eclss = eclss(&bus);
+        let store = MemStore::default();
+        let is_measuring = || case.measuring.map(|measuring| measuring(&bus));
+
+        block_on(async {
+            let mut task = (case.run)(eclss, store.clone());
+            run_until_up(eclss, case.name, &mut task).await;
+            if let Some(measuring) = is_measuring() {
+                assert_eq!(measuring, Some(true), "{} should be measuring", case.name);
+            }
+
+            eclss.shutdown();
+            assert_eq!(task.await, Ok(()), "{} should shut down", case.name);
+        });
+
+        (case.check)(eclss.metrics());
+        // shutting down stops measurement, and saves the sensor's state.
+        if let Some(measuring) = is_measuring() {
+            assert_eq!(
+                measuring,
+                Some(false),
+                "{} should stop measuring",
+                case.name
+            );
+        }
+        assert_eq!(store.get().is_some(), case.saves_state, "{}", case.name);
+
+        // sensors started after shutdown return immediately.
+        assert_eq!(block_on((case.run)(eclss, store.clone())), Ok(()));
+        if let Some(measuring) = is_measuring() {
+            assert_eq!(measuring, Some(false), "{} was restarted", case.name);
+        }
+    }
+}
+
+#[cfg(feature = "scd41")]
+#[test]
+fn scd41_rejects_unsupported_address() {
+    let eclss = eclss(&MockI2c::new());
+    let sensor_config = SensorConfig {
+        address: Some(0x63),
+        ..Default::default()
+    };
+    let Err(error) =
+        sensor::Scd41::new_with_config(eclss, &Config::default(), sensor_config, MockDelay::new())
+    else {
+        panic!("SCD41 should not support address 0x63");
+    };
+    assert_eq!(
+        error.to_string(),
+        "SCD41 does not support I²C address 0x63 (supported addresses: 0x62)"
+    );
+}
+
+#[cfg(feature = "pmsa003i")]
+#[test]
+fn pmsa003i_checksum_failure() {
+    let mut pmsa003i = mock::Pmsa003i::new();
+    pmsa003i.concentrations = [1, 12, 20];
+    let bus = MockI2c::new().with_device(mock::Pmsa003i::ADDR, pmsa003i);
+    let eclss = eclss(&bus);
+
+    let mut sensor = sensor::Pmsa003i::new(eclss, &Config::default());
+    bus.inject(mock::Pmsa003i::ADDR, Fault::Corrupt);
+    block_on(async {
+        let error = sensor
+            .poll()
+            .await
+            .expect_err("corrupted reading should fail");
+        assert_eq!(
+            eclss::error::SensorError::as_status(&error),
+            Status::SensorError
+        );
+        sensor.poll().await.expect("second reading should succeed");
+    });
+}
+
+#[cfg(feature = "scd41")]
+#[test]
+fn run_sensor_gives_up_after_max_init_attempts() {
+    // no device attached!
+    let bus = MockI2c::new();
+    let eclss = eclss(&bus);
+    let mut config = Config::default();
+    config.max_init_attempts = Some(3);
+
+    let sensor = sensor::Scd41::new(eclss, &config, MockDelay::new());
+    let result = block_on(eclss.run_sensor(sensor, config, MockDelay::new()));
+
+    assert!(result.is_err());
+    assert_eq!(status(eclss, SensorName::Scd41), Status::NoAcknowledge);
+    assert_eq!(
+        counter(&eclss.metrics().sensor_errors, SensorName::Scd41),
+        3
+    );
+}
+
+#[cfg(feature = "pmsa003i")]
+#[test]
+fn run_sensor_retries_bus_errors() {
+    let bus = MockI2c::new().with_device(mock::Pmsa003i::ADDR, mock::Pmsa003i::new());
+    let eclss = eclss(&bus);
+    let config = Config::default();
+    let sensor = sensor::Pmsa003i::new(eclss, &config);
+
+    block_on(async {
+        let mut task = pin!(eclss.run_sensor(sensor, config, MockDelay::new()));
+        for _ in 0..4 {
+            assert!(poll!(task.as_mut()).is_pending());
+        }
+        assert_eq!(status(eclss, SensorName::Pmsa003i), Status::Up);
+
+        bus.inject_n(mock::Pmsa003i::ADDR, Fault::BusError, 2);
+        // step until the sensor task hits the injected fault
+        for _ in 0..2 {
+            assert!(poll!(task.as_mut()).is_pending());
+        }
+        assert_eq!(status(eclss, SensorName::Pmsa003i), Status::BusError);
+
+        for _ in 0..8 {
+            assert!(poll!(task.as_mut()).is_pending());
+        }
+        assert_eq!(status(eclss, SensorName::Pmsa003i), Status::Up);
+    });
+
+    assert_eq!(
+        counter(&eclss.metrics().sensor_errors, SensorName::Pmsa003i),
+        2
+    );
+    assert_eq!(
+        counter(&eclss.metrics().sensor_reset_count, SensorName::Pmsa003i),
+        0
+    );
+}
+
+#[cfg(feature = "pmsa003i")]
+#[test]
+fn run_sensor_records_reading_times() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    static NOW: AtomicU64 = AtomicU64::new(1_000);
+
+    let bus = MockI2c::new().with_device(mock::Pmsa003i::ADDR, mock::Pmsa003i::new());
+    let eclss: &'static Eclss<MockI2c, 16> = Box::leak(Box::new(
+        Eclss::new(bus.clone())
+            .with_clock(|| NOW.load(Ordering::Relaxed))
+            .with_max_reading_age(Some(Duration::from_secs(60))),
+    ));
+    let config = Config::default();
+    let sensor = sensor::Pmsa003i::new(eclss, &config);
+    let id = SensorId::new(SensorName::Pmsa003i);
+    let aqi_readings = || {
+        let mut readings = 0;
+        eclss.metrics().for_each_reading(|name, sensor, _| {
+            if name == "aqi" && sensor == id {
+                readings += 1;
+            }
+        });
+        readings
+    };
+
+    block_on(async {
+        let mut task = pin!(eclss.run_sensor(sensor, config, MockDelay::new()));
+        run_until_up(eclss, SensorName::Pmsa003i, &mut task).await;
+    });
+
+    let metrics = eclss.metrics();
+    assert_eq!(metrics.last_reading(&id), Some(1_000));
+    assert!(!metrics.is_stale(&id, metrics.now()));
+    assert_eq!(aqi_readings(), 1);
+
+    // the sensor stops being polled, so its readings become stale.
+    NOW.store(1_120, Ordering::Relaxed);
+    assert!(metrics.is_stale(&id, metrics.now()));
+    assert_eq!(aqi_readings(), 0);
+    // reading ages are updated when the metrics are formatted.
+    let prometheus = metrics.to_string();
+    assert!(prometheus.contains("sensor_reading_age_seconds"));
+    assert_eq!(gauge(&metrics.reading_age, id), 120.0);
+}
+
+#[cfg(feature = "sht41")]
+#[test]
+fn run_fusion_combines_readings() {
+    let bus = MockI2c::new();
+    let sensors = [
+        ("SHT41:floor", 0x44, 20.0),
+        ("SHT41:desk", 0x45, 21.0),
+        ("SHT41:heater", 0x46, 35.0),
+    ]
+    .map(|(id, address, temp_c)| {
+        let mut sht41 = mock::Sht41::new();
+        sht41.temp_c = temp_c;
+        bus.attach(address, sht41);
+        (id.parse::<SensorId>().unwrap(), address)
+    });
+    let eclss = eclss(&bus);
+    let config = Config::default();
+    let [floor, desk, heater] = sensors.map(|(id, _)| id);
+    let fusion_config = fusion::Config {
+        interval: Duration::from_secs(1),
+        ..Default::default()
+    };
+
+    block_on(async {
+        let mut tasks = sensors.map(|(id, address)| {
+            let mut calibration = Calibration::IDENTITY;
+            if id == desk {
+                calibration.temp = Correction::offset(-1.0);
+            }
+            let sensor_config = SensorConfig {
+                address: Some(address),
+                instance: id.instance,
+                calibration,
+                ..Default::default()
+            };
+            let sensor =
+                sensor::Sht41::new_with_config(eclss, &config, sensor_config, MockDelay::new())
+                    .expect("SHT41 config should be valid");
+            Box::pin(eclss.run_sensor(sensor, config.clone(), MockDelay::new()))
+        });
+        let mut steps = 0;
+        while [floor, desk, heater]
+            .into_iter()
+            .any(|id| status(eclss, id) != Status::Up)
+        {
+            for task in &mut tasks {
+                assert!(poll!(task.as_mut()).is_pending());
+            }
+            steps += 1;
+            assert!(steps < 1000, "SHT41s never came up");
+        }
+
+        let mut fusion = pin!(eclss.run_fusion(fusion_config, MockDelay::new()));
+        let mut steps = 0;
+        while eclss.metrics().last_reading(&fusion::FUSED).is_none() {
+            assert!(poll!(fusion.as_mut()).is_pending());
+            steps += 1;
+            assert!(steps < 1000, "fused readings were never recorded");
+        }
+
+        eclss.shutdown();
+        assert_eq!(fusion.await, Ok(()));
+    });
+
+    let metrics = eclss.metrics();
+    // the desk sensor's calibration brings it in line with the floor, and the
+    // heater is rejected as an outlier.
+    let temp_c = gauge(&metrics.temp_c, fusion::FUSED);
+    assert!((temp_c - 20.0).abs() < 0.1, "fused temperature: {temp_c}");
+    // the desk sensor's humidity is recomputed for its corrected temperature,
+    // so the fused humidity is between the floor's and the desk's.
+    let rel_humidity = gauge(&metrics.rel_humidity_percent, fusion::FUSED);
+    let floor_rh = gauge(&metrics.rel_humidity_percent, floor);
+    let desk_rh = gauge(&metrics.rel_humidity_percent, desk);
+    assert!(floor_rh < desk_rh, "floor: {floor_rh}, desk: {desk_rh}");
+    assert!(
+        (floor_rh..=desk_rh).contains(&rel_humidity),
+        "fused humidity: {rel_humidity}"
+    );
+    assert!(gauge(&metrics.abs_humidity_grams_m3, fusion::FUSED) > 0.0);
+    let exposition = metrics.to_string();
+    assert!(exposition.contains(r#"sensor="FUSED""#), "{exposition}");
+}
+
+#[cfg(all(feature = "sht41", feature = "sgp30"))]
+#[test]
+fn sgp30_humidity_compensation_sources() {
+    let bus = MockI2c::new().with_device(mock::Sgp30::ADDR, mock::Sgp30::new());
+    let sensors = [
+        ("SHT41:desk", 0x44, 25.0, 40.0),
+        ("SHT41:kettle", 0x45, 35.0, 90.0),
+    ]
+    .map(|(id, address, temp_c, humidity_percent)| {
+        let mut sht41 = mock::Sht41::new();
+        sht41.temp_c = temp_c;
+        sht41.humidity_percent = humidity_percent;
+        bus.attach(address, sht41);
+        (id.parse::<SensorId>().unwrap(), address)
+    });
+    let eclss = eclss(&bus);
+    let config = Config::default();
+    let [desk, kettle] = sensors.map(|(id, _)| id);
+    let missing = "SHT41:missing".parse::<SensorId>().unwrap();
+
+    // compensates from the first configured source with a current reading.
+    let sgp30 = |humidity: Vec<SensorId>| {
+        let sensor_config = SensorConfig {
+            compensation: Compensation {
+                humidity: humidity.leak(),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        sensor::Sgp30::new_with_config(eclss, &config, sensor_config, MockDelay::new())
+            .expect("SGP30 config should be valid")
+    };
+    let compensated_humidity = || {
+        let humidity = bus.device(mock::Sgp30::ADDR, |sgp30: &mut mock::Sgp30| {
+            sgp30.humidity()
+        });
+        f64::from(humidity.flatten().expect("humidity should be set")) / 256.0
+    };
+
+    block_on(async {
+        let mut tasks = sensors.map(|(id, address)| {
+            let sensor_config = SensorConfig {
+                address: Some(address),
+                instance: id.instance,
+                ..Default::default()
+            };
+            let sensor =
+                sensor::Sht41::new_with_config(eclss, &config, sensor_config, MockDelay::new())
+                    .expect("SHT41 config should be valid");
+            Box::pin(eclss.run_sensor(sensor, config.clone(), MockDelay::new()))
+        });
+        let mut steps = 0;
+        while [desk, kettle]
+            .into_iter()
+            .any(|id| status(eclss, id) != Status::Up)
+        {
+            for task in &mut tasks {
+                assert!(poll!(task.as_mut()).is_pending());
+            }
+            steps += 1;
+            assert!(steps < 1000, "SHT41s never came up");
+        }
+
+        let metrics = eclss.metrics();
+        let desk_abs_humidity = gauge(&metrics.abs_humidity_grams_m3, desk);
+
+        let mut sensor = sgp30(vec![missing, desk]);
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+        assert_eq!(
+            sensor.compensation().humidity,
+            Some(CompensationSource::Sensor(desk))
+        );
+        assert!((compensated_humidity() - desk_abs_humidity).abs() < 0.01);
+
+        // if none of the configured sources have a reading, the humidity
+        // isn't taken from another sensor.
+        let mut sensor = sgp30(vec![missing]);
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+        assert_eq!(sensor.compensation().humidity, None);
+        assert_eq!(sensor.compensation().temp, None);
+
+        // without fused readings, the mean of both SHT41s is used by default.
+        let mut sensor = sgp30(Vec::new());
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+        assert_eq!(
+            sensor.compensation().humidity,
+            Some(CompensationSource::Mean)
+        );
+        let mean = (desk_abs_humidity + gauge(&metrics.abs_humidity_grams_m3, kettle)) / 2.0;
+        assert!((compensated_humidity() - mean).abs() < 0.01);
+    });
+}
+
+#[cfg(feature = "sgp30")]
+#[test]
+fn sgp30_reset_baseline_clears_stored_baseline() {
+    let bus = MockI2c::new().with_device(mock::Sgp30::ADDR, mock::Sgp30::new());
+    let config = Config::default();
+    let store = MemStore::default();
+    store.set(serde_json::json!({
+        "baseline": { "co2eq": 0x1234, "tvoc": 0x5678 },
+    }));
+
+    let mut sensor =
+        sensor::Sgp30::new(eclss(&bus), &config, MockDelay::new()).with_storage(store.clone());
+    block_on(async {
+        sensor.init().await.expect("init should succeed");
+        let baseline = bus.device(mock::Sgp30::ADDR, |sgp30: &mut mock::Sgp30| sgp30.baseline);
+        assert_eq!(baseline, Some((0x1234, 0x5678)));
+        let response = sensor.command(Command::ResetBaseline).await;
+        assert_eq!(response.ok(), Some(Response::Done));
+    });
+    assert_eq!(store.get().unwrap()["baseline"], serde_json::Value::Null);
+
+    // an SGP30 which is started with the cleared state keeps its own
+    // baseline, rather than the one which was reset.
+    let restarted = MockI2c::new().with_device(mock::Sgp30::ADDR, mock::Sgp30::new());
+    let mut sensor =
+        sensor::Sgp30::new(eclss(&restarted), &config, MockDelay::new()).with_storage(store);
+    block_on(sensor.init()).expect("init should succeed");
+    let baseline = restarted.device(mock::Sgp30::ADDR, |sgp30: &mut mock::Sgp30| sgp30.baseline);
+    assert_eq!(baseline, Some(mock::Sgp30::new().baseline));
+}
+
+#[cfg(feature = "sgp30")]
+#[test]
+fn sgp30_saves_baseline_periodically() {
+    let bus = MockI2c::new().with_device(mock::Sgp30::ADDR, mock::Sgp30::new());
+    let store = MemStore::default();
+    let mut sensor = sensor::Sgp30::new(eclss(&bus), &Config::default(), MockDelay::new())
+        .with_storage(store.clone());
+    block_on(async {
+        sensor.init().await.expect("init should succeed");
+        // polling past the end of calibration doesn't write the baseline on
+        // every poll...
+        for _ in 0..32 {
+            sensor.poll().await.expect("poll should succeed");
+        }
+        assert_eq!(store.get(), None);
+        // ...but it's saved when the sensor is shut down.
+        sensor.shutdown().await.expect("shutdown should succeed");
+    });
+    let (co2eq, tvoc) = mock::Sgp30::new().baseline;
+    assert_eq!(
+        store.get().unwrap()["baseline"],
+        serde_json::json!({ "co2eq": co2eq, "tvoc": tvoc })
+    );
+}
+
+#[cfg(feature = "sgp30")]
+#[test]
+fn run_sensor_resets_saturated_sgp30() {
+    let mut sgp30 = mock::Sgp30::new();
+    // the SGP30 reports its maximum tVOC value when it's misbehaving.
+    sgp30.tvoc_ppb = 60_000;
+    let bus = MockI2c::new().with_device(mock::Sgp30::ADDR, sgp30);
+    let eclss = eclss(&bus);
+    let config = Config::default();
+    let sensor = sensor::Sgp30::new(eclss, &config, MockDelay::new());
+
+    block_on(async {
+        let mut task = pin!(eclss.run_sensor(sensor, config, MockDelay::new()));
+        let mut steps = 0;
+        while counter(&eclss.metrics().sensor_reset_count, SensorName::Sgp30) == 0 {
+            assert!(poll!(task.as_mut()).is_pending());
+            steps += 1;
+            assert!(steps < 1000, "SGP30 was never reset");
+        }
+    });
+
+    let initialized = bus.device(mock::Sgp30::ADDR, |sgp30: &mut mock::Sgp30| {
+        sgp30.is_initialized()
+    });
+    assert_eq!(initialized, Some(true));
+    assert!(counter(&eclss.metrics().sensor_errors, SensorName::Sgp30) >= 1);
+}
+
+#[cfg(feature = "scd41")]
+#[test]
+fn run_sensor_executes_commands() {
+    let mut scd41 = mock::Scd41::new();
+    scd41.co2_ppm = 500;
+    let bus = MockI2c::new().with_device(mock::Scd41::ADDR, scd41);
+    let eclss = eclss(&bus);
+    let config = Config::default();
+    let sensor = sensor::Scd41::new(eclss, &config, MockDelay::new());
+
+    block_on(async {
+        let mut task = pin!(eclss.run_sensor(sensor, config, MockDelay::new()));
+        run_until_up(eclss, SensorName::Scd41, &mut task).await;
+
+        let (_, state) = eclss
+            .sensors()
+            .iter()
+            .find(|(id, _)| **id == SensorId::new(SensorName::Scd41))
+            .expect("SCD41 should be registered");
+        let commands = state.commands();
+        let mut recalibrate =
+            pin!(commands.send(Command::ForcedRecalibration { reference_ppm: 420 }));
+        assert!(poll!(recalibrate.as_mut()).is_pending());
+        // only one command may be sent at a time.
+        let asc = Command::SetAutomaticSelfCalibration { enabled: false };
+        assert_eq!(commands.send(asc).await, Err(CommandError::Busy));
+
+        let mut steps = 0;
+        let response = loop {
+            if let Poll::Ready(response) = poll!(recalibrate.as_mut()) {
+                break response;
+            }
+            assert!(poll!(task.as_mut()).is_pending());
+            steps += 1;
+            assert!(steps < 1000, "SCD41 was never recalibrated");
+        };
+        assert_eq!(
+            response,
+            Ok(Response::Recalibrated {
+                correction_ppm: Some(-80)
+            })
+        );
+
+        let mut disable_asc = pin!(commands.send(asc));
+        let response = loop {
+            if let Poll::Ready(response) = poll!(disable_asc.as_mut()) {
+                break response;
+            }
+            assert!(poll!(task.as_mut()).is_pending());
+        };
+        assert_eq!(
+            response,
+            Ok(Response::AutomaticSelfCalibration { enabled: false })
+        );
+    });
+
+    let (measuring, asc) = bus
+        .device(mock::Scd41::ADDR, |scd41: &mut mock::Scd41| {
+            (scd41.is_measuring(), scd41.automatic_self_calibration)
+        })
+        .unwrap();
+    assert!(measuring, "measurement should be restarted");
+    assert!(!asc);
+}
+
+#[cfg(feature = "scd41")]
+#[test]
+fn run_sensor_resets_sensor_on_command() {
+    let bus = MockI2c::new().with_device(mock::Scd41::ADDR, mock::Scd41::new());
+    let eclss = eclss(&bus);
+    let config = Config::default();
+    let sensor = sensor::Scd41::new(eclss, &config, MockDelay::new());
+
+    block_on(async {
+        let mut task = pin!(eclss.run_sensor(sensor, config, MockDelay::new()));
+        run_until_up(eclss, SensorName::Scd41, &mut task).await;
+
+        let (_, state) = eclss
+            .sensors()
+            .iter()
+            .find(|(id, _)| **id == SensorId::new(SensorName::Scd41))
+            .expect("SCD41 should be registered");
+        let mut reset = pin!(state.commands().send(Command::Reset));
+        let mut steps = 0;
+        let response = loop {
+            if let Poll::Ready(response) = poll!(reset.as_mut()) {
+                break response;
+            }
+            assert!(poll!(task.as_mut()).is_pending());
+            steps += 1;
+            assert!(steps < 1000, "SCD41 was never reset");
+        };
+        assert_eq!(response, Ok(Response::Done));
+    });
+
+    assert_eq!(
+        counter(&eclss.metrics().sensor_reset_count, SensorName::Scd41),
+        1
+    );
+    let measuring = bus.device(mock::Scd41::ADDR, |scd41: &mut mock::Scd41| {
+        scd41.is_measuring()
+    });
+    assert_eq!(measuring, Some(true));
+}
+
+#[cfg(feature = "scd41")]
+#[test]
+fn run_sensor_skips_shutdown_after_failed_reset() {
+    let bus = MockI2c::new().with_device(mock::Scd41::ADDR, mock::Scd41::new());
+    let eclss = eclss(&bus);
+    let config = Config::default();
+    let sensor = sensor::Scd41::new(eclss, &config, MockDelay::new());
+
+    block_on(async {
+        let mut task = pin!(eclss.run_sensor(sensor, config, MockDelay::new()));
+        run_until_up(eclss, SensorName::Scd41, &mut task).await;
+
+        let (_, state) = eclss
+            .sensors()
+            .iter()
+            .find(|(id, _)| **id == SensorId::new(SensorName::Scd41))
+            .expect("SCD41 should be registered");
+        assert!(bus.detach(mock::Scd41::ADDR));
+        let mut reset = pin!(state.commands().send(Command::Reset));
+        let mut steps = 0;
+        let response = loop {
+            if let Poll::Ready(response) = poll!(reset.as_mut()) {
+                break response;
+            }
+            assert!(poll!(task.as_mut()).is_pending());
+            steps += 1;
+            assert!(steps < 1000, "SCD41 reset never completed");
+        };
+        assert!(response.is_err(), "reset should fail: {response:?}");
+
+        let errors = counter(&eclss.metrics().sensor_errors, SensorName::Scd41);
+        eclss.shutdown();
+        assert_eq!(task.await, Ok(()));
+        // the sensor isn't shut down, which would fail again.
+        assert_eq!(
+            counter(&eclss.metrics().sensor_errors, SensorName::Scd41),
+            errors
+        );
+    });
+}
+
+#[cfg(feature = "sen55")]
+#[test]
+fn sen55_schedules_fan_cleaning() {
+    let bus = MockI2c::new().with_device(mock::Sen55::ADDR, mock::Sen55::new());
+    let eclss = eclss(&bus);
+    let mut config = Config::default();
+    config.fan_cleaning_interval = Duration::from_secs(3);
+    let store = MemStore::default();
+    // the fan was last cleaned a second before the sensor was restarted.
+    store.set(serde_json::json!({
+        "warm_start": null,
+        "voc_algorithm": null,
+        "since_fan_cleaning_secs": 1,
+    }));
+    let fan_cleanings = || {
+        bus.device(mock::Sen55::ADDR, |sen55: &mut mock::Sen55| {
+            sen55.fan_cleanings()
+        })
+    };
+
+    let mut sensor =
+        sensor::Sen55::new(eclss, &config, MockDelay::new()).with_storage(store.clone());
+    block_on(async {
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+        assert_eq!(fan_cleanings(), Some(0));
+        sensor.poll().await.expect("poll should succeed");
+        assert_eq!(fan_cleanings(), Some(1));
+    });
+
+    // the sensor's own cleaning schedule is disabled.
+    let auto_cleaning = bus.device(mock::Sen55::ADDR, |sen55: &mut mock::Sen55| {
+        sen55.auto_cleaning_interval
+    });
+    assert_eq!(auto_cleaning, Some(0));
+    assert_eq!(store.get().unwrap()["since_fan_cleaning_secs"], 0);
+}
+
+#[cfg(feature = "sen55")]
+#[test]
+fn sen55_restores_recent_voc_algorithm_state() {
+    const NOW: u64 = 100_000;
+    let restored = |saved_at: Option<u64>| {
+        let bus = MockI2c::new().with_device(mock::Sen55::ADDR, mock::Sen55::new());
+        let eclss: &'static Eclss<MockI2c, 16> =
+            Box::leak(Box::new(Eclss::new(bus.clone()).with_clock(|| NOW)));
+        let store = MemStore::default();
+        store.set(serde_json::json!({
+            "warm_start": null,
+            "voc_algorithm": [0, 1, 0, 2, 0, 3, 0, 4],
+            "voc_algorithm_saved_at": saved_at,
+            "since_fan_cleaning_secs": 0,
+        }));
+        let mut sensor = sensor::Sen55::new(eclss, &Config::default(), MockDelay::new())
+            .with_storage(store.clone());
+        block_on(sensor.init()).expect("init should succeed");
+        let voc_state = bus.device(mock::Sen55::ADDR, |sen55: &mut mock::Sen55| sen55.voc_state);
+        voc_state != Some([0; 4])
+    };
+
+    // saved a minute before the sensor was restarted.
+    assert!(restored(Some(NOW - 60)));
+    // saved an hour before, which is too long ago for the state to be valid.
+    assert!(!restored(Some(NOW - 60 * 60)));
+    // saved without a clock, so its age is unknown.
+    assert!(!restored(None));
+}
+
+#[cfg(feature = "sen55")]
+#[test]
+fn sen55_reports_device_status_and_number_concentrations() {
+    let mut sen55 = mock::Sen55::new();
+    // laser failure
+    sen55.device_status = 1 << 5;
+    let bus = MockI2c::new().with_device(mock::Sen55::ADDR, sen55);
+    let eclss = eclss(&bus);
+
+    let mut sensor = sensor::Sen55::new(eclss, &Config::default(), MockDelay::new());
+    block_on(async {
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+    });
+
+    let status = sensor.device_status();
+    assert!(status.laser_failure);
+    assert!(status.is_fault());
+    let metrics = eclss.metrics();
+    let flag = |flag: &str| {
+        metrics
+            .device_status
+            .metrics()
+            .iter()
+            .find(|(label, _)| label.flag == flag)
+            .map(|(_, gauge)| gauge.value())
+    };
+    assert_eq!(flag("laser_failure"), Some(1.0));
+    assert_eq!(flag("fan_failure"), Some(0.0));
+
+    let nc0_5 = metrics
+        .pm_number_conc
+        .metrics()
+        .iter()
+        .find(|(label, _)| label.diameter == "0.5")
+        .map(|(_, gauge)| gauge.value());
+    assert_eq!(nc0_5, Some(10.0));
+
+    // PM2.5 of 3.5 µg/m³ is an AQI of 19, and PM10 of 4.5 µg/m³ is 4.
+    assert_eq!(gauge(&metrics.aqi, SensorName::Sen55), 19.0);
+}
+
+#[cfg(all(feature = "serde", feature = "sen55"))]
+#[test]
+fn metrics_json_matches_api_schema() {
+    let bus = MockI2c::new().with_device(mock::Sen55::ADDR, mock::Sen55::new());
+    let eclss = eclss(&bus);
+
+    let mut sensor = sensor::Sen55::new(eclss, &Config::default(), MockDelay::new());
+    block_on(async {
+        sensor.init().await.expect("init should succeed");
+        sensor.poll().await.expect("poll should succeed");
+    });
+
+    let json = serde_json::to_value(eclss.metrics()).unwrap();
+    for metric in eclss_api::schema::ALL {
+        assert!(json.get(metric.name).is_some(), "{}", metric.name);
+    }
+
+    let metrics = serde_json::from_value::<eclss_api::Metrics>(json).unwrap();
+    assert_eq!(metrics.schema_version, eclss_api::schema::VERSION);
+    assert!(!metrics.temp_c.is_empty());
+    assert!(!metrics.aqi.is_empty());
+    assert!(!metrics.pm_number_concentration_cm3.is_empty());
+    let laser_failure = metrics
+        .device_status
+        .iter()
+        .find(|status| status.flag == "laser_failure")
+        .expect("device status should be reported");
+    assert_eq!(laser_failure.sensor, SensorId::new(SensorName::Sen55));
+    assert_eq!(laser_failure.value, 0.0);
+}
+
+#[test]
+fn probe_detects_sensors() {
+    let mut sen54 = mock::Sen55::new();
+    sen54.product_name = "SEN54";
+    let mut bus = MockI2c::new()
+        .with_device(mock::Scd41::ADDR, mock::Scd41::new())
+        .with_device(mock::Sgp30::ADDR, mock::Sgp30::new())
+        .with_device(0x45, mock::Sht41::new())
+        .with_device(mock::Pmsa003i::ADDR, mock::Pmsa003i::new())
+        // an unsupported model which shares the SEN55's address
+        .with_device(mock::Sen55::ADDR, sen54);
+
+    let mut detected = Vec::new();
+    block_on(probe::probe(&mut bus, &mut MockDelay::new(), |sensor| {
+        detected.push(sensor)
+    }));
+    assert_eq!(
+        detected,
+        [
+            Detected {
+                name: SensorName::Pmsa003i,
+                address: mock::Pmsa003i::ADDR
+            },
+            Detected {
+                name: SensorName::Sht41,
+                address: 0x45
+            },
+            Detected {
+                name: SensorName::Sgp30,
+                address: mock::Sgp30::ADDR
+            },
+            Detected {
+                name: SensorName::Scd41,
+                address: mock::Scd41::ADDR
+            },
+        ]
+    );
+
+    bus.attach(mock::Sen55::ADDR, mock::Sen55::new());
+    let found = block_on(probe::identify(
+        &mut bus,
+        &mut MockDelay::new(),
+        mock::Sen55::ADDR,
+    ));
+    assert_eq!(found, Some(SensorName::Sen55));
+}